@@ -0,0 +1,364 @@
+use serde_json::{Map, Value};
+
+use crate::models::enterprise_user::ENTERPRISE_USER_URN;
+use crate::models::group::{Group, GroupRequest};
+use crate::models::others::{PatchOp, PatchOperations};
+use crate::models::scim_schema::{attribute_type, find_attribute, get_schemas, Attributes, Schema};
+use crate::models::user::{User, UserRequest};
+use crate::utils::case::canonicalize_json;
+use crate::utils::error::SCIMError;
+use crate::utils::filter::Filter;
+use crate::utils::password::strip_password;
+use crate::utils::patch::PatchPath;
+
+
+/// Deserializes the body of a create or replace request for a user sent by the Microsoft Entra ID provisioning service.
+///
+/// Entra sends boolean attributes as strings such as `"True"` or `"false"`. They are converted to booleans wherever
+/// the User or Enterprise User schema declares a boolean, before the usual deserialization.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the payload is not a valid user once normalised.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::compat::entra;
+///
+/// let user = entra::parse_user(r#"{"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "bjensen", "active": "True"}"#).unwrap();
+/// assert_eq!(user.active, Some(true));
+/// ```
+pub fn parse_user(json: &str) -> Result<UserRequest, SCIMError> {
+    let mut value: Value = serde_json::from_str(json).map_err(SCIMError::DeserializationError)?;
+    let schemas = get_schemas(vec!["user", "enterprise_user"])?;
+    if cfg!(feature = "case-insensitive") {
+        canonicalize_json(&mut value, &schemas);
+    }
+    coerce_resource_booleans(&mut value, &schemas);
+    serde_json::from_value(value).map_err(SCIMError::DeserializationError)
+}
+
+/// Deserializes the body of a create or replace request for a group sent by the Microsoft Entra ID provisioning
+/// service. See `parse_user`.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the payload is not a valid group once normalised.
+pub fn parse_group(json: &str) -> Result<GroupRequest, SCIMError> {
+    let mut value: Value = serde_json::from_str(json).map_err(SCIMError::DeserializationError)?;
+    let schemas = get_schemas(vec!["group"])?;
+    if cfg!(feature = "case-insensitive") {
+        canonicalize_json(&mut value, &schemas);
+    }
+    coerce_resource_booleans(&mut value, &schemas);
+    serde_json::from_value(value).map_err(SCIMError::DeserializationError)
+}
+
+/// Deserializes a `PatchOp` for a user sent by the Microsoft Entra ID provisioning service. See `normalize_patch_op`.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the payload is not a valid `PatchOp`.
+pub fn parse_user_patch(json: &str) -> Result<PatchOp, SCIMError> {
+    parse_patch(json, &get_schemas(vec!["user", "enterprise_user"])?)
+}
+
+/// Deserializes a `PatchOp` for a group sent by the Microsoft Entra ID provisioning service. See `normalize_patch_op`.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the payload is not a valid `PatchOp`.
+pub fn parse_group_patch(json: &str) -> Result<PatchOp, SCIMError> {
+    parse_patch(json, &get_schemas(vec!["group"])?)
+}
+
+fn parse_patch(json: &str, schemas: &[Schema]) -> Result<PatchOp, SCIMError> {
+    let patch = PatchOp::try_from(json)?;
+    Ok(normalize_patch_op(patch, schemas))
+}
+
+/// Rewrites the operations of an Entra `PatchOp` into their standard form.
+///
+/// * `op` values such as `"Replace"` are lowercased.
+/// * Operations without a `path` are split into one operation per attribute, as Entra uses attribute paths such as
+///   `name.givenName` as keys of `value`. Extension objects in `value` are split into `urn:...:attribute` paths.
+/// * String booleans are converted wherever `schemas` declare a boolean for the target attribute.
+/// * `remove` operations on `members` that list the members to remove in `value` are turned into one
+///   `members[value eq "..."]` removal per member.
+///
+/// Paths are resolved against `schemas`, whose first entry is the core schema of the resource.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::compat::entra;
+///
+/// let patch = entra::parse_user_patch(r#"{
+///     "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+///     "Operations": [{"op": "Replace", "path": "active", "value": "False"}]
+/// }"#).unwrap();
+///
+/// assert_eq!(patch.operations[0].op, "replace");
+/// assert_eq!(patch.operations[0].value, Some(serde_json::Value::Bool(false)));
+/// ```
+pub fn normalize_patch_op(patch: PatchOp, schemas: &[Schema]) -> PatchOp {
+    let mut operations = Vec::new();
+    for operation in patch.operations {
+        let op = operation.op.to_lowercase();
+        match (operation.path, operation.value) {
+            (None, Some(Value::Object(values))) if op != "remove" => {
+                for (path, value) in values {
+                    match value {
+                        // Objects keyed by a schema URN are split into URN-qualified attribute paths.
+                        Value::Object(attributes) if is_schema_urn(&path) => {
+                            for (attribute, value) in attributes {
+                                operations.push(normalize_operation(&op, format!("{}:{}", path, attribute), Some(value), schemas));
+                            }
+                        }
+                        value => operations.push(normalize_operation(&op, path, Some(value), schemas)),
+                    }
+                }
+            }
+            (Some(path), Some(Value::Array(members))) if op == "remove" && path.eq_ignore_ascii_case("members") => {
+                for member in members {
+                    match member.get("value") {
+                        Some(value) => operations.push(PatchOperations {
+                            op: op.clone(),
+                            path: Some(format!("members[value eq {}]", value)),
+                            value: None,
+                        }),
+                        None => operations.push(PatchOperations { op: op.clone(), path: Some(path.clone()), value: Some(member) }),
+                    }
+                }
+            }
+            (Some(path), value) => operations.push(normalize_operation(&op, path, value, schemas)),
+            (None, value) => operations.push(PatchOperations { op, path: None, value }),
+        }
+    }
+    PatchOp {
+        operations,
+        ..patch
+    }
+}
+
+fn normalize_operation(op: &str, path: String, mut value: Option<Value>, schemas: &[Schema]) -> PatchOperations {
+    if let (Some(value), Ok(parsed)) = (value.as_mut(), PatchPath::parse(&path)) {
+        match attribute_type(schemas, &parsed).as_deref() {
+            Some("boolean") => coerce_boolean(value),
+            Some("complex") => {
+                if let Some(attribute) = find_attribute(schemas, &parsed) {
+                    coerce_attribute(value, attribute);
+                }
+            }
+            _ => {}
+        }
+    }
+    PatchOperations {
+        op: op.to_string(),
+        path: Some(path),
+        value,
+    }
+}
+
+/// Returns the value of an `attr eq "value"` filter, such as the `userName eq "..."` lookups Entra sends before
+/// creating a user, as `(attribute, value)`. See `Filter::equality`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::compat::entra;
+///
+/// let lookup = entra::equality_filter(r#"userName eq "Test_User_dfeef4c5-5681-4387-b016-bdf221e82081""#);
+/// assert_eq!(lookup, Some(("userName".to_string(), "Test_User_dfeef4c5-5681-4387-b016-bdf221e82081".to_string())));
+/// assert_eq!(entra::equality_filter(r#"userName sw "Test""#), None);
+/// ```
+pub fn equality_filter(filter: &str) -> Option<(String, String)> {
+    Filter::parse(filter).ok()?.equality()
+}
+
+/// Shapes a user for a response to Entra.
+///
+/// Entra's SCIM validator expects `meta.resourceType`, the Enterprise User URN in `schemas` whenever the extension is
+/// present, and no `password`.
+pub fn user_response(user: User) -> User {
+    let mut user = strip_password(user);
+    if user.body.enterprise_user.is_some() && !user.body.schemas.iter().any(|schema| schema.eq_ignore_ascii_case(ENTERPRISE_USER_URN)) {
+        user.body.schemas.push(ENTERPRISE_USER_URN.to_string());
+    }
+    user.meta.get_or_insert_with(Default::default).resource_type = Some("User".to_string());
+    user
+}
+
+/// Shapes a group for a response to Entra, which expects `meta.resourceType`.
+pub fn group_response(mut group: Group) -> Group {
+    group.meta.get_or_insert_with(Default::default).resource_type = Some("Group".to_string());
+    group
+}
+
+fn is_schema_urn(key: &str) -> bool {
+    key.get(..4).map_or(false, |prefix| prefix.eq_ignore_ascii_case("urn:"))
+}
+
+fn coerce_resource_booleans(resource: &mut Value, schemas: &[Schema]) {
+    let object = match resource.as_object_mut() {
+        Some(object) => object,
+        None => return,
+    };
+    for (key, value) in object.iter_mut() {
+        if let Some(extension) = schemas.iter().find(|schema| schema.id.eq_ignore_ascii_case(key)) {
+            if let Some(extension_object) = value.as_object_mut() {
+                coerce_attributes(extension_object, &extension.attributes);
+            }
+        } else if let Some(attribute) = schemas[0].attributes.iter().find(|attribute| attribute.name.eq_ignore_ascii_case(key)) {
+            coerce_attribute(value, attribute);
+        }
+    }
+}
+
+fn coerce_attributes(object: &mut Map<String, Value>, attributes: &[Attributes]) {
+    for (key, value) in object.iter_mut() {
+        if let Some(attribute) = attributes.iter().find(|attribute| attribute.name.eq_ignore_ascii_case(key)) {
+            coerce_attribute(value, attribute);
+        }
+    }
+}
+
+fn coerce_attribute(value: &mut Value, attribute: &Attributes) {
+    match value {
+        Value::Array(values) => values.iter_mut().for_each(|value| coerce_attribute(value, attribute)),
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                let sub_attribute = attribute.sub_attributes.iter().flatten().find(|sub_attribute| sub_attribute.name.eq_ignore_ascii_case(key));
+                if sub_attribute.map(|sub_attribute| sub_attribute.type_.as_str()) == Some("boolean") {
+                    coerce_boolean(value);
+                }
+            }
+        }
+        value if attribute.type_ == "boolean" => coerce_boolean(value),
+        _ => {}
+    }
+}
+
+fn coerce_boolean(value: &mut Value) {
+    if let Value::String(string) = value {
+        if string.eq_ignore_ascii_case("true") {
+            *value = Value::Bool(true);
+        } else if string.eq_ignore_ascii_case("false") {
+            *value = Value::Bool(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::utils::patch::apply;
+
+    use super::*;
+
+    const CREATE_USER: &str = include_str!("../../tests/fixtures/entra/create_user.json");
+    const CREATE_GROUP: &str = include_str!("../../tests/fixtures/entra/create_group.json");
+    const PATCH_USER: &str = include_str!("../../tests/fixtures/entra/patch_user.json");
+    const PATCH_USER_ENTERPRISE: &str = include_str!("../../tests/fixtures/entra/patch_user_enterprise.json");
+    const PATCH_GROUP_MEMBERS: &str = include_str!("../../tests/fixtures/entra/patch_group_members.json");
+
+    #[test]
+    fn create_user_with_string_booleans() {
+        assert!(UserRequest::try_from(CREATE_USER).is_err());
+
+        let user = parse_user(CREATE_USER).unwrap();
+
+        assert_eq!(user.active, Some(true));
+        assert_eq!(user.emails.as_ref().unwrap()[0].primary, Some(true));
+        assert_eq!(user.external_id, Some("0a21f0f2-8d2a-4f8e-bf98-7363c4aed4ef".to_string()));
+        assert_eq!(user.enterprise_user.unwrap().employee_number, Some("701984".to_string()));
+    }
+
+    #[test]
+    fn create_group() {
+        let group = parse_group(CREATE_GROUP).unwrap();
+
+        assert_eq!(group.display_name, "displayName");
+        assert_eq!(group.external_id, Some("8aa1a0c0-c4c3-4bc0-b4a5-2ef676900159".to_string()));
+    }
+
+    #[test]
+    fn patch_user_is_normalised_and_applies() {
+        let patch = parse_user_patch(PATCH_USER).unwrap();
+
+        let operations: Vec<(&str, Option<&str>)> = patch.operations.iter().map(|operation| (operation.op.as_str(), operation.path.as_deref())).collect();
+        assert_eq!(operations, vec![
+            ("replace", Some("active")),
+            ("add", Some("displayName")),
+            ("add", Some("name.givenName")),
+            ("add", Some("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department")),
+            ("replace", Some(r#"emails[type eq "work"].value"#)),
+            ("replace", Some(r#"emails[type eq "work"].primary"#)),
+        ]);
+        assert_eq!(patch.operations[0].value, Some(json!(false)));
+        assert_eq!(patch.operations[5].value, Some(json!(true)));
+
+        let mut user = serde_json::to_value(parse_user(CREATE_USER).unwrap()).unwrap();
+        apply(&mut user, &patch).unwrap();
+        let user: User = serde_json::from_value(user).unwrap();
+        assert_eq!(user.body.active, Some(false));
+        assert_eq!(user.body.name.unwrap().given_name, Some("Barbara".to_string()));
+        assert_eq!(user.body.emails.unwrap()[0].value, Some("updatedEmail@microsoft.com".to_string()));
+        assert_eq!(user.body.enterprise_user.unwrap().department, Some("Marketing".to_string()));
+    }
+
+    #[test]
+    fn pathless_enterprise_replace_targets_extension_attributes() {
+        let patch = parse_user_patch(PATCH_USER_ENTERPRISE).unwrap();
+
+        let paths: Vec<Option<&str>> = patch.operations.iter().map(|operation| operation.path.as_deref()).collect();
+        assert_eq!(paths, vec![
+            Some("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department"),
+            Some("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager"),
+        ]);
+
+        let mut user = serde_json::to_value(parse_user(CREATE_USER).unwrap()).unwrap();
+        apply(&mut user, &patch).unwrap();
+        let enterprise_user = serde_json::from_value::<User>(user).unwrap().body.enterprise_user.unwrap();
+        assert_eq!(enterprise_user.department, Some("Sales".to_string()));
+        assert_eq!(enterprise_user.employee_number, Some("701984".to_string()));
+        assert_eq!(enterprise_user.manager.unwrap().value, Some("26118915-6090-4610-87e4-49d8ca9f808d".to_string()));
+    }
+
+    #[test]
+    fn patch_group_removes_members_listed_in_value() {
+        let patch = parse_group_patch(PATCH_GROUP_MEMBERS).unwrap();
+
+        assert_eq!(patch.operations.len(), 3);
+        assert_eq!(patch.operations[1], PatchOperations {
+            op: "remove".to_string(),
+            path: Some(r#"members[value eq "2819c223-7f76-453a-919d-413861904646"]"#.to_string()),
+            value: None,
+        });
+
+        let mut group = json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+            "displayName": "Tour Guides",
+            "members": [{"value": "2819c223-7f76-453a-919d-413861904646"}, {"value": "f648f8d5ea4e4cd38e9c"}]
+        });
+        apply(&mut group, &patch).unwrap();
+        assert_eq!(group["members"], json!([{"value": "902c246b-6245-4190-8e05-00816be7344a"}]));
+    }
+
+    #[test]
+    fn responses_are_shaped_for_entra() {
+        let mut user: User = serde_json::from_str(CREATE_USER.replace("\"True\"", "true").replace("\"true\"", "true").as_str()).unwrap();
+        user.body.password = Some("hash".to_string());
+        user.body.schemas.truncate(1);
+
+        let user = user_response(user);
+
+        assert_eq!(user.body.password, None);
+        assert_eq!(user.body.schemas[1], ENTERPRISE_USER_URN);
+        assert_eq!(user.meta.unwrap().resource_type, Some("User".to_string()));
+        assert_eq!(group_response(Group::default()).meta.unwrap().resource_type, Some("Group".to_string()));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::models::others::{ListQuery, ListResponse, PatchOp, SearchRequest};
+use crate::models::user::{Email, StoredUser, User, UserRequest};
+use crate::provider::resource_provider::ResourceProvider;
+use crate::utils::error::SCIMError;
+use crate::utils::filter::Filter;
+use crate::utils::password::strip_password;
+
+/// What a service provider does when Okta deprovisions a user by setting `active` to false.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftDelete {
+    /// Keep the user with `active: false`, so Okta can reactivate it later.
+    Deactivate,
+    /// Delete the user.
+    Delete,
+}
+
+/// Handles the provisioning flows of Okta's SCIM 2.0 integration.
+///
+/// Okta looks users up with `filter=userName eq "..."` before creating them, updates them with full `PUT`s (or
+/// `PATCH` in newer integrations), never deletes them but sets `active` to false, pages with `startIndex` and
+/// `count`, and expects one email to be marked `primary`. The flows are carried out against a `ResourceProvider`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::compat::okta::{OktaProfile, SoftDelete};
+/// use scim_v2::models::others::ListQuery;
+///
+/// let profile = OktaProfile { soft_delete: SoftDelete::Deactivate };
+/// let query = ListQuery::from_query_string("filter=userName%20eq%20%22test.user%40okta.local%22&startIndex=1&count=100", 200).unwrap();
+///
+/// assert_eq!(OktaProfile::user_name_lookup(&query), Some("test.user@okta.local".to_string()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OktaProfile {
+    pub soft_delete: SoftDelete,
+}
+
+impl Default for OktaProfile {
+    fn default() -> Self {
+        OktaProfile {
+            soft_delete: SoftDelete::Deactivate,
+        }
+    }
+}
+
+impl OktaProfile {
+    /// Returns the `userName` Okta is looking up, when the query is exactly a `userName eq "..."` filter.
+    ///
+    /// Service providers can answer these lookups with an index on `userName` instead of evaluating a filter. The
+    /// attribute name is matched case-insensitively.
+    pub fn user_name_lookup(query: &ListQuery) -> Option<String> {
+        let (attribute, value) = Filter::parse(query.filter.as_deref()?).ok()?.equality()?;
+        if attribute.eq_ignore_ascii_case("userName") {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Creates a user Okta asked to create, making sure one email is primary.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `ResourceProvider::create_user`.
+    pub fn create_user(&self, provider: &dyn ResourceProvider, mut request: UserRequest) -> Result<StoredUser, SCIMError> {
+        ensure_primary_email(&mut request.emails);
+        provider.create_user(request)
+    }
+
+    /// Applies a full `PUT` from Okta to a user, and returns the user, or `None` if it was deleted.
+    ///
+    /// Okta only sends `password` when it changes; `ResourceProvider::replace_user` keeps the stored one when the request
+    /// has none. When the request sets `active` to false and the profile uses `SoftDelete::Delete`, the user is deleted
+    /// instead.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `ResourceProvider::replace_user` or `ResourceProvider::delete_user`.
+    pub fn replace_user(&self, provider: &dyn ResourceProvider, id: &str, mut request: UserRequest) -> Result<Option<StoredUser>, SCIMError> {
+        if request.active == Some(false) && self.soft_delete == SoftDelete::Delete {
+            return provider.delete_user(id).map(|()| None);
+        }
+        ensure_primary_email(&mut request.emails);
+        provider.replace_user(id, request).map(Some)
+    }
+
+    /// Applies a `PATCH` from Okta to a user, such as the `{"active": false}` replacement it sends to deprovision a
+    /// user, and returns the user, or `None` if it was deleted.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `ResourceProvider::patch_user`, `ResourceProvider::replace_user` or `ResourceProvider::delete_user`.
+    pub fn patch_user(&self, provider: &dyn ResourceProvider, id: &str, patch: &PatchOp) -> Result<Option<StoredUser>, SCIMError> {
+        let mut patched = provider.patch_user(id, patch)?;
+        if patched.user.active == Some(false) && self.soft_delete == SoftDelete::Delete {
+            return provider.delete_user(id).map(|()| None);
+        }
+        if ensure_primary_email(&mut patched.user.emails) {
+            patched = provider.replace_user(id, patched.user)?;
+        }
+        Ok(Some(patched))
+    }
+
+    /// Returns the users matching a list request, paged with `startIndex` and `count` by the provider.
+    ///
+    /// A lookup that matches nothing still returns a `ListResponse` with `totalResults` 0, which Okta reads as
+    /// "create the user".
+    ///
+    /// # Errors
+    ///
+    /// The errors of `ResourceProvider::search_users`.
+    pub fn list_users(provider: &dyn ResourceProvider, query: &ListQuery) -> Result<ListResponse<User>, SCIMError> {
+        let response = provider.search_users(&SearchRequest::from(query.clone()))?;
+        Ok(ListResponse {
+            items_per_page: response.items_per_page,
+            total_results: response.total_results,
+            start_index: response.start_index,
+            next_cursor: response.next_cursor,
+            previous_cursor: response.previous_cursor,
+            schemas: response.schemas,
+            resources: response.resources.into_iter().map(|user| OktaProfile::user_response(User::from(user))).collect(),
+        })
+    }
+
+    /// Shapes a user for a response to Okta: no password, and one email marked primary.
+    pub fn user_response(user: User) -> User {
+        let mut user = strip_password(user);
+        ensure_primary_email(&mut user.body.emails);
+        user
+    }
+}
+
+/// Marks the first email primary when none is, and returns whether it did.
+fn ensure_primary_email(emails: &mut Option<Vec<Email>>) -> bool {
+    if let Some(emails) = emails {
+        if !emails.iter().any(|email| email.primary == Some(true)) {
+            if let Some(first) = emails.first_mut() {
+                first.primary = Some(true);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::provider::memory::MemoryProvider;
+
+    use super::*;
+
+    const CREATE_USER: &str = include_str!("../../tests/fixtures/okta/create_user.json");
+    const REPLACE_USER: &str = include_str!("../../tests/fixtures/okta/replace_user.json");
+    const DEACTIVATE_USER: &str = include_str!("../../tests/fixtures/okta/deactivate_user.json");
+
+    const LOOKUP_QUERY: &str = "filter=userName%20eq%20%22test.user%40okta.local%22&startIndex=1&count=100";
+
+    fn lookup(provider: &MemoryProvider) -> ListResponse<User> {
+        let query = ListQuery::from_query_string(LOOKUP_QUERY, 200).unwrap();
+        assert_eq!(OktaProfile::user_name_lookup(&query), Some("test.user@okta.local".to_string()));
+        OktaProfile::list_users(provider, &query).unwrap()
+    }
+
+    #[test]
+    fn okta_provisioning_sequence() {
+        let profile = OktaProfile::default();
+        let provider = MemoryProvider::new("https://example.com/v2");
+
+        // 1. Okta checks whether the user exists.
+        let response = lookup(&provider);
+        assert_eq!(response.total_results, 0);
+        assert_eq!(serde_json::to_value(&response).unwrap()["Resources"], serde_json::json!([]));
+
+        // 2. Okta creates the user.
+        let id = profile.create_user(&provider, UserRequest::try_from(CREATE_USER).unwrap()).unwrap().id;
+
+        // 3. The lookup now finds it, without exposing the password.
+        let response = lookup(&provider);
+        assert_eq!(response.total_results, 1);
+        assert_eq!(response.resources[0].id, Some(id.clone()));
+        assert_eq!(response.resources[0].body.password, None);
+        assert_eq!(response.resources[0].body.emails.as_ref().unwrap()[0].primary, Some(true));
+
+        // 4. Okta updates the profile with a full PUT that omits the password and the primary flag.
+        let replaced = profile.replace_user(&provider, &id, UserRequest::try_from(REPLACE_USER).unwrap()).unwrap().unwrap();
+        assert_eq!(replaced.user.display_name, Some("Another User".to_string()));
+        assert_eq!(replaced.user.emails.as_ref().unwrap()[0].primary, Some(true));
+        assert_eq!(provider.stored_password(&id), Some(serde_json::json!("1mz050nq")));
+
+        // 5. Okta deprovisions the user by deactivating it.
+        let patched = profile.patch_user(&provider, &id, &serde_json::from_str(DEACTIVATE_USER).unwrap()).unwrap().unwrap();
+        assert_eq!(patched.user.active, Some(false));
+        assert_eq!(provider.get_user(&id).unwrap().user.active, Some(false));
+        assert_eq!(lookup(&provider).total_results, 1);
+    }
+
+    #[test]
+    fn soft_delete_can_map_deactivation_to_delete() {
+        let profile = OktaProfile { soft_delete: SoftDelete::Delete };
+        let provider = MemoryProvider::new("https://example.com/v2");
+
+        let id = profile.create_user(&provider, UserRequest::try_from(CREATE_USER).unwrap()).unwrap().id;
+        assert!(profile.patch_user(&provider, &id, &serde_json::from_str(DEACTIVATE_USER).unwrap()).unwrap().is_none());
+        assert!(matches!(provider.get_user(&id), Err(SCIMError::NotFoundError(_))));
+
+        let id = profile.create_user(&provider, UserRequest::try_from(CREATE_USER).unwrap()).unwrap().id;
+        let mut request = UserRequest::try_from(REPLACE_USER).unwrap();
+        request.active = Some(false);
+        assert!(profile.replace_user(&provider, &id, request).unwrap().is_none());
+        assert_eq!(lookup(&provider).total_results, 0);
+    }
+
+    #[test]
+    fn patch_user_keeps_one_email_primary() {
+        let profile = OktaProfile::default();
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let id = profile.create_user(&provider, UserRequest::try_from(CREATE_USER).unwrap()).unwrap().id;
+
+        let patch = serde_json::from_value(serde_json::json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [{"op": "replace", "path": "emails", "value": [{"value": "other@okta.local", "type": "work"}]}]
+        })).unwrap();
+        profile.patch_user(&provider, &id, &patch).unwrap();
+
+        assert_eq!(provider.get_user(&id).unwrap().user.emails.unwrap()[0].primary, Some(true));
+    }
+
+    #[test]
+    fn list_users_pages_with_start_index_and_count() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        for index in 0..5 {
+            provider.create_user(UserRequest { user_name: format!("user{}", index), ..Default::default() }).unwrap();
+        }
+        let query = ListQuery::from_query_string("sortBy=userName&startIndex=2&count=2", 200).unwrap();
+
+        let response = OktaProfile::list_users(&provider, &query).unwrap();
+
+        assert_eq!(response.total_results, 5);
+        assert_eq!(response.start_index, 2);
+        assert_eq!(response.resources.iter().map(|user| user.body.user_name.as_str()).collect::<Vec<_>>(), vec!["user1", "user2"]);
+        assert_eq!(OktaProfile::user_name_lookup(&ListQuery::from_query_string("filter=displayName%20eq%20%22x%22", 200).unwrap()), None);
+    }
+}
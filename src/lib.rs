@@ -17,11 +17,14 @@
 //! ### Validating a User
 //!
 //! ```rust
-//! use scim_v2::models::user::User;
+//! use scim_v2::models::user::{User, UserRequest};
 //!
 //! let user = User {
-//!     user_name: "jdoe@example.com".to_string(),
-//!    // other fields...
+//!     body: UserRequest {
+//!         user_name: "jdoe@example.com".to_string(),
+//!         // other fields...
+//!         ..Default::default()
+//!     },
 //!     ..Default::default()
 //! };
 //!
@@ -36,12 +39,15 @@
 //! # Examples
 //!
 //! ```rust
-//! use scim_v2::models::user::User;
+//! use scim_v2::models::user::{User, UserRequest};
 //!
 //! let user = User {
-//!     schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:User".to_string()],
-//!     user_name: "jdoe@example.com".to_string(),
-//!     // Initialize other fields as necessary...
+//!     body: UserRequest {
+//!         schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:User".to_string()],
+//!         user_name: "jdoe@example.com".to_string(),
+//!         // Initialize other fields as necessary...
+//!         ..Default::default()
+//!     },
 //!     ..Default::default()
 //! };
 //!
@@ -82,6 +88,9 @@
 const USER_SCHEMA: &str = include_str!("schemas/user.json");
 const GROUP_SCHEMA: &str = include_str!("schemas/group.json");
 const ENTERPRISE_USER_SCHEMA: &str = include_str!("schemas/enterprise_user.json");
+const RESOURCE_TYPE_SCHEMA: &str = include_str!("schemas/resource_type.json");
+const SERVICE_PROVIDER_CONFIG_SCHEMA: &str = include_str!("schemas/service_provider_config.json");
+const SCHEMA_SCHEMA: &str = include_str!("schemas/scim_schema.json");
 
 /// Declaring the models module which contains various submodules
 pub mod models {
@@ -92,12 +101,49 @@ pub mod models {
     pub mod enterprise_user;
     pub mod scim_schema;
     pub mod others;
+    pub mod discovery;
     pub mod errors;
 }
 
-/// Declaring the utils module which contains the error submodule
+/// Declaring the utils module which contains the error, cursor, etag, redact, password, me, patch, case, parse, manager and filter submodules
 pub mod utils {
     pub mod error;
+    pub mod cursor;
+    pub mod etag;
+    pub mod redact;
+    pub mod password;
+    pub mod me;
+    pub mod patch;
+    pub mod case;
+    pub mod parse;
+    pub mod manager;
+    pub mod filter;
+}
+
+/// Declaring the sync module which plans one-way provisioning of users and groups
+pub mod sync;
+
+/// Declaring the mapping module which maps arbitrary source records to SCIM resources
+pub mod mapping;
+
+/// Declaring the compat module which normalises the payloads of specific identity providers
+pub mod compat {
+    pub mod entra;
+    pub mod okta;
+}
+
+/// Declaring the provider module which contains the ResourceProvider trait and its in-memory and SQLite implementations
+pub mod provider {
+    pub mod resource_provider;
+    pub mod memory;
+    #[cfg(feature = "sqlite")]
+    pub mod sqlite;
+}
+
+/// Declaring the storage module which contains database layout helpers
+#[cfg(feature = "postgres")]
+pub mod storage {
+    pub mod postgres;
 }
 
 
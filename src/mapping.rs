@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::models::scim_schema::{attribute_type, get_schemas, Schema};
+use crate::models::user::User;
+use crate::utils::error::SCIMError;
+use crate::utils::patch::{key_of, PatchPath};
+
+/// The prefix of the URNs of the core schemas.
+const CORE_URN_PREFIX: &str = "urn:ietf:params:scim:schemas:core:";
+
+/// A declarative mapping from source records (such as rows exported by an HR system) to SCIM resources.
+///
+/// The configuration derives `Deserialize`, so it can be loaded from JSON with `TryFrom<&str>` or from any other
+/// serde format such as TOML.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::mapping::MappingConfig;
+///
+/// let config = MappingConfig::try_from(r#"{
+///     "schemas": ["user", "enterprise_user"],
+///     "mappings": [
+///         {"target": "userName", "source": "work_email", "transforms": [{"type": "lowercase"}]},
+///         {"target": "name.givenName", "source": "first_name"},
+///         {"target": "displayName", "transforms": [{"type": "concat", "fields": ["first_name", "last_name"], "separator": " "}]},
+///         {"target": "emails[type eq \"work\"].value", "source": "work_email"},
+///         {"target": "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:costCenter", "source": "cost_ctr"}
+///     ]
+/// }"#);
+/// assert!(config.is_ok());
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct MappingConfig {
+    /// Names of the schemas the resource uses, as accepted by `get_schemas`. The first is the core schema.
+    pub schemas: Vec<String>,
+    pub mappings: Vec<AttributeMapping>,
+}
+
+/// Maps one source field, or a value computed by transforms, to a SCIM attribute path.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AttributeMapping {
+    /// The SCIM attribute path to set, e.g. `name.givenName`, `emails[type eq "work"].value` or a URN-qualified
+    /// extension attribute.
+    pub target: String,
+    /// The source field the value starts from. Dots select nested fields, e.g. `address.city`.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Transforms applied in order to the value.
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+}
+
+/// A step that computes a new value from the current one and the source record.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transform {
+    /// Lowercases a string value.
+    Lowercase,
+    /// Joins the string values of several source fields, skipping missing ones.
+    Concat {
+        fields: Vec<String>,
+        #[serde(default)]
+        separator: String,
+    },
+    /// Uses `value` when the current value is missing or an empty string.
+    Default { value: Value },
+    /// Replaces a string value with its entry in `table`. Values missing from the table become `default`, or fail
+    /// the record when there is no default.
+    Lookup {
+        table: HashMap<String, Value>,
+        #[serde(default)]
+        default: Option<Value>,
+    },
+    /// Uses `then` when source field `field` equals `equals` (a missing field equals `null`), and `otherwise` (or no
+    /// value) when it does not.
+    Conditional {
+        field: String,
+        equals: Value,
+        then: Value,
+        #[serde(default)]
+        otherwise: Option<Value>,
+    },
+}
+
+/// Converts a JSON string into a `MappingConfig`.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the JSON is not a valid mapping configuration.
+impl TryFrom<&str> for MappingConfig {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        serde_json::from_str(value).map_err(SCIMError::DeserializationError)
+    }
+}
+
+/// A mapping error for one source record.
+#[derive(Debug)]
+pub struct RecordError {
+    /// The position of the record in the input.
+    pub index: usize,
+    pub error: SCIMError,
+}
+
+/// The result of mapping a batch of records: the resources that mapped cleanly and the errors of the others.
+#[derive(Debug)]
+pub struct MappingReport<T> {
+    /// The mapped resources, with the position of their source record.
+    pub resources: Vec<(usize, T)>,
+    pub errors: Vec<RecordError>,
+}
+
+/// Maps source records to SCIM resources according to a validated `MappingConfig`.
+#[derive(Debug)]
+pub struct Mapper {
+    mappings: Vec<(AttributeMapping, PatchPath, String)>,
+    schemas: Vec<Schema>,
+}
+
+impl Mapper {
+    /// Loads the schemas of a configuration and checks every target path against them.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::SchemaNotFound` - If a schema name is unknown.
+    /// * `SCIMError::InvalidFieldValue` - If a target path cannot be parsed or no schema defines it.
+    pub fn new(config: MappingConfig) -> Result<Mapper, SCIMError> {
+        let schemas = get_schemas(config.schemas.iter().map(String::as_str).collect())?;
+        if schemas.is_empty() {
+            return Err(SCIMError::MissingRequiredField("schemas".to_string()));
+        }
+        let mut mappings = Vec::new();
+        let core_prefix = format!("{}:", schemas[0].id);
+        for mapping in config.mappings {
+            // Core-URN-qualified targets are parsed to bare attribute names, so they must name the core schema in use.
+            let target = mapping.target.as_str();
+            if target.get(..CORE_URN_PREFIX.len()).map_or(false, |prefix| prefix.eq_ignore_ascii_case(CORE_URN_PREFIX))
+                && !target.get(..core_prefix.len()).map_or(false, |prefix| prefix.eq_ignore_ascii_case(&core_prefix)) {
+                return Err(SCIMError::InvalidFieldValue(format!("unknown attribute: {}", target)));
+            }
+            let path = PatchPath::parse(&mapping.target)?;
+            let type_ = attribute_type(&schemas, &path)
+                .ok_or_else(|| SCIMError::InvalidFieldValue(format!("unknown attribute: {}", mapping.target)))?;
+            mappings.push((mapping, path, type_));
+        }
+        Ok(Mapper { mappings, schemas })
+    }
+
+    /// Maps one record to the JSON representation of a resource.
+    ///
+    /// Mappings whose value ends up missing are skipped. The result lists the core schema and every extension that
+    /// received a value in `schemas`.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::InvalidFieldValue` - If a transform cannot be applied, or a value does not have the type the
+    ///   schema declares for its target.
+    /// * `SCIMError::MissingRequiredField` - If an attribute the core schema requires has no value.
+    pub fn map_record(&self, record: &Value) -> Result<Value, SCIMError> {
+        let mut resource = Map::new();
+        let mut schemas = vec![Value::String(self.schemas[0].id.clone())];
+        for (mapping, path, type_) in &self.mappings {
+            let mut value = mapping.source.as_deref().and_then(|source| field(record, source)).cloned();
+            for transform in &mapping.transforms {
+                value = apply_transform(transform, value, record)
+                    .map_err(|message| SCIMError::InvalidFieldValue(format!("{}: {}", mapping.target, message)))?;
+            }
+            let value = match value {
+                Some(value) if !value.is_null() => value,
+                _ => continue,
+            };
+            if !has_type(&value, type_) {
+                return Err(SCIMError::InvalidFieldValue(format!("{}: expected {} but got {}", mapping.target, type_, value)));
+            }
+            let container = match &path.urn {
+                Some(urn) => {
+                    if !schemas.iter().any(|schema| schema.as_str() == Some(urn)) {
+                        schemas.push(Value::String(urn.clone()));
+                    }
+                    resource.entry(urn.clone())
+                        .or_insert_with(|| Value::Object(Map::new()))
+                        .as_object_mut()
+                        .expect("extension containers are created as objects")
+                }
+                None => &mut resource,
+            };
+            set(container, path, value);
+        }
+
+        for attribute in &self.schemas[0].attributes {
+            if attribute.required == Some(true) && !resource.keys().any(|key| key.eq_ignore_ascii_case(&attribute.name)) {
+                return Err(SCIMError::MissingRequiredField(attribute.name.clone()));
+            }
+        }
+        resource.insert("schemas".to_string(), Value::Array(schemas));
+        Ok(Value::Object(resource))
+    }
+
+    /// Maps a batch of records to resources of type `T`, collecting the errors of each record instead of stopping.
+    pub fn map_records<T: DeserializeOwned>(&self, records: &[Value]) -> MappingReport<T> {
+        let mut report = MappingReport { resources: Vec::new(), errors: Vec::new() };
+        for (index, record) in records.iter().enumerate() {
+            let mapped = self.map_record(record)
+                .and_then(|value| serde_json::from_value(value).map_err(SCIMError::DeserializationError));
+            match mapped {
+                Ok(resource) => report.resources.push((index, resource)),
+                Err(error) => report.errors.push(RecordError { index, error }),
+            }
+        }
+        report
+    }
+
+    /// Maps a batch of records to `User`s. See `map_records`.
+    pub fn map_users(&self, records: &[Value]) -> MappingReport<User> {
+        self.map_records(records)
+    }
+}
+
+fn has_type(value: &Value, type_: &str) -> bool {
+    match type_ {
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "decimal" => value.is_number(),
+        "complex" => value.is_object() || value.is_array(),
+        _ => value.is_string(),
+    }
+}
+
+/// Reads a possibly nested field of a source record, e.g. `address.city`.
+fn field<'a>(record: &'a Value, name: &str) -> Option<&'a Value> {
+    name.split('.').try_fold(record, |value, key| value.get(key))
+}
+
+fn apply_transform(transform: &Transform, value: Option<Value>, record: &Value) -> Result<Option<Value>, String> {
+    match transform {
+        Transform::Lowercase => match value {
+            Some(Value::String(string)) => Ok(Some(Value::String(string.to_lowercase()))),
+            None => Ok(None),
+            Some(other) => Err(format!("cannot lowercase {}", other)),
+        },
+        Transform::Concat { fields, separator } => {
+            let parts: Vec<String> = fields.iter()
+                .filter_map(|name| field(record, name))
+                .filter_map(|value| match value {
+                    Value::String(string) => Some(string.clone()),
+                    Value::Null => None,
+                    other => Some(other.to_string()),
+                })
+                .filter(|part| !part.is_empty())
+                .collect();
+            Ok(if parts.is_empty() { None } else { Some(Value::String(parts.join(separator))) })
+        }
+        Transform::Default { value: default } => match value {
+            None | Some(Value::Null) => Ok(Some(default.clone())),
+            Some(Value::String(string)) if string.is_empty() => Ok(Some(default.clone())),
+            value => Ok(value),
+        },
+        Transform::Lookup { table, default } => match value {
+            None => Ok(None),
+            Some(value) => {
+                let key = match &value {
+                    Value::String(string) => string.clone(),
+                    other => other.to_string(),
+                };
+                table.get(&key)
+                    .or(default.as_ref())
+                    .cloned()
+                    .map(Some)
+                    .ok_or_else(|| format!("no lookup entry for {}", key))
+            }
+        },
+        Transform::Conditional { field: name, equals, then, otherwise } => {
+            // A missing field compares equal to null.
+            if field(record, name).unwrap_or(&Value::Null) == equals {
+                Ok(Some(then.clone()))
+            } else {
+                Ok(otherwise.clone())
+            }
+        }
+    }
+}
+
+/// Sets a value at a path, creating the multi-valued entry a value filter selects when it does not exist yet.
+fn set(container: &mut Map<String, Value>, path: &PatchPath, value: Value) {
+    let key = key_of(container, &path.attribute);
+    match (&path.filter, &path.sub_attribute) {
+        (Some((filter_key, filter_value)), sub_attribute) => {
+            let values = container.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+            if !values.is_array() {
+                *values = Value::Array(Vec::new());
+            }
+            let values = values.as_array_mut().expect("replaced with an array above");
+            let position = values.iter().position(|entry| entry.get(filter_key) == Some(filter_value));
+            let position = position.unwrap_or_else(|| {
+                let mut entry = Map::new();
+                entry.insert(filter_key.clone(), filter_value.clone());
+                values.push(Value::Object(entry));
+                values.len() - 1
+            });
+            match (sub_attribute, &mut values[position]) {
+                (Some(sub_attribute), Value::Object(entry)) => {
+                    entry.insert(sub_attribute.clone(), value);
+                }
+                (None, Value::Object(entry)) => {
+                    if let Value::Object(value) = value {
+                        entry.extend(value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        (None, Some(sub_attribute)) => {
+            let object = container.entry(key).or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(object) = object {
+                object.insert(sub_attribute.clone(), value);
+            }
+        }
+        (None, None) => {
+            container.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    fn mapper() -> Mapper {
+        Mapper::new(MappingConfig::try_from(r#"{
+            "schemas": ["user", "enterprise_user"],
+            "mappings": [
+                {"target": "userName", "source": "work_email", "transforms": [{"type": "lowercase"}]},
+                {"target": "name.givenName", "source": "first_name"},
+                {"target": "name.familyName", "source": "last_name"},
+                {"target": "displayName", "transforms": [{"type": "concat", "fields": ["first_name", "last_name"], "separator": " "}]},
+                {"target": "emails[type eq \"work\"].value", "source": "work_email"},
+                {"target": "emails[type eq \"work\"].primary", "transforms": [{"type": "conditional", "field": "work_email", "equals": null, "then": false, "otherwise": true}]},
+                {"target": "locale", "source": "country", "transforms": [{"type": "lookup", "table": {"DE": "de-DE", "US": "en-US"}}, {"type": "default", "value": "en-US"}]},
+                {"target": "active", "transforms": [{"type": "conditional", "field": "status", "equals": "terminated", "then": false, "otherwise": true}]},
+                {"target": "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:costCenter", "source": "cost_ctr"}
+            ]
+        }"#).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn map_record_builds_core_and_extension_attributes() {
+        let user = mapper().map_record(&json!({
+            "first_name": "Barbara",
+            "last_name": "Jensen",
+            "work_email": "BJensen@Example.com",
+            "country": "DE",
+            "status": "active",
+            "cost_ctr": "4130"
+        })).unwrap();
+
+        assert_eq!(user, json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User", "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User"],
+            "userName": "bjensen@example.com",
+            "name": {"givenName": "Barbara", "familyName": "Jensen"},
+            "displayName": "Barbara Jensen",
+            "emails": [{"type": "work", "value": "BJensen@Example.com", "primary": true}],
+            "locale": "de-DE",
+            "active": true,
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"costCenter": "4130"}
+        }));
+    }
+
+    #[test]
+    fn map_users_reports_errors_per_record() {
+        let report = mapper().map_users(&[
+            json!({"work_email": "a@example.com", "status": "terminated"}),
+            json!({"first_name": "No email"}),
+            json!({"work_email": "c@example.com", "country": "FR"}),
+            json!({"work_email": 42}),
+        ]);
+
+        assert_eq!(report.resources.len(), 1);
+        let (index, user) = &report.resources[0];
+        assert_eq!(*index, 0);
+        assert_eq!(user.body.active, Some(false));
+        assert_eq!(user.body.locale, Some("en-US".to_string()));
+        assert_eq!(user.body.emails.as_ref().unwrap()[0].primary, Some(true));
+        let errors: Vec<_> = report.errors.iter().map(|error| error.index).collect();
+        assert_eq!(errors, vec![1, 2, 3]);
+        assert!(matches!(report.errors[0].error, SCIMError::MissingRequiredField(_)));
+        assert!(matches!(report.errors[1].error, SCIMError::InvalidFieldValue(_)));
+    }
+
+    #[test]
+    fn mapper_rejects_unknown_targets() {
+        for target in ["nickname.first", "emails[kind eq \"work\"].value", "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:userName", "emails[type co \"w\"]", "urn:ietf:params:scim:schemas:core:2.0:Group:displayName", "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User"] {
+            let config = MappingConfig {
+                schemas: vec!["user".to_string(), "enterprise_user".to_string()],
+                mappings: vec![AttributeMapping { target: target.to_string(), source: None, transforms: vec![] }],
+            };
+            assert!(matches!(Mapper::new(config), Err(SCIMError::InvalidFieldValue(_))), "{}", target);
+        }
+    }
+
+    #[test]
+    fn core_urn_targets_map_to_top_level_attributes() {
+        let config = MappingConfig {
+            schemas: vec!["user".to_string()],
+            mappings: vec![AttributeMapping { target: "urn:ietf:params:scim:schemas:core:2.0:User:userName".to_string(), source: Some("login".to_string()), transforms: vec![] }],
+        };
+
+        let report = Mapper::new(config).unwrap().map_users(&[json!({"login": "bjensen"})]);
+
+        assert!(report.errors.is_empty());
+        assert_eq!(report.resources[0].1.body.user_name, "bjensen");
+    }
+}
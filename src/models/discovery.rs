@@ -0,0 +1,283 @@
+use crate::models::others::ListResponse;
+use crate::models::resource_types::{ResourceType, SchemaExtension};
+use crate::models::scim_schema::{Meta, Schema};
+use crate::models::service_provider_config::ServiceProviderConfig;
+use crate::utils::error::SCIMError;
+
+/// Builds the discovery endpoints (`/ResourceTypes`, `/Schemas` and `/ServiceProviderConfig`) of a service provider.
+///
+/// Register each resource type with its core schema, attach schema extensions with their `required` flag, and
+/// provide the `ServiceProviderConfig`. `build` fills in `meta.location` from the base URL for every resource.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::discovery::DiscoveryBuilder;
+/// use scim_v2::models::scim_schema::get_schemas;
+/// use scim_v2::models::service_provider_config::ServiceProviderConfig;
+///
+/// let mut schemas = get_schemas(vec!["user", "enterprise_user", "group"]).unwrap().into_iter();
+/// let (user, enterprise_user, group) = (schemas.next().unwrap(), schemas.next().unwrap(), schemas.next().unwrap());
+///
+/// let discovery = DiscoveryBuilder::new("https://scim.example.org/v2")
+///     .resource_type("User", "/Users", Some("User Account"), user)
+///     .schema_extension("User", enterprise_user, false)
+///     .resource_type("Group", "/Groups", Some("Group"), group)
+///     .service_provider_config(ServiceProviderConfig::default())
+///     .build();
+///
+/// match discovery {
+///     Ok(discovery) => println!("ResourceTypes: {:?}", discovery.resource_types()),
+///     Err(e) => println!("Invalid discovery configuration: {}", e),
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DiscoveryBuilder {
+    base_url: String,
+    resource_types: Vec<(ResourceType, Schema)>,
+    extensions: Vec<(String, Schema, bool)>,
+    service_provider_config: Option<ServiceProviderConfig>,
+}
+
+impl DiscoveryBuilder {
+    /// Creates a builder for a service provider whose SCIM endpoints live under `base_url`, e.g. `https://example.com/v2`.
+    pub fn new(base_url: &str) -> Self {
+        DiscoveryBuilder {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            resource_types: Vec::new(),
+            extensions: Vec::new(),
+            service_provider_config: None,
+        }
+    }
+
+    /// Registers a resource type with its name, endpoint relative to the base URL, and core schema.
+    pub fn resource_type(mut self, name: &str, endpoint: &str, description: Option<&str>, schema: Schema) -> Self {
+        let resource_type = ResourceType {
+            id: Some(name.to_string()),
+            name: name.to_string(),
+            description: description.map(|d| d.to_string()),
+            endpoint: endpoint.to_string(),
+            schema: schema.id.clone(),
+            ..Default::default()
+        };
+        self.resource_types.push((resource_type, schema));
+        self
+    }
+
+    /// Attaches a schema extension to a previously registered resource type.
+    ///
+    /// When `required` is true, resources of that type must include the extension.
+    pub fn schema_extension(mut self, resource_type: &str, schema: Schema, required: bool) -> Self {
+        self.extensions.push((resource_type.to_string(), schema, required));
+        self
+    }
+
+    /// Sets the `ServiceProviderConfig` served at `/ServiceProviderConfig`.
+    pub fn service_provider_config(mut self, config: ServiceProviderConfig) -> Self {
+        self.service_provider_config = Some(config);
+        self
+    }
+
+    /// Builds the discovery responses.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Discovery)` - If the configuration is complete.
+    /// * `Err(SCIMError::MissingRequiredField)` - If no `ServiceProviderConfig` was provided.
+    /// * `Err(SCIMError::ConflictError)` - If two resource types are registered with the same name.
+    /// * `Err(SCIMError::ResourceTypeNotFound)` - If an extension refers to a resource type that was not registered.
+    pub fn build(self) -> Result<Discovery, SCIMError> {
+        let base_url = self.base_url;
+        let mut service_provider_config = self.service_provider_config
+            .ok_or_else(|| SCIMError::MissingRequiredField("service_provider_config".to_string()))?;
+        let meta = service_provider_config.meta.get_or_insert_with(Meta::default);
+        meta.resource_type = Some("ServiceProviderConfig".to_string());
+        meta.location = Some(format!("{}/ServiceProviderConfig", base_url));
+
+        let mut resource_types = Vec::new();
+        let mut schemas: Vec<Schema> = Vec::new();
+        for (mut resource_type, schema) in self.resource_types {
+            if resource_types.iter().any(|existing: &ResourceType| existing.name.eq_ignore_ascii_case(&resource_type.name)) {
+                return Err(SCIMError::ConflictError(format!("resource type {} is registered more than once", resource_type.name)));
+            }
+            resource_type.meta = Some(Meta {
+                resource_type: Some("ResourceType".to_string()),
+                location: Some(format!("{}/ResourceTypes/{}", base_url, resource_type.name)),
+                ..Default::default()
+            });
+            resource_types.push(resource_type);
+            add_schema(&mut schemas, &base_url, schema);
+        }
+
+        for (resource_type_name, schema, required) in self.extensions {
+            let resource_type = resource_types.iter_mut()
+                .find(|resource_type| resource_type.name == resource_type_name)
+                .ok_or_else(|| SCIMError::ResourceTypeNotFound(resource_type_name.clone()))?;
+            resource_type.schema_extensions.get_or_insert_with(Vec::new).push(SchemaExtension {
+                schema: schema.id.clone(),
+                required,
+            });
+            add_schema(&mut schemas, &base_url, schema);
+        }
+
+        Ok(Discovery {
+            resource_types,
+            schemas,
+            service_provider_config,
+        })
+    }
+}
+
+/// Adds a schema to the list served at `/Schemas`, skipping schemas shared by several resource types.
+fn add_schema(schemas: &mut Vec<Schema>, base_url: &str, mut schema: Schema) {
+    if schemas.iter().any(|existing| existing.id == schema.id) {
+        return;
+    }
+    schema.schemas = vec!["urn:ietf:params:scim:schemas:core:2.0:Schema".to_string()];
+    schema.meta.resource_type = Some("Schema".to_string());
+    schema.meta.location = Some(format!("{}/Schemas/{}", base_url, schema.id));
+    schemas.push(schema);
+}
+
+/// The discovery responses of a service provider, produced by `DiscoveryBuilder`.
+#[derive(Debug)]
+pub struct Discovery {
+    resource_types: Vec<ResourceType>,
+    schemas: Vec<Schema>,
+    service_provider_config: ServiceProviderConfig,
+}
+
+impl Discovery {
+    /// Returns the `ListResponse` served at `/ResourceTypes`.
+    pub fn resource_types(&self) -> ListResponse<ResourceType> {
+        list_response(self.resource_types.clone())
+    }
+
+    /// Returns the resource type served at `/ResourceTypes/{name}`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::ResourceTypeNotFound` if no resource type with that name is registered.
+    pub fn resource_type(&self, name: &str) -> Result<ResourceType, SCIMError> {
+        self.resource_types.iter()
+            .find(|resource_type| resource_type.name == name)
+            .cloned()
+            .ok_or_else(|| SCIMError::ResourceTypeNotFound(name.to_string()))
+    }
+
+    /// Returns the `ListResponse` served at `/Schemas`.
+    pub fn schemas(&self) -> ListResponse<Schema> {
+        list_response(self.schemas.clone())
+    }
+
+    /// Returns the schema served at `/Schemas/{id}`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::SchemaNotFound` if no registered resource type uses a schema with that URN.
+    pub fn schema(&self, id: &str) -> Result<Schema, SCIMError> {
+        self.schemas.iter()
+            .find(|schema| schema.id == id)
+            .cloned()
+            .ok_or_else(|| SCIMError::SchemaNotFound(id.to_string()))
+    }
+
+    /// Returns the configuration served at `/ServiceProviderConfig`.
+    pub fn service_provider_config(&self) -> &ServiceProviderConfig {
+        &self.service_provider_config
+    }
+}
+
+fn list_response<T>(resources: Vec<T>) -> ListResponse<T> {
+    ListResponse {
+        items_per_page: resources.len() as i64,
+        total_results: resources.len() as i64,
+        start_index: 1,
+        resources,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::models::scim_schema::get_schemas;
+
+    use super::*;
+
+    fn discovery() -> Discovery {
+        let mut schemas = get_schemas(vec!["user", "enterprise_user", "group"]).unwrap().into_iter();
+        let (user, enterprise_user, group) = (schemas.next().unwrap(), schemas.next().unwrap(), schemas.next().unwrap());
+        DiscoveryBuilder::new("https://scim.example.org/v2/")
+            .resource_type("User", "/Users", Some("User Account"), user)
+            .schema_extension("User", enterprise_user, false)
+            .resource_type("Group", "/Groups", Some("Group"), group)
+            .service_provider_config(ServiceProviderConfig::default())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn resource_types_use_base_url_and_extension_flags() {
+        let response = serde_json::to_value(discovery().resource_types()).unwrap();
+
+        assert_eq!(response["totalResults"], json!(2));
+        assert_eq!(response["schemas"], json!(["urn:ietf:params:scim:api:messages:2.0:ListResponse"]));
+        let user = &response["Resources"][0];
+        assert_eq!(user["schemas"], json!(["urn:ietf:params:scim:schemas:core:2.0:ResourceType"]));
+        assert_eq!(user["meta"]["location"], json!("https://scim.example.org/v2/ResourceTypes/User"));
+        assert_eq!(user["schemaExtensions"], json!([{
+            "schema": "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User",
+            "required": false
+        }]));
+        let group = &response["Resources"][1];
+        assert_eq!(group["meta"]["location"], json!("https://scim.example.org/v2/ResourceTypes/Group"));
+        assert!(group.get("schemaExtensions").is_none());
+    }
+
+    #[test]
+    fn schemas_include_core_and_extension_schemas() {
+        let discovery = discovery();
+        let response = discovery.schemas();
+
+        assert_eq!(response.total_results, 3);
+        let schema = discovery.schema("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User").unwrap();
+        assert_eq!(schema.meta.location, Some("https://scim.example.org/v2/Schemas/urn:ietf:params:scim:schemas:extension:enterprise:2.0:User".to_string()));
+        assert!(matches!(discovery.schema("urn:example:unknown"), Err(SCIMError::SchemaNotFound(_))));
+    }
+
+    #[test]
+    fn service_provider_config_gets_location() {
+        let discovery = discovery();
+        let meta = discovery.service_provider_config().meta.as_ref().unwrap();
+
+        assert_eq!(meta.location, Some("https://scim.example.org/v2/ServiceProviderConfig".to_string()));
+        assert_eq!(meta.resource_type, Some("ServiceProviderConfig".to_string()));
+    }
+
+    #[test]
+    fn build_fails_for_extension_of_unknown_resource_type() {
+        let enterprise_user = get_schemas(vec!["enterprise_user"]).unwrap().remove(0);
+        let result = DiscoveryBuilder::new("https://scim.example.org/v2")
+            .schema_extension("User", enterprise_user, true)
+            .service_provider_config(ServiceProviderConfig::default())
+            .build();
+
+        assert!(matches!(result, Err(SCIMError::ResourceTypeNotFound(_))));
+    }
+
+    #[test]
+    fn build_fails_for_duplicate_resource_type_names() {
+        let mut schemas = get_schemas(vec!["user", "group"]).unwrap().into_iter();
+        let (user, group) = (schemas.next().unwrap(), schemas.next().unwrap());
+        let result = DiscoveryBuilder::new("https://scim.example.org/v2")
+            .resource_type("User", "/Users", None, user)
+            .resource_type("user", "/Accounts", None, group)
+            .service_provider_config(ServiceProviderConfig::default())
+            .build();
+
+        assert!(matches!(result, Err(SCIMError::ConflictError(_))));
+    }
+}
@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 use crate::utils::error::SCIMError;
+use crate::utils::parse::{from_json, ParseMode};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The schema URN of the Enterprise User extension (RFC 7643 §4.3).
+pub const ENTERPRISE_USER_URN: &str = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[derive(Default)]
 pub struct EnterpriseUser {
     #[serde(rename = "employeeNumber", skip_serializing_if = "Option::is_none")]
@@ -17,6 +22,9 @@ pub struct EnterpriseUser {
     pub department: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub manager: Option<Manager>,
+    // Attributes not defined by the schema, kept when parsing with ParseMode::Lenient.
+    #[serde(flatten)]
+    pub additional_attributes: Map<String, Value>,
 }
 
 
@@ -50,7 +58,7 @@ impl TryFrom<&str> for EnterpriseUser {
     type Error = SCIMError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        serde_json::from_str(value).map_err(SCIMError::DeserializationError)
+        from_json(value, &["enterprise_user"], ParseMode::Lenient)
     }
 }
 
@@ -161,15 +169,30 @@ impl EnterpriseUser {
     /// }
     /// ```
     pub fn deserialize(json: &str) -> Result<Self, SCIMError> {
-        serde_json::from_str(json).map_err(SCIMError::DeserializationError)
+        from_json(json, &["enterprise_user"], ParseMode::Lenient)
+    }
+
+    /// Deserializes a JSON string into a `EnterpriseUser` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the Enterprise User schema do not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `EnterpriseUser`.
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["enterprise_user"], mode)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default)]
 pub struct Manager {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub value: Option<String>,
-    #[serde(rename = "$ref")]
+    #[serde(rename = "$ref", skip_serializing_if = "Option::is_none")]
     pub ref_: Option<String>,
-    #[serde(rename = "displayName")]
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
     pub display_name: Option<String>,
 }
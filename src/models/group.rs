@@ -1,34 +1,75 @@
 //Schema for group
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
+use crate::models::resource_types::{extension_urns, ResourceType};
 use crate::models::scim_schema::Meta;
 use crate::utils::error::SCIMError;
+use crate::utils::parse::{from_json, without_server_attributes, ParseMode};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A Group resource, as returned by a service provider.
+///
+/// The client-supplied attributes are kept in `body`, which is serialized inline; `id` and `meta` are assigned by the
+/// service provider.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Group {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub body: GroupRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+/// The body of a request that creates or replaces a group.
+///
+/// Server-assigned attributes (`id` and `meta`) are not part of a request; if a client sends them they are ignored.
+/// Use `into_stored` to turn a create request into a `StoredGroup`, and `StoredGroup::replace` to apply a replace request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupRequest {
     pub schemas: Vec<String>,
-    pub id: String,
+    #[serde(rename = "externalId", skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
     #[serde(rename = "displayName")]
     pub display_name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub members: Option<Vec<Member>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<Meta>,
+    // Attributes not defined by the schema, kept when parsing with ParseMode::Lenient. Server-assigned attributes are dropped.
+    #[serde(flatten, deserialize_with = "without_server_attributes")]
+    pub additional_attributes: Map<String, Value>,
 }
 
-impl Default for Group {
+/// The body of a `POST /Groups` request.
+///
+/// An alias of `GroupRequest`, as is `ReplaceGroupRequest`: both requests carry the same attributes, and the aliases
+/// only name the request a value is used for.
+pub type CreateGroupRequest = GroupRequest;
+
+/// The body of a `PUT /Groups/{id}` request. An alias of `GroupRequest`, like `CreateGroupRequest`.
+pub type ReplaceGroupRequest = GroupRequest;
+
+impl Default for GroupRequest {
     fn default() -> Self {
-        Group {
+        GroupRequest {
             schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:Group".to_string()],
-            id: "default_id".to_string(),
-            display_name: "default_display_name".to_string(),
+            external_id: None,
+            display_name: "".to_string(),
             members: None,
-            meta: None,
+            additional_attributes: Map::new(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A group as held by a service provider, with its server-assigned `id` and `meta` always present.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredGroup {
+    pub id: String,
+    #[serde(flatten)]
+    pub group: GroupRequest,
+    pub meta: Meta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[derive(Default)]
 pub struct Member {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -67,7 +108,7 @@ impl TryFrom<&str> for Group {
     type Error = SCIMError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        serde_json::from_str(value).map_err(SCIMError::DeserializationError)
+        from_json(value, &["group"], ParseMode::Lenient)
     }
 }
 
@@ -88,13 +129,15 @@ impl Group {
     /// # Example
     ///
     /// ```
-    /// use scim_v2::models::group::Group;
+    /// use scim_v2::models::group::{Group, GroupRequest};
     ///
     /// let group = Group {
-    ///     schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:Group".to_string()],
-    ///     id: "e9e30dba-f08f-4109-8486-d5c6a331660a".to_string(),
-    ///     display_name: "Tour Guides".to_string(),
-    ///     // other fields...
+    ///     id: Some("e9e30dba-f08f-4109-8486-d5c6a331660a".to_string()),
+    ///     body: GroupRequest {
+    ///         display_name: "Tour Guides".to_string(),
+    ///         // other fields...
+    ///         ..Default::default()
+    ///     },
     ///     ..Default::default()
     /// };
     ///
@@ -104,16 +147,13 @@ impl Group {
     /// }
     /// ```
     pub fn validate(&self) -> Result<(), SCIMError> {
-        if self.schemas.is_empty() {
+        if self.body.schemas.is_empty() {
             return Err(SCIMError::MissingRequiredField("schemas".to_string()));
         }
-        if self.id.is_empty() {
+        if self.id.as_deref().map_or(true, str::is_empty) {
             return Err(SCIMError::MissingRequiredField("id".to_string()));
         }
-        if self.display_name.is_empty() {
-            return Err(SCIMError::MissingRequiredField("display_name".to_string()));
-        }
-        Ok(())
+        self.body.validate()
     }
 
     /// Serializes the `Group` instance to a JSON string, using the custom SCIMError for error handling.
@@ -127,13 +167,15 @@ impl Group {
     /// # Examples
     ///
     /// ```
-    /// use scim_v2::models::group::Group;
+    /// use scim_v2::models::group::{Group, GroupRequest};
     ///
     /// let group = Group {
-    ///     schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:Group".to_string()],
-    ///     id: "e9e30dba-f08f-4109-8486-d5c6a331660a".to_string(),
-    ///     display_name: "Tour Guides".to_string(),
-    ///     // other fields...
+    ///     id: Some("e9e30dba-f08f-4109-8486-d5c6a331660a".to_string()),
+    ///     body: GroupRequest {
+    ///         display_name: "Tour Guides".to_string(),
+    ///         // other fields...
+    ///         ..Default::default()
+    ///     },
     ///     ..Default::default()
     /// };
     ///
@@ -169,7 +211,206 @@ impl Group {
     /// }
     /// ```
     pub fn deserialize(json: &str) -> Result<Self, SCIMError> {
-        serde_json::from_str(json).map_err(SCIMError::DeserializationError)
+        from_json(json, &["group"], ParseMode::Lenient)
+    }
+
+    /// Checks `schemas` against the resource type definition and the extensions kept in `additional_attributes`.
+    ///
+    /// See `ResourceType::validate_schemas` for the errors returned.
+    pub fn validate_schemas(&self, resource_type: &ResourceType) -> Result<(), SCIMError> {
+        self.body.validate_schemas(resource_type)
+    }
+
+    /// Rewrites `schemas` to the core schema of `resource_type` followed by the registered extensions the group
+    /// carries.
+    pub fn reconcile_schemas(&mut self, resource_type: &ResourceType) {
+        self.body.reconcile_schemas(resource_type);
+    }
+
+    /// Deserializes a JSON string into a `Group` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the Group schema do not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `Group`.
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["group"], mode)
+    }
+}
+
+/// Converts a JSON string into a `GroupRequest`, as received in the body of a create or replace request.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the provided JSON string cannot be parsed into a `GroupRequest`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::group::CreateGroupRequest;
+///
+/// let request_json = r#"{"schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"], "displayName": "Tour Guides"}"#;
+/// match CreateGroupRequest::try_from(request_json) {
+///     Ok(request) => println!("Create group: {:?}", request),
+///     Err(e) => println!("Error converting from JSON to GroupRequest: {}", e),
+/// }
+/// ```
+impl TryFrom<&str> for GroupRequest {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        from_json(value, &["group"], ParseMode::Lenient)
+    }
+}
+
+impl GroupRequest {
+    /// Validates a group request.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the request has `schemas` and a `display_name`.
+    /// * `Err(SCIMError::MissingRequiredField)` - If a required field is missing.
+    pub fn validate(&self) -> Result<(), SCIMError> {
+        if self.schemas.is_empty() {
+            return Err(SCIMError::MissingRequiredField("schemas".to_string()));
+        }
+        if self.display_name.is_empty() {
+            return Err(SCIMError::MissingRequiredField("display_name".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Checks `schemas` against the resource type definition and the extensions kept in `additional_attributes`.
+    ///
+    /// See `ResourceType::validate_schemas` for the errors returned.
+    pub fn validate_schemas(&self, resource_type: &ResourceType) -> Result<(), SCIMError> {
+        resource_type.validate_schemas(&self.schemas, &extension_urns(&self.additional_attributes).collect::<Vec<_>>())
+    }
+
+    /// Rewrites `schemas` to the core schema of `resource_type` followed by the registered extensions the request
+    /// carries.
+    pub fn reconcile_schemas(&mut self, resource_type: &ResourceType) {
+        self.schemas = resource_type.reconcile_schemas(&extension_urns(&self.additional_attributes).collect::<Vec<_>>());
+    }
+
+    /// Deserializes a JSON string into a `GroupRequest` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the Group schema do not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `GroupRequest`.
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["group"], mode)
+    }
+
+    /// Turns a create request into a stored group with the server-assigned `id` and `meta`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::models::group::CreateGroupRequest;
+    /// use scim_v2::models::scim_schema::Meta;
+    ///
+    /// let request = CreateGroupRequest {
+    ///     display_name: "Tour Guides".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let stored = request.into_stored("e9e30dba-f08f-4109-8486-d5c6a331660a".to_string(), Meta {
+    ///     resource_type: Some("Group".to_string()),
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(stored.group.display_name, "Tour Guides");
+    /// ```
+    pub fn into_stored(self, id: String, meta: Meta) -> StoredGroup {
+        StoredGroup {
+            id,
+            group: self,
+            meta,
+        }
+    }
+}
+
+/// Drops the server-assigned attributes of a `Group`.
+impl From<Group> for GroupRequest {
+    fn from(group: Group) -> Self {
+        group.body
+    }
+}
+
+impl StoredGroup {
+    /// Replaces every client-supplied attribute with the ones in `request`, keeping `id` and `meta`.
+    ///
+    /// Updating `meta.lastModified` and `meta.version` is left to the caller.
+    pub fn replace(self, request: ReplaceGroupRequest) -> StoredGroup {
+        StoredGroup {
+            group: request,
+            ..self
+        }
+    }
+
+    /// Validates a stored group.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the group is valid.
+    /// * `Err(SCIMError::MissingRequiredField)` - If `id` or a required attribute of the group is missing.
+    pub fn validate(&self) -> Result<(), SCIMError> {
+        if self.id.is_empty() {
+            return Err(SCIMError::MissingRequiredField("id".to_string()));
+        }
+        self.group.validate()
+    }
+}
+
+impl From<StoredGroup> for Group {
+    fn from(stored: StoredGroup) -> Self {
+        Group {
+            id: Some(stored.id),
+            body: stored.group,
+            meta: Some(stored.meta),
+        }
+    }
+}
+
+/// Converts a `Group` into a `StoredGroup`, checking that the server-assigned attributes are present.
+///
+/// # Errors
+///
+/// Returns `SCIMError::MissingRequiredField` if `id` or `meta` is missing.
+impl TryFrom<Group> for StoredGroup {
+    type Error = SCIMError;
+
+    fn try_from(group: Group) -> Result<Self, Self::Error> {
+        let id = group.id
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| SCIMError::MissingRequiredField("id".to_string()))?;
+        let meta = group.meta.ok_or_else(|| SCIMError::MissingRequiredField("meta".to_string()))?;
+        Ok(StoredGroup {
+            id,
+            group: group.body,
+            meta,
+        })
+    }
+}
+
+/// Converts the JSON representation of a stored group, such as a row read back from storage, into a `StoredGroup`.
+///
+/// Like `Group`, attribute names are matched case-insensitively when the `case-insensitive` feature is enabled.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the JSON is not a group with an `id` and `meta`.
+impl TryFrom<&str> for StoredGroup {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        from_json(value, &["group"], ParseMode::Lenient)
     }
 }
 
@@ -216,16 +457,16 @@ mod tests {
         }
         assert!(group.is_ok());
         let group = group.unwrap();
-        assert_eq!(group.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:Group"]);
-        assert_eq!(group.id, "e9e30dba-f08f-4109-8486-d5c6a331660a");
-        assert_eq!(group.display_name, "Tour Guides");
+        assert_eq!(group.body.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:Group"]);
+        assert_eq!(group.id.as_deref(), Some("e9e30dba-f08f-4109-8486-d5c6a331660a"));
+        assert_eq!(group.body.display_name, "Tour Guides");
 
         // Check members
-        assert_eq!(group.members.as_ref().unwrap().len(), 2);
-        assert_eq!(group.members.as_ref().unwrap()[0].value, Some("2819c223-7f76-453a-919d-413861904646".to_string()));
-        assert_eq!(group.members.as_ref().unwrap()[0].display, Some("Babs Jensen".to_string()));
-        assert_eq!(group.members.as_ref().unwrap()[1].value, Some("902c246b-6245-4190-8e05-00816be7344a".to_string()));
-        assert_eq!(group.members.as_ref().unwrap()[1].display, Some("Mandy Pepperidge".to_string()));
+        assert_eq!(group.body.members.as_ref().unwrap().len(), 2);
+        assert_eq!(group.body.members.as_ref().unwrap()[0].value, Some("2819c223-7f76-453a-919d-413861904646".to_string()));
+        assert_eq!(group.body.members.as_ref().unwrap()[0].display, Some("Babs Jensen".to_string()));
+        assert_eq!(group.body.members.as_ref().unwrap()[1].value, Some("902c246b-6245-4190-8e05-00816be7344a".to_string()));
+        assert_eq!(group.body.members.as_ref().unwrap()[1].display, Some("Mandy Pepperidge".to_string()));
 
         // Check meta
         let meta = group.meta.unwrap();
@@ -258,9 +499,9 @@ mod tests {
         }
         assert!(group.is_ok());
         let group = group.unwrap();
-        assert_eq!(group.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:Group"]);
-        assert_eq!(group.id, "e9e30dba-f08f-4109-8486-d5c6a331660a");
-        assert_eq!(group.display_name, "Tour Guides");
+        assert_eq!(group.body.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:Group"]);
+        assert_eq!(group.id.as_deref(), Some("e9e30dba-f08f-4109-8486-d5c6a331660a"));
+        assert_eq!(group.body.display_name, "Tour Guides");
     }
 
     #[test]
@@ -295,10 +536,88 @@ mod tests {
 
         assert!(group.is_ok());
         let group = group.unwrap();
-        assert_eq!(group.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:Group"]);
-        assert_eq!(group.id, "e9e30dba-f08f-4109-8486-d5c6a331660a");
-        assert_eq!(group.display_name, "Tour Guides");
-        assert!(group.members.is_none());
+        assert_eq!(group.body.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:Group"]);
+        assert_eq!(group.id.as_deref(), Some("e9e30dba-f08f-4109-8486-d5c6a331660a"));
+        assert_eq!(group.body.display_name, "Tour Guides");
+        assert!(group.body.members.is_none());
         assert!(group.meta.is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn group_request_deserializes_without_id_and_ignores_server_fields() {
+        let request = CreateGroupRequest::try_from(r#"{
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+            "id": "client-chosen-id",
+            "displayName": "Tour Guides",
+            "members": [{"value": "2819c223-7f76-453a-919d-413861904646"}]
+        }"#).unwrap();
+
+        assert_eq!(request.display_name, "Tour Guides");
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("id").is_none());
+    }
+
+    #[test]
+    fn stored_group_keeps_id_and_meta_on_replace() {
+        let stored = CreateGroupRequest {
+            display_name: "Tour Guides".to_string(),
+            ..Default::default()
+        }.into_stored("e9e30dba-f08f-4109-8486-d5c6a331660a".to_string(), Meta {
+            resource_type: Some("Group".to_string()),
+            ..Default::default()
+        });
+
+        let replaced = stored.replace(ReplaceGroupRequest {
+            display_name: "Guides".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(replaced.id, "e9e30dba-f08f-4109-8486-d5c6a331660a");
+        assert_eq!(replaced.group.display_name, "Guides");
+        assert_eq!(replaced.meta.resource_type, Some("Group".to_string()));
+
+        let json = serde_json::to_value(&replaced).unwrap();
+        assert_eq!(json["id"], "e9e30dba-f08f-4109-8486-d5c6a331660a");
+        assert_eq!(json["displayName"], "Guides");
+        assert_eq!(json["meta"]["resourceType"], "Group");
+
+        let group = Group::from(replaced);
+        assert!(group.meta.is_some());
+    }
+
+    #[test]
+    fn stored_group_requires_id_and_meta() {
+        assert!(StoredGroup::try_from(Group::default()).is_err());
+        let missing_meta = Group {
+            id: Some("e9e30dba-f08f-4109-8486-d5c6a331660a".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(StoredGroup::try_from(missing_meta), Err(SCIMError::MissingRequiredField(field)) if field == "meta"));
+        let stored: Result<StoredGroup, _> = serde_json::from_str(r#"{
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+            "displayName": "Tour Guides",
+            "meta": {"resourceType": "Group"}
+        }"#);
+        assert!(stored.is_err());
+    }
+
+    #[test]
+    fn group_request_parse_modes_handle_unknown_attributes() {
+        let json = r#"{
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+            "id": "e9e30dba-f08f-4109-8486-d5c6a331660a",
+            "displayName": "Tour Guides",
+            "members": [{"value": "2819c223-7f76-453a-919d-413861904646", "dispaly": "Babs Jensen"}],
+            "owner": "2819c223-7f76-453a-919d-413861904646"
+        }"#;
+
+        let request = GroupRequest::deserialize_with_mode(json, ParseMode::Lenient).unwrap();
+        assert_eq!(request.additional_attributes.keys().collect::<Vec<_>>(), vec!["owner"]);
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["owner"],
+            "2819c223-7f76-453a-919d-413861904646"
+        );
+
+        let error = GroupRequest::deserialize_with_mode(json, ParseMode::Strict).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid syntax: unknown attribute: members.dispaly");
+    }
+}
@@ -1,24 +1,39 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
 
-use serde::{Deserialize, Serialize};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
 use crate::models::group::Group;
 use crate::models::resource_types::ResourceType;
 use crate::models::scim_schema::Schema;
+use crate::models::service_provider_config::ServiceProviderConfig;
 use crate::models::user::User;
+use crate::utils::case::canonicalize_names;
+use crate::utils::error::SCIMError;
+use crate::utils::filter::{AttributePath, Filter};
+use crate::utils::parse::{canonicalize_declared, message_from_json};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SearchRequest {
     pub schemas: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attributes: Option<Vec<String>>,
     #[serde(rename = "excludedAttributes", skip_serializing_if = "Option::is_none")]
-    excluded_attributes: Option<Vec<String>>,
-    pub filter: String,
-    #[serde(rename = "startIndex")]
-    pub start_index: i64,
-    pub count: i64,
+    pub excluded_attributes: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(rename = "sortBy", skip_serializing_if = "Option::is_none")]
+    pub sort_by: Option<String>,
+    #[serde(rename = "sortOrder", skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<String>,
+    #[serde(rename = "startIndex", skip_serializing_if = "Option::is_none")]
+    pub start_index: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
 }
 
 impl Default for SearchRequest {
@@ -27,40 +42,512 @@ impl Default for SearchRequest {
             schemas: vec!["urn:ietf:params:scim:api:messages:2.0:SearchRequest".to_string()],
             attributes: None,
             excluded_attributes: None,
-            filter: "".to_string(),
-            start_index: 1,
-            count: 100,
+            filter: None,
+            sort_by: None,
+            sort_order: None,
+            start_index: Some(1),
+            count: Some(100),
+            cursor: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The attributes of a `SearchRequest` (RFC 7644 section 3.4.3).
+const SEARCH_REQUEST_ATTRIBUTES: [&str; 9] = ["schemas", "attributes", "excludedAttributes", "filter", "sortBy", "sortOrder", "startIndex", "count", "cursor"];
+
+/// Converts the JSON body of a `POST /.search` request into a `SearchRequest`.
+///
+/// With the `case-insensitive` feature, attribute names are matched case-insensitively.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the JSON is not a valid search request.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::SearchRequest;
+///
+/// let request = SearchRequest::try_from(r#"{"schemas": ["urn:ietf:params:scim:api:messages:2.0:SearchRequest"], "filter": "userName eq \"bjensen\""}"#).unwrap();
+/// assert_eq!(request.filter, Some(r#"userName eq "bjensen""#.to_string()));
+/// ```
+impl TryFrom<&str> for SearchRequest {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        message_from_json(value, |value| {
+            canonicalize_names(value, &SEARCH_REQUEST_ATTRIBUTES);
+            Ok(())
+        })
+    }
+}
+
+/// Converts the query parameters of a `GET` list request into the body of an equivalent `POST /.search` request.
+impl From<ListQuery> for SearchRequest {
+    fn from(query: ListQuery) -> Self {
+        SearchRequest {
+            attributes: query.attributes,
+            excluded_attributes: query.excluded_attributes,
+            filter: query.filter,
+            sort_by: query.sort_by,
+            sort_order: query.sort_order,
+            start_index: query.start_index,
+            count: query.count,
+            cursor: query.cursor,
+            ..Default::default()
+        }
+    }
+}
+
+/// Converts the body of a `POST /.search` request into the query parameters of an equivalent `GET` list request.
+impl From<SearchRequest> for ListQuery {
+    fn from(request: SearchRequest) -> Self {
+        ListQuery {
+            filter: request.filter,
+            sort_by: request.sort_by,
+            sort_order: request.sort_order,
+            start_index: request.start_index,
+            count: request.count,
+            attributes: request.attributes,
+            excluded_attributes: request.excluded_attributes,
+            cursor: request.cursor,
+        }
+    }
+}
+
+/// Attributes common to every resource (RFC 7643 section 3.1), which schemas do not list.
+const COMMON_ATTRIBUTES: [&str; 4] = ["id", "externalId", "meta", "schemas"];
+
+impl SearchRequest {
+    /// Checks that every attribute the request refers to exists in at least one of the searched schemas.
+    ///
+    /// The attribute paths in `filter`, `sortBy`, `attributes` and `excludedAttributes` are checked, case-insensitively
+    /// and with support for URN-qualified paths such as `urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:employeeNumber`.
+    /// A root-level `/.search` should pass the schemas of every resource type it searches, so an attribute only needs
+    /// to exist in one of them.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::InvalidSyntax` - If `filter` cannot be parsed.
+    /// * `SCIMError::InvalidFieldValue` - Naming the first attribute that no schema defines.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::others::SearchRequest;
+    /// use scim_v2::models::scim_schema::get_schemas;
+    ///
+    /// let schemas = get_schemas(vec!["user", "group"]).unwrap();
+    /// let request = SearchRequest {
+    ///     filter: Some("displayName sw \"Tour\" or emails[type eq \"work\"]".to_string()),
+    ///     sort_by: Some("meta.lastModified".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// assert!(request.validate_attributes(&schemas).is_ok());
+    /// ```
+    pub fn validate_attributes(&self, schemas: &[Schema]) -> Result<(), SCIMError> {
+        let filter_paths = self.filter.as_deref().map(filter_attribute_paths).transpose()?.unwrap_or_default();
+        let paths = filter_paths.iter()
+            .chain(self.sort_by.iter())
+            .chain(self.attributes.iter().flatten())
+            .chain(self.excluded_attributes.iter().flatten());
+        for path in paths {
+            if !schemas_define(schemas, path) {
+                return Err(SCIMError::InvalidFieldValue(format!("unknown attribute: {}", path)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Combines the matching resources of one or more resource types into a single page of results.
+    ///
+    /// Use it for root-level `/.search`, passing the resources each endpoint matched for the request's filter. The
+    /// resources are sorted together by `sortBy` (resources without a value come last) and then paged with
+    /// `startIndex` and `count`, so ordering and pagination are consistent across resource types. It pages the results
+    /// of a single endpoint the same way, such as a `Vec<User>` into a `ListResponse<User>`.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::InvalidFieldValue` - If `sortOrder` is neither `ascending` nor `descending`, in any case.
+    /// * `SCIMError::SerializationError` - If a resource cannot be serialized to read its `sortBy` value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::group::{Group, GroupRequest};
+    /// use scim_v2::models::others::{Resource, SearchRequest};
+    /// use scim_v2::models::user::{User, UserRequest};
+    ///
+    /// let request = SearchRequest {
+    ///     sort_by: Some("displayName".to_string()),
+    ///     count: Some(1),
+    ///     ..Default::default()
+    /// };
+    /// let user = User { body: UserRequest { user_name: "bjensen".to_string(), display_name: Some("Babs Jensen".to_string()), ..Default::default() }, ..Default::default() };
+    /// let group = Group { body: GroupRequest { display_name: "Admins".to_string(), ..Default::default() }, ..Default::default() };
+    ///
+    /// let response = request.merge_results(vec![Resource::User(Box::new(user)), Resource::Group(Box::new(group))]).unwrap();
+    /// assert_eq!(response.total_results, 2);
+    /// assert!(matches!(response.resources[0], Resource::Group(_)));
+    /// ```
+    pub fn merge_results<T: Serialize>(&self, resources: Vec<T>) -> Result<ListResponse<T>, SCIMError> {
+        let descending = match self.sort_order.as_deref() {
+            None => false,
+            Some(order) if order.eq_ignore_ascii_case("ascending") => false,
+            Some(order) if order.eq_ignore_ascii_case("descending") => true,
+            Some(order) => return Err(SCIMError::InvalidFieldValue(format!("unknown sortOrder: {}", order))),
+        };
+        let mut resources = match &self.sort_by {
+            Some(sort_by) => {
+                let mut keyed = resources.into_iter()
+                    .map(|resource| {
+                        let value = serde_json::to_value(&resource).map_err(SCIMError::SerializationError)?;
+                        Ok((sort_value(&value, sort_by), resource))
+                    })
+                    .collect::<Result<Vec<_>, SCIMError>>()?;
+                keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+                    (Some(a), Some(b)) if descending => compare_values(b, a),
+                    (Some(a), Some(b)) => compare_values(a, b),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                });
+                keyed.into_iter().map(|(_, resource)| resource).collect()
+            }
+            None => resources,
+        };
+
+        let total_results = resources.len();
+        let start_index = self.start_index.unwrap_or(1).max(1);
+        let skip = usize::try_from(start_index - 1).unwrap_or(usize::MAX).min(total_results);
+        let mut page = resources.split_off(skip);
+        if let Some(count) = self.count {
+            page.truncate(usize::try_from(count.max(0)).unwrap_or(usize::MAX));
+        }
+        Ok(ListResponse {
+            items_per_page: page.len() as i64,
+            total_results: total_results as i64,
+            start_index,
+            resources: page,
+            ..Default::default()
+        })
+    }
+}
+
+/// Extracts the attribute paths a filter compares, such as `userName` or `emails.type` for `emails[type eq "work"]`.
+fn filter_attribute_paths(filter: &str) -> Result<Vec<String>, SCIMError> {
+    let mut paths = Vec::new();
+    collect_attribute_paths(&Filter::parse(filter)?, None, &mut paths);
+    Ok(paths)
+}
+
+/// Walks a parsed filter, naming the attributes inside a value path after the attribute that encloses them.
+fn collect_attribute_paths(filter: &Filter, parent: Option<&str>, paths: &mut Vec<String>) {
+    let name = |path: &AttributePath| match parent {
+        Some(parent) => format!("{}.{}", parent, path),
+        None => path.to_string(),
+    };
+    match filter {
+        Filter::Compare(path, _, _) | Filter::Present(path) => paths.push(name(path)),
+        Filter::ValuePath(path, inner) => {
+            let path = name(path);
+            paths.push(path.clone());
+            collect_attribute_paths(inner, Some(&path), paths);
+        }
+        Filter::And(left, right) | Filter::Or(left, right) => {
+            collect_attribute_paths(left, parent, paths);
+            collect_attribute_paths(right, parent, paths);
+        }
+        Filter::Not(inner) => collect_attribute_paths(inner, parent, paths),
+    }
+}
+
+/// Checks whether an attribute path is a common attribute or is defined by one of the schemas.
+fn schemas_define(schemas: &[Schema], path: &str) -> bool {
+    let lowercase = path.to_lowercase();
+    let (schemas, path): (Vec<&Schema>, &str) = match schemas.iter().find(|schema| lowercase.starts_with(&format!("{}:", schema.id.to_lowercase()))) {
+        Some(schema) => (vec![schema], &path[schema.id.len() + 1..]),
+        None => (schemas.iter().collect(), path),
+    };
+    let (name, sub_name) = match path.split_once('.') {
+        Some((name, sub_name)) => (name, Some(sub_name)),
+        None => (path, None),
+    };
+    if COMMON_ATTRIBUTES.iter().any(|common| common.eq_ignore_ascii_case(name)) {
+        return true;
+    }
+    schemas.iter()
+        .flat_map(|schema| schema.attributes.iter())
+        .filter(|attribute| attribute.name.eq_ignore_ascii_case(name))
+        .any(|attribute| match sub_name {
+            None => true,
+            Some(sub_name) => attribute.sub_attributes.iter().flatten().any(|sub_attribute| sub_attribute.name.eq_ignore_ascii_case(sub_name)),
+        })
+}
+
+/// Reads the value of an attribute path from a resource, using the primary (or first) value of multi-valued attributes.
+fn sort_value(resource: &Value, path: &str) -> Option<Value> {
+    let lowercase = path.to_lowercase();
+    let (mut value, path) = match resource.as_object()?.iter().find(|(key, _)| key.contains(':') && lowercase.starts_with(&format!("{}:", key.to_lowercase()))) {
+        Some((key, extension)) => (extension, &path[key.len() + 1..]),
+        None => (resource, path),
+    };
+    for name in path.split('.') {
+        value = single_value(value)?.as_object()?
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)?;
+    }
+    let value = single_value(value)?;
+    match value {
+        Value::Object(object) => object.get("value").cloned(),
+        Value::Null => None,
+        value => Some(value.clone()),
+    }
+}
+
+fn single_value(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Array(values) => values.iter()
+            .find(|value| value.get("primary") == Some(&Value::Bool(true)))
+            .or_else(|| values.first()),
+        value => Some(value),
+    }
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+        (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()).unwrap_or(Ordering::Equal),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (a, b) => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+/// The query parameters of a SCIM list request (RFC 7644 section 3.4.2).
+///
+/// `attributes` and `excludedAttributes` are comma-separated lists on the wire and are split into their names here.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ListQuery {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub filter: Option<String>,
+    #[serde(rename = "sortBy", skip_serializing_if = "Option::is_none")]
+    pub sort_by: Option<String>,
+    #[serde(rename = "sortOrder", skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<String>,
     #[serde(rename = "startIndex", skip_serializing_if = "Option::is_none")]
     pub start_index: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub count: Option<i64>,
+    #[serde(default, with = "comma_separated", skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<Vec<String>>,
+    #[serde(rename = "excludedAttributes", default, with = "comma_separated", skip_serializing_if = "Option::is_none")]
+    pub excluded_attributes: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub attributes: Option<String>,
-    #[serde(rename = "excludedAttributes", skip_serializing_if = "Option::is_none")]
-    pub excluded_attributes: Option<String>,
+    pub cursor: Option<String>,
 }
 
 impl Default for ListQuery {
     fn default() -> Self {
         ListQuery {
-            filter: Some("".to_string()),
+            filter: None,
+            sort_by: None,
+            sort_order: None,
             start_index: Some(1),
             count: Some(100),
-            attributes: Some("".to_string()),
-            excluded_attributes: Some("".to_string()),
+            attributes: None,
+            excluded_attributes: None,
+            cursor: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// (De)serializes an attribute list as the comma-separated string used in query parameters.
+mod comma_separated {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<Vec<String>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(names) => serializer.serialize_str(&names.join(",")),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+        let value: Option<String> = Option::deserialize(deserializer)?;
+        Ok(value.map(|names| super::split_attribute_list(&names)).filter(|names| !names.is_empty()))
+    }
+}
+
+fn split_attribute_list(names: &str) -> Vec<String> {
+    names.split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// The characters left unencoded in query parameter values: the RFC 3986 unreserved set, plus the comma that
+/// separates attribute names.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~').remove(b',');
+
+/// The characters escaped when a resource id is used as a path segment, such as in `/Users/{id}`.
+pub(crate) const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+impl ListQuery {
+    /// Parses the query string of a list request, such as `filter=userName%20eq%20%22bjensen%22&count=10`.
+    ///
+    /// Values are percent-decoded (with `+` meaning a space) and attribute lists are split on commas. Following
+    /// RFC 7644 section 3.4.2.4, a `startIndex` below 1 is read as 1 and a negative `count` as 0. `count` is also
+    /// clamped to `max_count`. Unknown parameters are ignored and a leading `?` is allowed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidFieldValue` if a value is not valid UTF-8 after decoding, if `startIndex` or `count`
+    /// is not an integer, or if `sortOrder` is neither `ascending` nor `descending`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::others::ListQuery;
+    ///
+    /// let query = ListQuery::from_query_string("filter=userName+eq+%22bjensen%22&attributes=userName,emails&count=500", 200).unwrap();
+    /// assert_eq!(query.filter, Some("userName eq \"bjensen\"".to_string()));
+    /// assert_eq!(query.attributes, Some(vec!["userName".to_string(), "emails".to_string()]));
+    /// assert_eq!(query.count, Some(200));
+    /// ```
+    pub fn from_query_string(query: &str, max_count: i64) -> Result<ListQuery, SCIMError> {
+        let mut list_query = ListQuery {
+            start_index: None,
+            count: None,
+            ..Default::default()
+        };
+        for pair in query.trim_start_matches('?').split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let name = decode_query_component(name)?;
+            let value = decode_query_component(value)?;
+            match name.to_ascii_lowercase().as_str() {
+                "filter" => list_query.filter = Some(value),
+                "sortby" => list_query.sort_by = Some(value),
+                "sortorder" => {
+                    if !value.eq_ignore_ascii_case("ascending") && !value.eq_ignore_ascii_case("descending") {
+                        return Err(SCIMError::InvalidFieldValue(format!("sortOrder: {}", value)));
+                    }
+                    list_query.sort_order = Some(value.to_ascii_lowercase());
+                }
+                "startindex" => list_query.start_index = Some(parse_integer("startIndex", &value)?.max(1)),
+                "count" => list_query.count = Some(parse_integer("count", &value)?.max(0)),
+                "attributes" => list_query.attributes = Some(split_attribute_list(&value)).filter(|names| !names.is_empty()),
+                "excludedattributes" => list_query.excluded_attributes = Some(split_attribute_list(&value)).filter(|names| !names.is_empty()),
+                "cursor" => list_query.cursor = Some(value),
+                _ => {}
+            }
+        }
+        list_query.count = list_query.count.map(|count| count.min(max_count.max(0)));
+        Ok(list_query)
+    }
+
+    /// Renders the query as a percent-encoded query string, without a leading `?`.
+    ///
+    /// Parameters that are `None` are left out and attribute lists are joined with commas.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::others::ListQuery;
+    ///
+    /// let query = ListQuery {
+    ///     filter: Some("userName eq \"bjensen\"".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(query.to_query_string(), "filter=userName%20eq%20%22bjensen%22&startIndex=1&count=100");
+    /// ```
+    pub fn to_query_string(&self) -> String {
+        let start_index = self.start_index.map(|value| value.to_string());
+        let count = self.count.map(|value| value.to_string());
+        let attributes = self.attributes.as_ref().map(|names| names.join(","));
+        let excluded_attributes = self.excluded_attributes.as_ref().map(|names| names.join(","));
+        let parameters = [
+            ("filter", self.filter.as_ref()),
+            ("sortBy", self.sort_by.as_ref()),
+            ("sortOrder", self.sort_order.as_ref()),
+            ("startIndex", start_index.as_ref()),
+            ("count", count.as_ref()),
+            ("attributes", attributes.as_ref()),
+            ("excludedAttributes", excluded_attributes.as_ref()),
+            ("cursor", self.cursor.as_ref()),
+        ];
+        parameters.iter()
+            .filter_map(|(name, value)| value.map(|value| format!("{}={}", name, utf8_percent_encode(value, QUERY_VALUE))))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+
+    /// Returns the query for the page that follows `response`, or `None` when `response` is the last page.
+    ///
+    /// Cursor pagination (RFC 9865) is preferred when the service provider advertises it and the response carries
+    /// a `nextCursor`. Otherwise the next page is requested by index, based on `startIndex` and `itemsPerPage`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::others::{ListQuery, ListResponse};
+    /// use scim_v2::models::service_provider_config::ServiceProviderConfig;
+    ///
+    /// let config = ServiceProviderConfig::default();
+    /// let query = ListQuery::default();
+    /// let response: ListResponse = ListResponse {
+    ///     items_per_page: 100,
+    ///     total_results: 250,
+    ///     start_index: 1,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let next = query.next_page(&response, &config).unwrap();
+    /// assert_eq!(next.start_index, Some(101));
+    /// ```
+    pub fn next_page<T>(&self, response: &ListResponse<T>, config: &ServiceProviderConfig) -> Option<ListQuery> {
+        let cursor_supported = config.pagination.as_ref().map_or(false, |pagination| pagination.cursor);
+        if cursor_supported && (self.cursor.is_some() || response.next_cursor.is_some()) {
+            let next_cursor = response.next_cursor.clone()?;
+            return Some(ListQuery {
+                start_index: None,
+                cursor: Some(next_cursor),
+                ..self.clone()
+            });
+        }
+        if response.items_per_page <= 0 {
+            return None;
+        }
+        let next_index = response.start_index + response.items_per_page;
+        if next_index > response.total_results {
+            return None;
+        }
+        Some(ListQuery {
+            start_index: Some(next_index),
+            cursor: None,
+            ..self.clone()
+        })
+    }
+}
+
+fn decode_query_component(component: &str) -> Result<String, SCIMError> {
+    percent_decode_str(&component.replace('+', " "))
+        .decode_utf8()
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| SCIMError::InvalidFieldValue(format!("query parameter is not valid UTF-8: {}", component)))
+}
+
+fn parse_integer(name: &str, value: &str) -> Result<i64, SCIMError> {
+    value.trim().parse().map_err(|_| SCIMError::InvalidFieldValue(format!("{}: {}", name, value)))
+}
+
+/// A resource of any of the types this crate models, as found in a heterogeneous `ListResponse` such as the result of
+/// a root-level `/.search`.
+///
+/// Deserialization dispatches on the URNs in `schemas`, falling back to `meta.resourceType`, so a resource is only
+/// ever parsed as the type it declares. A resource that declares no known type is rejected instead of guessed.
+#[derive(Serialize, Debug)]
 #[serde(untagged)]
 pub enum Resource {
     User(Box<User>),
@@ -69,33 +556,121 @@ pub enum Resource {
     ResourceType(Box<ResourceType>),
 }
 
+impl<'de> Deserialize<'de> for Resource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        canonicalize_declared(&mut value).map_err(de::Error::custom)?;
+        let declares = |urn: &str, resource_type: &str| {
+            let in_schemas = value.get("schemas")
+                .and_then(Value::as_array)
+                .map_or(false, |schemas| schemas.iter().filter_map(Value::as_str).any(|schema| schema.eq_ignore_ascii_case(urn)));
+            let in_meta = value.get("meta")
+                .and_then(|meta| meta.get("resourceType"))
+                .and_then(Value::as_str) == Some(resource_type);
+            in_schemas || in_meta
+        };
+        let resource = if declares("urn:ietf:params:scim:schemas:core:2.0:User", "User") {
+            Resource::User(Box::new(serde_json::from_value(value).map_err(de::Error::custom)?))
+        } else if declares("urn:ietf:params:scim:schemas:core:2.0:Group", "Group") {
+            Resource::Group(Box::new(serde_json::from_value(value).map_err(de::Error::custom)?))
+        } else if declares("urn:ietf:params:scim:schemas:core:2.0:ResourceType", "ResourceType") {
+            Resource::ResourceType(Box::new(serde_json::from_value(value).map_err(de::Error::custom)?))
+        } else if declares("urn:ietf:params:scim:schemas:core:2.0:Schema", "Schema") {
+            Resource::Schema(Box::new(serde_json::from_value(value).map_err(de::Error::custom)?))
+        } else {
+            return Err(de::Error::custom("resource does not declare a known schema in `schemas` or `meta.resourceType`"));
+        };
+        Ok(resource)
+    }
+}
+
+/// A SCIM list response (RFC 7644 section 3.4.2).
+///
+/// Use `ListResponse<User>` or `ListResponse<Group>` for the results of a single endpoint, and the default
+/// `ListResponse<Resource>` when results can contain several resource types.
 #[derive(Serialize, Deserialize, Debug)]
-pub struct ListResponse {
+pub struct ListResponse<T = Resource> {
     #[serde(rename = "itemsPerPage")]
     pub items_per_page: i64,
     #[serde(rename = "totalResults")]
     pub total_results: i64,
-    #[serde(rename = "startIndex")]
+    // Cursor-paginated responses (RFC 9865) omit startIndex.
+    #[serde(rename = "startIndex", default = "default_start_index")]
     pub start_index: i64,
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "previousCursor", skip_serializing_if = "Option::is_none")]
+    pub previous_cursor: Option<String>,
     pub schemas: Vec<String>,
-    #[serde(rename = "Resources")]
-    pub resources: Vec<Resource>,
+    #[serde(rename = "Resources", default = "Vec::new")]
+    pub resources: Vec<T>,
 }
 
-impl Default for ListResponse {
+/// The attributes of a `ListResponse` (RFC 7644 section 3.4.2 and RFC 9865).
+const LIST_RESPONSE_ATTRIBUTES: [&str; 7] = ["schemas", "totalResults", "itemsPerPage", "startIndex", "nextCursor", "previousCursor", "Resources"];
+
+fn default_start_index() -> i64 {
+    1
+}
+
+impl<T> Default for ListResponse<T> {
     fn default() -> Self {
         ListResponse {
             items_per_page: 0,
             total_results: 0,
             start_index: 1,
+            next_cursor: None,
+            previous_cursor: None,
             schemas: vec!["urn:ietf:params:scim:api:messages:2.0:ListResponse".to_string()],
             resources: vec![],
         }
     }
 }
 
+/// Converts a JSON string into a `ListResponse`.
+///
+/// With the `case-insensitive` feature, attribute names are matched case-insensitively, and the attribute names of
+/// each resource are canonicalised with the built-in schemas listed in its `schemas`.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the JSON is not a list response of `T`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::group::Group;
+/// use scim_v2::models::others::ListResponse;
+///
+/// let response_json = r#"{
+///     "schemas": ["urn:ietf:params:scim:api:messages:2.0:ListResponse"],
+///     "totalResults": 1,
+///     "itemsPerPage": 1,
+///     "startIndex": 1,
+///     "Resources": [{"schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"], "id": "e9e30dba-f08f-4109-8486-d5c6a331660a", "displayName": "Tour Guides"}]
+/// }"#;
+/// match ListResponse::<Group>::try_from(response_json) {
+///     Ok(response) => println!("Groups: {:?}", response.resources),
+///     Err(e) => println!("Error converting from JSON to ListResponse: {}", e),
+/// }
+/// ```
+impl<T: DeserializeOwned> TryFrom<&str> for ListResponse<T> {
+    type Error = SCIMError;
 
-#[derive(Serialize, Deserialize, Debug)]
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        message_from_json(value, |value| {
+            canonicalize_names(value, &LIST_RESPONSE_ATTRIBUTES);
+            if let Some(Value::Array(resources)) = value.get_mut("Resources") {
+                for resource in resources {
+                    canonicalize_declared(resource)?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PatchOp {
     pub schemas: Vec<String>,
     #[serde(rename = "Operations")]
@@ -111,20 +686,433 @@ impl Default for PatchOp {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Converts the JSON body of a `PATCH` request into a `PatchOp`.
+///
+/// With the `case-insensitive` feature, attribute names such as `operations` or `OP` are matched case-insensitively.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the JSON is not a valid `PatchOp`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::PatchOp;
+///
+/// let patch = PatchOp::try_from(r#"{
+///     "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+///     "Operations": [{"op": "replace", "path": "active", "value": false}]
+/// }"#).unwrap();
+/// assert_eq!(patch.operations[0].path, Some("active".to_string()));
+/// ```
+impl TryFrom<&str> for PatchOp {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        message_from_json(value, |value| {
+            canonicalize_names(value, &["schemas", "Operations"]);
+            if let Some(operations) = value.get_mut("Operations") {
+                canonicalize_names(operations, &["op", "path", "value"]);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A single operation of a `PatchOp` (RFC 7644 section 3.5.2).
+///
+/// `path` is optional for `add` and `replace`, in which case `value` is an object of attributes to modify, and
+/// `value` is absent for `remove`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PatchOperations {
     pub op: String,
-    pub value: HashMap<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
 }
 
 impl Default for PatchOperations {
     fn default() -> Self {
         PatchOperations {
             op: "".to_string(),
-            value: HashMap::new(),
+            path: None,
+            value: None,
+        }
+    }
+}
+
+/// A `BulkRequest` message (RFC 7644 section 3.7).
+///
+/// `failOnErrors` is the number of errors after which the service provider stops processing the operations; when it
+/// is absent, every operation is attempted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkRequest {
+    pub schemas: Vec<String>,
+    #[serde(rename = "failOnErrors", skip_serializing_if = "Option::is_none")]
+    pub fail_on_errors: Option<i64>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<BulkOperation>,
+}
+
+impl Default for BulkRequest {
+    fn default() -> Self {
+        BulkRequest {
+            schemas: vec!["urn:ietf:params:scim:api:messages:2.0:BulkRequest".to_string()],
+            fail_on_errors: None,
+            operations: Vec::new(),
         }
     }
 }
 
+/// Converts the JSON body of a `POST /Bulk` request into a `BulkRequest`.
+///
+/// With the `case-insensitive` feature, attribute names such as `operations` or `BULKID` are matched case-insensitively.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the JSON is not a valid `BulkRequest`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::BulkRequest;
+///
+/// let request = BulkRequest::try_from(r#"{
+///     "schemas": ["urn:ietf:params:scim:api:messages:2.0:BulkRequest"],
+///     "failOnErrors": 1,
+///     "Operations": [{"method": "POST", "path": "/Users", "bulkId": "qwerty", "data": {"userName": "Alice"}}]
+/// }"#).unwrap();
+/// assert_eq!(request.operations[0].bulk_id, Some("qwerty".to_string()));
+/// ```
+impl TryFrom<&str> for BulkRequest {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        message_from_json(value, |value| {
+            canonicalize_names(value, &["schemas", "failOnErrors", "Operations"]);
+            if let Some(operations) = value.get_mut("Operations") {
+                canonicalize_names(operations, &["method", "bulkId", "version", "path", "data"]);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A single operation of a `BulkRequest`.
+///
+/// `path` is relative to the SCIM base URL, e.g. `/Users` for a `POST` or `/Groups/{id}` for a `PATCH`, and may
+/// reference a resource created earlier in the same request as `bulkId:{bulkId}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BulkOperation {
+    pub method: String,
+    #[serde(rename = "bulkId", skip_serializing_if = "Option::is_none")]
+    pub bulk_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// A `BulkResponse` message, with one result per operation that was processed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkResponse {
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<BulkOperationResponse>,
+}
+
+impl Default for BulkResponse {
+    fn default() -> Self {
+        BulkResponse {
+            schemas: vec!["urn:ietf:params:scim:api:messages:2.0:BulkResponse".to_string()],
+            operations: Vec::new(),
+        }
+    }
+}
+
+/// The result of a single bulk operation. `response` holds the `ScimHttpError` of a failed operation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BulkOperationResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    pub method: String,
+    #[serde(rename = "bulkId", skip_serializing_if = "Option::is_none")]
+    pub bulk_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::models::group::GroupRequest;
+    use crate::models::service_provider_config::Pagination;
+    use crate::models::user::UserRequest;
 
+    use super::*;
 
+    #[test]
+    fn next_page_prefers_cursor_when_supported() {
+        let config = ServiceProviderConfig {
+            pagination: Some(Pagination { cursor: true, ..Default::default() }),
+            ..Default::default()
+        };
+        let response: ListResponse = serde_json::from_str(r#"{
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:ListResponse"],
+            "totalResults": 250,
+            "itemsPerPage": 100,
+            "nextCursor": "VZUTiyhEQJ94IR",
+            "Resources": []
+        }"#).unwrap();
+
+        let next = ListQuery::default().next_page(&response, &config).unwrap();
+        assert_eq!(next.cursor, Some("VZUTiyhEQJ94IR".to_string()));
+        assert_eq!(next.start_index, None);
+
+        let last_page = ListResponse { next_cursor: None, ..response };
+        assert!(next.next_page(&last_page, &config).is_none());
+    }
+
+    #[test]
+    fn from_query_string_decodes_and_normalises_values() {
+        let query = ListQuery::from_query_string(
+            "?filter=emails%5Btype%20eq%20%22work%22%5D&sortBy=userName&sortOrder=Descending&startIndex=0&count=-5&excludedAttributes=groups,%20members&unknown=1",
+            100,
+        ).unwrap();
+
+        assert_eq!(query, ListQuery {
+            filter: Some("emails[type eq \"work\"]".to_string()),
+            sort_by: Some("userName".to_string()),
+            sort_order: Some("descending".to_string()),
+            start_index: Some(1),
+            count: Some(0),
+            attributes: None,
+            excluded_attributes: Some(vec!["groups".to_string(), "members".to_string()]),
+            cursor: None,
+        });
+    }
+
+    #[test]
+    fn from_query_string_rejects_invalid_values() {
+        assert!(matches!(ListQuery::from_query_string("count=ten", 100), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(ListQuery::from_query_string("sortOrder=up", 100), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(ListQuery::from_query_string("filter=%FF", 100), Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn query_string_round_trips_through_search_request() {
+        let query = ListQuery {
+            filter: Some("displayName co \"R&D\"".to_string()),
+            sort_by: Some("displayName".to_string()),
+            sort_order: Some("ascending".to_string()),
+            start_index: Some(11),
+            count: Some(10),
+            attributes: Some(vec!["displayName".to_string(), "members.value".to_string()]),
+            excluded_attributes: None,
+            cursor: None,
+        };
+
+        let rendered = query.to_query_string();
+        assert_eq!(ListQuery::from_query_string(&rendered, 100).unwrap(), query);
+
+        let search_request = SearchRequest::from(query.clone());
+        let json = serde_json::to_value(&search_request).unwrap();
+        assert_eq!(json["attributes"], serde_json::json!(["displayName", "members.value"]));
+        assert_eq!(ListQuery::from(search_request), query);
+    }
+
+    #[test]
+    fn list_query_default_does_not_render_empty_filter() {
+        assert_eq!(ListQuery::default().to_query_string(), "startIndex=1&count=100");
+        let json = serde_json::to_value(ListQuery::default()).unwrap();
+        assert!(json.get("filter").is_none());
+    }
+
+    #[test]
+    fn resource_dispatches_on_schemas_urn() {
+        let response: ListResponse = serde_json::from_str(r#"{
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:ListResponse"],
+            "totalResults": 3,
+            "itemsPerPage": 3,
+            "startIndex": 1,
+            "Resources": [
+                {
+                    "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+                    "id": "e9e30dba-f08f-4109-8486-d5c6a331660a",
+                    "displayName": "userName lookalikes",
+                    "userName": "not a user"
+                },
+                {
+                    "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User", "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User"],
+                    "id": "2819c223-7f76-453a-919d-413861904646",
+                    "userName": "bjensen@example.com"
+                },
+                {
+                    "id": "urn:ietf:params:scim:schemas:core:2.0:Group",
+                    "name": "Group",
+                    "description": "Group",
+                    "attributes": [],
+                    "meta": {"resourceType": "Schema"}
+                }
+            ]
+        }"#).unwrap();
+
+        assert!(matches!(&response.resources[0], Resource::Group(group) if group.body.display_name == "userName lookalikes"));
+        assert!(matches!(&response.resources[1], Resource::User(user) if user.body.user_name == "bjensen@example.com"));
+        assert!(matches!(&response.resources[2], Resource::Schema(_)));
+    }
+
+    #[test]
+    fn resource_rejects_unknown_or_invalid_resources() {
+        let unknown: Result<Resource, _> = serde_json::from_str(r#"{"userName": "bjensen@example.com"}"#);
+        assert!(unknown.is_err());
+
+        let group_without_name: Result<Resource, _> = serde_json::from_str(r#"{
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+            "id": "e9e30dba-f08f-4109-8486-d5c6a331660a"
+        }"#);
+        assert!(group_without_name.is_err());
+    }
+
+    #[test]
+    fn typed_list_response_parses_endpoint_results() {
+        let response = ListResponse::<Group>::try_from(r#"{
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:ListResponse"],
+            "totalResults": 0,
+            "itemsPerPage": 0,
+            "startIndex": 1
+        }"#).unwrap();
+        assert!(response.resources.is_empty());
+
+        let response = ListResponse::<User>::try_from(r#"{
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:ListResponse"],
+            "totalResults": 1,
+            "itemsPerPage": 1,
+            "startIndex": 1,
+            "Resources": [{"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "bjensen@example.com"}]
+        }"#).unwrap();
+        assert_eq!(response.resources[0].body.user_name, "bjensen@example.com");
+    }
+
+    #[test]
+    fn next_page_falls_back_to_index() {
+        let config = ServiceProviderConfig::default();
+        let response: ListResponse = ListResponse {
+            items_per_page: 100,
+            total_results: 150,
+            start_index: 101,
+            next_cursor: Some("ignored".to_string()),
+            ..Default::default()
+        };
+
+        assert!(ListQuery::default().next_page(&response, &config).is_none());
+        let first_page = ListResponse { start_index: 1, ..response };
+        assert_eq!(ListQuery::default().next_page(&first_page, &config).unwrap().start_index, Some(101));
+    }
+
+    #[test]
+    fn validate_attributes_checks_filter_sort_and_attribute_paths() {
+        let schemas = crate::models::scim_schema::get_schemas(vec!["user", "enterprise_user", "group"]).unwrap();
+        let request = SearchRequest {
+            filter: Some(r#"(userName eq "a b" or not (members[value eq "x"])) and urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:employeeNumber pr and name.GIVENNAME sw "eq""#.to_string()),
+            sort_by: Some("meta.created".to_string()),
+            attributes: Some(vec!["displayName".to_string(), "emails.value".to_string()]),
+            ..Default::default()
+        };
+        assert!(request.validate_attributes(&schemas).is_ok());
+        assert_eq!(filter_attribute_paths(r#"emails[type eq "work" and value co "@"] or userName eq "eq""#).unwrap(), vec!["emails", "emails.type", "emails.value", "userName"]);
+        let unbalanced = SearchRequest { filter: Some(r#"emails[type eq "work""#.to_string()), ..Default::default() };
+        assert!(matches!(unbalanced.validate_attributes(&schemas), Err(SCIMError::InvalidSyntax(_))));
+
+        for request in [
+            SearchRequest { filter: Some(r#"nick eq "x""#.to_string()), ..Default::default() },
+            SearchRequest { filter: Some(r#"emails[kind eq "work"]"#.to_string()), ..Default::default() },
+            SearchRequest { sort_by: Some("name.nickName".to_string()), ..Default::default() },
+            SearchRequest { excluded_attributes: Some(vec!["urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:userName".to_string()]), ..Default::default() },
+        ] {
+            assert!(matches!(request.validate_attributes(&schemas), Err(SCIMError::InvalidFieldValue(_))));
+        }
+    }
+
+    #[test]
+    fn merge_results_sorts_and_pages_across_resource_types() {
+        let user = |name: &str, display_name: Option<&str>| Resource::User(Box::new(User {
+            body: UserRequest {
+                user_name: name.to_string(),
+                display_name: display_name.map(|d| d.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+        let group = |display_name: &str| Resource::Group(Box::new(Group {
+            body: GroupRequest {
+                display_name: display_name.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+        let resources = || vec![user("carol", Some("carol")), group("Bob's team"), user("nobody", None), group("alice's team")];
+        let names = |response: &ListResponse| response.resources.iter().map(|resource| match resource {
+            Resource::User(user) => user.body.user_name.clone(),
+            Resource::Group(group) => group.body.display_name.clone(),
+            _ => unreachable!(),
+        }).collect::<Vec<_>>();
+
+        let request = SearchRequest {
+            sort_by: Some("displayName".to_string()),
+            start_index: Some(2),
+            count: Some(2),
+            ..Default::default()
+        };
+        let response = request.merge_results(resources()).unwrap();
+        assert_eq!(response.total_results, 4);
+        assert_eq!(response.items_per_page, 2);
+        assert_eq!(response.start_index, 2);
+        assert_eq!(names(&response), vec!["Bob's team", "carol"]);
+
+        let descending = SearchRequest {
+            sort_by: Some("displayName".to_string()),
+            sort_order: Some("Descending".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(&descending.merge_results(resources()).unwrap()), vec!["carol", "Bob's team", "alice's team", "nobody"]);
+        let unknown = SearchRequest { sort_order: Some("down".to_string()), ..Default::default() };
+        assert!(matches!(unknown.merge_results(resources()), Err(SCIMError::InvalidFieldValue(_))));
+
+        let past_end = SearchRequest { start_index: Some(10), ..Default::default() };
+        assert_eq!(past_end.merge_results(resources()).unwrap().items_per_page, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "case-insensitive")]
+    fn list_responses_and_messages_ignore_attribute_name_case() {
+        let list = r#"{
+            "SCHEMAS": ["urn:ietf:params:scim:api:messages:2.0:ListResponse"],
+            "totalresults": 1,
+            "ItemsPerPage": 1,
+            "resources": [{"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "id": "2819c223", "USERNAME": "bjensen", "Name": {"givenname": "Barbara"}}]
+        }"#;
+
+        let users = ListResponse::<User>::try_from(list).unwrap();
+        assert_eq!(users.total_results, 1);
+        assert_eq!(users.resources[0].body.user_name, "bjensen");
+        assert_eq!(users.resources[0].body.name.as_ref().unwrap().given_name, Some("Barbara".to_string()));
+        match &ListResponse::<Resource>::try_from(list).unwrap().resources[0] {
+            Resource::User(user) => assert_eq!(user.body.user_name, "bjensen"),
+            other => panic!("expected a user, got {:?}", other),
+        }
+
+        let patch = PatchOp::try_from(r#"{"Schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"], "operations": [{"OP": "replace", "Path": "active", "VALUE": false}]}"#).unwrap();
+        assert_eq!(patch.operations[0], PatchOperations { op: "replace".to_string(), path: Some("active".to_string()), value: Some(Value::Bool(false)) });
+
+        let search = SearchRequest::try_from(r#"{"schemas": ["urn:ietf:params:scim:api:messages:2.0:SearchRequest"], "FILTER": "userName eq \"bjensen\"", "SortBy": "userName"}"#).unwrap();
+        assert_eq!(search.filter, Some(r#"userName eq "bjensen""#.to_string()));
+        assert_eq!(search.sort_by, Some("userName".to_string()));
+    }
+}
@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 use crate::models::scim_schema::Meta;
 use crate::utils::error::SCIMError;
+use crate::utils::parse::{from_json, ParseMode};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ResourceType {
+    #[serde(default)]
+    pub schemas: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     pub name: String,
@@ -16,11 +20,15 @@ pub struct ResourceType {
     pub schema_extensions: Option<Vec<SchemaExtension>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub meta: Option<Meta>,
+    // Attributes not defined by the schemas, kept when parsing with ParseMode::Lenient.
+    #[serde(flatten)]
+    pub additional_attributes: Map<String, Value>,
 }
 
 impl Default for ResourceType {
     fn default() -> Self {
         ResourceType {
+            schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:ResourceType".to_string()],
             id: None,
             name: "".to_string(),
             description: None,
@@ -28,11 +36,12 @@ impl Default for ResourceType {
             schema: "".to_string(),
             schema_extensions: None,
             meta: None,
+            additional_attributes: Map::new(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SchemaExtension {
     pub schema: String,
     pub required: bool,
@@ -47,14 +56,26 @@ impl Default for SchemaExtension {
     }
 }
 
+/// Returns the URNs of the extension objects kept in the additional attributes of a resource.
+pub(crate) fn extension_urns(additional_attributes: &Map<String, Value>) -> impl Iterator<Item = &str> {
+    additional_attributes.iter()
+        .filter(|(key, value)| key.get(..4).map(|prefix| prefix.eq_ignore_ascii_case("urn:")) == Some(true) && value.is_object())
+        .map(|(key, _)| key.as_str())
+}
+
 /// Returns a vector of `ResourceType` instances based on the provided resource type names.
 ///
 /// This function creates `ResourceType` instances for "user" and "group" with default values if their names are included in the `resource_type_names` vector.
 /// If "enterprise_user" is included in the `resource_type_names` vector, the "user" `ResourceType` will include the enterprise user schema extension.
 ///
+/// The `meta.location` of each resource type is built from `base_url`. The enterprise user extension is
+/// optional, so users without enterprise data still pass `validate_schemas`; set `required` on the returned
+/// `SchemaExtension` or use `DiscoveryBuilder` from the `discovery` module to describe your own deployment.
+///
 /// # Parameters
 ///
 /// * `resource_type_names` - A vector of string slices that represent the names of the resource types to be returned. Options are: user, group, enterprise_user
+/// * `base_url` - The SCIM base URL of the service provider, e.g. `https://example.com/v2`.
 ///
 /// # Returns
 ///
@@ -67,7 +88,7 @@ impl Default for SchemaExtension {
 /// use scim_v2::models::resource_types::get_resource_types;
 ///
 /// let resource_type_names = vec!["user", "group", "enterprise_user"];
-/// match get_resource_types(resource_type_names) {
+/// match get_resource_types(resource_type_names, "https://example.com/v2") {
 ///     Ok(resource_types) => {
 ///         for resource_type in resource_types {
 ///             println!("ResourceType: {:?}", resource_type);
@@ -76,7 +97,8 @@ impl Default for SchemaExtension {
 ///     Err(e) => println!("Error getting resource types: {}", e),
 /// }
 /// ```
-pub fn get_resource_types(mut resource_type_names: Vec<&str>) -> Result<Vec<ResourceType>, SCIMError> {
+pub fn get_resource_types(mut resource_type_names: Vec<&str>, base_url: &str) -> Result<Vec<ResourceType>, SCIMError> {
+    let base_url = base_url.trim_end_matches('/');
     let mut resource_types = Vec::new();
     let has_enterprise_user = resource_type_names.contains(&"enterprise_user");
     // Remove "enterprise_user" from the vector
@@ -88,6 +110,7 @@ pub fn get_resource_types(mut resource_type_names: Vec<&str>) -> Result<Vec<Reso
         match resource_type_name {
             "user" => {
                 let user_resource_type = ResourceType {
+                    schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:ResourceType".to_string()],
                     id: Some("User".to_string()),
                     name: "User".to_string(),
                     endpoint: "/Users".to_string(),
@@ -97,24 +120,26 @@ pub fn get_resource_types(mut resource_type_names: Vec<&str>) -> Result<Vec<Reso
                         Some(vec![
                             SchemaExtension {
                                 schema: "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User".to_string(),
-                                required: true,
+                                required: false,
                             },
                         ])
                     } else {
                         None
                     },
                     meta: Some(Meta {
-                        location: Some("https://example.com/v2/ResourceTypes/User".to_string()),
+                        location: Some(format!("{}/ResourceTypes/User", base_url)),
                         resource_type: Some("ResourceType".to_string()),
                         created: None,
                         last_modified: None,
                         version: None,
                     }),
+                    additional_attributes: Map::new(),
                 };
                 resource_types.push(user_resource_type);
             }
             "group" => {
                 let group_resource_type = ResourceType {
+                    schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:ResourceType".to_string()],
                     id: Some("Group".to_string()),
                     name: "Group".to_string(),
                     endpoint: "/Groups".to_string(),
@@ -122,12 +147,13 @@ pub fn get_resource_types(mut resource_type_names: Vec<&str>) -> Result<Vec<Reso
                     schema: "urn:ietf:params:scim:schemas:core:2.0:Group".to_string(),
                     schema_extensions: None,
                     meta: Some(Meta {
-                        location: Some("https://example.com/v2/ResourceTypes/Group".to_string()),
+                        location: Some(format!("{}/ResourceTypes/Group", base_url)),
                         resource_type: Some("ResourceType".to_string()),
                         created: None,
                         last_modified: None,
                         version: None,
                     }),
+                    additional_attributes: Map::new(),
                 };
                 resource_types.push(group_resource_type);
             }
@@ -184,7 +210,7 @@ impl TryFrom<&str> for ResourceType {
     type Error = SCIMError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        serde_json::from_str(value).map_err(SCIMError::DeserializationError)
+        from_json(value, &["resource_type"], ParseMode::Lenient)
     }
 }
 
@@ -232,6 +258,76 @@ impl ResourceType {
         }
         Ok(())
     }
+
+    /// Checks the `schemas` of a resource of this type against the extensions it carries.
+    ///
+    /// `extensions` are the URNs of the extension objects present in the resource. Schema URNs are compared
+    /// case-insensitively.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::SchemaNotFound` - If `schemas` or `extensions` contain a URN that is neither the core schema nor a
+    ///   schema extension of this resource type.
+    /// * `SCIMError::InvalidFieldValue` - If `schemas` lacks the core schema or a present extension, or lists an
+    ///   extension that has no data.
+    /// * `SCIMError::MissingRequiredField` - If a required schema extension is not present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::resource_types::get_resource_types;
+    ///
+    /// let user_type = get_resource_types(vec!["user", "enterprise_user"], "https://example.com/v2").unwrap().remove(0);
+    /// let core = "urn:ietf:params:scim:schemas:core:2.0:User".to_string();
+    /// let enterprise = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User";
+    ///
+    /// assert!(user_type.validate_schemas(&[core.clone(), enterprise.to_string()], &[enterprise]).is_ok());
+    /// assert!(user_type.validate_schemas(&[core.clone(), enterprise.to_string()], &[]).is_err());
+    /// assert!(user_type.validate_schemas(&[core], &[enterprise]).is_err());
+    /// ```
+    pub fn validate_schemas(&self, schemas: &[String], extensions: &[&str]) -> Result<(), SCIMError> {
+        let registered = |urn: &str| self.schema_extensions.iter().flatten().any(|extension| extension.schema.eq_ignore_ascii_case(urn));
+        let present = |urn: &str| extensions.iter().any(|extension| extension.eq_ignore_ascii_case(urn));
+        let listed = |urn: &str| schemas.iter().any(|schema| schema.eq_ignore_ascii_case(urn));
+
+        if !listed(&self.schema) {
+            return Err(SCIMError::InvalidFieldValue(format!("schemas does not list the core schema {}", self.schema)));
+        }
+        for urn in schemas.iter().filter(|urn| !urn.eq_ignore_ascii_case(&self.schema)) {
+            if !registered(urn) {
+                return Err(SCIMError::SchemaNotFound(urn.to_string()));
+            }
+            if !present(urn) {
+                return Err(SCIMError::InvalidFieldValue(format!("schemas lists {} but the resource has no data for it", urn)));
+            }
+        }
+        for urn in extensions {
+            if !registered(urn) {
+                return Err(SCIMError::SchemaNotFound(urn.to_string()));
+            }
+            if !listed(urn) {
+                return Err(SCIMError::InvalidFieldValue(format!("schemas does not list the extension {}", urn)));
+            }
+        }
+        for extension in self.schema_extensions.iter().flatten().filter(|extension| extension.required) {
+            if !present(&extension.schema) {
+                return Err(SCIMError::MissingRequiredField(extension.schema.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `schemas` a resource of this type should list: the core schema followed by the registered
+    /// extensions among `extensions`, in the order this resource type declares them.
+    ///
+    /// Unregistered extensions are left out; `validate_schemas` reports them.
+    pub fn reconcile_schemas(&self, extensions: &[&str]) -> Vec<String> {
+        let mut schemas = vec![self.schema.clone()];
+        schemas.extend(self.schema_extensions.iter().flatten()
+            .filter(|extension| extensions.iter().any(|urn| urn.eq_ignore_ascii_case(&extension.schema)))
+            .map(|extension| extension.schema.clone()));
+        schemas
+    }
     /// Serializes the `ResourceType` instance to a JSON string, using the custom SCIMError for error handling.
     ///
     /// # Returns
@@ -308,7 +404,35 @@ impl ResourceType {
     /// }
     /// ```
     pub fn deserialize(json: &str) -> Result<Self, SCIMError> {
-        serde_json::from_str(json).map_err(SCIMError::DeserializationError)
+        from_json(json, &["resource_type"], ParseMode::Lenient)
+    }
+
+    /// Deserializes a JSON string into a `ResourceType` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the Resource Type schema does not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `ResourceType`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::resource_types::ResourceType;
+    /// use scim_v2::utils::parse::ParseMode;
+    ///
+    /// let json = r#"{"id": "User", "name": "User", "endpoint": "/Users", "schema": "urn:ietf:params:scim:schemas:core:2.0:User", "icon": "user.png"}"#;
+    ///
+    /// let parsed = ResourceType::deserialize_with_mode(json, ParseMode::Lenient).unwrap();
+    /// assert!(parsed.additional_attributes.contains_key("icon"));
+    ///
+    /// let error = ResourceType::deserialize_with_mode(json, ParseMode::Strict).unwrap_err();
+    /// assert_eq!(error.to_string(), "Invalid syntax: unknown attribute: icon");
+    /// ```
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["resource_type"], mode)
     }
 }
 
@@ -401,7 +525,7 @@ mod tests {
     #[test]
     fn test_get_resource_types() {
         let resource_type_names = vec!["user", "group", "enterprise_user"];
-        let resource_types = get_resource_types(resource_type_names).unwrap();
+        let resource_types = get_resource_types(resource_type_names, "https://scim.example.org/v2/").unwrap();
 
         assert_eq!(resource_types.len(), 2);
 
@@ -410,6 +534,7 @@ mod tests {
         assert_eq!(user_resource_type.endpoint, "/Users");
         assert_eq!(user_resource_type.schema, "urn:ietf:params:scim:schemas:core:2.0:User");
         assert!(user_resource_type.schema_extensions.is_some());
+        assert_eq!(user_resource_type.meta.as_ref().unwrap().location, Some("https://scim.example.org/v2/ResourceTypes/User".to_string()));
 
         let group_resource_type = &resource_types[1];
         assert_eq!(group_resource_type.name, "Group");
@@ -417,4 +542,25 @@ mod tests {
         assert_eq!(group_resource_type.schema, "urn:ietf:params:scim:schemas:core:2.0:Group");
         assert!(group_resource_type.schema_extensions.is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn validate_schemas_rejects_unregistered_and_missing_required_extensions() {
+        let mut user_type = get_resource_types(vec!["user", "enterprise_user"], "https://example.com/v2").unwrap().remove(0);
+        let core = "urn:ietf:params:scim:schemas:core:2.0:User".to_string();
+        let custom = "urn:example:params:scim:schemas:extension:custom:2.0:User";
+
+        let unregistered = user_type.validate_schemas(&[core.clone(), custom.to_string()], &[custom]);
+        assert!(matches!(unregistered, Err(SCIMError::SchemaNotFound(urn)) if urn == custom));
+
+        // The enterprise extension is optional unless the deployment marks it as required.
+        assert!(user_type.validate_schemas(std::slice::from_ref(&core), &[]).is_ok());
+        user_type.schema_extensions.as_mut().unwrap()[0].required = true;
+        let missing = user_type.validate_schemas(&[core], &[]);
+        assert!(matches!(missing, Err(SCIMError::MissingRequiredField(urn)) if urn == "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User"));
+
+        assert_eq!(user_type.reconcile_schemas(&[custom, "URN:IETF:PARAMS:SCIM:SCHEMAS:EXTENSION:ENTERPRISE:2.0:USER"]), vec![
+            "urn:ietf:params:scim:schemas:core:2.0:User",
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User",
+        ]);
+    }
+}
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::{ENTERPRISE_USER_SCHEMA, GROUP_SCHEMA, USER_SCHEMA};
+use crate::{ENTERPRISE_USER_SCHEMA, GROUP_SCHEMA, RESOURCE_TYPE_SCHEMA, SCHEMA_SCHEMA, SERVICE_PROVIDER_CONFIG_SCHEMA, USER_SCHEMA};
 use crate::utils::error::SCIMError;
+use crate::utils::parse::{from_json, ParseMode};
+use crate::utils::patch::PatchPath;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[derive(Default)]
 pub struct Meta {
     #[serde(rename = "resourceType", skip_serializing_if = "Option::is_none")]
@@ -19,16 +22,22 @@ pub struct Meta {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Schema {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schemas: Vec<String>,
     pub id: String,
     pub name: String,
     pub description: String,
     pub attributes: Vec<Attributes>,
+    #[serde(default)]
     pub meta: Meta,
+    // Attributes not defined by the schemas, kept when parsing with ParseMode::Lenient.
+    #[serde(flatten)]
+    pub additional_attributes: Map<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Attributes {
     pub name: String,
     #[serde(rename = "type")]
@@ -55,7 +64,7 @@ pub struct Attributes {
     pub reference_types: Option<Vec<String>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SubAttributes {
     pub name: String,
     #[serde(rename = "type")]
@@ -83,7 +92,8 @@ pub struct SubAttributes {
 /// Retrieves a list of `Schema` instances based on the provided schema names.
 ///
 /// This function takes a vector of schema names as input and attempts to retrieve the corresponding `Schema` instances.
-/// It uses a predefined list of schema contents to match the input schema names.
+/// It uses a predefined list of schema contents to match the input schema names: `user`, `enterprise_user`, `group`,
+/// `resource_type`, `service_provider_config` and `schema`.
 ///
 /// # Parameters
 ///
@@ -118,6 +128,9 @@ pub fn get_schemas(schema_names: Vec<&str>) -> Result<Vec<Schema>, SCIMError> {
         ("user", USER_SCHEMA),
         ("enterprise_user", ENTERPRISE_USER_SCHEMA),
         ("group", GROUP_SCHEMA),
+        ("resource_type", RESOURCE_TYPE_SCHEMA),
+        ("service_provider_config", SERVICE_PROVIDER_CONFIG_SCHEMA),
+        ("schema", SCHEMA_SCHEMA),
     ].iter().cloned().collect::<std::collections::HashMap<_, _>>();
 
     for schema_name in schema_names {
@@ -253,11 +266,38 @@ pub fn get_schemas(schema_names: Vec<&str>) -> Result<Vec<Schema>, SCIMError> {
 ///     Err(e) => println!("Error converting from JSON to Schema: {}", e),
 /// }
 /// ```
+/// Returns the attribute a path targets. Paths without a URN use the first schema.
+pub(crate) fn find_attribute<'a>(schemas: &'a [Schema], path: &PatchPath) -> Option<&'a Attributes> {
+    let schema = match &path.urn {
+        Some(urn) => schemas.iter().find(|schema| schema.id.eq_ignore_ascii_case(urn))?,
+        None => schemas.first()?,
+    };
+    schema.attributes.iter().find(|attribute| attribute.name.eq_ignore_ascii_case(&path.attribute))
+}
+
+/// Returns the type the schemas declare for the attribute a path targets. Paths without a URN use the first schema.
+pub(crate) fn attribute_type(schemas: &[Schema], path: &PatchPath) -> Option<String> {
+    let attribute = find_attribute(schemas, path)?;
+    let mut sub_attributes = attribute.sub_attributes.iter().flatten();
+    if let Some((key, _)) = &path.filter {
+        if !attribute.multi_valued || !sub_attributes.clone().any(|sub_attribute| sub_attribute.name.eq_ignore_ascii_case(key)) {
+            return None;
+        }
+    }
+    match &path.sub_attribute {
+        Some(name) => sub_attributes
+            .find(|sub_attribute| sub_attribute.name.eq_ignore_ascii_case(name))
+            .map(|sub_attribute| sub_attribute.type_.clone()),
+        None if path.filter.is_some() => Some("complex".to_string()),
+        None => Some(attribute.type_.clone()),
+    }
+}
+
 impl TryFrom<&str> for Schema {
     type Error = SCIMError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        serde_json::from_str(value).map_err(SCIMError::DeserializationError)
+        from_json(value, &["schema"], ParseMode::Lenient)
     }
 }
 
@@ -276,6 +316,7 @@ impl Schema {
     /// use scim_v2::models::scim_schema::{Schema, Attributes, Meta};
     ///
     ///     let user = Schema {
+    ///     schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:Schema".to_string()],
     ///     id: "urn:ietf:params:scim:schemas:core:2.0:User".to_string(),
     ///     name: "User".to_string(),
     ///     description: "User Account".to_string(),
@@ -302,6 +343,7 @@ impl Schema {
     ///     version: None,
     ///     location: Some("/v2/Schemas/urn:ietf:params:scim:schemas:core:2.0:User".to_string()),
     /// },
+    ///     additional_attributes: Default::default(),
     /// };
     ///
     /// match user.serialize() {
@@ -437,7 +479,35 @@ impl Schema {
     /// }
     /// ```
     pub fn deserialize(json: &str) -> Result<Self, SCIMError> {
-        serde_json::from_str(json).map_err(SCIMError::DeserializationError)
+        from_json(json, &["schema"], ParseMode::Lenient)
+    }
+
+    /// Deserializes a JSON string into a `Schema` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the Schema schema does not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `Schema`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::scim_schema::Schema;
+    /// use scim_v2::utils::parse::ParseMode;
+    ///
+    /// let json = r#"{"id": "urn:example:params:scim:schemas:core:2.0:Device", "name": "Device", "description": "Device", "attributes": [], "version": "2"}"#;
+    ///
+    /// let parsed = Schema::deserialize_with_mode(json, ParseMode::Lenient).unwrap();
+    /// assert!(parsed.additional_attributes.contains_key("version"));
+    ///
+    /// let error = Schema::deserialize_with_mode(json, ParseMode::Strict).unwrap_err();
+    /// assert_eq!(error.to_string(), "Invalid syntax: unknown attribute: version");
+    /// ```
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["schema"], mode)
     }
 }
 
@@ -1,10 +1,17 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 
 use crate::models::scim_schema::Meta;
 use crate::utils::error::SCIMError;
+use crate::utils::parse::{from_json, ParseMode};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ServiceProviderConfig {
+    #[serde(default = "default_schemas")]
+    pub schemas: Vec<String>,
     #[serde(rename = "documentationUri", skip_serializing_if = "Option::is_none")]
     pub documentation_uri: Option<String>,
     pub patch: Supported,
@@ -17,12 +24,22 @@ pub struct ServiceProviderConfig {
     #[serde(rename = "authenticationSchemes")]
     pub authentication_schemes: Vec<AuthenticationScheme>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<Pagination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub meta: Option<Meta>,
+    // Attributes not defined by the schemas, kept when parsing with ParseMode::Lenient.
+    #[serde(flatten)]
+    pub additional_attributes: Map<String, Value>,
+}
+
+fn default_schemas() -> Vec<String> {
+    vec!["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig".to_string()]
 }
 
 impl Default for ServiceProviderConfig {
     fn default() -> Self {
         ServiceProviderConfig {
+            schemas: default_schemas(),
             documentation_uri: None,
             patch: Supported { supported: false },
             bulk: Bulk {
@@ -38,7 +55,9 @@ impl Default for ServiceProviderConfig {
             sort: Supported { supported: false },
             etag: Supported { supported: false },
             authentication_schemes: vec![],
+            pagination: None,
             meta: None,
+            additional_attributes: Map::new(),
         }
     }
 }
@@ -70,6 +89,35 @@ impl Default for AuthenticationScheme {
     }
 }
 
+/// The authentication scheme types defined in RFC 7643 section 5.
+pub const AUTHENTICATION_SCHEME_TYPES: [&str; 5] = ["oauth", "oauth2", "oauthbearertoken", "httpbasic", "httpdigest"];
+
+impl AuthenticationScheme {
+    /// Returns the OAuth 2.0 Bearer Token scheme (RFC 6750).
+    pub fn oauth_bearer_token() -> Self {
+        AuthenticationScheme {
+            name: "OAuth Bearer Token".to_string(),
+            type_: "oauthbearertoken".to_string(),
+            description: "Authentication scheme using the OAuth Bearer Token Standard".to_string(),
+            spec_uri: "https://www.rfc-editor.org/info/rfc6750".to_string(),
+            documentation_uri: None,
+            primary: None,
+        }
+    }
+
+    /// Returns the HTTP Basic scheme (RFC 7617).
+    pub fn http_basic() -> Self {
+        AuthenticationScheme {
+            name: "HTTP Basic".to_string(),
+            type_: "httpbasic".to_string(),
+            description: "Authentication scheme using the HTTP Basic Standard".to_string(),
+            spec_uri: "https://www.rfc-editor.org/info/rfc7617".to_string(),
+            documentation_uri: None,
+            primary: None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Filter {
     pub supported: bool,
@@ -105,12 +153,158 @@ impl Default for Bulk {
     }
 }
 
+/// The pagination methods a service provider supports, as defined by RFC 9865.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pagination {
+    pub cursor: bool,
+    pub index: bool,
+    #[serde(rename = "defaultPaginationMethod", skip_serializing_if = "Option::is_none")]
+    pub default_pagination_method: Option<String>,
+    #[serde(rename = "defaultPageSize", skip_serializing_if = "Option::is_none")]
+    pub default_page_size: Option<i64>,
+    #[serde(rename = "maxPageSize", skip_serializing_if = "Option::is_none")]
+    pub max_page_size: Option<i64>,
+    #[serde(rename = "cursorTimeout", skip_serializing_if = "Option::is_none")]
+    pub cursor_timeout: Option<i64>,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Pagination {
+            cursor: false,
+            index: true,
+            default_pagination_method: None,
+            default_page_size: None,
+            max_page_size: None,
+            cursor_timeout: None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[derive(Default)]
 pub struct Supported {
     pub supported: bool,
 }
 
+/// An optional feature a service provider advertises in its `ServiceProviderConfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Patch,
+    Bulk,
+    Filter,
+    ChangePassword,
+    Sort,
+    Etag,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Patch => write!(f, "patch"),
+            Capability::Bulk => write!(f, "bulk"),
+            Capability::Filter => write!(f, "filter"),
+            Capability::ChangePassword => write!(f, "changePassword"),
+            Capability::Sort => write!(f, "sort"),
+            Capability::Etag => write!(f, "etag"),
+        }
+    }
+}
+
+/// How a client should modify an existing resource on a given service provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateStrategy {
+    /// Send a `PatchOp` with `PATCH`.
+    Patch,
+    /// Fetch the resource with `GET`, modify it locally and send it back with `PUT`.
+    GetAndReplace,
+}
+
+
+/// A fluent builder for `ServiceProviderConfig`, created with `ServiceProviderConfig::builder()`.
+#[derive(Debug)]
+pub struct ServiceProviderConfigBuilder {
+    config: ServiceProviderConfig,
+}
+
+impl ServiceProviderConfigBuilder {
+    /// Sets the URI of the service provider's human-readable help documentation.
+    pub fn documentation_uri(mut self, uri: &str) -> Self {
+        self.config.documentation_uri = Some(uri.to_string());
+        self
+    }
+
+    /// Sets whether PATCH is supported.
+    pub fn patch(mut self, supported: bool) -> Self {
+        self.config.patch.supported = supported;
+        self
+    }
+
+    /// Enables bulk operations with the given limits.
+    pub fn bulk(mut self, max_operations: i64, max_payload_size: i64) -> Self {
+        self.config.bulk = Bulk {
+            supported: true,
+            max_operations,
+            max_payload_size,
+        };
+        self
+    }
+
+    /// Enables filtering with the given maximum number of results.
+    pub fn filter(mut self, max_results: i64) -> Self {
+        self.config.filter = Filter {
+            supported: true,
+            max_results,
+        };
+        self
+    }
+
+    /// Sets whether changing a password is supported.
+    pub fn change_password(mut self, supported: bool) -> Self {
+        self.config.change_password.supported = supported;
+        self
+    }
+
+    /// Sets whether sorting is supported.
+    pub fn sort(mut self, supported: bool) -> Self {
+        self.config.sort.supported = supported;
+        self
+    }
+
+    /// Sets whether ETags are supported.
+    pub fn etag(mut self, supported: bool) -> Self {
+        self.config.etag.supported = supported;
+        self
+    }
+
+    /// Adds an authentication scheme, such as `AuthenticationScheme::oauth_bearer_token()`.
+    pub fn authentication_scheme(mut self, scheme: AuthenticationScheme) -> Self {
+        self.config.authentication_schemes.push(scheme);
+        self
+    }
+
+    /// Sets the pagination methods the service provider supports.
+    pub fn pagination(mut self, pagination: Pagination) -> Self {
+        self.config.pagination = Some(pagination);
+        self
+    }
+
+    /// Sets the `meta` attribute.
+    pub fn meta(mut self, meta: Meta) -> Self {
+        self.config.meta = Some(meta);
+        self
+    }
+
+    /// Validates and returns the `ServiceProviderConfig`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `ServiceProviderConfig::validate` if the configuration is inconsistent.
+    pub fn build(self) -> Result<ServiceProviderConfig, SCIMError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
 
 /// Converts a JSON string into a `ServiceProviderConfig` struct.
 ///
@@ -167,23 +361,22 @@ impl TryFrom<&str> for ServiceProviderConfig {
     type Error = SCIMError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        serde_json::from_str(value).map_err(SCIMError::DeserializationError)
+        from_json(value, &["service_provider_config"], ParseMode::Lenient)
     }
 }
 
 impl ServiceProviderConfig {
     /// Validates a service provider config.
     ///
-    /// This function checks if the service provider config has `patch`, `bulk`, `filter`, `change_password`, `sort`, and `etag`. If any of these fields are missing, it returns an error.
-    ///
-    /// # Arguments
-    ///
-    /// * `config` - A reference to a ServiceProviderConfig instance.
+    /// Unsupported features are legal, so this function checks that the advertised values are consistent:
+    /// bulk limits must be positive when bulk is supported, `filter.maxResults` must be positive when filtering is
+    /// supported, and every authentication scheme needs a name, a description and a `type` from RFC 7643 section 5.
     ///
     /// # Returns
     ///
     /// * `Ok(())` - If the service provider config is valid.
-    /// * `Err(SCIMError::MissingRequiredField)` - If a required field is missing.
+    /// * `Err(SCIMError::MissingRequiredField)` - If an authentication scheme is missing its name or description.
+    /// * `Err(SCIMError::InvalidFieldValue)` - If a limit or an authentication scheme type is invalid.
     ///
     /// # Example
     ///
@@ -202,27 +395,73 @@ impl ServiceProviderConfig {
     /// }
     /// ```
     pub fn validate(&self) -> Result<(), SCIMError> {
-        if !self.patch.supported {
-            return Err(SCIMError::MissingRequiredField("patch".to_string()));
+        if self.schemas.is_empty() {
+            return Err(SCIMError::MissingRequiredField("schemas".to_string()));
         }
-        if !self.bulk.supported {
-            return Err(SCIMError::MissingRequiredField("bulk".to_string()));
+        if self.bulk.supported && self.bulk.max_operations <= 0 {
+            return Err(SCIMError::InvalidFieldValue("bulk.maxOperations must be positive when bulk is supported".to_string()));
         }
-        if !self.filter.supported {
-            return Err(SCIMError::MissingRequiredField("filter".to_string()));
+        if self.bulk.supported && self.bulk.max_payload_size <= 0 {
+            return Err(SCIMError::InvalidFieldValue("bulk.maxPayloadSize must be positive when bulk is supported".to_string()));
         }
-        if !self.change_password.supported {
-            return Err(SCIMError::MissingRequiredField("change_password".to_string()));
+        if self.filter.supported && self.filter.max_results <= 0 {
+            return Err(SCIMError::InvalidFieldValue("filter.maxResults must be positive when filter is supported".to_string()));
         }
-        if !self.sort.supported {
-            return Err(SCIMError::MissingRequiredField("sort".to_string()));
+        for scheme in &self.authentication_schemes {
+            if scheme.name.is_empty() {
+                return Err(SCIMError::MissingRequiredField("authenticationSchemes.name".to_string()));
+            }
+            if scheme.description.is_empty() {
+                return Err(SCIMError::MissingRequiredField("authenticationSchemes.description".to_string()));
+            }
+            if !AUTHENTICATION_SCHEME_TYPES.contains(&scheme.type_.as_str()) {
+                return Err(SCIMError::InvalidFieldValue(format!("authenticationSchemes.type: {}", scheme.type_)));
+            }
         }
-        if !self.etag.supported {
-            return Err(SCIMError::MissingRequiredField("etag".to_string()));
+        if self.authentication_schemes.iter().filter(|scheme| scheme.primary == Some(true)).count() > 1 {
+            return Err(SCIMError::InvalidFieldValue("authenticationSchemes may have at most one primary scheme".to_string()));
+        }
+        if let Some(pagination) = &self.pagination {
+            match pagination.default_pagination_method.as_deref() {
+                None => {}
+                Some("cursor") if pagination.cursor => {}
+                Some("index") if pagination.index => {}
+                Some(method) => return Err(SCIMError::InvalidFieldValue(format!("pagination.defaultPaginationMethod: {}", method))),
+            }
+            for (name, value) in [("defaultPageSize", pagination.default_page_size), ("maxPageSize", pagination.max_page_size), ("cursorTimeout", pagination.cursor_timeout)] {
+                if matches!(value, Some(value) if value <= 0) {
+                    return Err(SCIMError::InvalidFieldValue(format!("pagination.{} must be positive", name)));
+                }
+            }
         }
         Ok(())
     }
 
+    /// Returns a builder for a `ServiceProviderConfig` with every feature unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::service_provider_config::{AuthenticationScheme, ServiceProviderConfig};
+    ///
+    /// let config = ServiceProviderConfig::builder()
+    ///     .patch(true)
+    ///     .filter(200)
+    ///     .bulk(1000, 1048576)
+    ///     .authentication_scheme(AuthenticationScheme::oauth_bearer_token())
+    ///     .build();
+    ///
+    /// match config {
+    ///     Ok(config) => println!("ServiceProviderConfig: {:?}", config),
+    ///     Err(e) => println!("ServiceProviderConfig is invalid: {}", e),
+    /// }
+    /// ```
+    pub fn builder() -> ServiceProviderConfigBuilder {
+        ServiceProviderConfigBuilder {
+            config: ServiceProviderConfig::default(),
+        }
+    }
+
     /// Serializes the `ServiceProviderConfig` instance to a JSON string, using the custom SCIMError for error handling.
     ///
     /// # Returns
@@ -304,7 +543,191 @@ impl ServiceProviderConfig {
     /// }
     /// ```
     pub fn deserialize(json: &str) -> Result<Self, SCIMError> {
-        serde_json::from_str(json).map_err(SCIMError::DeserializationError)
+        from_json(json, &["service_provider_config"], ParseMode::Lenient)
+    }
+
+    /// Deserializes a JSON string into a `ServiceProviderConfig` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the Service Provider Configuration schema does not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `ServiceProviderConfig`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::service_provider_config::ServiceProviderConfig;
+    /// use scim_v2::utils::parse::ParseMode;
+    ///
+    /// let json = r#"{"patch": {"supported": true}, "bulk": {"supported": false, "maxOperations": 0, "maxPayloadSize": 0}, "filter": {"supported": false, "maxResults": 0}, "changePassword": {"supported": false}, "sort": {"supported": false}, "etag": {"supported": false}, "authenticationSchemes": [], "xmlDataFormat": {"supported": false}}"#;
+    ///
+    /// let parsed = ServiceProviderConfig::deserialize_with_mode(json, ParseMode::Lenient).unwrap();
+    /// assert!(parsed.additional_attributes.contains_key("xmlDataFormat"));
+    ///
+    /// let error = ServiceProviderConfig::deserialize_with_mode(json, ParseMode::Strict).unwrap_err();
+    /// assert_eq!(error.to_string(), "Invalid syntax: unknown attribute: xmlDataFormat");
+    /// ```
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["service_provider_config"], mode)
+    }
+
+    /// Returns whether the service provider advertises the given capability.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::service_provider_config::{Capability, ServiceProviderConfig, Supported};
+    ///
+    /// let config = ServiceProviderConfig {
+    ///     patch: Supported { supported: true },
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert!(config.supports(Capability::Patch));
+    /// assert!(!config.supports(Capability::Bulk));
+    /// ```
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Patch => self.patch.supported,
+            Capability::Bulk => self.bulk.supported,
+            Capability::Filter => self.filter.supported,
+            Capability::ChangePassword => self.change_password.supported,
+            Capability::Sort => self.sort.supported,
+            Capability::Etag => self.etag.supported,
+        }
+    }
+
+    /// Checks that the service provider advertises the given capability.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the capability is supported.
+    /// * `Err(SCIMError::UnsupportedCapability)` - If the service provider does not support it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::service_provider_config::{Capability, ServiceProviderConfig};
+    ///
+    /// let config = ServiceProviderConfig::default();
+    ///
+    /// match config.require(Capability::Sort) {
+    ///     Ok(_) => println!("Sorting is available."),
+    ///     Err(e) => println!("Cannot sort: {}", e),
+    /// }
+    /// ```
+    pub fn require(&self, capability: Capability) -> Result<(), SCIMError> {
+        if !self.supports(capability) {
+            return Err(SCIMError::UnsupportedCapability(capability.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns how a client should update resources on this service provider.
+    ///
+    /// Providers that do not support PATCH still accept replacing the whole resource,
+    /// so the client falls back to `GET` followed by `PUT`.
+    pub fn update_strategy(&self) -> UpdateStrategy {
+        if self.patch.supported {
+            UpdateStrategy::Patch
+        } else {
+            UpdateStrategy::GetAndReplace
+        }
+    }
+
+    /// Caps a requested page size at the provider's `filter.maxResults`.
+    ///
+    /// The requested size is returned unchanged when filtering is not supported or no positive limit is advertised.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::service_provider_config::{Filter, ServiceProviderConfig};
+    ///
+    /// let config = ServiceProviderConfig {
+    ///     filter: Filter { supported: true, max_results: 200 },
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(config.cap_page_size(1000), 200);
+    /// assert_eq!(config.cap_page_size(50), 50);
+    /// ```
+    pub fn cap_page_size(&self, count: i64) -> i64 {
+        if self.filter.supported && self.filter.max_results > 0 {
+            count.min(self.filter.max_results)
+        } else {
+            count
+        }
+    }
+
+    /// Splits bulk operations into batches that respect `bulk.maxOperations` and `bulk.maxPayloadSize`.
+    ///
+    /// The payload size of each batch is measured on the serialized `BulkRequest` message that would carry it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Vec<T>>)` - The operations, in their original order, grouped into batches.
+    /// * `Err(SCIMError::UnsupportedCapability)` - If the service provider does not support bulk.
+    /// * `Err(SCIMError::RequestError)` - If a single operation is larger than `bulk.maxPayloadSize`.
+    /// * `Err(SCIMError::SerializationError)` - If an operation cannot be serialized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::service_provider_config::{Bulk, ServiceProviderConfig};
+    /// use serde_json::{json, Map, Value};
+    ///
+    /// let config = ServiceProviderConfig {
+    ///     bulk: Bulk { supported: true, max_operations: 2, max_payload_size: 1048576 },
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let operations = vec![
+    ///     json!({"method": "DELETE", "path": "/Users/1"}),
+    ///     json!({"method": "DELETE", "path": "/Users/2"}),
+    ///     json!({"method": "DELETE", "path": "/Users/3"}),
+    /// ];
+    /// let batches = config.split_bulk_operations(operations).unwrap();
+    /// assert_eq!(batches.len(), 2);
+    /// ```
+    pub fn split_bulk_operations<T: Serialize>(&self, operations: Vec<T>) -> Result<Vec<Vec<T>>, SCIMError> {
+        self.require(Capability::Bulk)?;
+        let max_operations = if self.bulk.max_operations > 0 { self.bulk.max_operations as usize } else { usize::MAX };
+        let max_payload_size = if self.bulk.max_payload_size > 0 { self.bulk.max_payload_size as usize } else { usize::MAX };
+        let envelope = json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:BulkRequest"],
+            "Operations": [],
+        });
+        let envelope_size = serde_json::to_string(&envelope).map_err(SCIMError::SerializationError)?.len();
+
+        let mut batches: Vec<Vec<T>> = Vec::new();
+        let mut batch: Vec<T> = Vec::new();
+        let mut batch_size = envelope_size;
+        for operation in operations {
+            let operation_size = serde_json::to_string(&operation).map_err(SCIMError::SerializationError)?.len();
+            if envelope_size + operation_size > max_payload_size {
+                return Err(SCIMError::RequestError(format!(
+                    "bulk operation of {} bytes exceeds maxPayloadSize of {} bytes",
+                    operation_size, max_payload_size
+                )));
+            }
+            // Operations after the first in a batch are preceded by a comma.
+            let added_size = if batch.is_empty() { operation_size } else { operation_size + 1 };
+            if !batch.is_empty() && (batch.len() >= max_operations || batch_size + added_size > max_payload_size) {
+                batches.push(std::mem::take(&mut batch));
+                batch_size = envelope_size + operation_size;
+            } else {
+                batch_size += added_size;
+            }
+            batch.push(operation);
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        Ok(batches)
     }
 }
 
@@ -383,4 +806,155 @@ mod tests {
         assert_eq!(http_scheme.documentation_uri, Some("http://example.com/help/httpBasic.html".to_string()));
         assert_eq!(http_scheme.type_, "httpbasic");
     }
+
+    #[test]
+    fn validate_accepts_unsupported_features() {
+        let config = ServiceProviderConfig::default();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_limits_and_scheme_types() {
+        let config = ServiceProviderConfig {
+            bulk: Bulk { supported: true, max_operations: 0, max_payload_size: 1048576 },
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(SCIMError::InvalidFieldValue(_))));
+
+        let config = ServiceProviderConfig {
+            filter: Filter { supported: true, max_results: 0 },
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(SCIMError::InvalidFieldValue(_))));
+
+        let config = ServiceProviderConfig {
+            authentication_schemes: vec![AuthenticationScheme {
+                type_: "kerberos".to_string(),
+                ..AuthenticationScheme::http_basic()
+            }],
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn builder_serializes_schemas_and_canned_schemes() {
+        let config = ServiceProviderConfig::builder()
+            .patch(true)
+            .filter(200)
+            .etag(true)
+            .authentication_scheme(AuthenticationScheme::oauth_bearer_token())
+            .authentication_scheme(AuthenticationScheme::http_basic())
+            .build()
+            .unwrap();
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["schemas"], json!(["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"]));
+        assert_eq!(json["patch"], json!({"supported": true}));
+        assert_eq!(json["filter"], json!({"supported": true, "maxResults": 200}));
+        assert_eq!(json["bulk"]["supported"], json!(false));
+        assert_eq!(json["authenticationSchemes"][0]["type"], json!("oauthbearertoken"));
+        assert_eq!(json["authenticationSchemes"][1]["type"], json!("httpbasic"));
+    }
+
+    #[test]
+    fn pagination_round_trips_and_validates_default_method() {
+        let json_data = r#"{
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": 200 },
+            "changePassword": { "supported": false },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [],
+            "pagination": {
+                "cursor": true,
+                "index": true,
+                "defaultPaginationMethod": "cursor",
+                "defaultPageSize": 100,
+                "maxPageSize": 250,
+                "cursorTimeout": 3600
+            }
+        }"#;
+
+        let mut config = ServiceProviderConfig::deserialize(json_data).unwrap();
+        let pagination = config.pagination.as_ref().unwrap();
+        assert!(pagination.cursor);
+        assert_eq!(pagination.max_page_size, Some(250));
+        assert!(config.validate().is_ok());
+
+        config.pagination.as_mut().unwrap().cursor = false;
+        assert!(matches!(config.validate(), Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn builder_fails_for_invalid_limits() {
+        let result = ServiceProviderConfig::builder().bulk(-1, 1048576).build();
+        assert!(matches!(result, Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn require_returns_unsupported_capability_error() {
+        let config = ServiceProviderConfig {
+            patch: Supported { supported: true },
+            ..Default::default()
+        };
+
+        assert!(config.require(Capability::Patch).is_ok());
+        match config.require(Capability::Etag) {
+            Err(SCIMError::UnsupportedCapability(capability)) => assert_eq!(capability, "etag"),
+            other => panic!("Expected UnsupportedCapability, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_strategy_falls_back_to_replace_without_patch() {
+        let mut config = ServiceProviderConfig::default();
+        assert_eq!(config.update_strategy(), UpdateStrategy::GetAndReplace);
+
+        config.patch.supported = true;
+        assert_eq!(config.update_strategy(), UpdateStrategy::Patch);
+    }
+
+    #[test]
+    fn cap_page_size_ignores_limit_when_filter_unsupported() {
+        let config = ServiceProviderConfig {
+            filter: Filter { supported: false, max_results: 10 },
+            ..Default::default()
+        };
+
+        assert_eq!(config.cap_page_size(500), 500);
+    }
+
+    #[test]
+    fn split_bulk_operations_respects_payload_size() {
+        let operation = json!({"method": "DELETE", "path": "/Users/1"});
+        let operation_size = serde_json::to_string(&operation).unwrap().len();
+        let envelope_size = r#"{"Operations":[],"schemas":["urn:ietf:params:scim:api:messages:2.0:BulkRequest"]}"#.len();
+        let config = ServiceProviderConfig {
+            bulk: Bulk {
+                supported: true,
+                max_operations: 100,
+                // Room for exactly two operations and the comma between them.
+                max_payload_size: (envelope_size + operation_size * 2 + 1) as i64,
+            },
+            ..Default::default()
+        };
+
+        let batches = config.split_bulk_operations(vec![operation.clone(); 5]).unwrap();
+        let sizes: Vec<usize> = batches.iter().map(|batch| batch.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn split_bulk_operations_fails_when_bulk_unsupported_or_operation_too_large() {
+        let config = ServiceProviderConfig::default();
+        assert!(matches!(config.split_bulk_operations(vec![json!({})]), Err(SCIMError::UnsupportedCapability(_))));
+
+        let config = ServiceProviderConfig {
+            bulk: Bulk { supported: true, max_operations: 10, max_payload_size: 10 },
+            ..Default::default()
+        };
+        assert!(matches!(config.split_bulk_operations(vec![json!({"method": "DELETE"})]), Err(SCIMError::RequestError(_))));
+    }
 }
\ No newline at end of file
@@ -1,17 +1,43 @@
 use std::convert::TryFrom;
+use std::fmt;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
 
-use crate::models::enterprise_user::EnterpriseUser;
-use crate::models::scim_schema::Meta;
+use crate::models::enterprise_user::{EnterpriseUser, ENTERPRISE_USER_URN};
+use crate::models::resource_types::{extension_urns, ResourceType};
+use crate::models::scim_schema::{get_schemas, Meta};
 use crate::utils::error::SCIMError;
+use crate::utils::parse::{from_json, without_server_attributes, ParseMode};
+use crate::utils::redact::{redact_json, truncate_binary, Sensitive};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A User resource, as returned by a service provider.
+///
+/// The client-supplied attributes are kept in `body`, which is serialized inline; `id`, `groups` and `meta` are
+/// assigned by the service provider.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct User {
-    // urn:ietf:params:scim:schemas:core:2.0:User
-    pub schemas: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
+    #[serde(flatten)]
+    pub body: UserRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<Group>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+/// The body of a request that creates or replaces a user.
+///
+/// Server-assigned attributes (`id` and `meta`) and the readOnly `groups` are not part of a request; if a client sends
+/// them they are ignored. Use `into_stored` to turn a create request into a `StoredUser`, and `StoredUser::replace` to
+/// apply a replace request.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserRequest {
+    // urn:ietf:params:scim:schemas:core:2.0:User
+    pub schemas: Vec<String>,
+    #[serde(rename = "externalId", skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
     #[serde(rename = "userName")]
     pub user_name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -47,25 +73,65 @@ pub struct User {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub photos: Option<Vec<Photo>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub groups: Option<Vec<Group>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub entitlements: Option<Vec<Entitlement>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub roles: Option<Vec<Role>>,
     #[serde(rename = "x509Certificates", skip_serializing_if = "Option::is_none")]
     pub x509_certificates: Option<Vec<X509Certificate>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<Meta>,
     #[serde(rename = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User", skip_serializing_if = "Option::is_none")]
     pub enterprise_user: Option<EnterpriseUser>,
+    // Attributes not defined by the schemas, kept when parsing with ParseMode::Lenient. Server-assigned attributes are dropped.
+    #[serde(flatten, deserialize_with = "without_read_only_attributes")]
+    pub additional_attributes: Map<String, Value>,
+}
+
+/// Formats the user request for debugging with `password` redacted.
+impl fmt::Debug for UserRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserRequest")
+            .field("schemas", &self.schemas)
+            .field("external_id", &self.external_id)
+            .field("user_name", &self.user_name)
+            .field("name", &self.name)
+            .field("display_name", &self.display_name)
+            .field("nick_name", &self.nick_name)
+            .field("profile_url", &self.profile_url)
+            .field("title", &self.title)
+            .field("user_type", &self.user_type)
+            .field("preferred_language", &self.preferred_language)
+            .field("locale", &self.locale)
+            .field("timezone", &self.timezone)
+            .field("active", &self.active)
+            .field("password", &self.password.as_ref().map(Sensitive))
+            .field("emails", &self.emails)
+            .field("addresses", &self.addresses)
+            .field("phone_numbers", &self.phone_numbers)
+            .field("ims", &self.ims)
+            .field("photos", &self.photos)
+            .field("entitlements", &self.entitlements)
+            .field("roles", &self.roles)
+            .field("x509_certificates", &self.x509_certificates)
+            .field("enterprise_user", &self.enterprise_user)
+            .field("additional_attributes", &self.additional_attributes)
+            .finish()
+    }
 }
 
-impl Default for User {
+/// The body of a `POST /Users` request.
+///
+/// An alias of `UserRequest`, as is `ReplaceUserRequest`: both requests carry the same attributes, and the aliases
+/// only name the request a value is used for.
+pub type CreateUserRequest = UserRequest;
+
+/// The body of a `PUT /Users/{id}` request. An alias of `UserRequest`, like `CreateUserRequest`.
+pub type ReplaceUserRequest = UserRequest;
+
+impl Default for UserRequest {
     fn default() -> Self {
-        User {
+        UserRequest {
             schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:User".to_string()],
             user_name: "".to_string(),
-            id: None,
+            external_id: None,
             name: None,
             display_name: None,
             nick_name: None,
@@ -82,17 +148,35 @@ impl Default for User {
             phone_numbers: None,
             ims: None,
             photos: None,
-            groups: None,
             entitlements: None,
             roles: None,
             x509_certificates: None,
-            meta: None,
             enterprise_user: None,
+            additional_attributes: Map::new(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A user as held by a service provider, with its server-assigned `id` and `meta` always present.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredUser {
+    pub id: String,
+    #[serde(flatten)]
+    pub user: UserRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<Group>>,
+    pub meta: Meta,
+}
+
+/// Deserializes the additional attributes of a `UserRequest`, dropping the server-assigned `id` and `meta` and the
+/// readOnly `groups`.
+fn without_read_only_attributes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Map<String, Value>, D::Error> {
+    let mut attributes = without_server_attributes(deserializer)?;
+    attributes.remove("groups");
+    Ok(attributes)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[derive(Default)]
 pub struct Name {
     pub formatted: Option<String>,
@@ -109,7 +193,7 @@ pub struct Name {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[derive(Default)]
 pub struct Email {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -123,7 +207,7 @@ pub struct Email {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[derive(Default)]
 pub struct Address {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -140,10 +224,12 @@ pub struct Address {
     pub country: Option<String>,
     #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     pub type_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary: Option<bool>,
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[derive(Default)]
 pub struct PhoneNumber {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -157,7 +243,7 @@ pub struct PhoneNumber {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[derive(Default)]
 pub struct Im {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -171,7 +257,7 @@ pub struct Im {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[derive(Default)]
 pub struct Photo {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -185,7 +271,7 @@ pub struct Photo {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[derive(Default)]
 pub struct Group {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -194,12 +280,12 @@ pub struct Group {
     pub ref_: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub display: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     pub type_: Option<String>,
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[derive(Default)]
 pub struct Entitlement {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -213,7 +299,7 @@ pub struct Entitlement {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[derive(Default)]
 pub struct Role {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -227,7 +313,7 @@ pub struct Role {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 #[derive(Default)]
 pub struct X509Certificate {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -240,6 +326,145 @@ pub struct X509Certificate {
     pub primary: Option<bool>,
 }
 
+/// Formats the certificate for debugging with its base64 value truncated.
+impl fmt::Debug for X509Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("X509Certificate")
+            .field("value", &self.value.as_deref().map(truncate_binary))
+            .field("display", &self.display)
+            .field("type_", &self.type_)
+            .field("primary", &self.primary)
+            .finish()
+    }
+}
+
+/// What `normalize_multi_valued` does when a multi-valued attribute has more than one `primary` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrimaryPolicy {
+    /// Keep the first primary value and set `primary` to false on the others.
+    #[default]
+    KeepFirst,
+    /// Return `SCIMError::InvalidFieldValue`.
+    Reject,
+}
+
+/// A value of a multi-valued attribute that can be marked `primary`.
+trait MultiValued: PartialEq {
+    fn primary(&self) -> Option<bool>;
+    fn set_primary(&mut self, primary: Option<bool>);
+}
+
+macro_rules! impl_multi_valued {
+    ($($type_:ty),*) => {
+        $(impl MultiValued for $type_ {
+            fn primary(&self) -> Option<bool> {
+                self.primary
+            }
+
+            fn set_primary(&mut self, primary: Option<bool>) {
+                self.primary = primary;
+            }
+        })*
+    };
+}
+
+impl_multi_valued!(Email, Address, PhoneNumber, Im, Photo, Entitlement, Role, X509Certificate);
+
+/// The values of a multi-valued attribute whose type can be marked `primary`.
+trait PrimaryValues {
+    /// Returns how many values are primary, counting exact duplicates once when `distinct` is true.
+    fn primary_count(&self, distinct: bool) -> usize;
+    /// Removes exact duplicates (RFC 7644 §3.5.2.1), keeping the first occurrence.
+    fn remove_duplicates(&mut self);
+    /// Sets `primary` to false on every primary value after the first.
+    fn keep_first_primary(&mut self);
+}
+
+impl<T: MultiValued> PrimaryValues for Option<Vec<T>> {
+    fn primary_count(&self, distinct: bool) -> usize {
+        let mut primaries: Vec<&T> = Vec::new();
+        for value in self.iter().flatten().filter(|value| value.primary() == Some(true)) {
+            if !distinct || !primaries.contains(&value) {
+                primaries.push(value);
+            }
+        }
+        primaries.len()
+    }
+
+    fn remove_duplicates(&mut self) {
+        if let Some(list) = self {
+            let mut unique: Vec<T> = Vec::with_capacity(list.len());
+            for value in list.drain(..) {
+                if !unique.contains(&value) {
+                    unique.push(value);
+                }
+            }
+            *list = unique;
+        }
+    }
+
+    fn keep_first_primary(&mut self) {
+        let mut seen_primary = false;
+        for value in self.iter_mut().flatten().filter(|value| value.primary() == Some(true)) {
+            if seen_primary {
+                value.set_primary(Some(false));
+            }
+            seen_primary = true;
+        }
+    }
+}
+
+/// Checks that at most one value of a multi-valued attribute is primary (RFC 7643 §2.4).
+fn check_primary(attribute: &str, primaries: usize) -> Result<(), SCIMError> {
+    if primaries > 1 {
+        return Err(SCIMError::InvalidFieldValue(format!("{} has {} primary values", attribute, primaries)));
+    }
+    Ok(())
+}
+
+/// Lists the multi-valued attributes of `UserRequest` that can have a primary value, with their names in the schema.
+macro_rules! primary_attributes {
+    ($($name:literal => $field:ident),*) => {
+        impl UserRequest {
+            fn primary_attributes(&self) -> Vec<(&'static str, &dyn PrimaryValues)> {
+                vec![$(($name, &self.$field)),*]
+            }
+
+            fn primary_attributes_mut(&mut self) -> Vec<(&'static str, &mut dyn PrimaryValues)> {
+                vec![$(($name, &mut self.$field)),*]
+            }
+        }
+    };
+}
+
+primary_attributes!(
+    "emails" => emails,
+    "addresses" => addresses,
+    "phoneNumbers" => phone_numbers,
+    "ims" => ims,
+    "photos" => photos,
+    "entitlements" => entitlements,
+    "roles" => roles,
+    "x509Certificates" => x509_certificates
+);
+
+/// Checks that `schemas` lists the Enterprise User extension exactly when the user has enterprise data.
+fn check_enterprise_schema(schemas: &[String], enterprise_user: &Option<EnterpriseUser>) -> Result<(), SCIMError> {
+    let listed = schemas.iter().any(|schema| schema.eq_ignore_ascii_case(ENTERPRISE_USER_URN));
+    match (listed, enterprise_user.is_some()) {
+        (false, true) => Err(SCIMError::InvalidFieldValue(format!("schemas does not list the extension {}", ENTERPRISE_USER_URN))),
+        (true, false) => Err(SCIMError::InvalidFieldValue(format!("schemas lists {} but the resource has no data for it", ENTERPRISE_USER_URN))),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the URNs of the extensions a user carries: the Enterprise User extension and any kept in
+/// `additional_attributes`.
+fn user_extension_urns<'a>(enterprise_user: &Option<EnterpriseUser>, additional_attributes: &'a Map<String, Value>) -> Vec<&'a str> {
+    enterprise_user.as_ref().map(|_| ENTERPRISE_USER_URN).into_iter()
+        .chain(extension_urns(additional_attributes))
+        .collect()
+}
 
 /// Converts a JSON string into a `User` struct.
 ///
@@ -266,7 +491,7 @@ impl TryFrom<&str> for User {
     type Error = SCIMError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        serde_json::from_str(value).map_err(SCIMError::DeserializationError)
+        from_json(value, &["user", "enterprise_user"], ParseMode::Lenient)
     }
 }
 
@@ -274,7 +499,8 @@ impl User {
     /// Validates a user.
     ///
     /// This function checks if the user has a `name` and `user_name`. If either is missing, it returns an error.
-    /// It also checks if the `emails` field is present and if each email in the vector is in a valid email format.
+    /// It also checks if the `emails` field is present and if each email in the vector is in a valid email format, and
+    /// that no multi-valued attribute has more than one `primary` value.
     ///
     /// # Arguments
     ///
@@ -289,11 +515,14 @@ impl User {
     /// # Example
     ///
     /// ```rust
-    /// use scim_v2::models::user::User;
+    /// use scim_v2::models::user::{User, UserRequest};
     ///
     /// let user = User {
-    ///     user_name: "jdoe@example.com".to_string(),
-    ///     // other fields...
+    ///     body: UserRequest {
+    ///         user_name: "jdoe@example.com".to_string(),
+    ///         // other fields...
+    ///         ..Default::default()
+    ///     },
     ///     ..Default::default()
     /// };
     ///
@@ -307,14 +536,85 @@ impl User {
     ///
     /// The actual validation requirements will depend on the specifics of your application and the SCIM (System for Cross-domain Identity Management) protocol you are implementing.
     pub fn validate(&self) -> Result<(), SCIMError> {
-        // Pretty much every field is optional in the schema except for 2. We'll check for those here.
-        if self.schemas.is_empty() {
-            return Err(SCIMError::MissingRequiredField("schemas".to_string()));
-        }
-        if self.user_name.is_empty() {
-            return Err(SCIMError::MissingRequiredField("user_name".to_string()));
-        }
-        Ok(())
+        self.body.validate()
+    }
+
+    /// Normalises the multi-valued attributes of the user.
+    ///
+    /// Exact duplicate values are removed from `emails`, `addresses`, `phoneNumbers`, `ims`, `photos`, `entitlements`,
+    /// `roles` and `x509Certificates`, as RFC 7644 requires when values are added. Then, if an attribute still has more
+    /// than one `primary` value, `policy` decides whether the first one is kept or the user is rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidFieldValue` naming the attribute if it has more than one primary value and `policy`
+    /// is `PrimaryPolicy::Reject`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::user::{Email, PrimaryPolicy, User, UserRequest};
+    ///
+    /// let email = |value: &str| Email { value: Some(value.to_string()), primary: Some(true), ..Default::default() };
+    /// let mut user = User {
+    ///     body: UserRequest {
+    ///         user_name: "bjensen@example.com".to_string(),
+    ///         emails: Some(vec![email("bjensen@example.com"), email("bjensen@example.com"), email("babs@jensen.org")]),
+    ///         ..Default::default()
+    ///     },
+    ///     ..Default::default()
+    /// };
+    /// assert!(user.validate().is_err());
+    ///
+    /// user.normalize_multi_valued(PrimaryPolicy::KeepFirst).unwrap();
+    /// let emails = user.body.emails.as_ref().unwrap();
+    /// assert_eq!(emails.len(), 2);
+    /// assert_eq!(emails[1].primary, Some(false));
+    /// assert!(user.validate().is_ok());
+    /// ```
+    pub fn normalize_multi_valued(&mut self, policy: PrimaryPolicy) -> Result<(), SCIMError> {
+        self.body.normalize_multi_valued(policy)
+    }
+
+    /// Checks `schemas` against the resource type definition and the extensions the user carries.
+    ///
+    /// Every URN in `schemas` must be the core schema or a schema extension of `resource_type`, every extension with
+    /// data must be listed, and every extension that `resource_type` marks as required must be present. See
+    /// `ResourceType::validate_schemas` for the errors returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::enterprise_user::EnterpriseUser;
+    /// use scim_v2::models::resource_types::get_resource_types;
+    /// use scim_v2::models::user::{User, UserRequest};
+    ///
+    /// let user_type = get_resource_types(vec!["user", "enterprise_user"], "https://example.com/v2").unwrap().remove(0);
+    /// let mut user = User {
+    ///     body: UserRequest {
+    ///         user_name: "bjensen@example.com".to_string(),
+    ///         enterprise_user: Some(EnterpriseUser::default()),
+    ///         ..Default::default()
+    ///     },
+    ///     ..Default::default()
+    /// };
+    /// assert!(user.validate_schemas(&user_type).is_err());
+    ///
+    /// user.reconcile_schemas(&user_type);
+    /// assert_eq!(user.body.schemas, vec![
+    ///     "urn:ietf:params:scim:schemas:core:2.0:User",
+    ///     "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User",
+    /// ]);
+    /// assert!(user.validate_schemas(&user_type).is_ok());
+    /// ```
+    pub fn validate_schemas(&self, resource_type: &ResourceType) -> Result<(), SCIMError> {
+        self.body.validate_schemas(resource_type)
+    }
+
+    /// Rewrites `schemas` to the core schema of `resource_type` followed by the registered extensions the user
+    /// carries.
+    pub fn reconcile_schemas(&mut self, resource_type: &ResourceType) {
+        self.body.reconcile_schemas(resource_type);
     }
 
     /// Serializes the `User` instance to a JSON string, using the custom SCIMError for error handling.
@@ -328,12 +628,15 @@ impl User {
     /// # Examples
     ///
     /// ```
-    /// use scim_v2::models::user::User;
+    /// use scim_v2::models::user::{User, UserRequest};
     ///
     /// let user = User {
-    ///     schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:User".to_string()],
-    ///     user_name: "jdoe@example.com".to_string(),
-    ///     // Initialize other fields as necessary...
+    ///     body: UserRequest {
+    ///         schemas: vec!["urn:ietf:params:scim:schemas:core:2.0:User".to_string()],
+    ///         user_name: "jdoe@example.com".to_string(),
+    ///         // Initialize other fields as necessary...
+    ///         ..Default::default()
+    ///     },
     ///     ..Default::default()
     /// };
     ///
@@ -369,7 +672,283 @@ impl User {
     /// }
     /// ```
     pub fn deserialize(json: &str) -> Result<Self, SCIMError> {
-        serde_json::from_str(json).map_err(SCIMError::DeserializationError)
+        from_json(json, &["user", "enterprise_user"], ParseMode::Lenient)
+    }
+
+    /// Deserializes a JSON string into a `User` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the User and Enterprise User schemas do not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `User`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::user::User;
+    /// use scim_v2::utils::parse::ParseMode;
+    ///
+    /// let user_json = r#"{"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "jdoe@example.com", "emials": [{"value": "jdoe@example.com"}]}"#;
+    ///
+    /// let user = User::deserialize_with_mode(user_json, ParseMode::Lenient).unwrap();
+    /// assert!(user.body.additional_attributes.contains_key("emials"));
+    /// assert!(user.serialize().unwrap().contains("emials"));
+    ///
+    /// let error = User::deserialize_with_mode(user_json, ParseMode::Strict).unwrap_err();
+    /// assert_eq!(error.to_string(), "Invalid syntax: unknown attribute: emials");
+    /// ```
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["user", "enterprise_user"], mode)
+    }
+
+    /// Returns a redacted JSON copy of the user for structured logging.
+    ///
+    /// Attributes that the User and Enterprise User schemas mark `returned: never`, such as `password`, are replaced
+    /// with `[REDACTED]`, and certificate values are truncated. See `redact_json` for the details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::SerializationError` if the user cannot be serialized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scim_v2::models::user::{User, UserRequest};
+    ///
+    /// let user = User {
+    ///     body: UserRequest {
+    ///         user_name: "jdoe@example.com".to_string(),
+    ///         password: Some("t1meMa$heen".to_string()),
+    ///         ..Default::default()
+    ///     },
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let log = user.to_log_json().unwrap();
+    /// assert_eq!(log["password"], "[REDACTED]");
+    /// ```
+    pub fn to_log_json(&self) -> Result<Value, SCIMError> {
+        let mut value = serde_json::to_value(self).map_err(SCIMError::SerializationError)?;
+        redact_json(&mut value, &get_schemas(vec!["user", "enterprise_user"])?);
+        Ok(value)
+    }
+}
+
+/// Converts a JSON string into a `UserRequest`, as received in the body of a create or replace request.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the provided JSON string cannot be parsed into a `UserRequest`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::user::CreateUserRequest;
+///
+/// let request_json = r#"{"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "bjensen@example.com"}"#;
+/// match CreateUserRequest::try_from(request_json) {
+///     Ok(request) => println!("Create user: {:?}", request),
+///     Err(e) => println!("Error converting from JSON to UserRequest: {}", e),
+/// }
+/// ```
+impl TryFrom<&str> for UserRequest {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        from_json(value, &["user", "enterprise_user"], ParseMode::Lenient)
+    }
+}
+
+impl UserRequest {
+    /// Validates a user request.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the request has `schemas` and a `user_name`, and no multi-valued attribute has more than one
+    ///   `primary` value.
+    /// * `Err(SCIMError::MissingRequiredField)` - If a required field is missing.
+    /// * `Err(SCIMError::InvalidFieldValue)` - If a multi-valued attribute has more than one `primary` value.
+    pub fn validate(&self) -> Result<(), SCIMError> {
+        if self.schemas.is_empty() {
+            return Err(SCIMError::MissingRequiredField("schemas".to_string()));
+        }
+        if self.user_name.is_empty() {
+            return Err(SCIMError::MissingRequiredField("user_name".to_string()));
+        }
+        check_enterprise_schema(&self.schemas, &self.enterprise_user)?;
+        for (attribute, values) in self.primary_attributes() {
+            check_primary(attribute, values.primary_count(false))?;
+        }
+        Ok(())
+    }
+
+    /// Normalises the multi-valued attributes of the request.
+    ///
+    /// Exact duplicate values are removed from `emails`, `addresses`, `phoneNumbers`, `ims`, `photos`, `entitlements`,
+    /// `roles` and `x509Certificates`, as RFC 7644 requires when values are added. Then, if an attribute still has more
+    /// than one `primary` value, `policy` decides whether the first one is kept or the request is rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidFieldValue` naming the attribute if it has more than one primary value and `policy`
+    /// is `PrimaryPolicy::Reject`. The request is left unchanged in that case.
+    pub fn normalize_multi_valued(&mut self, policy: PrimaryPolicy) -> Result<(), SCIMError> {
+        if policy == PrimaryPolicy::Reject {
+            for (attribute, values) in self.primary_attributes() {
+                check_primary(attribute, values.primary_count(true))?;
+            }
+        }
+        for (_, values) in self.primary_attributes_mut() {
+            values.remove_duplicates();
+            if policy == PrimaryPolicy::KeepFirst {
+                values.keep_first_primary();
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks `schemas` against the resource type definition and the extensions the request carries.
+    ///
+    /// Every URN in `schemas` must be the core schema or a schema extension of `resource_type`, every extension with
+    /// data must be listed, and every extension that `resource_type` marks as required must be present. See
+    /// `ResourceType::validate_schemas` for the errors returned.
+    pub fn validate_schemas(&self, resource_type: &ResourceType) -> Result<(), SCIMError> {
+        resource_type.validate_schemas(&self.schemas, &user_extension_urns(&self.enterprise_user, &self.additional_attributes))
+    }
+
+    /// Rewrites `schemas` to the core schema of `resource_type` followed by the registered extensions the request
+    /// carries.
+    pub fn reconcile_schemas(&mut self, resource_type: &ResourceType) {
+        self.schemas = resource_type.reconcile_schemas(&user_extension_urns(&self.enterprise_user, &self.additional_attributes));
+    }
+
+    /// Deserializes a JSON string into a `UserRequest` instance in the given `ParseMode`.
+    ///
+    /// In `ParseMode::Lenient`, attributes that the User and Enterprise User schemas do not define are kept in
+    /// `additional_attributes` and serialized again. In `ParseMode::Strict` they are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` naming the offending attribute path if an unknown attribute is found in strict
+    /// mode, or `SCIMError::DeserializationError` if the JSON cannot be parsed into a `UserRequest`.
+    pub fn deserialize_with_mode(json: &str, mode: ParseMode) -> Result<Self, SCIMError> {
+        from_json(json, &["user", "enterprise_user"], mode)
+    }
+
+    /// Turns a create request into a stored user with the server-assigned `id` and `meta`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::models::user::CreateUserRequest;
+    /// use scim_v2::models::scim_schema::Meta;
+    ///
+    /// let request = CreateUserRequest {
+    ///     user_name: "bjensen@example.com".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let stored = request.into_stored("2819c223-7f76-453a-919d-413861904646".to_string(), Meta {
+    ///     resource_type: Some("User".to_string()),
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(stored.user.user_name, "bjensen@example.com");
+    /// ```
+    pub fn into_stored(self, id: String, meta: Meta) -> StoredUser {
+        StoredUser {
+            id,
+            user: self,
+            groups: None,
+            meta,
+        }
+    }
+}
+
+/// Drops the server-assigned attributes of a `User`.
+impl From<User> for UserRequest {
+    fn from(user: User) -> Self {
+        user.body
+    }
+}
+
+impl StoredUser {
+    /// Replaces every client-supplied attribute with the ones in `request`, keeping `id`, `groups` and `meta`.
+    ///
+    /// `password` is writeOnly, so clients cannot read it back to include it in a `PUT`: when `request` has none, the
+    /// stored one is kept. Updating `meta.lastModified` and `meta.version` is left to the caller.
+    pub fn replace(self, mut request: ReplaceUserRequest) -> StoredUser {
+        if request.password.is_none() {
+            request.password = self.user.password;
+        }
+        StoredUser {
+            id: self.id,
+            user: request,
+            groups: self.groups,
+            meta: self.meta,
+        }
+    }
+
+    /// Validates a stored user.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the user is valid.
+    /// * `Err(SCIMError::MissingRequiredField)` - If `id` or a required attribute of the user is missing.
+    pub fn validate(&self) -> Result<(), SCIMError> {
+        if self.id.is_empty() {
+            return Err(SCIMError::MissingRequiredField("id".to_string()));
+        }
+        self.user.validate()
+    }
+}
+
+impl From<StoredUser> for User {
+    fn from(stored: StoredUser) -> Self {
+        User {
+            id: Some(stored.id),
+            body: stored.user,
+            groups: stored.groups,
+            meta: Some(stored.meta),
+        }
+    }
+}
+
+/// Converts a `User` into a `StoredUser`, checking that the server-assigned attributes are present.
+///
+/// # Errors
+///
+/// Returns `SCIMError::MissingRequiredField` if `id` or `meta` is missing.
+impl TryFrom<User> for StoredUser {
+    type Error = SCIMError;
+
+    fn try_from(user: User) -> Result<Self, Self::Error> {
+        let id = user.id
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| SCIMError::MissingRequiredField("id".to_string()))?;
+        let meta = user.meta.ok_or_else(|| SCIMError::MissingRequiredField("meta".to_string()))?;
+        Ok(StoredUser {
+            id,
+            user: user.body,
+            groups: user.groups,
+            meta,
+        })
+    }
+}
+
+/// Converts the JSON representation of a stored user, such as a row read back from storage, into a `StoredUser`.
+///
+/// Like `User`, attribute names are matched case-insensitively when the `case-insensitive` feature is enabled.
+///
+/// # Errors
+///
+/// Returns `SCIMError::DeserializationError` if the JSON is not a user with an `id` and `meta`.
+impl TryFrom<&str> for StoredUser {
+    type Error = SCIMError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        from_json(value, &["user", "enterprise_user"], ParseMode::Lenient)
     }
 }
 
@@ -402,9 +981,9 @@ mod tests {
         }
         assert!(user.is_ok());
         let user = user.unwrap();
-        assert_eq!(user.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:User"]);
+        assert_eq!(user.body.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:User"]);
         assert_eq!(user.id, Some("2819c223-7f76-453a-919d-413861904646".to_string()));
-        assert_eq!(user.user_name, "bjensen@example.com");
+        assert_eq!(user.body.user_name, "bjensen@example.com");
         let meta = user.meta.unwrap();
         assert_eq!(meta.resource_type, Some("User".to_string()));
         assert_eq!(meta.created, Some("2010-01-23T04:56:22Z".to_string()));
@@ -536,27 +1115,31 @@ mod tests {
         }
 
         assert!(user.is_ok());
+        // The full representation of RFC 7643 section 8.2 only uses attributes the User schema defines.
+        if let Err(e) = User::deserialize_with_mode(json_data, ParseMode::Strict) {
+            panic!("strict deserialization failed: {}", e);
+        }
         let user = user.unwrap();
-        assert_eq!(user.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:User"]);
+        assert_eq!(user.body.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:User"]);
         assert_eq!(user.id, Some("2819c223-7f76-453a-919d-413861904646".to_string()));
-        assert_eq!(user.user_name, "bjensen@example.com");
-        assert_eq!(user.name.as_ref().unwrap().formatted, Some("Ms. Barbara J Jensen, III".to_string()));
-        assert_eq!(user.display_name, Some("Babs Jensen".to_string()));
-        assert_eq!(user.nick_name, Some("Babs".to_string()));
-        assert_eq!(user.profile_url, Some("https://login.example.com/bjensen".to_string()));
-        assert_eq!(user.emails.as_ref().unwrap().len(), 2);
-        assert_eq!(user.emails.as_ref().unwrap()[0].value, Some("bjensen@example.com".to_string()));
-        assert_eq!(user.emails.as_ref().unwrap()[0].type_, Some("work".to_string()));
-        assert_eq!(user.addresses.as_ref().unwrap().len(), 2);
-        assert_eq!(user.addresses.as_ref().unwrap()[0].type_.as_ref().unwrap(), "work");
-        assert_eq!(user.phone_numbers.as_ref().unwrap().len(), 2);
-        assert_eq!(user.phone_numbers.as_ref().unwrap()[0].value, Some("555-555-5555".to_string()));
-        assert_eq!(user.ims.as_ref().unwrap().len(), 1);
-        assert_eq!(user.ims.as_ref().unwrap()[0].value, Some("someaimhandle".to_string()));
+        assert_eq!(user.body.user_name, "bjensen@example.com");
+        assert_eq!(user.body.name.as_ref().unwrap().formatted, Some("Ms. Barbara J Jensen, III".to_string()));
+        assert_eq!(user.body.display_name, Some("Babs Jensen".to_string()));
+        assert_eq!(user.body.nick_name, Some("Babs".to_string()));
+        assert_eq!(user.body.profile_url, Some("https://login.example.com/bjensen".to_string()));
+        assert_eq!(user.body.emails.as_ref().unwrap().len(), 2);
+        assert_eq!(user.body.emails.as_ref().unwrap()[0].value, Some("bjensen@example.com".to_string()));
+        assert_eq!(user.body.emails.as_ref().unwrap()[0].type_, Some("work".to_string()));
+        assert_eq!(user.body.addresses.as_ref().unwrap().len(), 2);
+        assert_eq!(user.body.addresses.as_ref().unwrap()[0].type_.as_ref().unwrap(), "work");
+        assert_eq!(user.body.phone_numbers.as_ref().unwrap().len(), 2);
+        assert_eq!(user.body.phone_numbers.as_ref().unwrap()[0].value, Some("555-555-5555".to_string()));
+        assert_eq!(user.body.ims.as_ref().unwrap().len(), 1);
+        assert_eq!(user.body.ims.as_ref().unwrap()[0].value, Some("someaimhandle".to_string()));
         assert_eq!(user.groups.as_ref().unwrap().len(), 3);
         assert_eq!(user.groups.as_ref().unwrap()[0].value, Some("e9e30dba-f08f-4109-8486-d5c6a331660a".to_string()));
-        assert_eq!(user.x509_certificates.as_ref().unwrap().len(), 1);
-        assert_eq!(user.x509_certificates.as_ref().unwrap()[0].value, Some("MIIDQzCCAqygAwIBAgICEAAwDQYJKoZIhvcNAQEFBQAwTjELMAkGA1UEBhMCVVMxEzARBgNVBAgMCkNhbGlmb3JuaWExFDASBgNVBAoMC2V4YW1wbGUuY29tMRQwEgYDVQQDDAtleGFtcGxlLmNvbTAeFw0xMTEwMjIwNjI0MzFaFw0xMjEwMDQwNjI0MzFaMH8xCzAJBgNVBAYTAlVTMRMwEQYDVQQIDApDYWxpZm9ybmlhMRQwEgYDVQQKDAtleGFtcGxlLmNvbTEhMB8GA1UEAwwYTXMuIEJhcmJhcmEgSiBKZW5zZW4gSUlJMSIwIAYJKoZIhvcNAQkBFhNiamVuc2VuQGV4YW1wbGUuY29tMIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA7Kr+Dcds/JQ5GwejJFcBIP682X3xpjis56AK02bc1FLgzdLI8auoR+cC9/Vrh5t66HkQIOdA4unHh0AaZ4xL5PhVbXIPMB5vAPKpzz5iPSi8xO8SL7I7SDhcBVJhqVqr3HgllEG6UClDdHO7nkLuwXq8HcISKkbT5WFTVfFZzidPl8HZ7DhXkZIRtJwBweq4bvm3hM1Os7UQH05ZS6cVDgweKNwdLLrT51ikSQG3DYrl+ft781UQRIqxgwqCfXEuDiinPh0kkvIi5jivVu1Z9QiwlYEdRbLJ4zJQBmDrSGTMYn4lRc2HgHO4DqB/bnMVorHB0CC6AV1QoFK4GPe1LwIDAQABo3sweTAJBgNVHRMEAjAAMCwGCWCGSAGG+EIBDQQfFh1PcGVuU1NMIEdlbmVyYXRlZCBDZXJ0aWZpY2F0ZTAdBgNVHQ4EFgQU8pD0U0vsZIsaA16lL8En8bx0F/gwHwYDVR0jBBgwFoAUdGeKitcaF7gnzsNwDx708kqaVt0wDQYJKoZIhvcNAQEFBQADgYEAA81SsFnOdYJtNg5Tcq+/ByEDrBgnusx0jloUhByPMEVkoMZ3J7j1ZgI8rAbOkNngX8+pKfTiDz1RC4+dx8oU6Za+4NJXUjlL5CvV6BEYb1+QAEJwitTVvxB/A67g42/vzgAtoRUeDov1+GFiBZ+GNF/cAYKcMtGcrs2i97ZkJMo=".to_string()), "x509_certificates[0].value did not match expected value");
+        assert_eq!(user.body.x509_certificates.as_ref().unwrap().len(), 1);
+        assert_eq!(user.body.x509_certificates.as_ref().unwrap()[0].value, Some("MIIDQzCCAqygAwIBAgICEAAwDQYJKoZIhvcNAQEFBQAwTjELMAkGA1UEBhMCVVMxEzARBgNVBAgMCkNhbGlmb3JuaWExFDASBgNVBAoMC2V4YW1wbGUuY29tMRQwEgYDVQQDDAtleGFtcGxlLmNvbTAeFw0xMTEwMjIwNjI0MzFaFw0xMjEwMDQwNjI0MzFaMH8xCzAJBgNVBAYTAlVTMRMwEQYDVQQIDApDYWxpZm9ybmlhMRQwEgYDVQQKDAtleGFtcGxlLmNvbTEhMB8GA1UEAwwYTXMuIEJhcmJhcmEgSiBKZW5zZW4gSUlJMSIwIAYJKoZIhvcNAQkBFhNiamVuc2VuQGV4YW1wbGUuY29tMIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA7Kr+Dcds/JQ5GwejJFcBIP682X3xpjis56AK02bc1FLgzdLI8auoR+cC9/Vrh5t66HkQIOdA4unHh0AaZ4xL5PhVbXIPMB5vAPKpzz5iPSi8xO8SL7I7SDhcBVJhqVqr3HgllEG6UClDdHO7nkLuwXq8HcISKkbT5WFTVfFZzidPl8HZ7DhXkZIRtJwBweq4bvm3hM1Os7UQH05ZS6cVDgweKNwdLLrT51ikSQG3DYrl+ft781UQRIqxgwqCfXEuDiinPh0kkvIi5jivVu1Z9QiwlYEdRbLJ4zJQBmDrSGTMYn4lRc2HgHO4DqB/bnMVorHB0CC6AV1QoFK4GPe1LwIDAQABo3sweTAJBgNVHRMEAjAAMCwGCWCGSAGG+EIBDQQfFh1PcGVuU1NMIEdlbmVyYXRlZCBDZXJ0aWZpY2F0ZTAdBgNVHQ4EFgQU8pD0U0vsZIsaA16lL8En8bx0F/gwHwYDVR0jBBgwFoAUdGeKitcaF7gnzsNwDx708kqaVt0wDQYJKoZIhvcNAQEFBQADgYEAA81SsFnOdYJtNg5Tcq+/ByEDrBgnusx0jloUhByPMEVkoMZ3J7j1ZgI8rAbOkNngX8+pKfTiDz1RC4+dx8oU6Za+4NJXUjlL5CvV6BEYb1+QAEJwitTVvxB/A67g42/vzgAtoRUeDov1+GFiBZ+GNF/cAYKcMtGcrs2i97ZkJMo=".to_string()), "x509_certificates[0].value did not match expected value");
         let meta = user.meta.unwrap();
         assert_eq!(meta.resource_type, Some("User".to_string()));
         assert_eq!(meta.created, Some("2010-01-23T04:56:22Z".to_string()));
@@ -714,7 +1297,7 @@ mod tests {
         }
         assert!(user.is_ok());
         let user = user.unwrap();
-        let enterprise_user = user.enterprise_user.unwrap();
+        let enterprise_user = user.body.enterprise_user.unwrap();
         assert_eq!(enterprise_user.employee_number, Some("701984".to_string()));
         assert_eq!(enterprise_user.cost_center, Some("4130".to_string()));
         assert_eq!(enterprise_user.organization, Some("Universal Studios".to_string()));
@@ -740,6 +1323,166 @@ mod tests {
         }
         assert!(user.is_ok());
         let user = user.unwrap();
-        assert!(user.enterprise_user.is_none());
+        assert!(user.body.enterprise_user.is_none());
+    }
+
+    #[test]
+    fn user_request_deserializes_without_id_and_ignores_server_fields() {
+        let request = CreateUserRequest::try_from(r#"{
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+            "id": "client-chosen-id",
+            "userName": "bjensen@example.com",
+            "meta": {"resourceType": "User"},
+            "groups": [{"value": "e9e30dba-f08f-4109-8486-d5c6a331660a"}],
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"employeeNumber": "701984"}
+        }"#).unwrap();
+
+        assert_eq!(request.user_name, "bjensen@example.com");
+        assert_eq!(request.enterprise_user.as_ref().unwrap().employee_number, Some("701984".to_string()));
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("id").is_none());
+        assert!(json.get("meta").is_none());
+        assert!(json.get("groups").is_none());
+    }
+
+    #[test]
+    fn stored_user_round_trips_and_keeps_id_meta_and_password_on_replace() {
+        let stored = CreateUserRequest {
+            user_name: "bjensen@example.com".to_string(),
+            password: Some("hashed:t1meMa$heen".to_string()),
+            ..Default::default()
+        }.into_stored("2819c223-7f76-453a-919d-413861904646".to_string(), Meta {
+            resource_type: Some("User".to_string()),
+            ..Default::default()
+        });
+
+        let replaced = stored.replace(ReplaceUserRequest {
+            user_name: "barbara@example.com".to_string(),
+            ..Default::default()
+        });
+        let json = serde_json::to_string(&replaced).unwrap();
+        let parsed: StoredUser = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.id, "2819c223-7f76-453a-919d-413861904646");
+        assert_eq!(parsed.user.user_name, "barbara@example.com");
+        assert_eq!(parsed.user.password, Some("hashed:t1meMa$heen".to_string()));
+        assert_eq!(parsed.meta.resource_type, Some("User".to_string()));
+
+        let changed = parsed.replace(ReplaceUserRequest {
+            user_name: "barbara@example.com".to_string(),
+            password: Some("hashed:new".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(changed.user.password, Some("hashed:new".to_string()));
+
+        let user = User::from(changed);
+        assert_eq!(user.id, Some("2819c223-7f76-453a-919d-413861904646".to_string()));
+        assert!(StoredUser::try_from(user).is_ok());
+    }
+
+    #[test]
+    fn stored_user_requires_id_and_meta() {
+        let missing_id = User {
+            body: UserRequest {
+                user_name: "bjensen@example.com".to_string(),
+                ..Default::default()
+            },
+            meta: Some(Meta::default()),
+            ..Default::default()
+        };
+        assert!(matches!(StoredUser::try_from(missing_id), Err(SCIMError::MissingRequiredField(field)) if field == "id"));
+        let missing_meta = User {
+            id: Some("2819c223-7f76-453a-919d-413861904646".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(StoredUser::try_from(missing_meta), Err(SCIMError::MissingRequiredField(field)) if field == "meta"));
+    }
+
+    #[test]
+    fn user_debug_and_log_json_redact_password_and_certificates() {
+        let user = User {
+            body: UserRequest {
+                user_name: "bjensen@example.com".to_string(),
+                password: Some("t1meMa$heen".to_string()),
+                x509_certificates: Some(vec![X509Certificate {
+                    value: Some("MIIDQzCCAqygAwIBAgICEAAwDQYJKoZIhvcNAQEFBQAw".to_string()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let debug = format!("{:?}", user);
+        assert!(debug.contains("bjensen@example.com"));
+        assert!(debug.contains("[REDACTED]"));
+        assert!(!debug.contains("t1meMa$heen"));
+        assert!(!debug.contains("AQEFBQAw"));
+        assert!(!format!("{:?}", UserRequest::from(user.serialize().and_then(|json| User::deserialize(&json)).unwrap())).contains("t1meMa$heen"));
+
+        let log = user.to_log_json().unwrap();
+        assert_eq!(log["userName"], "bjensen@example.com");
+        assert_eq!(log["password"], "[REDACTED]");
+        assert_eq!(log["x509Certificates"][0]["value"], "MIIDQzCCAqygAwIB... (44 chars)");
+    }
+
+    #[test]
+    #[cfg(feature = "case-insensitive")]
+    fn user_deserialization_ignores_attribute_name_case() {
+        let user = User::try_from(r#"{
+            "SCHEMAS": ["urn:ietf:params:scim:schemas:core:2.0:user"],
+            "username": "bjensen@example.com",
+            "Name": {"GIVENNAME": "Barbara"},
+            "emails": [{"Value": "bjensen@example.com", "PRIMARY": true}],
+            "Meta": {"resourcetype": "User"},
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:user": {"EmployeeNumber": "701984"}
+        }"#).unwrap();
+
+        assert_eq!(user.body.schemas, vec!["urn:ietf:params:scim:schemas:core:2.0:User"]);
+        assert_eq!(user.body.user_name, "bjensen@example.com");
+        assert_eq!(user.body.name.as_ref().unwrap().given_name, Some("Barbara".to_string()));
+        assert_eq!(user.body.emails.as_ref().unwrap()[0].primary, Some(true));
+        assert_eq!(user.meta.as_ref().unwrap().resource_type, Some("User".to_string()));
+        assert_eq!(user.body.enterprise_user.as_ref().unwrap().employee_number, Some("701984".to_string()));
+        assert!(user.serialize().unwrap().contains(r#""userName":"bjensen@example.com""#));
+
+
+        let stored = StoredUser::try_from(r#"{"Schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "ID": "2819c223", "UserName": "bjensen", "META": {"ResourceType": "User"}}"#).unwrap();
+        assert_eq!(stored.id, "2819c223");
+        assert_eq!(stored.user.user_name, "bjensen");
+    }
+
+    #[test]
+    fn normalize_multi_valued_rejects_multiple_primaries_after_removing_duplicates() {
+        let phone = |value: &str| PhoneNumber { value: Some(value.to_string()), primary: Some(true), ..Default::default() };
+        let mut request = UserRequest {
+            user_name: "bjensen@example.com".to_string(),
+            phone_numbers: Some(vec![phone("555-555-5555"), phone("555-555-5555")]),
+            ..Default::default()
+        };
+
+        request.normalize_multi_valued(PrimaryPolicy::Reject).unwrap();
+        assert_eq!(request.phone_numbers.as_ref().unwrap().len(), 1);
+
+        request.phone_numbers.as_mut().unwrap().push(phone("555-555-4444"));
+        assert!(request.validate().is_err());
+        let error = request.normalize_multi_valued(PrimaryPolicy::Reject).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid field value: phoneNumbers has 2 primary values");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn normalize_multi_valued_leaves_a_rejected_request_unchanged() {
+        let email = || Email { value: Some("bjensen@example.com".to_string()), ..Default::default() };
+        let role = |value: &str| Role { value: Some(value.to_string()), primary: Some(true), ..Default::default() };
+        let mut request = UserRequest {
+            user_name: "bjensen@example.com".to_string(),
+            emails: Some(vec![email(), email()]),
+            roles: Some(vec![role("admin"), role("auditor")]),
+            ..Default::default()
+        };
+
+        let error = request.normalize_multi_valued(PrimaryPolicy::Reject).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid field value: roles has 2 primary values");
+        assert_eq!(request.emails.as_ref().unwrap().len(), 2);
+        assert_eq!(request.roles.as_ref().unwrap().iter().filter(|role| role.primary == Some(true)).count(), 2);
+    }
+}
@@ -0,0 +1,719 @@
+use std::collections::HashSet;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::SystemTime;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::models::group::{CreateGroupRequest, Group, ReplaceGroupRequest, StoredGroup};
+use crate::models::others::{ListResponse, PatchOp, SearchRequest};
+use crate::models::scim_schema::get_schemas;
+use crate::models::user::{CreateUserRequest, ReplaceUserRequest, StoredUser, User};
+use crate::provider::resource_provider::{check_if_match, new_id, timestamp, ResourceProvider};
+use crate::utils::error::SCIMError;
+use crate::utils::etag::ETag;
+use crate::utils::filter::Filter;
+use crate::utils::patch::apply;
+
+/// Attributes a client cannot set: the provider assigns `id` and `meta`, and derives `User.groups`.
+const READ_ONLY_ATTRIBUTES: [&str; 3] = ["id", "meta", "groups"];
+
+/// A thread-safe `ResourceProvider` that keeps Users and Groups in memory.
+///
+/// Ids are random-looking UUIDs, `meta.version` is a weak ETag of a revision counter shared by all resources, and
+/// `meta.location` is built from the base URL the provider was created with. `userName` is unique ignoring case.
+/// Group members must be existing users or groups; their `type` and `$ref` are filled in, as is `display` when the
+/// client sends none. `User.groups` lists the groups a user belongs to directly and, through nested groups,
+/// indirectly. A write that changes those memberships also updates `meta.lastModified` and `meta.version` of the
+/// users concerned. Everything is lost when the provider is dropped.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::group::{CreateGroupRequest, Member};
+/// use scim_v2::models::user::CreateUserRequest;
+/// use scim_v2::provider::memory::MemoryProvider;
+/// use scim_v2::provider::resource_provider::ResourceProvider;
+///
+/// let provider = MemoryProvider::new("https://example.com/v2");
+/// let user = provider.create_user(CreateUserRequest {
+///     user_name: "bjensen".to_string(),
+///     ..Default::default()
+/// }).unwrap();
+/// let group = provider.create_group(CreateGroupRequest {
+///     display_name: "Tour Guides".to_string(),
+///     members: Some(vec![Member { value: Some(user.id.clone()), ..Default::default() }]),
+///     ..Default::default()
+/// }).unwrap();
+///
+/// let user = provider.get_user(&user.id).unwrap();
+/// assert_eq!(user.groups.unwrap()[0].value, Some(group.id));
+/// ```
+#[derive(Debug)]
+pub struct MemoryProvider {
+    store: RwLock<Store>,
+}
+
+/// Resources are kept as JSON objects holding their `id` and `meta`, but not the derived `User.groups`.
+#[derive(Debug, Default)]
+struct Store {
+    base_url: String,
+    revision: u64,
+    users: Vec<Map<String, Value>>,
+    groups: Vec<Map<String, Value>>,
+}
+
+impl MemoryProvider {
+    /// Creates an empty provider whose resources are located under `base_url`, e.g. `https://example.com/v2`.
+    pub fn new(base_url: &str) -> Self {
+        MemoryProvider {
+            store: RwLock::new(Store {
+                base_url: base_url.trim_end_matches('/').to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Store> {
+        self.store.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Store> {
+        self.store.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the stored `password` of a user, which the provider never returns, for tests of other modules.
+    #[cfg(test)]
+    pub(crate) fn stored_password(&self, id: &str) -> Option<Value> {
+        let store = self.read();
+        store.users.iter().find(|stored| id_of(stored) == id)?.get("password").cloned()
+    }
+}
+
+impl ResourceProvider for MemoryProvider {
+    fn create_user(&self, request: CreateUserRequest) -> Result<StoredUser, SCIMError> {
+        let mut store = self.write();
+        let id = store.create_user(to_object(&request)?)?;
+        store.user(&id)
+    }
+
+    fn get_user(&self, id: &str) -> Result<StoredUser, SCIMError> {
+        self.read().user(id)
+    }
+
+    fn replace_user(&self, id: &str, request: ReplaceUserRequest) -> Result<StoredUser, SCIMError> {
+        self.write().replace_user(id, request)
+    }
+
+    fn patch_user(&self, id: &str, patch: &PatchOp) -> Result<StoredUser, SCIMError> {
+        self.write().patch_user(id, patch)
+    }
+
+    fn replace_user_if_match(&self, id: &str, request: ReplaceUserRequest, if_match: &str) -> Result<StoredUser, SCIMError> {
+        let mut store = self.write();
+        check_if_match(&store.user(id)?.meta, if_match)?;
+        store.replace_user(id, request)
+    }
+
+    fn patch_user_if_match(&self, id: &str, patch: &PatchOp, if_match: &str) -> Result<StoredUser, SCIMError> {
+        let mut store = self.write();
+        check_if_match(&store.user(id)?.meta, if_match)?;
+        store.patch_user(id, patch)
+    }
+
+    fn delete_user(&self, id: &str) -> Result<(), SCIMError> {
+        self.write().delete_user(id)
+    }
+
+    fn search_users(&self, search: &SearchRequest) -> Result<ListResponse<StoredUser>, SCIMError> {
+        let store = self.read();
+        let users = store.users.iter().map(|stored| store.user_view(stored)).collect();
+        search_resources(users, search, vec!["user", "enterprise_user"], from_object)
+    }
+
+    fn create_group(&self, request: CreateGroupRequest) -> Result<StoredGroup, SCIMError> {
+        let mut store = self.write();
+        let id = store.create_group(to_object(&request)?)?;
+        store.group(&id)
+    }
+
+    fn get_group(&self, id: &str) -> Result<StoredGroup, SCIMError> {
+        self.read().group(id)
+    }
+
+    fn replace_group(&self, id: &str, request: ReplaceGroupRequest) -> Result<StoredGroup, SCIMError> {
+        let mut store = self.write();
+        store.update_group(id, to_object(&request)?)?;
+        store.group(id)
+    }
+
+    fn patch_group(&self, id: &str, patch: &PatchOp) -> Result<StoredGroup, SCIMError> {
+        let mut store = self.write();
+        let mut patched = Value::Object(store.groups[store.group_index(id)?].clone());
+        apply(&mut patched, patch)?;
+        store.update_group(id, into_object(patched))?;
+        store.group(id)
+    }
+
+    fn delete_group(&self, id: &str) -> Result<(), SCIMError> {
+        self.write().delete_group(id)
+    }
+
+    fn search_groups(&self, search: &SearchRequest) -> Result<ListResponse<StoredGroup>, SCIMError> {
+        let store = self.read();
+        let groups = store.groups.to_vec();
+        search_resources(groups, search, vec!["group"], from_object)
+    }
+}
+
+impl Store {
+    fn location(&self, endpoint: &str, id: &str) -> String {
+        format!("{}/{}/{}", self.base_url, endpoint, id)
+    }
+
+    /// Starts a write, returning the `lastModified` and `version` of the resources it changes.
+    fn next_revision(&mut self) -> (String, String) {
+        self.revision += 1;
+        (timestamp(SystemTime::now()), ETag::weak(&self.revision.to_string()).to_string())
+    }
+
+    fn new_meta(&self, resource_type: &str, endpoint: &str, id: &str, (now, version): &(String, String)) -> Value {
+        json!({
+            "resourceType": resource_type,
+            "created": now,
+            "lastModified": now,
+            "version": version,
+            "location": self.location(endpoint, id),
+        })
+    }
+
+    fn user_index(&self, id: &str) -> Result<usize, SCIMError> {
+        self.users.iter()
+            .position(|stored| id_of(stored) == id)
+            .ok_or_else(|| SCIMError::NotFoundError(format!("user {}", id)))
+    }
+
+    fn group_index(&self, id: &str) -> Result<usize, SCIMError> {
+        self.groups.iter()
+            .position(|stored| id_of(stored) == id)
+            .ok_or_else(|| SCIMError::NotFoundError(format!("group {}", id)))
+    }
+
+    fn user(&self, id: &str) -> Result<StoredUser, SCIMError> {
+        from_object(self.user_view(&self.users[self.user_index(id)?]))
+    }
+
+    fn replace_user(&mut self, id: &str, request: ReplaceUserRequest) -> Result<StoredUser, SCIMError> {
+        let mut replacement = to_object(&request)?;
+        if !replacement.contains_key("password") {
+            if let Some(password) = self.users[self.user_index(id)?].get("password") {
+                replacement.insert("password".to_string(), password.clone());
+            }
+        }
+        self.update_user(id, replacement)?;
+        self.user(id)
+    }
+
+    fn patch_user(&mut self, id: &str, patch: &PatchOp) -> Result<StoredUser, SCIMError> {
+        let mut patched = Value::Object(self.users[self.user_index(id)?].clone());
+        apply(&mut patched, patch)?;
+        self.update_user(id, into_object(patched))?;
+        self.user(id)
+    }
+
+    fn group(&self, id: &str) -> Result<StoredGroup, SCIMError> {
+        from_object(self.groups[self.group_index(id)?].clone())
+    }
+
+    /// Returns a stored user as clients see it: with its groups and without its password.
+    fn user_view(&self, stored: &Map<String, Value>) -> Map<String, Value> {
+        let mut user = stored.clone();
+        user.remove("password");
+        let groups = self.groups_of(id_of(stored));
+        if !groups.is_empty() {
+            user.insert("groups".to_string(), Value::Array(groups));
+        }
+        user
+    }
+
+    /// Lists the groups `member_id` belongs to, first directly and then through nested groups.
+    fn groups_of(&self, member_id: &str) -> Vec<Value> {
+        let mut memberships = Vec::new();
+        let mut seen = HashSet::new();
+        let mut frontier = vec![member_id];
+        let mut type_ = "direct";
+        while !frontier.is_empty() {
+            let mut next = Vec::new();
+            for group in &self.groups {
+                let id = id_of(group);
+                if !seen.contains(id) && frontier.iter().any(|member| has_member(group, member)) {
+                    seen.insert(id);
+                    next.push(id);
+                    memberships.push(json!({
+                        "value": id,
+                        "$ref": self.location("Groups", id),
+                        "display": group["displayName"],
+                        "type": type_,
+                    }));
+                }
+            }
+            frontier = next;
+            type_ = "indirect";
+        }
+        memberships
+    }
+
+    /// Returns the ids of the users that belong to a group, directly or through nested groups.
+    fn users_below(&self, group_id: &str) -> HashSet<String> {
+        let mut users = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![group_id.to_string()];
+        while let Some(id) = pending.pop() {
+            let group = match self.groups.iter().find(|stored| id_of(stored) == id) {
+                Some(group) if visited.insert(id) => group,
+                _ => continue,
+            };
+            for value in member_values(group) {
+                if self.users.iter().any(|stored| id_of(stored) == value) {
+                    users.insert(value.to_string());
+                } else {
+                    pending.push(value.to_string());
+                }
+            }
+        }
+        users
+    }
+
+    fn touch_users(&mut self, ids: &HashSet<String>, revision: &(String, String)) {
+        for stored in self.users.iter_mut().filter(|stored| ids.contains(id_of(stored))) {
+            touch(stored, revision);
+        }
+    }
+
+    fn check_user_name(&self, user_name: &str, id: Option<&str>) -> Result<(), SCIMError> {
+        let user_name = user_name.to_lowercase();
+        let taken = self.users.iter().any(|stored| {
+            Some(id_of(stored)) != id && stored["userName"].as_str().map(str::to_lowercase) == Some(user_name.clone())
+        });
+        if taken {
+            return Err(SCIMError::ConflictError(format!("userName {} is already taken", user_name)));
+        }
+        Ok(())
+    }
+
+    /// Checks a user sent by a client, returning it without its readOnly attributes.
+    fn validate_user(&self, mut user: Map<String, Value>, id: Option<&str>) -> Result<Map<String, Value>, SCIMError> {
+        strip_read_only(&mut user);
+        let parsed: User = from_object(user)?;
+        parsed.validate()?;
+        self.check_user_name(&parsed.body.user_name, id)?;
+        to_object(&parsed)
+    }
+
+    fn create_user(&mut self, user: Map<String, Value>) -> Result<String, SCIMError> {
+        let mut user = self.validate_user(user, None)?;
+        let id = new_id(&format!("{}:{}", self.base_url, self.revision));
+        let revision = self.next_revision();
+        user.insert("id".to_string(), Value::String(id.clone()));
+        user.insert("meta".to_string(), self.new_meta("User", "Users", &id, &revision));
+        self.users.push(user);
+        Ok(id)
+    }
+
+    fn update_user(&mut self, id: &str, user: Map<String, Value>) -> Result<(), SCIMError> {
+        let index = self.user_index(id)?;
+        let mut user = self.validate_user(user, Some(id))?;
+        let revision = self.next_revision();
+        let stored = &mut self.users[index];
+        for key in ["id", "meta"] {
+            if let Some(value) = stored.remove(key) {
+                user.insert(key.to_string(), value);
+            }
+        }
+        *stored = user;
+        touch(stored, &revision);
+        Ok(())
+    }
+
+    fn delete_user(&mut self, id: &str) -> Result<(), SCIMError> {
+        let index = self.user_index(id)?;
+        self.users.remove(index);
+        let revision = self.next_revision();
+        for group in self.groups.iter_mut().filter(|group| has_member(group, id)) {
+            remove_member(group, id);
+            touch(group, &revision);
+        }
+        Ok(())
+    }
+
+    /// Checks a group sent by a client, returning it without its readOnly attributes. Every member must be an
+    /// existing user or another group; its `type`, `$ref` and missing `display` are filled in.
+    fn validate_group(&self, mut group: Map<String, Value>, id: &str) -> Result<Map<String, Value>, SCIMError> {
+        strip_read_only(&mut group);
+        for member in group.get_mut("members").and_then(Value::as_array_mut).into_iter().flatten() {
+            let member = member.as_object_mut()
+                .ok_or_else(|| SCIMError::InvalidFieldValue("members must be objects".to_string()))?;
+            let value = member.get("value").and_then(Value::as_str).map(str::to_string)
+                .ok_or_else(|| SCIMError::InvalidFieldValue("members.value is required".to_string()))?;
+            let (type_, endpoint, display) = if let Some(stored) = self.users.iter().find(|stored| id_of(stored) == value) {
+                ("User", "Users", stored.get("displayName").unwrap_or(&stored["userName"]).clone())
+            } else if let Some(stored) = self.groups.iter().find(|stored| id_of(stored) == value && value != id) {
+                ("Group", "Groups", stored["displayName"].clone())
+            } else {
+                return Err(SCIMError::InvalidFieldValue(format!("member {} is not an existing user or group", value)));
+            };
+            member.insert("type".to_string(), json!(type_));
+            member.insert("$ref".to_string(), json!(self.location(endpoint, &value)));
+            member.entry("display").or_insert(display);
+        }
+        drop_empty_members(&mut group);
+        group.insert("id".to_string(), Value::String(id.to_string()));
+        let parsed: Group = from_object(group)?;
+        parsed.validate()?;
+        to_object(&parsed)
+    }
+
+    fn create_group(&mut self, group: Map<String, Value>) -> Result<String, SCIMError> {
+        let id = new_id(&format!("{}:{}", self.base_url, self.revision));
+        let mut group = self.validate_group(group, &id)?;
+        let revision = self.next_revision();
+        group.insert("meta".to_string(), self.new_meta("Group", "Groups", &id, &revision));
+        self.groups.push(group);
+        let affected = self.users_below(&id);
+        self.touch_users(&affected, &revision);
+        Ok(id)
+    }
+
+    fn update_group(&mut self, id: &str, group: Map<String, Value>) -> Result<(), SCIMError> {
+        let index = self.group_index(id)?;
+        let mut group = self.validate_group(group, id)?;
+        let mut affected = self.users_below(id);
+        let revision = self.next_revision();
+        let stored = &mut self.groups[index];
+        if let Some(meta) = stored.remove("meta") {
+            group.insert("meta".to_string(), meta);
+        }
+        *stored = group;
+        touch(stored, &revision);
+        affected.extend(self.users_below(id));
+        self.touch_users(&affected, &revision);
+        Ok(())
+    }
+
+    fn delete_group(&mut self, id: &str) -> Result<(), SCIMError> {
+        let index = self.group_index(id)?;
+        let affected = self.users_below(id);
+        self.groups.remove(index);
+        let revision = self.next_revision();
+        for group in self.groups.iter_mut().filter(|group| has_member(group, id)) {
+            remove_member(group, id);
+            touch(group, &revision);
+        }
+        self.touch_users(&affected, &revision);
+        Ok(())
+    }
+}
+
+fn id_of(resource: &Map<String, Value>) -> &str {
+    resource.get("id").and_then(Value::as_str).unwrap_or_default()
+}
+
+fn member_values(group: &Map<String, Value>) -> impl Iterator<Item = &str> {
+    group.get("members")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|member| member.get("value").and_then(Value::as_str))
+}
+
+fn has_member(group: &Map<String, Value>, id: &str) -> bool {
+    member_values(group).any(|value| value == id)
+}
+
+fn remove_member(group: &mut Map<String, Value>, id: &str) {
+    if let Some(members) = group.get_mut("members").and_then(Value::as_array_mut) {
+        members.retain(|member| member.get("value").and_then(Value::as_str) != Some(id));
+    }
+    drop_empty_members(group);
+}
+
+/// Unassigns an empty `members`, which RFC 7643 (section 2.5) treats as unassigned anyway.
+fn drop_empty_members(group: &mut Map<String, Value>) {
+    if group.get("members").and_then(Value::as_array).map_or(false, Vec::is_empty) {
+        group.remove("members");
+    }
+}
+
+fn touch(resource: &mut Map<String, Value>, (now, version): &(String, String)) {
+    if let Some(meta) = resource.get_mut("meta").and_then(Value::as_object_mut) {
+        meta.insert("lastModified".to_string(), json!(now));
+        meta.insert("version".to_string(), json!(version));
+    }
+}
+
+/// Drops the attributes a client cannot set, ignoring the case of their names.
+fn strip_read_only(resource: &mut Map<String, Value>) {
+    resource.retain(|key, _| !READ_ONLY_ATTRIBUTES.iter().any(|read_only| read_only.eq_ignore_ascii_case(key)));
+}
+
+fn to_object<T: Serialize>(resource: &T) -> Result<Map<String, Value>, SCIMError> {
+    match serde_json::to_value(resource).map_err(SCIMError::SerializationError)? {
+        Value::Object(object) => Ok(object),
+        _ => Err(SCIMError::InvalidFieldValue("resource is not a JSON object".to_string())),
+    }
+}
+
+fn into_object(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(object) => object,
+        _ => Map::new(),
+    }
+}
+
+fn from_object<T: DeserializeOwned>(object: Map<String, Value>) -> Result<T, SCIMError> {
+    serde_json::from_value(Value::Object(object)).map_err(SCIMError::DeserializationError)
+}
+
+/// Filters resources with the filter of a search, evaluated against the named built-in schemas, then sorts and pages
+/// them into a `ListResponse`.
+fn search_resources<T: Serialize>(
+    resources: Vec<Map<String, Value>>,
+    search: &SearchRequest,
+    schema_names: Vec<&str>,
+    to_resource: impl Fn(Map<String, Value>) -> Result<T, SCIMError>,
+) -> Result<ListResponse<T>, SCIMError> {
+    if search.cursor.is_some() {
+        return Err(SCIMError::UnsupportedCapability("cursor".to_string()));
+    }
+    let mut resources: Vec<Value> = resources.into_iter().map(Value::Object).collect();
+    if let Some(filter) = &search.filter {
+        let filter = Filter::parse(filter)?;
+        let schemas = get_schemas(schema_names)?;
+        resources.retain(|resource| filter.matches(resource, &schemas));
+    }
+    let resources = resources.into_iter()
+        .map(|resource| to_resource(into_object(resource)))
+        .collect::<Result<Vec<T>, SCIMError>>()?;
+    search.merge_results(resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::models::group::{GroupRequest, Member};
+    use crate::models::user::UserRequest;
+
+    use super::*;
+
+    fn user(user_name: &str) -> UserRequest {
+        UserRequest {
+            user_name: user_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn group(display_name: &str, members: &[Option<&str>]) -> GroupRequest {
+        GroupRequest {
+            display_name: display_name.to_string(),
+            members: Some(members.iter().map(|id| Member { value: id.map(str::to_string), ..Default::default() }).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn patch(operations: Value) -> PatchOp {
+        serde_json::from_value(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": operations
+        })).unwrap()
+    }
+
+    fn search(filter: &str) -> SearchRequest {
+        SearchRequest {
+            filter: Some(filter.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn user_names(response: &ListResponse<StoredUser>) -> Vec<&str> {
+        response.resources.iter().map(|user| user.user.user_name.as_str()).collect()
+    }
+
+    #[test]
+    fn create_user_assigns_id_and_meta_and_hides_password() {
+        let provider = MemoryProvider::new("https://example.com/v2/");
+        let created = provider.create_user(UserRequest {
+            password: Some("t1meMa$heen".to_string()),
+            ..user("bjensen")
+        }).unwrap();
+
+        let id = created.id.clone();
+        let meta = created.meta;
+        assert_eq!(id.len(), 36);
+        assert_eq!(meta.resource_type, Some("User".to_string()));
+        assert_eq!(meta.location, Some(format!("https://example.com/v2/Users/{}", id)));
+        assert_eq!(meta.version, Some("W/\"1\"".to_string()));
+        assert_eq!(meta.created, meta.last_modified);
+        assert_eq!(created.user.password, None);
+        assert_eq!(provider.get_user(&id).unwrap().user.user_name, "bjensen");
+        assert!(matches!(provider.get_user("unknown"), Err(SCIMError::NotFoundError(_))));
+        assert!(matches!(provider.create_user(user("")), Err(SCIMError::MissingRequiredField(_))));
+    }
+
+    #[test]
+    fn user_name_is_unique_ignoring_case() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        provider.create_user(user("bjensen")).unwrap();
+        let other = provider.create_user(user("jsmith")).unwrap().id;
+
+        assert!(matches!(provider.create_user(user("BJensen")), Err(SCIMError::ConflictError(_))));
+        assert!(matches!(provider.replace_user(&other, user("bjensen")), Err(SCIMError::ConflictError(_))));
+        let rename = patch(json!([{"op": "replace", "path": "userName", "value": "BJENSEN"}]));
+        assert!(matches!(provider.patch_user(&other, &rename), Err(SCIMError::ConflictError(_))));
+        assert_eq!(provider.get_user(&other).unwrap().user.user_name, "jsmith");
+        assert!(provider.replace_user(&other, user("JSmith")).is_ok());
+
+        provider.create_user(user("ÄRGER")).unwrap();
+        assert!(matches!(provider.create_user(user("ärger")), Err(SCIMError::ConflictError(_))));
+        assert_eq!(provider.search_users(&search(r#"userName eq "ärger""#)).unwrap().total_results, 1);
+    }
+
+    #[test]
+    fn replace_and_patch_keep_password_and_update_meta() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let created = provider.create_user(UserRequest {
+            password: Some("t1meMa$heen".to_string()),
+            ..user("bjensen")
+        }).unwrap();
+        let id = created.id.clone();
+
+        let replaced = provider.replace_user(&id, UserRequest {
+            display_name: Some("Babs Jensen".to_string()),
+            ..user("bjensen")
+        }).unwrap();
+        assert_eq!(replaced.user.display_name, Some("Babs Jensen".to_string()));
+        assert_eq!(replaced.meta.created, created.meta.created);
+        assert_eq!(replaced.meta.version, Some("W/\"2\"".to_string()));
+        assert_eq!(provider.read().users[0]["password"], json!("t1meMa$heen"));
+
+        let patched = provider.patch_user(&id, &patch(json!([
+            {"op": "replace", "path": "active", "value": false},
+            {"op": "replace", "path": "id", "value": "changed"}
+        ]))).unwrap();
+        assert_eq!(patched.id, id);
+        assert_eq!(patched.user.active, Some(false));
+        assert_eq!(provider.read().users[0]["password"], json!("t1meMa$heen"));
+    }
+
+    #[test]
+    fn search_users_filters_sorts_and_pages() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        for (user_name, title) in [("carol", "Engineer"), ("alice", "Engineer"), ("bob", "Manager"), ("dave", "Engineer")] {
+            provider.create_user(UserRequest {
+                title: Some(title.to_string()),
+                ..user(user_name)
+            }).unwrap();
+        }
+
+        let request = SearchRequest {
+            sort_by: Some("userName".to_string()),
+            start_index: Some(2),
+            count: Some(1),
+            ..search(r#"title eq "engineer""#)
+        };
+        let response = provider.search_users(&request).unwrap();
+        assert_eq!(response.total_results, 3);
+        assert_eq!(user_names(&response), vec!["carol"]);
+
+        let descending = SearchRequest {
+            sort_by: Some("userName".to_string()),
+            sort_order: Some("Descending".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(user_names(&provider.search_users(&descending).unwrap()), vec!["dave", "carol", "bob", "alice"]);
+        assert!(matches!(provider.search_users(&search("title eq")), Err(SCIMError::InvalidSyntax(_))));
+        let cursor = SearchRequest { cursor: Some("".to_string()), ..Default::default() };
+        assert!(matches!(provider.search_users(&cursor), Err(SCIMError::UnsupportedCapability(_))));
+    }
+
+    #[test]
+    fn user_groups_follow_group_members() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let babs = provider.create_user(user("bjensen")).unwrap().id;
+        let mary = provider.create_user(UserRequest {
+            display_name: Some("Mary Smith".to_string()),
+            ..user("mary")
+        }).unwrap().id;
+        let guides = provider.create_group(group("Tour Guides", &[Some(&babs), Some(&mary)])).unwrap();
+        let staff = provider.create_group(group("Staff", &[Some(&guides.id)])).unwrap();
+
+        let member = &guides.group.members.as_ref().unwrap()[1];
+        assert_eq!(member.type_, Some("User".to_string()));
+        assert_eq!(member.ref_, Some(format!("https://example.com/v2/Users/{}", mary)));
+        assert_eq!(member.display, Some("Mary Smith".to_string()));
+        assert_eq!(staff.group.members.as_ref().unwrap()[0].type_, Some("Group".to_string()));
+
+        let babs_user = provider.get_user(&babs).unwrap();
+        let groups = serde_json::to_value(&babs_user.groups).unwrap();
+        assert_eq!(groups, json!([
+            {"value": guides.id, "$ref": format!("https://example.com/v2/Groups/{}", guides.id), "display": "Tour Guides", "type": "direct"},
+            {"value": staff.id, "$ref": format!("https://example.com/v2/Groups/{}", staff.id), "display": "Staff", "type": "indirect"}
+        ]));
+        assert_eq!(babs_user.meta.version, staff.meta.version);
+        let in_staff = search(&format!(r#"groups.value eq "{}""#, staff.id));
+        assert_eq!(provider.search_users(&in_staff).unwrap().total_results, 2);
+
+        let remove_mary = patch(json!([{"op": "remove", "path": format!(r#"members[value eq "{}"]"#, mary)}]));
+        let guides = provider.patch_group(&guides.id, &remove_mary).unwrap();
+        assert_eq!(guides.group.members.unwrap().len(), 1);
+        let mary = provider.get_user(&mary).unwrap();
+        assert!(mary.groups.is_none());
+        assert_eq!(mary.meta.version, guides.meta.version);
+
+        provider.delete_user(&babs).unwrap();
+        assert!(provider.get_group(&guides.id).unwrap().group.members.is_none());
+        provider.delete_group(&guides.id).unwrap();
+        assert!(provider.get_group(&staff.id).unwrap().group.members.is_none());
+        assert!(matches!(provider.delete_group(&guides.id), Err(SCIMError::NotFoundError(_))));
+    }
+
+    #[test]
+    fn group_members_must_exist() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let created = provider.create_group(group("Tour Guides", &[])).unwrap();
+
+        let unknown = group("Staff", &[Some("unknown")]);
+        assert!(matches!(provider.create_group(unknown), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(provider.create_group(group("Staff", &[None])), Err(SCIMError::InvalidFieldValue(_))));
+        let itself = group("Tour Guides", &[Some(&created.id)]);
+        assert!(matches!(provider.replace_group(&created.id, itself), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(provider.create_group(group("", &[])), Err(SCIMError::MissingRequiredField(_))));
+    }
+
+    #[test]
+    fn provider_can_be_shared_between_threads() {
+        let provider = Arc::new(MemoryProvider::new("https://example.com/v2"));
+        let handles: Vec<_> = (0..8)
+            .map(|thread| {
+                let provider = Arc::clone(&provider);
+                thread::spawn(move || {
+                    for index in 0..25 {
+                        provider.create_user(user(&format!("user{}-{}", thread, index))).unwrap();
+                    }
+                    provider.create_user(user("shared")).is_ok()
+                })
+            })
+            .collect();
+        let created_shared = handles.into_iter().map(|handle| handle.join().unwrap()).filter(|created| *created).count();
+
+        let response = provider.search_users(&SearchRequest { count: Some(500), ..Default::default() }).unwrap();
+        let ids: HashSet<_> = response.resources.iter().map(|user| user.id.clone()).collect();
+        assert_eq!(created_shared, 1);
+        assert_eq!(response.resources.len(), 201);
+        assert_eq!(ids.len(), 201);
+    }
+}
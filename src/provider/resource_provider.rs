@@ -0,0 +1,497 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::models::errors::ScimHttpError;
+use crate::models::group::{CreateGroupRequest, ReplaceGroupRequest, StoredGroup};
+use crate::models::others::{BulkOperation, BulkOperationResponse, BulkRequest, BulkResponse, ListResponse, PatchOp, SearchRequest};
+use crate::models::scim_schema::Meta;
+use crate::models::user::{CreateUserRequest, ReplaceUserRequest, StoredUser, User};
+use crate::utils::error::SCIMError;
+use crate::utils::etag::{evaluate_preconditions, ETag};
+use crate::utils::manager::UserLookup;
+use crate::utils::me::MeResolver;
+
+/// A SCIM backend that stores Users and Groups.
+///
+/// Implementations assign `id` and maintain `meta` (`created`, `lastModified`, `version` and `location`), keep
+/// `userName` unique, evaluate `filter`, `sortBy`, `sortOrder`, `startIndex` and `count` of a `SearchRequest`, and keep
+/// the readOnly `User.groups` in sync with `Group.members`. Resources are created and replaced from request bodies, whose
+/// `User.groups` is ignored, and returned as `StoredUser` and `StoredGroup`, whose `id` and `meta` are always present;
+/// users are returned without their `password`, and groups without members without `members`. Convert them with `User::from` and `Group::from` to send them to a
+/// client. Every method takes `&self`, so a provider can be shared between request handlers, e.g. behind an `Arc`.
+///
+/// `ProviderLookup` lets a provider back the lookups of `utils::manager` and `utils::me`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::SearchRequest;
+/// use scim_v2::models::user::CreateUserRequest;
+/// use scim_v2::provider::memory::MemoryProvider;
+/// use scim_v2::provider::resource_provider::ResourceProvider;
+///
+/// let provider = MemoryProvider::new("https://example.com/v2");
+/// let user = provider.create_user(CreateUserRequest {
+///     user_name: "bjensen".to_string(),
+///     ..Default::default()
+/// }).unwrap();
+///
+/// let search = SearchRequest {
+///     filter: Some(r#"userName eq "BJENSEN""#.to_string()),
+///     ..Default::default()
+/// };
+/// let response = provider.search_users(&search).unwrap();
+/// assert_eq!(response.total_results, 1);
+/// assert_eq!(response.resources[0].id, user.id);
+/// ```
+pub trait ResourceProvider {
+    /// Creates a user from a `POST /Users` request.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::MissingRequiredField` - If the user has no `schemas` or `userName`.
+    /// * `SCIMError::ConflictError` - If another user has the same `userName`.
+    fn create_user(&self, request: CreateUserRequest) -> Result<StoredUser, SCIMError>;
+
+    /// Returns the user with the given id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::NotFoundError` if there is no such user.
+    fn get_user(&self, id: &str) -> Result<StoredUser, SCIMError>;
+
+    /// Replaces a user with a `PUT /Users/{id}` request, keeping the stored `password` when the request has none.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `create_user`, or `SCIMError::NotFoundError` if there is no such user.
+    fn replace_user(&self, id: &str, request: ReplaceUserRequest) -> Result<StoredUser, SCIMError>;
+
+    /// Applies a `PATCH /Users/{id}` request to a user.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `replace_user`, or those of `utils::patch::apply` if the patch cannot be applied.
+    fn patch_user(&self, id: &str, patch: &PatchOp) -> Result<StoredUser, SCIMError>;
+
+    /// Replaces a user like `replace_user`, provided `if_match`, the value of an `If-Match` header, matches the user's
+    /// current `meta.version`.
+    ///
+    /// The default implementation reads the user and then replaces it, so another write can slip in between;
+    /// implementations override it to check the version and write in one step.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::PreconditionFailed` - If `if_match` does not match the current version.
+    /// * `SCIMError::InvalidFieldValue` - If `if_match` cannot be parsed.
+    ///
+    /// And the errors of `replace_user`.
+    fn replace_user_if_match(&self, id: &str, request: ReplaceUserRequest, if_match: &str) -> Result<StoredUser, SCIMError> {
+        check_if_match(&self.get_user(id)?.meta, if_match)?;
+        self.replace_user(id, request)
+    }
+
+    /// Applies a patch like `patch_user`, provided `if_match` matches the user's current `meta.version`, as
+    /// `replace_user_if_match` does.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `replace_user_if_match`, or those of `patch_user`.
+    fn patch_user_if_match(&self, id: &str, patch: &PatchOp, if_match: &str) -> Result<StoredUser, SCIMError> {
+        check_if_match(&self.get_user(id)?.meta, if_match)?;
+        self.patch_user(id, patch)
+    }
+
+    /// Deletes a user and removes it from the members of every group.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::NotFoundError` if there is no such user.
+    fn delete_user(&self, id: &str) -> Result<(), SCIMError>;
+
+    /// Returns the users matching a search, sorted by `sortBy` (users without a value come last)
+    /// and paged with `startIndex` and `count`. A search without a `filter` matches every user.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::InvalidSyntax` - If the filter cannot be parsed.
+    /// * `SCIMError::UnsupportedCapability` - If the search asks for cursor-based pagination and the provider does not
+    ///   support it.
+    fn search_users(&self, search: &SearchRequest) -> Result<ListResponse<StoredUser>, SCIMError>;
+
+    /// Creates a group from a `POST /Groups` request.
+    ///
+    /// # Errors
+    ///
+    /// * `SCIMError::MissingRequiredField` - If the group has no `schemas` or `displayName`.
+    /// * `SCIMError::InvalidFieldValue` - If a member has no `value` or is not an existing user or group.
+    fn create_group(&self, request: CreateGroupRequest) -> Result<StoredGroup, SCIMError>;
+
+    /// Returns the group with the given id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::NotFoundError` if there is no such group.
+    fn get_group(&self, id: &str) -> Result<StoredGroup, SCIMError>;
+
+    /// Replaces a group with a `PUT /Groups/{id}` request.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `create_group`, or `SCIMError::NotFoundError` if there is no such group.
+    fn replace_group(&self, id: &str, request: ReplaceGroupRequest) -> Result<StoredGroup, SCIMError>;
+
+    /// Applies a `PATCH /Groups/{id}` request to a group, such as adding or removing members.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `replace_group`, or those of `utils::patch::apply` if the patch cannot be applied.
+    fn patch_group(&self, id: &str, patch: &PatchOp) -> Result<StoredGroup, SCIMError>;
+
+    /// Deletes a group and removes it from the members of every other group.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::NotFoundError` if there is no such group.
+    fn delete_group(&self, id: &str) -> Result<(), SCIMError>;
+
+    /// Returns the groups matching a search, sorted and paged as `search_users` does.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `search_users`.
+    fn search_groups(&self, search: &SearchRequest) -> Result<ListResponse<StoredGroup>, SCIMError>;
+
+    /// Processes the operations of a `POST /Bulk` request in order.
+    ///
+    /// Operations create, replace, patch or delete `/Users` and `/Groups`, and may reference resources created by
+    /// earlier operations as `bulkId:{bulkId}` in their `path` or `data`. A failed operation is reported with its
+    /// error status in the response and does not stop the others, unless `failOnErrors` errors have occurred. The
+    /// default implementation calls the other methods of the provider one operation at a time.
+    ///
+    /// # Errors
+    ///
+    /// Errors of individual operations are part of the response; implementations return an error only if the request
+    /// as a whole cannot be processed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::models::others::BulkRequest;
+    /// use scim_v2::provider::memory::MemoryProvider;
+    /// use scim_v2::provider::resource_provider::ResourceProvider;
+    ///
+    /// let provider = MemoryProvider::new("https://example.com/v2");
+    /// let request = BulkRequest::try_from(r#"{
+    ///     "schemas": ["urn:ietf:params:scim:api:messages:2.0:BulkRequest"],
+    ///     "Operations": [
+    ///         {"method": "POST", "path": "/Users", "bulkId": "alice", "data": {"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "Alice"}},
+    ///         {"method": "POST", "path": "/Groups", "data": {
+    ///             "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+    ///             "displayName": "Tour Guides",
+    ///             "members": [{"value": "bulkId:alice"}]
+    ///         }}
+    ///     ]
+    /// }"#).unwrap();
+    ///
+    /// let response = provider.bulk(&request).unwrap();
+    /// assert_eq!(response.operations[1].status, "201");
+    /// ```
+    fn bulk(&self, request: &BulkRequest) -> Result<BulkResponse, SCIMError> {
+        Ok(run_bulk(self, request))
+    }
+}
+
+/// Looks users up in a `ResourceProvider`, for `utils::manager` and `utils::me`.
+///
+/// As a `MeResolver`, it takes the authenticated subject to be a `userName`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::user::CreateUserRequest;
+/// use scim_v2::provider::memory::MemoryProvider;
+/// use scim_v2::provider::resource_provider::{ProviderLookup, ResourceProvider};
+/// use scim_v2::utils::me::{resolve_me_path, MeResolver};
+///
+/// let provider = MemoryProvider::new("https://example.com/v2");
+/// let user = provider.create_user(CreateUserRequest {
+///     user_name: "bjensen".to_string(),
+///     ..Default::default()
+/// }).unwrap();
+///
+/// let lookup = ProviderLookup(&provider);
+/// let path = resolve_me_path("GET", "/Me", Some("bjensen"), Some(&lookup as &dyn MeResolver));
+/// assert_eq!(path.unwrap(), Some(format!("/Users/{}", user.id)));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ProviderLookup<'a, P: ?Sized>(pub &'a P);
+
+impl<P: ResourceProvider + ?Sized> UserLookup for ProviderLookup<'_, P> {
+    fn lookup(&self, id: &str) -> Option<User> {
+        self.0.get_user(id).ok().map(User::from)
+    }
+}
+
+impl<P: ResourceProvider + ?Sized> MeResolver for ProviderLookup<'_, P> {
+    fn resolve(&self, principal: &str) -> Option<String> {
+        let search = SearchRequest {
+            filter: Some(format!("userName eq {}", Value::from(principal))),
+            ..Default::default()
+        };
+        let mut response = self.0.search_users(&search).ok()?;
+        match response.resources.len() {
+            1 => response.resources.pop().map(|user| user.id),
+            _ => None,
+        }
+    }
+}
+
+/// Checks the `If-Match` header of a conditional write against the current version of a resource.
+pub(crate) fn check_if_match(meta: &Meta, if_match: &str) -> Result<(), SCIMError> {
+    match evaluate_preconditions(ETag::from_meta(meta).as_ref(), Some(if_match), None, false) {
+        Ok(_) => Ok(()),
+        Err(error) if error.status == "412" => Err(SCIMError::PreconditionFailed(error.detail.unwrap_or_default())),
+        Err(error) => Err(SCIMError::InvalidFieldValue(error.detail.unwrap_or_default())),
+    }
+}
+
+/// Processes the operations of a bulk request against a provider, stopping once `failOnErrors` operations have failed.
+pub(crate) fn run_bulk<P: ResourceProvider + ?Sized>(provider: &P, request: &BulkRequest) -> BulkResponse {
+    let mut bulk_ids = HashMap::new();
+    let mut errors = 0;
+    let mut response = BulkResponse::default();
+    for operation in &request.operations {
+        if request.fail_on_errors.map_or(false, |fail_on_errors| fail_on_errors > 0 && errors >= fail_on_errors) {
+            break;
+        }
+        let result = match run_bulk_operation(provider, operation, &bulk_ids) {
+            Ok((status, id, meta)) => {
+                if let (Some(bulk_id), Some(id)) = (&operation.bulk_id, id) {
+                    bulk_ids.insert(bulk_id.clone(), id);
+                }
+                BulkOperationResponse {
+                    location: meta.as_ref().and_then(|meta| meta.location.clone()),
+                    version: meta.and_then(|meta| meta.version),
+                    status: status.to_string(),
+                    ..Default::default()
+                }
+            }
+            Err(error) => {
+                errors += 1;
+                let error = error_response(&error);
+                BulkOperationResponse {
+                    status: error.status.clone(),
+                    response: serde_json::to_value(&error).ok(),
+                    ..Default::default()
+                }
+            }
+        };
+        response.operations.push(BulkOperationResponse {
+            method: operation.method.clone(),
+            bulk_id: operation.bulk_id.clone(),
+            ..result
+        });
+    }
+    response
+}
+
+/// Runs one bulk operation, returning its status with the id and `meta` of the resource it wrote.
+fn run_bulk_operation<P: ResourceProvider + ?Sized>(provider: &P, operation: &BulkOperation, bulk_ids: &HashMap<String, String>) -> Result<(&'static str, Option<String>, Option<Meta>), SCIMError> {
+    let mut path = Value::String(operation.path.clone());
+    resolve_bulk_ids(&mut path, bulk_ids)?;
+    let path = path.as_str().unwrap_or_default().trim_matches('/').to_string();
+    let mut data = operation.data.clone();
+    if let Some(data) = &mut data {
+        resolve_bulk_ids(data, bulk_ids)?;
+    }
+    let data = || data.clone().ok_or_else(|| SCIMError::MissingRequiredField("data".to_string()));
+    let user = |status, user: StoredUser| Ok((status, Some(user.id), Some(user.meta)));
+    let group = |status, group: StoredGroup| Ok((status, Some(group.id), Some(group.meta)));
+
+    let segments: Vec<&str> = path.split('/').collect();
+    match (operation.method.to_ascii_uppercase().as_str(), segments.as_slice()) {
+        ("POST", ["Users"]) => user("201", provider.create_user(from_data(data()?)?)?),
+        ("PUT", ["Users", id]) => user("200", provider.replace_user(id, from_data(data()?)?)?),
+        ("PATCH", ["Users", id]) => user("200", provider.patch_user(id, &from_data(data()?)?)?),
+        ("DELETE", ["Users", id]) => provider.delete_user(id).map(|_| ("204", None, None)),
+        ("POST", ["Groups"]) => group("201", provider.create_group(from_data(data()?)?)?),
+        ("PUT", ["Groups", id]) => group("200", provider.replace_group(id, from_data(data()?)?)?),
+        ("PATCH", ["Groups", id]) => group("200", provider.patch_group(id, &from_data(data()?)?)?),
+        ("DELETE", ["Groups", id]) => provider.delete_group(id).map(|_| ("204", None, None)),
+        _ => Err(SCIMError::InvalidFieldValue(format!("unsupported bulk operation: {} {}", operation.method, operation.path))),
+    }
+}
+
+fn from_data<T: DeserializeOwned>(data: Value) -> Result<T, SCIMError> {
+    serde_json::from_value(data).map_err(SCIMError::DeserializationError)
+}
+
+/// Replaces every `bulkId:{bulkId}` reference in a value, or in the segments of a path, with the id of the resource
+/// created for that bulkId.
+fn resolve_bulk_ids(value: &mut Value, bulk_ids: &HashMap<String, String>) -> Result<(), SCIMError> {
+    match value {
+        Value::String(string) if string.contains("bulkId:") => {
+            let segments = string.split('/').map(|segment| match segment.strip_prefix("bulkId:") {
+                Some(bulk_id) => bulk_ids.get(bulk_id).map(String::as_str)
+                    .ok_or_else(|| SCIMError::InvalidFieldValue(format!("unknown bulkId {}", bulk_id))),
+                None => Ok(segment),
+            });
+            *string = segments.collect::<Result<Vec<_>, _>>()?.join("/");
+        }
+        Value::Array(values) => {
+            for value in values {
+                resolve_bulk_ids(value, bulk_ids)?;
+            }
+        }
+        Value::Object(object) => {
+            for value in object.values_mut() {
+                resolve_bulk_ids(value, bulk_ids)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns the SCIM error response for a failed bulk operation (RFC 7644 section 3.12).
+fn error_response(error: &SCIMError) -> ScimHttpError {
+    let (status, scim_type) = match error {
+        SCIMError::NotFoundError(_) | SCIMError::ResourceTypeNotFound(_) | SCIMError::SchemaNotFound(_) => ("404", None),
+        SCIMError::ConflictError(_) => ("409", Some("uniqueness")),
+        SCIMError::InvalidSyntax(_) | SCIMError::InvalidJsonFormat | SCIMError::DeserializationError(_) => ("400", Some("invalidSyntax")),
+        SCIMError::InvalidFieldValue(_) | SCIMError::MissingRequiredField(_) => ("400", Some("invalidValue")),
+        SCIMError::InvalidCursor(_) => ("400", Some("invalidCursor")),
+        SCIMError::UnsupportedCapability(_) => ("501", None),
+        SCIMError::PreconditionFailed(_) => ("412", None),
+        _ => ("500", None),
+    };
+    ScimHttpError {
+        scim_type: scim_type.map(str::to_string),
+        detail: Some(error.to_string()),
+        status: status.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Returns a new id shaped like a version 4 UUID, derived from `seed`, the current time and a random hash key.
+pub(crate) fn new_id(seed: &str) -> String {
+    let state = RandomState::new();
+    let [high, low] = [0u8, 1].map(|half| {
+        let mut hasher = state.build_hasher();
+        (seed, SystemTime::now(), half).hash(&mut hasher);
+        hasher.finish()
+    });
+    let hex = format!("{:016x}{:016x}", high, low);
+    let variant = ["8", "9", "a", "b"][(low >> 62) as usize];
+    format!("{}-{}-4{}-{}{}-{}", &hex[..8], &hex[8..12], &hex[13..16], variant, &hex[17..20], &hex[20..32])
+}
+
+/// Formats a point in time as an RFC 3339 `dateTime` in UTC, as `meta.created` and `meta.lastModified` expect.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (days, seconds_of_day) = ((seconds / 86_400) as i64, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::provider::memory::MemoryProvider;
+
+    use super::*;
+
+    #[test]
+    fn bulk_resolves_bulk_ids_and_stops_after_fail_on_errors() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let request: BulkRequest = serde_json::from_value(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:BulkRequest"],
+            "failOnErrors": 2,
+            "Operations": [
+                {"method": "POST", "path": "/Users", "bulkId": "alice", "data": {"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "Alice"}},
+                {"method": "PATCH", "path": "/Users/bulkId:alice", "data": {
+                    "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+                    "Operations": [{"op": "replace", "path": "active", "value": false}]
+                }},
+                {"method": "POST", "path": "/Users", "data": {"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "alice"}},
+                {"method": "POST", "path": "/Groups", "data": {"schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"], "displayName": "Admins", "members": [{"value": "bulkId:bob"}]}},
+                {"method": "DELETE", "path": "/Users/bulkId:alice"}
+            ]
+        })).unwrap();
+
+        let response = provider.bulk(&request).unwrap();
+
+        let statuses: Vec<&str> = response.operations.iter().map(|operation| operation.status.as_str()).collect();
+        assert_eq!(statuses, vec!["201", "200", "409", "400"]);
+        let id = provider.search_users(&SearchRequest::default()).unwrap().resources[0].id.clone();
+        assert_eq!(response.operations[0].location, Some(format!("https://example.com/v2/Users/{}", id)));
+        assert_eq!(response.operations[0].bulk_id, Some("alice".to_string()));
+        assert_eq!(response.operations[2].response.as_ref().unwrap()["scimType"], json!("uniqueness"));
+        assert_eq!(provider.get_user(&id).unwrap().user.active, Some(false));
+    }
+
+    #[test]
+    fn provider_lookup_finds_users_by_id_and_user_name() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let user = provider.create_user(CreateUserRequest {
+            user_name: "bjensen@example.com".to_string(),
+            display_name: Some("Babs Jensen".to_string()),
+            ..Default::default()
+        }).unwrap();
+        let lookup = ProviderLookup(&provider);
+
+        assert_eq!(lookup.lookup(&user.id).unwrap().body.display_name, Some("Babs Jensen".to_string()));
+        assert!(lookup.lookup("unknown").is_none());
+        assert_eq!(lookup.resolve("BJensen@example.com"), Some(user.id));
+        assert_eq!(lookup.resolve(r#"x" or userName pr or userName eq "y"#), None);
+        assert_eq!(lookup.resolve("unknown"), None);
+    }
+
+    #[test]
+    fn new_id_is_shaped_like_a_uuid() {
+        let id = new_id("seed");
+        let lengths: Vec<usize> = id.split('-').map(str::len).collect();
+        assert_eq!(lengths, vec![8, 4, 4, 4, 12]);
+        assert_eq!(&id[14..15], "4");
+        assert!(["8", "9", "a", "b"].contains(&&id[19..20]));
+        assert_ne!(new_id("seed"), new_id("seed"));
+    }
+
+    #[test]
+    fn timestamp_formats_rfc_3339_in_utc() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(timestamp(UNIX_EPOCH + Duration::from_millis(1_305_261_754_123)), "2011-05-13T04:42:34.123Z");
+        assert_eq!(timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00.000Z");
+    }
+}
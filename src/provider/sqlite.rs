@@ -0,0 +1,1345 @@
+//! A `ResourceProvider` that persists Users and Groups in SQLite.
+//!
+//! Each resource is a row of `scim_users` or `scim_groups` holding its core attributes as JSON, with the attributes
+//! used for lookups (`userName`, `displayName` and `externalId`) and `meta` copied into indexed columns. Extension
+//! data lives in `scim_extensions`, one row per resource and extension, and group members in `scim_members`, from
+//! which `Group.members` and `User.groups` are rebuilt when resources are read. Filters are translated into `WHERE`
+//! clauses that use those indexes where the attribute has one, and SQLite's JSON functions elsewhere.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, ErrorCode, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::models::group::{CreateGroupRequest, GroupRequest, ReplaceGroupRequest, StoredGroup};
+use crate::models::others::{BulkRequest, BulkResponse, ListResponse, PatchOp, SearchRequest};
+use crate::models::scim_schema::{get_schemas, Attributes, Meta, Schema, SubAttributes};
+use crate::models::user::{CreateUserRequest, ReplaceUserRequest, StoredUser, UserRequest};
+use crate::provider::resource_provider::{check_if_match, new_id, run_bulk, timestamp, ResourceProvider};
+use crate::utils::error::SCIMError;
+use crate::utils::etag::ETag;
+use crate::utils::filter::{AttributePath, Filter, Operator};
+use crate::utils::patch::apply;
+
+/// The tables and indexes the provider creates when they do not exist yet.
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS "scim_users" (
+    "id" TEXT PRIMARY KEY NOT NULL,
+    "user_name" TEXT NOT NULL,
+    "external_id" TEXT,
+    "created" TEXT NOT NULL,
+    "last_modified" TEXT NOT NULL,
+    "version" INTEGER NOT NULL,
+    "resource" TEXT NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS "scim_users_user_name" ON "scim_users" (scim_lower("user_name"));
+CREATE INDEX IF NOT EXISTS "scim_users_external_id" ON "scim_users" ("external_id");
+CREATE TABLE IF NOT EXISTS "scim_groups" (
+    "id" TEXT PRIMARY KEY NOT NULL,
+    "display_name" TEXT NOT NULL,
+    "external_id" TEXT,
+    "created" TEXT NOT NULL,
+    "last_modified" TEXT NOT NULL,
+    "version" INTEGER NOT NULL,
+    "resource" TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "scim_groups_display_name" ON "scim_groups" (scim_lower("display_name"));
+CREATE INDEX IF NOT EXISTS "scim_groups_external_id" ON "scim_groups" ("external_id");
+CREATE TABLE IF NOT EXISTS "scim_members" (
+    "group_id" TEXT NOT NULL,
+    "position" INTEGER NOT NULL,
+    "member_id" TEXT NOT NULL,
+    "type" TEXT NOT NULL,
+    "display" TEXT,
+    PRIMARY KEY ("group_id", "position")
+);
+CREATE INDEX IF NOT EXISTS "scim_members_member_id" ON "scim_members" ("member_id");
+CREATE TABLE IF NOT EXISTS "scim_extensions" (
+    "resource_id" TEXT NOT NULL,
+    "urn" TEXT NOT NULL,
+    "data" TEXT NOT NULL,
+    PRIMARY KEY ("resource_id", "urn")
+);
+"#;
+
+/// Rebuilds `User.groups`, direct memberships first, from `scim_members`. `?1` is the base URL.
+const USER_GROUPS: &str = r#"(WITH RECURSIVE "memberships" ("group_id", "depth") AS (
+    SELECT "group_id", 0 FROM "scim_members" WHERE "member_id" = "t"."id"
+    UNION
+    SELECT "m"."group_id", 1 FROM "scim_members" AS "m" JOIN "memberships" ON "m"."member_id" = "memberships"."group_id"
+)
+SELECT json_group_array(json("group")) FROM (
+    SELECT json_object('value', "g"."id", '$ref', ?1 || '/Groups/' || "g"."id", 'display', "g"."display_name",
+        'type', CASE WHEN "d"."depth" = 0 THEN 'direct' ELSE 'indirect' END) AS "group"
+    FROM (SELECT "group_id", min("depth") AS "depth" FROM "memberships" GROUP BY "group_id") AS "d"
+    JOIN "scim_groups" AS "g" ON "g"."id" = "d"."group_id"
+    ORDER BY "d"."depth", "g"."rowid"
+))"#;
+
+/// Rebuilds `Group.members` from `scim_members`. `?1` is the base URL.
+const GROUP_MEMBERS: &str = r#"(SELECT json_group_array(json("member")) FROM (
+    SELECT json_object('value', "member_id", '$ref', ?1 || CASE "type" WHEN 'Group' THEN '/Groups/' ELSE '/Users/' END || "member_id",
+        'type', "type", 'display', "display") AS "member"
+    FROM "scim_members" WHERE "group_id" = "t"."id" ORDER BY "position"
+))"#;
+
+const EXTENSIONS: &str = r#"(SELECT json_group_object("urn", json("data")) FROM "scim_extensions" WHERE "resource_id" = "t"."id")"#;
+
+/// Used with the process id and the base URL to derive ids that differ between connections.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// How a resource type is laid out in the database.
+struct Table {
+    name: &'static str,
+    endpoint: &'static str,
+    resource_type: &'static str,
+    schema_names: &'static [&'static str],
+    /// The indexed column holding `userName` or `displayName`, and that attribute.
+    key: (&'static str, &'static str),
+    /// The attribute rebuilt from `scim_members`, and the expression that rebuilds it.
+    related: (&'static str, &'static str),
+}
+
+const USERS: Table = Table {
+    name: "scim_users",
+    endpoint: "Users",
+    resource_type: "User",
+    schema_names: &["user", "enterprise_user"],
+    key: ("user_name", "userName"),
+    related: ("groups", USER_GROUPS),
+};
+
+const GROUPS: Table = Table {
+    name: "scim_groups",
+    endpoint: "Groups",
+    resource_type: "Group",
+    schema_names: &["group"],
+    key: ("display_name", "displayName"),
+    related: ("members", GROUP_MEMBERS),
+};
+
+/// A thread-safe `ResourceProvider` that persists Users, Groups and their extension data in a SQLite database.
+///
+/// The provider creates its tables when they do not exist. `userName` is kept unique, ignoring ASCII case, by a unique
+/// index, and a write that breaks it fails with `SCIMError::ConflictError`. Searches are translated into SQL, paged
+/// with `LIMIT` and `OFFSET`, and counted for `totalResults`. Every write runs in a transaction (a savepoint when one
+/// is already open), so a `PATCH` reads and rewrites a resource atomically, and a bulk request is committed once, with
+/// each of its operations rolled back on its own if it fails. Group members must be existing users or groups, as
+/// with `MemoryProvider`, and `meta.version` is a weak ETag of a per-resource revision.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::SearchRequest;
+/// use scim_v2::models::user::CreateUserRequest;
+/// use scim_v2::provider::resource_provider::ResourceProvider;
+/// use scim_v2::provider::sqlite::SqliteProvider;
+///
+/// let provider = SqliteProvider::open_in_memory("https://example.com/v2").unwrap();
+/// provider.create_user(CreateUserRequest {
+///     user_name: "bjensen".to_string(),
+///     ..Default::default()
+/// }).unwrap();
+///
+/// let search = SearchRequest {
+///     filter: Some(r#"userName sw "BJ""#.to_string()),
+///     ..Default::default()
+/// };
+/// assert_eq!(provider.search_users(&search).unwrap().total_results, 1);
+/// ```
+#[derive(Debug)]
+pub struct SqliteProvider {
+    connection: Mutex<Connection>,
+    base_url: String,
+}
+
+impl SqliteProvider {
+    /// Opens or creates the database at `path`, creating the provider's tables if needed. Resources are located
+    /// under `base_url`, e.g. `https://example.com/v2`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::OtherError` if the database cannot be opened or its tables cannot be created.
+    pub fn open<P: AsRef<Path>>(path: P, base_url: &str) -> Result<Self, SCIMError> {
+        SqliteProvider::new(Connection::open(path).map_err(storage_error)?, base_url)
+    }
+
+    /// Creates a provider backed by a new in-memory database.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::OtherError` if the database cannot be created.
+    pub fn open_in_memory(base_url: &str) -> Result<Self, SCIMError> {
+        SqliteProvider::new(Connection::open_in_memory().map_err(storage_error)?, base_url)
+    }
+
+    /// Creates a provider on an open connection, creating the provider's tables if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::OtherError` if the tables cannot be created.
+    pub fn new(connection: Connection, base_url: &str) -> Result<Self, SCIMError> {
+        register_lower(&connection).map_err(storage_error)?;
+        connection.execute_batch(SCHEMA).map_err(storage_error)?;
+        Ok(SqliteProvider {
+            connection: Mutex::new(connection),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn session<T>(&self, run: impl FnOnce(&Session) -> Result<T, SCIMError>) -> Result<T, SCIMError> {
+        let connection: MutexGuard<Connection> = self.connection.lock().unwrap_or_else(PoisonError::into_inner);
+        run(&Session { connection: &connection, base_url: &self.base_url })
+    }
+}
+
+impl ResourceProvider for SqliteProvider {
+    fn create_user(&self, request: CreateUserRequest) -> Result<StoredUser, SCIMError> {
+        self.session(|session| session.create_user(request))
+    }
+
+    fn get_user(&self, id: &str) -> Result<StoredUser, SCIMError> {
+        self.session(|session| session.get_user(id))
+    }
+
+    fn replace_user(&self, id: &str, request: ReplaceUserRequest) -> Result<StoredUser, SCIMError> {
+        self.session(|session| session.replace_user(id, request))
+    }
+
+    fn patch_user(&self, id: &str, patch: &PatchOp) -> Result<StoredUser, SCIMError> {
+        self.session(|session| session.patch_user(id, patch))
+    }
+
+    fn replace_user_if_match(&self, id: &str, request: ReplaceUserRequest, if_match: &str) -> Result<StoredUser, SCIMError> {
+        self.session(|session| atomically(session.connection, || {
+            check_if_match(&session.get_user(id)?.meta, if_match)?;
+            session.replace_user(id, request)
+        }))
+    }
+
+    fn patch_user_if_match(&self, id: &str, patch: &PatchOp, if_match: &str) -> Result<StoredUser, SCIMError> {
+        self.session(|session| atomically(session.connection, || {
+            check_if_match(&session.get_user(id)?.meta, if_match)?;
+            session.patch_user(id, patch)
+        }))
+    }
+
+    fn delete_user(&self, id: &str) -> Result<(), SCIMError> {
+        self.session(|session| session.delete_user(id))
+    }
+
+    fn search_users(&self, search: &SearchRequest) -> Result<ListResponse<StoredUser>, SCIMError> {
+        self.session(|session| session.search_users(search))
+    }
+
+    fn create_group(&self, request: CreateGroupRequest) -> Result<StoredGroup, SCIMError> {
+        self.session(|session| session.create_group(request))
+    }
+
+    fn get_group(&self, id: &str) -> Result<StoredGroup, SCIMError> {
+        self.session(|session| session.get_group(id))
+    }
+
+    fn replace_group(&self, id: &str, request: ReplaceGroupRequest) -> Result<StoredGroup, SCIMError> {
+        self.session(|session| session.replace_group(id, request))
+    }
+
+    fn patch_group(&self, id: &str, patch: &PatchOp) -> Result<StoredGroup, SCIMError> {
+        self.session(|session| session.patch_group(id, patch))
+    }
+
+    fn delete_group(&self, id: &str) -> Result<(), SCIMError> {
+        self.session(|session| session.delete_group(id))
+    }
+
+    fn search_groups(&self, search: &SearchRequest) -> Result<ListResponse<StoredGroup>, SCIMError> {
+        self.session(|session| session.search_groups(search))
+    }
+
+    /// Processes a bulk request in one transaction. Each operation runs in its own savepoint, so a failed operation
+    /// leaves no partial changes, and the operations that succeeded before `failOnErrors` was reached are committed
+    /// together.
+    fn bulk(&self, request: &BulkRequest) -> Result<BulkResponse, SCIMError> {
+        self.session(|session| atomically(session.connection, || Ok(run_bulk(session, request))))
+    }
+}
+
+/// The provider's operations on a locked connection.
+struct Session<'a> {
+    connection: &'a Connection,
+    base_url: &'a str,
+}
+
+impl ResourceProvider for Session<'_> {
+    fn create_user(&self, request: CreateUserRequest) -> Result<StoredUser, SCIMError> {
+        request.validate()?;
+        let id = atomically(self.connection, || {
+            let id = self.new_id();
+            let document = document(&request, USERS.related.0)?;
+            self.connection.execute(
+                r#"INSERT INTO "scim_users" ("id", "user_name", "external_id", "created", "last_modified", "version", "resource") VALUES (?1, ?2, ?3, ?4, ?4, 1, ?5)"#,
+                params![id, request.user_name, document.external_id, timestamp(SystemTime::now()), document.resource],
+            ).map_err(|error| user_name_conflict(error, &request.user_name))?;
+            self.write_extensions(&id, &document.extensions)?;
+            Ok(id)
+        })?;
+        self.get_user(&id)
+    }
+
+    fn get_user(&self, id: &str) -> Result<StoredUser, SCIMError> {
+        let mut user = self.user(id)?;
+        user.user.password = None;
+        Ok(user)
+    }
+
+    fn replace_user(&self, id: &str, mut request: ReplaceUserRequest) -> Result<StoredUser, SCIMError> {
+        atomically(self.connection, || {
+            if request.password.is_none() {
+                request.password = self.user(id)?.user.password;
+            }
+            self.update_user(id, &request)
+        })?;
+        self.get_user(id)
+    }
+
+    fn patch_user(&self, id: &str, patch: &PatchOp) -> Result<StoredUser, SCIMError> {
+        atomically(self.connection, || {
+            let request: UserRequest = patched(&self.user(id)?, patch)?;
+            self.update_user(id, &request)
+        })?;
+        self.get_user(id)
+    }
+
+    fn delete_user(&self, id: &str) -> Result<(), SCIMError> {
+        atomically(self.connection, || {
+            self.require(&USERS, id)?;
+            let groups = self.groups_containing(id)?;
+            self.execute(r#"DELETE FROM "scim_users" WHERE "id" = ?1"#, id)?;
+            self.execute(r#"DELETE FROM "scim_members" WHERE "member_id" = ?1"#, id)?;
+            self.execute(r#"DELETE FROM "scim_extensions" WHERE "resource_id" = ?1"#, id)?;
+            self.touch(&GROUPS, &groups)
+        })
+    }
+
+    fn search_users(&self, search: &SearchRequest) -> Result<ListResponse<StoredUser>, SCIMError> {
+        let (users, total_results) = self.search(&USERS, search)?;
+        let users = users.into_iter()
+            .map(|user| {
+                let mut user: StoredUser = serde_json::from_value(user).map_err(SCIMError::DeserializationError)?;
+                user.user.password = None;
+                Ok(user)
+            })
+            .collect::<Result<Vec<StoredUser>, SCIMError>>()?;
+        Ok(list_response(users, total_results, search))
+    }
+
+    fn create_group(&self, mut request: CreateGroupRequest) -> Result<StoredGroup, SCIMError> {
+        request.validate()?;
+        let id = atomically(self.connection, || {
+            self.resolve_members(None, &mut request)?;
+            let id = self.new_id();
+            let document = document(&request, GROUPS.related.0)?;
+            self.connection.execute(
+                r#"INSERT INTO "scim_groups" ("id", "display_name", "external_id", "created", "last_modified", "version", "resource") VALUES (?1, ?2, ?3, ?4, ?4, 1, ?5)"#,
+                params![id, request.display_name, document.external_id, timestamp(SystemTime::now()), document.resource],
+            ).map_err(storage_error)?;
+            self.write_members(&id, &request)?;
+            self.write_extensions(&id, &document.extensions)?;
+            self.touch(&USERS, &self.users_below(&id)?)?;
+            Ok(id)
+        })?;
+        self.get_group(&id)
+    }
+
+    fn get_group(&self, id: &str) -> Result<StoredGroup, SCIMError> {
+        serde_json::from_value(self.select_one(&GROUPS, id)?).map_err(SCIMError::DeserializationError)
+    }
+
+    fn replace_group(&self, id: &str, request: ReplaceGroupRequest) -> Result<StoredGroup, SCIMError> {
+        atomically(self.connection, || self.update_group(id, request))?;
+        self.get_group(id)
+    }
+
+    fn patch_group(&self, id: &str, patch: &PatchOp) -> Result<StoredGroup, SCIMError> {
+        atomically(self.connection, || {
+            let request: GroupRequest = patched(&self.get_group(id)?, patch)?;
+            self.update_group(id, request)
+        })?;
+        self.get_group(id)
+    }
+
+    fn delete_group(&self, id: &str) -> Result<(), SCIMError> {
+        atomically(self.connection, || {
+            self.require(&GROUPS, id)?;
+            let users = self.users_below(id)?;
+            let groups = self.groups_containing(id)?;
+            self.execute(r#"DELETE FROM "scim_groups" WHERE "id" = ?1"#, id)?;
+            self.execute(r#"DELETE FROM "scim_members" WHERE "group_id" = ?1 OR "member_id" = ?1"#, id)?;
+            self.execute(r#"DELETE FROM "scim_extensions" WHERE "resource_id" = ?1"#, id)?;
+            self.touch(&GROUPS, &groups)?;
+            self.touch(&USERS, &users)
+        })
+    }
+
+    fn search_groups(&self, search: &SearchRequest) -> Result<ListResponse<StoredGroup>, SCIMError> {
+        let (groups, total_results) = self.search(&GROUPS, search)?;
+        let groups = groups.into_iter()
+            .map(|group| serde_json::from_value(group).map_err(SCIMError::DeserializationError))
+            .collect::<Result<Vec<StoredGroup>, SCIMError>>()?;
+        Ok(list_response(groups, total_results, search))
+    }
+}
+
+impl Session<'_> {
+    fn new_id(&self) -> String {
+        new_id(&format!("{}:{}:{}", self.base_url, std::process::id(), NEXT_ID.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    fn execute(&self, sql: &str, id: &str) -> Result<usize, SCIMError> {
+        self.connection.execute(sql, [id]).map_err(storage_error)
+    }
+
+    /// Returns a user with its password, as stored.
+    fn user(&self, id: &str) -> Result<StoredUser, SCIMError> {
+        serde_json::from_value(self.select_one(&USERS, id)?).map_err(SCIMError::DeserializationError)
+    }
+
+    fn update_user(&self, id: &str, request: &UserRequest) -> Result<(), SCIMError> {
+        request.validate()?;
+        let document = document(request, USERS.related.0)?;
+        let updated = self.connection.execute(
+            r#"UPDATE "scim_users" SET "user_name" = ?2, "external_id" = ?3, "last_modified" = ?4, "version" = "version" + 1, "resource" = ?5 WHERE "id" = ?1"#,
+            params![id, request.user_name, document.external_id, timestamp(SystemTime::now()), document.resource],
+        ).map_err(|error| user_name_conflict(error, &request.user_name))?;
+        if updated == 0 {
+            return Err(SCIMError::NotFoundError(format!("user {}", id)));
+        }
+        self.write_extensions(id, &document.extensions)
+    }
+
+    fn update_group(&self, id: &str, mut request: GroupRequest) -> Result<(), SCIMError> {
+        self.require(&GROUPS, id)?;
+        request.validate()?;
+        self.resolve_members(Some(id), &mut request)?;
+        let mut users = self.users_below(id)?;
+        let document = document(&request, GROUPS.related.0)?;
+        self.connection.execute(
+            r#"UPDATE "scim_groups" SET "display_name" = ?2, "external_id" = ?3, "last_modified" = ?4, "version" = "version" + 1, "resource" = ?5 WHERE "id" = ?1"#,
+            params![id, request.display_name, document.external_id, timestamp(SystemTime::now()), document.resource],
+        ).map_err(storage_error)?;
+        self.write_members(id, &request)?;
+        self.write_extensions(id, &document.extensions)?;
+        users.extend(self.users_below(id)?);
+        self.touch(&USERS, &users)
+    }
+
+    fn require(&self, table: &Table, id: &str) -> Result<(), SCIMError> {
+        let sql = format!(r#"SELECT 1 FROM "{}" WHERE "id" = ?1"#, table.name);
+        match self.connection.query_row(&sql, [id], |_| Ok(())).optional().map_err(storage_error)? {
+            Some(()) => Ok(()),
+            None => Err(SCIMError::NotFoundError(format!("{} {}", table.resource_type.to_lowercase(), id))),
+        }
+    }
+
+    /// Checks that every member of a group is an existing user or another group, and fills in its `type`, `$ref`
+    /// and missing `display`.
+    fn resolve_members(&self, group_id: Option<&str>, request: &mut GroupRequest) -> Result<(), SCIMError> {
+        for member in request.members.iter_mut().flatten() {
+            let value = member.value.clone().ok_or_else(|| SCIMError::InvalidFieldValue("members.value is required".to_string()))?;
+            let user = self.connection.query_row(
+                r#"SELECT coalesce(json_extract("resource", '$.displayName'), "user_name") FROM "scim_users" WHERE "id" = ?1"#,
+                [&value],
+                |row| row.get::<_, String>(0),
+            ).optional().map_err(storage_error)?;
+            let group = || self.connection.query_row(
+                r#"SELECT "display_name" FROM "scim_groups" WHERE "id" = ?1 AND "id" IS NOT ?2"#,
+                params![value, group_id],
+                |row| row.get::<_, String>(0),
+            ).optional().map_err(storage_error);
+            let (type_, table, display) = match user {
+                Some(display) => ("User", &USERS, display),
+                None => match group()? {
+                    Some(display) => ("Group", &GROUPS, display),
+                    None => return Err(SCIMError::InvalidFieldValue(format!("member {} is not an existing user or group", value))),
+                },
+            };
+            member.type_ = Some(type_.to_string());
+            member.ref_ = Some(format!("{}/{}/{}", self.base_url, table.endpoint, value));
+            member.display.get_or_insert(display);
+        }
+        Ok(())
+    }
+
+    fn write_members(&self, group_id: &str, request: &GroupRequest) -> Result<(), SCIMError> {
+        self.execute(r#"DELETE FROM "scim_members" WHERE "group_id" = ?1"#, group_id)?;
+        for (position, member) in request.members.iter().flatten().enumerate() {
+            self.connection.execute(
+                r#"INSERT INTO "scim_members" ("group_id", "position", "member_id", "type", "display") VALUES (?1, ?2, ?3, ?4, ?5)"#,
+                params![group_id, position as i64, member.value, member.type_, member.display],
+            ).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    fn write_extensions(&self, id: &str, extensions: &[(String, String)]) -> Result<(), SCIMError> {
+        self.execute(r#"DELETE FROM "scim_extensions" WHERE "resource_id" = ?1"#, id)?;
+        for (urn, data) in extensions {
+            self.connection.execute(
+                r#"INSERT INTO "scim_extensions" ("resource_id", "urn", "data") VALUES (?1, ?2, ?3)"#,
+                params![id, urn, data],
+            ).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the ids of the groups that have `member_id` as a direct member.
+    fn groups_containing(&self, member_id: &str) -> Result<HashSet<String>, SCIMError> {
+        self.ids(r#"SELECT DISTINCT "group_id" FROM "scim_members" WHERE "member_id" = ?1"#, member_id)
+    }
+
+    /// Returns the ids of the users that belong to a group, directly or through nested groups.
+    fn users_below(&self, group_id: &str) -> Result<HashSet<String>, SCIMError> {
+        self.ids(
+            r#"WITH RECURSIVE "below" ("id") AS (
+                SELECT "member_id" FROM "scim_members" WHERE "group_id" = ?1
+                UNION
+                SELECT "m"."member_id" FROM "scim_members" AS "m" JOIN "below" ON "m"."group_id" = "below"."id"
+            )
+            SELECT "id" FROM "below" WHERE "id" IN (SELECT "id" FROM "scim_users")"#,
+            group_id,
+        )
+    }
+
+    fn ids(&self, sql: &str, id: &str) -> Result<HashSet<String>, SCIMError> {
+        let mut statement = self.connection.prepare(sql).map_err(storage_error)?;
+        let ids = statement.query_map([id], |row| row.get(0)).map_err(storage_error)?;
+        ids.collect::<Result<_, _>>().map_err(storage_error)
+    }
+
+    /// Updates `meta.lastModified` and `meta.version` of resources whose memberships changed.
+    fn touch(&self, table: &Table, ids: &HashSet<String>) -> Result<(), SCIMError> {
+        let sql = format!(r#"UPDATE "{}" SET "last_modified" = ?2, "version" = "version" + 1 WHERE "id" = ?1"#, table.name);
+        let now = timestamp(SystemTime::now());
+        for id in ids {
+            self.connection.execute(&sql, params![id, now]).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    fn select_one(&self, table: &Table, id: &str) -> Result<Value, SCIMError> {
+        let params = vec![SqlValue::Text(self.base_url.to_string()), SqlValue::Text(id.to_string())];
+        self.select(table, r#" WHERE "t"."id" = ?2"#, params)?
+            .pop()
+            .ok_or_else(|| SCIMError::NotFoundError(format!("{} {}", table.resource_type.to_lowercase(), id)))
+    }
+
+    /// Selects resources in their SCIM form. `?1` must be the base URL.
+    fn select(&self, table: &Table, clause: &str, params: Vec<SqlValue>) -> Result<Vec<Value>, SCIMError> {
+        let sql = format!(
+            r#"SELECT "t"."id", "t"."created", "t"."last_modified", "t"."version", "t"."resource", {}, {} FROM "{}" AS "t"{}"#,
+            EXTENSIONS, table.related.1, table.name, clause
+        );
+        let mut statement = self.connection.prepare(&sql).map_err(storage_error)?;
+        let rows = statement.query_map(params_from_iter(params), |row| {
+            Ok(Row {
+                id: row.get(0)?,
+                created: row.get(1)?,
+                last_modified: row.get(2)?,
+                version: row.get(3)?,
+                resource: row.get(4)?,
+                extensions: row.get(5)?,
+                related: row.get(6)?,
+            })
+        }).map_err(storage_error)?;
+        let rows = rows.collect::<Result<Vec<Row>, _>>().map_err(storage_error)?;
+        rows.into_iter().map(|row| row.into_resource(table, self.base_url)).collect()
+    }
+
+    /// Returns one page of the resources matching a search, and the number of matching resources.
+    fn search(&self, table: &Table, search: &SearchRequest) -> Result<(Vec<Value>, i64), SCIMError> {
+        let (clause, params) = search_clause(table, self.base_url, search)?;
+        let count_sql = format!(r#"SELECT count(*) FROM "{}" AS "t"{}"#, table.name, clause.filter);
+        let mut count = self.connection.prepare(&count_sql).map_err(storage_error)?;
+        // The count only refers to the base URL when the filter reads the attribute rebuilt from `scim_members`.
+        let count_params = params.iter().take(count.parameter_count());
+        let total_results = count.query_row(params_from_iter(count_params), |row| row.get(0)).map_err(storage_error)?;
+        let resources = self.select(table, &format!("{}{}", clause.filter, clause.page), params)?;
+        Ok((resources, total_results))
+    }
+}
+
+/// A row selected by `Session::select`.
+struct Row {
+    id: String,
+    created: String,
+    last_modified: String,
+    version: i64,
+    resource: String,
+    extensions: Option<String>,
+    related: Option<String>,
+}
+
+impl Row {
+    fn into_resource(self, table: &Table, base_url: &str) -> Result<Value, SCIMError> {
+        let parse = |json: &str| serde_json::from_str::<Value>(json).map_err(SCIMError::DeserializationError);
+        let mut resource = match parse(&self.resource)? {
+            Value::Object(object) => object,
+            _ => Map::new(),
+        };
+        if let Some(Value::Object(extensions)) = self.extensions.as_deref().map(parse).transpose()? {
+            resource.extend(extensions);
+        }
+        if let Some(related) = self.related.as_deref().map(parse).transpose()? {
+            if related.as_array().map_or(false, |related| !related.is_empty()) {
+                resource.insert(table.related.0.to_string(), related);
+            }
+        }
+        let meta = Meta {
+            resource_type: Some(table.resource_type.to_string()),
+            created: Some(self.created),
+            last_modified: Some(self.last_modified),
+            version: Some(ETag::weak(&self.version.to_string()).to_string()),
+            location: Some(format!("{}/{}/{}", base_url, table.endpoint, self.id)),
+        };
+        resource.insert("id".to_string(), Value::String(self.id));
+        resource.insert("meta".to_string(), serde_json::to_value(meta).map_err(SCIMError::SerializationError)?);
+        Ok(Value::Object(resource))
+    }
+}
+
+/// The `WHERE` clause of a search, and the `ORDER BY`, `LIMIT` and `OFFSET` clauses of its page.
+struct SearchClause {
+    filter: String,
+    page: String,
+}
+
+/// Translates a search into SQL clauses and their parameters, the first of which is the base URL.
+fn search_clause(table: &Table, base_url: &str, search: &SearchRequest) -> Result<(SearchClause, Vec<SqlValue>), SCIMError> {
+    if search.cursor.is_some() {
+        return Err(SCIMError::UnsupportedCapability("cursor".to_string()));
+    }
+    let schemas = get_schemas(table.schema_names.to_vec())?;
+    let mut query = Query { table, schemas: &schemas, params: vec![SqlValue::Text(base_url.to_string())] };
+
+    let filter = match &search.filter {
+        Some(filter) => format!(" WHERE {}", query.condition(&Filter::parse(filter)?, None)?),
+        None => String::new(),
+    };
+
+    let direction = match search.sort_order.as_deref() {
+        None => "ASC",
+        Some(order) if order.eq_ignore_ascii_case("ascending") => "ASC",
+        Some(order) if order.eq_ignore_ascii_case("descending") => "DESC",
+        Some(order) => return Err(SCIMError::InvalidFieldValue(format!("unknown sortOrder: {}", order))),
+    };
+    let mut page = match &search.sort_by {
+        Some(sort_by) => {
+            let path = match Filter::parse(&format!("{} pr", sort_by)) {
+                Ok(Filter::Present(path)) => path,
+                _ => return Err(SCIMError::InvalidFieldValue(format!("invalid sortBy: {}", sort_by))),
+            };
+            format!(r#" ORDER BY {} {} NULLS LAST, "t"."rowid""#, query.sort_expression(&path)?, direction)
+        }
+        None => r#" ORDER BY "t"."rowid""#.to_string(),
+    };
+    page.push_str(&format!(" LIMIT {} OFFSET {}", search.count.map_or(-1, |count| count.max(0)), search.start_index.unwrap_or(1).max(1) - 1));
+    Ok((SearchClause { filter, page }, query.params))
+}
+
+/// Where an attribute path is stored.
+enum Target<'a> {
+    /// A single value, read by an SQL expression.
+    Scalar { expression: String, type_: &'a str, case_exact: Option<bool> },
+    /// A single-valued complex attribute, read as a JSON object.
+    Object { expression: String, sub_attributes: &'a [SubAttributes] },
+    /// A multi-valued attribute at `path` in the JSON `document`, optionally narrowed to one sub-attribute.
+    Array { document: String, path: String, attribute: &'a Attributes, sub_attribute: Option<&'a SubAttributes> },
+}
+
+impl Target<'_> {
+    fn elements(document: &str, path: &str) -> String {
+        format!("json_each({}, {}) AS \"v\"", document, literal(path))
+    }
+}
+
+/// The value compared in each element of a multi-valued attribute: a sub-attribute, or the element itself.
+struct ElementValue<'a> {
+    expression: String,
+    type_: &'a str,
+    case_exact: Option<bool>,
+}
+
+/// A filter being translated, with the parameters bound so far.
+struct Query<'a> {
+    table: &'a Table,
+    schemas: &'a [Schema],
+    params: Vec<SqlValue>,
+}
+
+impl<'a> Query<'a> {
+    fn bind(&mut self, value: SqlValue) -> String {
+        self.params.push(value);
+        format!("?{}", self.params.len())
+    }
+
+    /// Resolves an attribute path to an indexed column or to a value in the JSON of the resource.
+    fn resolve(&self, path: &AttributePath) -> Result<Target<'a>, SCIMError> {
+        let unknown = || SCIMError::InvalidFieldValue(format!("unknown attribute path: {}", path));
+        let schemas: &'a [Schema] = self.schemas;
+        let (attributes, document) = match &path.urn {
+            Some(urn) => {
+                let extension = schemas.iter().skip(1).find(|extension| extension.id.eq_ignore_ascii_case(urn)).ok_or_else(unknown)?;
+                let document = format!(r#"(SELECT "data" FROM "scim_extensions" WHERE "resource_id" = "t"."id" AND "urn" = {})"#, literal(&extension.id));
+                (&extension.attributes, document)
+            }
+            None => {
+                let common = match (path.attribute.to_ascii_lowercase().as_str(), path.sub_attribute.as_ref().map(|sub| sub.to_ascii_lowercase())) {
+                    ("id", None) => Some(("id", "string")),
+                    ("externalid", None) => Some(("external_id", "string")),
+                    ("meta", Some(ref sub)) if sub == "created" => Some(("created", "dateTime")),
+                    ("meta", Some(ref sub)) if sub == "lastmodified" => Some(("last_modified", "dateTime")),
+                    _ => None,
+                };
+                if let Some((column, type_)) = common {
+                    return Ok(Target::Scalar { expression: format!(r#""t"."{}""#, column), type_, case_exact: Some(true) });
+                }
+                let core = schemas.first().ok_or_else(unknown)?;
+                (&core.attributes, r#""t"."resource""#.to_string())
+            }
+        };
+
+        let attribute = attributes.iter().find(|attribute| attribute.name.eq_ignore_ascii_case(&path.attribute)).ok_or_else(unknown)?;
+        if attribute.returned.as_deref() == Some("never") {
+            return Err(SCIMError::InvalidFieldValue(format!("attribute cannot be filtered: {}", path)));
+        }
+        let sub_attribute = match &path.sub_attribute {
+            Some(name) => Some(
+                attribute.sub_attributes.iter().flatten()
+                    .find(|sub| sub.name.eq_ignore_ascii_case(name))
+                    .ok_or_else(unknown)?,
+            ),
+            None => None,
+        };
+        if path.urn.is_none() && attribute.name == self.table.key.1 {
+            let expression = format!(r#""t"."{}""#, self.table.key.0);
+            return Ok(Target::Scalar { expression, type_: &attribute.type_, case_exact: attribute.case_exact });
+        }
+        let (document, json_path) = if path.urn.is_none() && attribute.name == self.table.related.0 {
+            (self.table.related.1.to_string(), "$".to_string())
+        } else {
+            (document, format!("$.\"{}\"", attribute.name))
+        };
+
+        Ok(if attribute.multi_valued {
+            Target::Array { document, path: json_path, attribute, sub_attribute }
+        } else if let Some(sub) = sub_attribute {
+            let expression = format!("json_extract({}, {})", document, literal(&format!("{}.\"{}\"", json_path, sub.name)));
+            Target::Scalar { expression, type_: &sub.type_, case_exact: sub.case_exact }
+        } else if attribute.type_ == "complex" {
+            let expression = format!("json_extract({}, {})", document, literal(&json_path));
+            Target::Object { expression, sub_attributes: attribute.sub_attributes.as_deref().unwrap_or(&[]) }
+        } else {
+            let expression = format!("json_extract({}, {})", document, literal(&json_path));
+            Target::Scalar { expression, type_: &attribute.type_, case_exact: attribute.case_exact }
+        })
+    }
+
+    /// Translates a filter into a boolean SQL expression, relative to the complex value `scope` inside a value filter
+    /// such as `emails[type eq "work"]`.
+    fn condition(&mut self, filter: &Filter, scope: Option<(&str, &'a [SubAttributes])>) -> Result<String, SCIMError> {
+        let target = |query: &Query<'a>, path: &AttributePath| -> Result<Target<'a>, SCIMError> {
+            match scope {
+                None => query.resolve(path),
+                Some((expression, sub_attributes)) => {
+                    let sub = match (&path.urn, &path.sub_attribute) {
+                        (None, None) => sub_attributes.iter().find(|sub| sub.name.eq_ignore_ascii_case(&path.attribute)),
+                        _ => None,
+                    };
+                    let sub = sub.ok_or_else(|| SCIMError::InvalidFieldValue(format!("unknown attribute path in value filter: {}", path)))?;
+                    let expression = format!("json_extract({}, {})", expression, literal(&format!("$.\"{}\"", sub.name)));
+                    Ok(Target::Scalar { expression, type_: &sub.type_, case_exact: sub.case_exact })
+                }
+            }
+        };
+        match filter {
+            Filter::And(left, right) => Ok(format!("({} AND {})", self.condition(left, scope)?, self.condition(right, scope)?)),
+            Filter::Or(left, right) => Ok(format!("({} OR {})", self.condition(left, scope)?, self.condition(right, scope)?)),
+            Filter::Not(filter) => Ok(format!("NOT coalesce({}, 0)", self.condition(filter, scope)?)),
+            Filter::Present(path) => present(target(self, path)?),
+            Filter::Compare(path, operator, Value::Null) => match operator {
+                Operator::Eq => Ok(format!("NOT coalesce({}, 0)", present(target(self, path)?)?)),
+                Operator::Ne => present(target(self, path)?),
+                _ => Err(SCIMError::InvalidFieldValue(format!("cannot compare {} {} null", path, operator))),
+            },
+            Filter::Compare(path, operator, value) => match target(self, path)? {
+                Target::Scalar { expression, type_, case_exact } => self.compare(&expression, type_, case_exact, *operator, value),
+                Target::Array { document, path: json_path, attribute, sub_attribute } => {
+                    let element = element_value(attribute, sub_attribute)
+                        .ok_or_else(|| SCIMError::InvalidFieldValue(format!("cannot compare complex attribute: {}", path)))?;
+                    // A multi-valued attribute is not equal to a value when none of its values is.
+                    let (negate, operator) = match operator {
+                        Operator::Ne => ("NOT ", Operator::Eq),
+                        operator => ("", *operator),
+                    };
+                    let comparison = self.compare(&element.expression, element.type_, element.case_exact, operator, value)?;
+                    Ok(format!("{}EXISTS (SELECT 1 FROM {} WHERE {})", negate, Target::elements(&document, &json_path), comparison))
+                }
+                Target::Object { .. } => Err(SCIMError::InvalidFieldValue(format!("cannot compare complex attribute: {}", path))),
+            },
+            Filter::ValuePath(path, filter) => {
+                if scope.is_some() {
+                    return Err(SCIMError::InvalidFieldValue(format!("value filters cannot be nested: {}", path)));
+                }
+                match target(self, path)? {
+                    Target::Object { expression, sub_attributes } => self.condition(filter, Some((&expression, sub_attributes))),
+                    Target::Array { document, path: json_path, attribute, sub_attribute: None } if attribute.sub_attributes.is_some() => {
+                        let sub_attributes = attribute.sub_attributes.as_deref().unwrap_or(&[]);
+                        let condition = self.condition(filter, Some((r#""v"."value""#, sub_attributes)))?;
+                        Ok(format!("EXISTS (SELECT 1 FROM {} WHERE {})", Target::elements(&document, &json_path), condition))
+                    }
+                    _ => Err(SCIMError::InvalidFieldValue(format!("value filters need a complex attribute: {}", path))),
+                }
+            }
+        }
+    }
+
+    /// Returns the condition comparing a scalar expression with a filter value of the attribute's type.
+    fn compare(&mut self, expression: &str, type_: &str, case_exact: Option<bool>, operator: Operator, value: &Value) -> Result<String, SCIMError> {
+        let invalid = || SCIMError::InvalidFieldValue(format!("cannot compare a {} attribute with {} {}", type_, operator, value));
+        let (expression, param) = match (type_, value) {
+            ("boolean", Value::Bool(value)) => {
+                let param = self.bind(SqlValue::Integer(i64::from(*value)));
+                return match operator {
+                    Operator::Eq => Ok(format!("{} = {}", expression, param)),
+                    Operator::Ne => Ok(format!("{} IS NOT {}", expression, param)),
+                    _ => Err(invalid()),
+                };
+            }
+            ("integer", Value::Number(number)) | ("decimal", Value::Number(number)) => {
+                let param = match number.as_i64() {
+                    Some(integer) => SqlValue::Integer(integer),
+                    None => SqlValue::Real(number.as_f64().ok_or_else(invalid)?),
+                };
+                (expression.to_string(), self.bind(param))
+            }
+            ("dateTime", Value::String(value)) => {
+                let param = self.bind(SqlValue::Text(value.clone()));
+                (format!("julianday({})", expression), format!("julianday({})", param))
+            }
+            ("boolean", _) | ("integer", _) | ("decimal", _) | ("dateTime", _) | ("complex", _) => return Err(invalid()),
+            (_, Value::String(value)) => {
+                let param = case_fold(self.bind(SqlValue::Text(value.clone())), case_exact);
+                let expression = case_fold(expression.to_string(), case_exact);
+                return Ok(match operator {
+                    Operator::Ne => format!("{} IS NOT {}", expression, param),
+                    Operator::Co => format!("instr({}, {}) > 0", expression, param),
+                    Operator::Sw => format!("substr({0}, 1, length({1})) = {1}", expression, param),
+                    Operator::Ew => format!("substr({0}, length({0}) - length({1}) + 1) = {1}", expression, param),
+                    operator => format!("{} {} {}", expression, comparison_operator(operator), param),
+                });
+            }
+            _ => return Err(invalid()),
+        };
+        match operator {
+            Operator::Ne => Ok(format!("{} IS NOT {}", expression, param)),
+            Operator::Co | Operator::Sw | Operator::Ew => Err(invalid()),
+            operator => Ok(format!("{} {} {}", expression, comparison_operator(operator), param)),
+        }
+    }
+
+    /// Returns the `ORDER BY` expression for an attribute path. Multi-valued attributes sort by their primary value,
+    /// or by their first value if none is primary.
+    fn sort_expression(&self, path: &AttributePath) -> Result<String, SCIMError> {
+        let invalid = || SCIMError::InvalidFieldValue(format!("cannot sort by complex attribute: {}", path));
+        let (expression, type_, case_exact) = match self.resolve(path)? {
+            Target::Scalar { expression, type_, case_exact } => (expression, type_, case_exact),
+            Target::Array { document, path: json_path, attribute, sub_attribute } => {
+                let element = element_value(attribute, sub_attribute).ok_or_else(invalid)?;
+                let primary = match &attribute.sub_attributes {
+                    Some(subs) if subs.iter().any(|sub| sub.name == "primary") => r#"coalesce(json_extract("v"."value", '$.primary'), 0) DESC, "#,
+                    _ => "",
+                };
+                let expression = format!(
+                    r#"(SELECT {} FROM {} ORDER BY {}"v"."key" LIMIT 1)"#,
+                    element.expression,
+                    Target::elements(&document, &json_path),
+                    primary
+                );
+                (expression, element.type_, element.case_exact)
+            }
+            Target::Object { .. } => return Err(invalid()),
+        };
+        Ok(match type_ {
+            "dateTime" => format!("julianday({})", expression),
+            type_ if is_string_type(type_) => case_fold(expression, case_exact),
+            _ => expression,
+        })
+    }
+}
+
+/// Returns the condition for `attrPath pr`: the attribute has a non-null, non-empty value.
+fn present(target: Target) -> Result<String, SCIMError> {
+    match target {
+        Target::Scalar { expression, type_, .. } if is_string_type(type_) => Ok(format!("coalesce({}, '') <> ''", expression)),
+        Target::Scalar { expression, .. } => Ok(format!("({} IS NOT NULL)", expression)),
+        Target::Object { expression, .. } => Ok(format!("coalesce({} <> '{{}}', 0)", expression)),
+        Target::Array { document, path, attribute, sub_attribute } => match sub_attribute.and_then(|sub| element_value(attribute, Some(sub))) {
+            Some(element) => {
+                let present = present(Target::Scalar { expression: element.expression, type_: element.type_, case_exact: None })?;
+                Ok(format!("EXISTS (SELECT 1 FROM {} WHERE {})", Target::elements(&document, &path), present))
+            }
+            None => Ok(format!("coalesce(json_array_length({}, {}) > 0, 0)", document, literal(&path))),
+        },
+    }
+}
+
+/// Returns the value compared in each element of a multi-valued attribute. Complex attributes compare the named
+/// sub-attribute, or `value` if none is named.
+fn element_value<'a>(attribute: &'a Attributes, sub_attribute: Option<&'a SubAttributes>) -> Option<ElementValue<'a>> {
+    let sub = match (sub_attribute, attribute.sub_attributes.as_deref()) {
+        (Some(sub), _) => sub,
+        (None, None) => return Some(ElementValue { expression: r#""v"."value""#.to_string(), type_: &attribute.type_, case_exact: attribute.case_exact }),
+        (None, Some(subs)) => subs.iter().find(|sub| sub.name == "value")?,
+    };
+    let expression = format!(r#"json_extract("v"."value", {})"#, literal(&format!("$.\"{}\"", sub.name)));
+    Some(ElementValue { expression, type_: &sub.type_, case_exact: sub.case_exact })
+}
+
+fn comparison_operator(operator: Operator) -> &'static str {
+    match operator {
+        Operator::Gt => ">",
+        Operator::Ge => ">=",
+        Operator::Lt => "<",
+        Operator::Le => "<=",
+        _ => "=",
+    }
+}
+
+/// Registers `scim_lower`, which lower-cases text as `str::to_lowercase` does. SQLite's own `lower` only folds ASCII,
+/// so `ÄRGER` and `ärger` would otherwise be different user names here and the same one in `MemoryProvider`.
+fn register_lower(connection: &Connection) -> rusqlite::Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    connection.create_scalar_function("scim_lower", 1, flags, |context| match context.get_raw(0) {
+        ValueRef::Text(text) => Ok(SqlValue::Text(String::from_utf8_lossy(text).to_lowercase())),
+        _ => context.get::<SqlValue>(0),
+    })
+}
+
+/// Lower-cases a string expression with `scim_lower` unless the attribute is case-exact.
+fn case_fold(expression: String, case_exact: Option<bool>) -> String {
+    if case_exact == Some(true) {
+        expression
+    } else {
+        format!("scim_lower({})", expression)
+    }
+}
+
+fn is_string_type(type_: &str) -> bool {
+    matches!(type_, "string" | "reference" | "binary")
+}
+
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// A request as it is written to the database.
+struct Document {
+    /// The JSON of the core attributes, without the attribute stored in `scim_members`.
+    resource: String,
+    external_id: Option<String>,
+    /// The extension objects, keyed by URN.
+    extensions: Vec<(String, String)>,
+}
+
+/// Splits the JSON of a request into its core attributes and its extension objects.
+fn document<T: Serialize>(request: &T, related: &str) -> Result<Document, SCIMError> {
+    let mut resource = match serde_json::to_value(request).map_err(SCIMError::SerializationError)? {
+        Value::Object(object) => object,
+        _ => return Err(SCIMError::InvalidFieldValue("resource is not a JSON object".to_string())),
+    };
+    resource.remove(related);
+    let urns: Vec<String> = resource.iter()
+        .filter(|(key, value)| key.len() > 4 && key[..4].eq_ignore_ascii_case("urn:") && value.is_object())
+        .map(|(key, _)| key.clone())
+        .collect();
+    let extensions = urns.into_iter()
+        .filter_map(|urn| resource.remove(&urn).map(|data| (urn, data.to_string())))
+        .collect();
+    Ok(Document {
+        external_id: resource.get("externalId").and_then(Value::as_str).map(str::to_string),
+        resource: Value::Object(resource).to_string(),
+        extensions,
+    })
+}
+
+/// Applies a patch to the JSON of a stored resource, returning the request that writes the patched resource.
+fn patched<T: Serialize, R: DeserializeOwned>(resource: &T, patch: &PatchOp) -> Result<R, SCIMError> {
+    let mut resource = serde_json::to_value(resource).map_err(SCIMError::SerializationError)?;
+    apply(&mut resource, patch)?;
+    serde_json::from_value(resource).map_err(SCIMError::DeserializationError)
+}
+
+fn list_response<T>(resources: Vec<T>, total_results: i64, search: &SearchRequest) -> ListResponse<T> {
+    ListResponse {
+        items_per_page: resources.len() as i64,
+        total_results,
+        start_index: search.start_index.unwrap_or(1).max(1),
+        resources,
+        ..Default::default()
+    }
+}
+
+/// Runs `write` in a savepoint, which starts a transaction when none is open, and rolls it back if `write` fails.
+fn atomically<T>(connection: &Connection, write: impl FnOnce() -> Result<T, SCIMError>) -> Result<T, SCIMError> {
+    connection.execute_batch("SAVEPOINT scim_write").map_err(storage_error)?;
+    match write() {
+        Ok(value) => {
+            connection.execute_batch("RELEASE scim_write").map_err(storage_error)?;
+            Ok(value)
+        }
+        Err(error) => {
+            connection.execute_batch("ROLLBACK TO scim_write; RELEASE scim_write").map_err(storage_error)?;
+            Err(error)
+        }
+    }
+}
+
+fn storage_error(error: rusqlite::Error) -> SCIMError {
+    SCIMError::OtherError(format!("sqlite: {}", error))
+}
+
+/// Reports a violation of the unique index on `userName` as a conflict. Other constraint violations are storage errors.
+fn user_name_conflict(error: rusqlite::Error, user_name: &str) -> SCIMError {
+    match &error {
+        rusqlite::Error::SqliteFailure(failure, Some(message))
+            if failure.code == ErrorCode::ConstraintViolation && message.contains("scim_users_user_name") =>
+        {
+            SCIMError::ConflictError(format!("userName {} is already taken", user_name.to_lowercase()))
+        }
+        _ => storage_error(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::models::enterprise_user::EnterpriseUser;
+    use crate::models::group::Member;
+    use crate::models::user::Email;
+
+    use super::*;
+
+    fn provider() -> SqliteProvider {
+        SqliteProvider::open_in_memory("https://example.com/v2").unwrap()
+    }
+
+    fn user(user_name: &str) -> UserRequest {
+        UserRequest {
+            user_name: user_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn group(display_name: &str, members: &[Option<&str>]) -> GroupRequest {
+        GroupRequest {
+            display_name: display_name.to_string(),
+            members: Some(members.iter().map(|id| Member { value: id.map(str::to_string), ..Default::default() }).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn patch(operations: Value) -> PatchOp {
+        serde_json::from_value(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": operations
+        })).unwrap()
+    }
+
+    fn search(filter: &str) -> SearchRequest {
+        SearchRequest {
+            filter: Some(filter.to_string()),
+            count: Some(100),
+            ..Default::default()
+        }
+    }
+
+    fn user_names(response: ListResponse<StoredUser>) -> Vec<String> {
+        response.resources.into_iter().map(|user| user.user.user_name).collect()
+    }
+
+    #[test]
+    fn users_and_extension_data_persist_across_connections() {
+        let path = std::env::temp_dir().join(format!("scim_v2_sqlite_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let id = {
+            let provider = SqliteProvider::open(&path, "https://example.com/v2").unwrap();
+            let user = provider.create_user(UserRequest {
+                schemas: vec![
+                    "urn:ietf:params:scim:schemas:core:2.0:User".to_string(),
+                    "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User".to_string(),
+                ],
+                password: Some("t1meMa$heen".to_string()),
+                enterprise_user: Some(EnterpriseUser { employee_number: Some("701984".to_string()), ..Default::default() }),
+                ..user("bjensen")
+            }).unwrap();
+            assert_eq!(user.user.password, None);
+            user.id
+        };
+
+        let provider = SqliteProvider::open(&path, "https://example.com/v2").unwrap();
+        let user = provider.get_user(&id).unwrap();
+        let meta = user.meta;
+        assert_eq!(user.user.enterprise_user.unwrap().employee_number, Some("701984".to_string()));
+        assert_eq!(meta.location, Some(format!("https://example.com/v2/Users/{}", id)));
+        assert_eq!(meta.version, Some("W/\"1\"".to_string()));
+        assert_eq!(meta.created, meta.last_modified);
+        let extensions: i64 = provider.connection.lock().unwrap()
+            .query_row(r#"SELECT count(*) FROM "scim_extensions" WHERE "resource_id" = ?1"#, [&id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(extensions, 1);
+        drop(provider);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn user_name_is_unique_ignoring_case() {
+        let provider = provider();
+        provider.create_user(user("bjensen")).unwrap();
+        let other = provider.create_user(user("jsmith")).unwrap().id;
+
+        assert!(matches!(provider.create_user(user("BJensen")), Err(SCIMError::ConflictError(_))));
+        assert!(matches!(provider.replace_user(&other, user("bjensen")), Err(SCIMError::ConflictError(_))));
+        let rename = patch(json!([{"op": "replace", "path": "userName", "value": "BJENSEN"}]));
+        assert!(matches!(provider.patch_user(&other, &rename), Err(SCIMError::ConflictError(_))));
+        assert_eq!(provider.get_user(&other).unwrap().user.user_name, "jsmith");
+        assert!(matches!(provider.get_user("unknown"), Err(SCIMError::NotFoundError(_))));
+
+        provider.create_user(user("ÄRGER")).unwrap();
+        assert!(matches!(provider.create_user(user("ärger")), Err(SCIMError::ConflictError(_))));
+        assert_eq!(provider.search_users(&search(r#"userName eq "ärger""#)).unwrap().total_results, 1);
+        assert_eq!(provider.search_users(&search(r#"userName sw "Är""#)).unwrap().total_results, 1);
+    }
+
+    #[test]
+    fn only_the_user_name_index_reports_a_conflict() {
+        let violation = |message: &str| rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE),
+            Some(message.to_string()),
+        );
+
+        let conflict = user_name_conflict(violation("UNIQUE constraint failed: index 'scim_users_user_name'"), "BJensen");
+        assert!(matches!(conflict, SCIMError::ConflictError(message) if message == "userName bjensen is already taken"));
+        let other = user_name_conflict(violation("UNIQUE constraint failed: scim_members.group_id, scim_members.position"), "bjensen");
+        assert!(matches!(other, SCIMError::OtherError(_)));
+    }
+
+    #[test]
+    fn replace_and_patch_keep_password_and_update_meta() {
+        let provider = provider();
+        let created = provider.create_user(UserRequest {
+            password: Some("t1meMa$heen".to_string()),
+            ..user("bjensen")
+        }).unwrap();
+        let id = created.id.clone();
+
+        let replaced = provider.replace_user(&id, UserRequest {
+            display_name: Some("Babs Jensen".to_string()),
+            ..user("bjensen")
+        }).unwrap();
+        assert_eq!(replaced.meta.created, created.meta.created);
+        assert_eq!(replaced.meta.version, Some("W/\"2\"".to_string()));
+
+        let deactivate = patch(json!([{"op": "replace", "path": "active", "value": false}]));
+        let patched = provider.patch_user(&id, &deactivate).unwrap();
+        assert_eq!(patched.user.active, Some(false));
+        assert_eq!(patched.user.display_name, Some("Babs Jensen".to_string()));
+        let session = provider.connection.lock().unwrap();
+        let stored = Session { connection: &session, base_url: "https://example.com/v2" }.user(&id).unwrap();
+        assert_eq!(stored.user.password, Some("t1meMa$heen".to_string()));
+    }
+
+    #[test]
+    fn conditional_writes_check_the_current_version() {
+        let provider = provider();
+        let id = provider.create_user(user("bjensen")).unwrap().id;
+        let rename = patch(json!([{"op": "replace", "path": "displayName", "value": "Babs"}]));
+
+        assert!(matches!(provider.replace_user_if_match(&id, user("babs"), r#"W/"2""#), Err(SCIMError::PreconditionFailed(_))));
+        assert!(matches!(provider.patch_user_if_match(&id, &rename, "2"), Err(SCIMError::InvalidFieldValue(_))));
+        assert_eq!(provider.get_user(&id).unwrap().meta.version, Some(r#"W/"1""#.to_string()));
+
+        let replaced = provider.replace_user_if_match(&id, user("babs"), r#"W/"1""#).unwrap();
+        assert_eq!(replaced.user.user_name, "babs");
+        let patched = provider.patch_user_if_match(&id, &rename, r#"W/"2""#).unwrap();
+        assert_eq!(patched.meta.version, Some(r#"W/"3""#.to_string()));
+        assert!(matches!(provider.patch_user_if_match("unknown", &rename, "*"), Err(SCIMError::NotFoundError(_))));
+    }
+
+    #[test]
+    fn search_translates_filters_sorting_and_paging() {
+        let provider = provider();
+        for (user_name, title, emails) in [
+            ("carol", "Engineer", vec!["carol@example.com"]),
+            ("alice", "Engineer", vec!["alice@example.org", "ALICE@example.com"]),
+            ("bob", "Manager", vec![]),
+            ("dave", "engineer", vec!["dave@example.com"]),
+        ] {
+            provider.create_user(UserRequest {
+                title: Some(title.to_string()),
+                emails: Some(emails.into_iter().map(|value| Email { value: Some(value.to_string()), ..Default::default() }).collect()),
+                ..user(user_name)
+            }).unwrap();
+        }
+
+        let request = SearchRequest {
+            sort_by: Some("userName".to_string()),
+            sort_order: Some("descending".to_string()),
+            start_index: Some(2),
+            count: Some(1),
+            ..search(r#"title eq "ENGINEER""#)
+        };
+        let response = provider.search_users(&request).unwrap();
+        assert_eq!(response.total_results, 3);
+        assert_eq!(response.start_index, 2);
+        assert_eq!(user_names(response), vec!["carol"]);
+
+        assert_eq!(user_names(provider.search_users(&search(r#"emails ew "@EXAMPLE.COM" and not (userName sw "c")"#)).unwrap()), vec!["alice", "dave"]);
+        assert_eq!(user_names(provider.search_users(&search(r#"emails[value co "org"] or userName eq "BOB""#)).unwrap()), vec!["alice", "bob"]);
+        assert_eq!(user_names(provider.search_users(&search("emails pr and meta.lastModified gt \"2000-01-01T00:00:00Z\"")).unwrap()), vec!["carol", "alice", "dave"]);
+        assert_eq!(user_names(provider.search_users(&search(r#"emails ne "carol@example.com" and userName lt "d""#)).unwrap()), vec!["alice", "bob"]);
+        assert_eq!(provider.search_users(&search("externalId eq null")).unwrap().total_results, 4);
+        assert!(matches!(provider.search_users(&search("title eq")), Err(SCIMError::InvalidSyntax(_))));
+        assert!(matches!(provider.search_users(&search(r#"password eq "x""#)), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(provider.search_users(&search(r#"unknown eq "x""#)), Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn user_name_filters_use_the_unique_index() {
+        let request = search(r#"userName eq "bjensen""#);
+        let (clause, params) = search_clause(&USERS, "https://example.com/v2", &request).unwrap();
+        assert_eq!(clause.filter, r#" WHERE scim_lower("t"."user_name") = scim_lower(?2)"#);
+
+        let provider = provider();
+        let connection = provider.connection.lock().unwrap();
+        let sql = format!(r#"EXPLAIN QUERY PLAN SELECT "t"."id" FROM "scim_users" AS "t"{}"#, clause.filter);
+        let mut statement = connection.prepare(&sql).unwrap();
+        let plan: Vec<String> = statement.query_map(params_from_iter(params), |row| row.get(3)).unwrap().map(Result::unwrap).collect();
+        assert!(plan.iter().any(|step| step.contains("USING INDEX scim_users_user_name")), "{:?}", plan);
+    }
+
+    #[test]
+    fn user_groups_follow_group_members() {
+        let provider = provider();
+        let babs = provider.create_user(user("bjensen")).unwrap().id;
+        let mary = provider.create_user(UserRequest {
+            display_name: Some("Mary Smith".to_string()),
+            ..user("mary")
+        }).unwrap().id;
+        let guides = provider.create_group(group("Tour Guides", &[Some(&babs), Some(&mary)])).unwrap();
+        let staff = provider.create_group(group("Staff", &[Some(&guides.id)])).unwrap();
+
+        let member = &guides.group.members.as_ref().unwrap()[1];
+        assert_eq!(member.type_, Some("User".to_string()));
+        assert_eq!(member.ref_, Some(format!("https://example.com/v2/Users/{}", mary)));
+        assert_eq!(member.display, Some("Mary Smith".to_string()));
+
+        let user = provider.get_user(&babs).unwrap();
+        assert_eq!(serde_json::to_value(&user.groups).unwrap(), json!([
+            {"value": guides.id, "$ref": format!("https://example.com/v2/Groups/{}", guides.id), "display": "Tour Guides", "type": "direct"},
+            {"value": staff.id, "$ref": format!("https://example.com/v2/Groups/{}", staff.id), "display": "Staff", "type": "indirect"}
+        ]));
+        assert_eq!(user.meta.version, Some("W/\"3\"".to_string()));
+        let in_staff = search(&format!(r#"groups[value eq "{}" and type eq "indirect"]"#, staff.id));
+        assert_eq!(provider.search_users(&in_staff).unwrap().total_results, 2);
+        let with_mary = search(&format!(r#"members.value eq "{}""#, mary));
+        assert_eq!(provider.search_groups(&with_mary).unwrap().resources[0].id, guides.id);
+
+        let remove_mary = patch(json!([{"op": "remove", "path": format!(r#"members[value eq "{}"]"#, mary)}]));
+        let guides = provider.patch_group(&guides.id, &remove_mary).unwrap();
+        assert_eq!(guides.group.members.unwrap().len(), 1);
+        assert!(provider.get_user(&mary).unwrap().groups.is_none());
+
+        provider.delete_user(&babs).unwrap();
+        assert!(provider.get_group(&guides.id).unwrap().group.members.is_none());
+        provider.delete_group(&guides.id).unwrap();
+        assert!(provider.get_group(&staff.id).unwrap().group.members.is_none());
+        assert!(matches!(provider.delete_group(&guides.id), Err(SCIMError::NotFoundError(_))));
+        let unknown = group("Admins", &[Some("unknown")]);
+        assert!(matches!(provider.create_group(unknown), Err(SCIMError::InvalidFieldValue(_))));
+        assert_eq!(provider.search_groups(&SearchRequest::default()).unwrap().total_results, 1);
+    }
+
+    #[test]
+    fn failed_patch_leaves_the_user_unchanged() {
+        let provider = provider();
+        let id = provider.create_user(user("bjensen")).unwrap().id;
+
+        let invalid = patch(json!([
+            {"op": "replace", "path": "displayName", "value": "Babs"},
+            {"op": "replace", "path": "userName", "value": ""}
+        ]));
+        assert!(matches!(provider.patch_user(&id, &invalid), Err(SCIMError::MissingRequiredField(_))));
+
+        let user = provider.get_user(&id).unwrap();
+        assert_eq!(user.user.display_name, None);
+        assert_eq!(user.meta.version, Some("W/\"1\"".to_string()));
+    }
+
+    #[test]
+    fn bulk_rolls_back_failed_operations_and_commits_the_rest() {
+        let provider = provider();
+        let request: BulkRequest = serde_json::from_value(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:BulkRequest"],
+            "failOnErrors": 1,
+            "Operations": [
+                {"method": "POST", "path": "/Users", "bulkId": "alice", "data": {"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "Alice"}},
+                {"method": "POST", "path": "/Groups", "data": {
+                    "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+                    "displayName": "Admins",
+                    "members": [{"value": "bulkId:alice"}, {"value": "unknown"}]
+                }},
+                {"method": "POST", "path": "/Users", "data": {"schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"], "userName": "Bob"}}
+            ]
+        })).unwrap();
+
+        let response = provider.bulk(&request).unwrap();
+
+        let statuses: Vec<&str> = response.operations.iter().map(|operation| operation.status.as_str()).collect();
+        assert_eq!(statuses, vec!["201", "400"]);
+        assert_eq!(user_names(provider.search_users(&SearchRequest::default()).unwrap()), vec!["Alice"]);
+        assert_eq!(provider.search_groups(&SearchRequest::default()).unwrap().total_results, 0);
+    }
+
+    #[test]
+    fn provider_can_be_shared_between_threads() {
+        let provider = Arc::new(provider());
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let provider = Arc::clone(&provider);
+                thread::spawn(move || {
+                    for index in 0..10 {
+                        provider.create_user(user(&format!("user{}-{}", thread, index))).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(provider.search_users(&SearchRequest { count: Some(0), ..Default::default() }).unwrap().total_results, 40);
+    }
+}
@@ -0,0 +1,907 @@
+//! Postgres storage layout and queries generated from SCIM `Schema` definitions.
+//!
+//! Every resource type is stored in one table. Single-valued simple attributes become typed columns,
+//! complex and multi-valued attributes become `JSONB` columns, and each schema extension is kept in its
+//! own `JSONB` column. Attributes the caller wants to filter on get an index that matches how they are stored,
+//! and [`generate_select`] translates filters, sorting and pagination into queries that use those indexes.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::models::others::SearchRequest;
+use crate::models::scim_schema::{Attributes, Schema, SubAttributes};
+use crate::utils::error::SCIMError;
+use crate::utils::filter::{AttributePath, Filter, Operator};
+
+/// Postgres truncates identifiers longer than this many bytes.
+const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// `jsonb_build_object` takes at most 100 arguments, so resources are built from objects of at most 50 keys.
+const MAX_OBJECT_KEYS: usize = 50;
+
+/// A parameterised SQL statement.
+///
+/// Every parameter is bound as text (`$1::text`) and cast to the attribute's type inside the statement, so the
+/// parameters can be passed to any Postgres client as strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlQuery {
+    pub sql: String,
+    pub params: Vec<String>,
+}
+
+/// Generates the `CREATE TABLE` and `CREATE INDEX` statements for a resource type.
+///
+/// The table always has `id`, `external_id`, `created`, `last_modified` and `version` columns for the common
+/// attributes, followed by one column per attribute of `schema` and one `JSONB` column per extension.
+/// Attributes that are never returned, such as `password`, are stored in a `<name>_hash` column that should hold
+/// the output of a [`PasswordHasher`](crate::utils::password::PasswordHasher); they are never selected or filtered.
+/// Attributes with `uniqueness` of `server` or `global` get a unique index. Each path in `indexed_attributes`
+/// gets a lookup index; paths use SCIM attribute notation such as `userName`, `emails.value`, `externalId`
+/// or `urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:employeeNumber`. Lookups that an earlier index
+/// already serves are skipped, and index names longer than 63 bytes are shortened with a hash suffix.
+///
+/// # Parameters
+///
+/// * `table` - The name of the table to create.
+/// * `schema` - The core schema of the resource type.
+/// * `extensions` - The schema extensions registered for the resource type.
+/// * `indexed_attributes` - The attribute paths that should be indexed for filtering.
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` - The DDL statements, in the order they should be executed.
+/// * `Err(SCIMError::InvalidFieldValue)` - If the table name is too long or an indexed attribute path does not
+///   exist in the schemas.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::scim_schema::get_schemas;
+/// use scim_v2::storage::postgres::generate_ddl;
+///
+/// let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+/// let (core, extensions) = schemas.split_at(1);
+/// match generate_ddl("scim_users", &core[0], extensions, &["userName", "emails.value", "externalId"]) {
+///     Ok(statements) => {
+///         for statement in statements {
+///             println!("{};", statement);
+///         }
+///     }
+///     Err(e) => println!("Error generating DDL: {}", e),
+/// }
+/// ```
+pub fn generate_ddl(table: &str, schema: &Schema, extensions: &[Schema], indexed_attributes: &[&str]) -> Result<Vec<String>, SCIMError> {
+    if table.len() > MAX_IDENTIFIER_LENGTH {
+        return Err(SCIMError::InvalidFieldValue(format!("table name is longer than {} bytes: {}", MAX_IDENTIFIER_LENGTH, table)));
+    }
+    let mut columns = vec![
+        "\"id\" TEXT PRIMARY KEY".to_string(),
+        "\"external_id\" TEXT".to_string(),
+        "\"created\" TIMESTAMPTZ NOT NULL DEFAULT now()".to_string(),
+        "\"last_modified\" TIMESTAMPTZ NOT NULL DEFAULT now()".to_string(),
+        "\"version\" TEXT".to_string(),
+    ];
+    for attribute in &schema.attributes {
+        let mut column = if is_write_only(attribute) {
+            format!("{} TEXT", quote(&format!("{}_hash", column_name(&attribute.name))))
+        } else {
+            format!("{} {}", quote(&column_name(&attribute.name)), column_type(attribute))
+        };
+        if attribute.required == Some(true) {
+            column.push_str(" NOT NULL");
+        }
+        columns.push(column);
+    }
+    for extension in extensions {
+        columns.push(format!("{} JSONB", quote(&column_name(&extension.name))));
+    }
+
+    let mut statements = vec![format!("CREATE TABLE IF NOT EXISTS {} (\n    {}\n)", quote(table), columns.join(",\n    "))];
+    let mut indexed = Vec::new();
+
+    for attribute in &schema.attributes {
+        if attribute.multi_valued || attribute.type_ == "complex" || is_write_only(attribute) {
+            continue;
+        }
+        if matches!(attribute.uniqueness.as_deref(), Some("server") | Some("global")) {
+            let column = column_name(&attribute.name);
+            let target = format!("({})", case_fold(quote(&column), attribute.case_exact));
+            statements.push(format!("CREATE UNIQUE INDEX IF NOT EXISTS {} ON {} {}", quote(&index_name(table, &column, "key")), quote(table), target));
+            indexed.push(target);
+        }
+    }
+
+    for path in indexed_attributes {
+        let (key, target) = index_target(schema, extensions, path)?;
+        if !indexed.contains(&target) {
+            statements.push(format!("CREATE INDEX IF NOT EXISTS {} ON {} {}", quote(&index_name(table, &key, "idx")), quote(table), target));
+            indexed.push(target);
+        }
+    }
+    Ok(statements)
+}
+
+/// Generates the query that returns one page of the resources matching a search request.
+///
+/// Each row has a single `JSONB` column, `resource`, holding the stored resource in SCIM form with its `schemas`
+/// and `meta`. The `filter` is translated into a `WHERE` clause, `sortBy` and `sortOrder` into an `ORDER BY`
+/// (unassigned values sort last, multi-valued attributes sort by their primary or first value, and `id` breaks
+/// ties), and `startIndex` and `count` into `OFFSET` and `LIMIT`. `attributes` and `excludedAttributes` are left
+/// to the caller. Use [`generate_count`] for the `totalResults` of the same search.
+///
+/// # Parameters
+///
+/// * `table` - The table created by [`generate_ddl`].
+/// * `schema` - The core schema of the resource type.
+/// * `extensions` - The schema extensions registered for the resource type.
+/// * `request` - The search to translate.
+///
+/// # Returns
+///
+/// * `Ok(SqlQuery)` - The query and its parameters.
+/// * `Err(SCIMError::InvalidSyntax)` - If the filter cannot be parsed.
+/// * `Err(SCIMError::InvalidFieldValue)` - If the filter or `sortBy` names an unknown attribute, or compares an
+///   attribute with a value of the wrong type.
+/// * `Err(SCIMError::UnsupportedCapability)` - If the request asks for cursor pagination.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::SearchRequest;
+/// use scim_v2::models::scim_schema::get_schemas;
+/// use scim_v2::storage::postgres::generate_select;
+///
+/// let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+/// let request = SearchRequest {
+///     filter: Some(r#"emails.value eq "bjensen@example.com""#.to_string()),
+///     sort_by: Some("name.familyName".to_string()),
+///     count: Some(10),
+///     ..Default::default()
+/// };
+/// let query = generate_select("scim_users", &schemas[0], &schemas[1..], &request).unwrap();
+/// assert!(query.sql.contains(r#"WHERE lower(jsonb_path_query_array("emails", '$[*]."value"')::text)::jsonb @> jsonb_build_array(lower($1::text))"#));
+/// assert!(query.sql.ends_with(r#"ORDER BY lower(("name" ->> 'familyName')) ASC NULLS LAST, "id" LIMIT 10 OFFSET 0"#));
+/// assert_eq!(query.params, vec!["bjensen@example.com"]);
+/// ```
+pub fn generate_select(table: &str, schema: &Schema, extensions: &[Schema], request: &SearchRequest) -> Result<SqlQuery, SCIMError> {
+    if request.cursor.is_some() {
+        return Err(SCIMError::UnsupportedCapability("cursor".to_string()));
+    }
+    let mut params = Vec::new();
+    let mut sql = format!("SELECT {} AS \"resource\" FROM {}", resource_expression(schema, extensions), quote(table));
+    sql.push_str(&where_clause(schema, extensions, request.filter.as_deref(), &mut params)?);
+
+    let direction = match request.sort_order.as_deref() {
+        None => "ASC",
+        Some(order) if order.eq_ignore_ascii_case("ascending") => "ASC",
+        Some(order) if order.eq_ignore_ascii_case("descending") => "DESC",
+        Some(order) => return Err(SCIMError::InvalidFieldValue(format!("unknown sortOrder: {}", order))),
+    };
+    match &request.sort_by {
+        Some(sort_by) => {
+            let path = Filter::parse(&format!("{} pr", sort_by)).ok().and_then(|filter| match filter {
+                Filter::Present(path) => Some(path),
+                _ => None,
+            });
+            let path = path.ok_or_else(|| SCIMError::InvalidFieldValue(format!("invalid sortBy: {}", sort_by)))?;
+            sql.push_str(&format!(" ORDER BY {} {} NULLS LAST, \"id\"", sort_expression(schema, extensions, &path)?, direction));
+        }
+        None => sql.push_str(" ORDER BY \"id\""),
+    }
+
+    if let Some(count) = request.count {
+        sql.push_str(&format!(" LIMIT {}", count.max(0)));
+    }
+    sql.push_str(&format!(" OFFSET {}", request.start_index.unwrap_or(1).max(1) - 1));
+    Ok(SqlQuery { sql, params })
+}
+
+/// Generates the query that counts the resources matching a search request, for the `totalResults` of a list
+/// response. Sorting and pagination are ignored.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::SearchRequest;
+/// use scim_v2::models::scim_schema::get_schemas;
+/// use scim_v2::storage::postgres::generate_count;
+///
+/// let schemas = get_schemas(vec!["group"]).unwrap();
+/// let request = SearchRequest { filter: Some(r#"displayName sw "eng""#.to_string()), ..Default::default() };
+/// let query = generate_count("scim_groups", &schemas[0], &[], &request).unwrap();
+/// assert_eq!(query.sql, r#"SELECT count(*) FROM "scim_groups" WHERE starts_with(lower("display_name"), lower($1::text))"#);
+/// ```
+pub fn generate_count(table: &str, schema: &Schema, extensions: &[Schema], request: &SearchRequest) -> Result<SqlQuery, SCIMError> {
+    let mut params = Vec::new();
+    let mut sql = format!("SELECT count(*) FROM {}", quote(table));
+    sql.push_str(&where_clause(schema, extensions, request.filter.as_deref(), &mut params)?);
+    Ok(SqlQuery { sql, params })
+}
+
+/// Where an attribute path is stored in the table.
+enum Target<'a> {
+    /// A single value, read by an expression of the attribute's SQL type.
+    Scalar { expression: String, type_: &'a str, case_exact: Option<bool> },
+    /// A single-valued complex attribute, stored as a `JSONB` object.
+    Object { expression: String, sub_attributes: &'a [SubAttributes] },
+    /// A multi-valued attribute, stored as a `JSONB` array, optionally narrowed to one sub-attribute.
+    Array { expression: String, attribute: &'a Attributes, sub_attribute: Option<&'a SubAttributes> },
+}
+
+/// The value compared in each element of a multi-valued attribute: a sub-attribute, or the element itself.
+struct ElementValue<'a> {
+    key: Option<&'a str>,
+    type_: &'a str,
+    case_exact: Option<bool>,
+}
+
+/// Resolves an attribute path to where it is stored, with the snake_case key used to name its index.
+fn resolve<'a>(schema: &'a Schema, extensions: &'a [Schema], path: &AttributePath) -> Result<(Target<'a>, String), SCIMError> {
+    let unknown = || SCIMError::InvalidFieldValue(format!("unknown attribute path: {}", path));
+
+    let (attributes, object, mut key) = match &path.urn {
+        Some(urn) => {
+            let extension = extensions.iter().find(|extension| extension.id.eq_ignore_ascii_case(urn)).ok_or_else(unknown)?;
+            let column = column_name(&extension.name);
+            (&extension.attributes, Some(quote(&column)), format!("{}_", column))
+        }
+        None => {
+            let common = match (path.attribute.to_ascii_lowercase().as_str(), path.sub_attribute.as_ref().map(|sub| sub.to_ascii_lowercase())) {
+                ("id", None) => Some(("id", "string")),
+                ("externalid", None) => Some(("external_id", "string")),
+                ("meta", Some(ref sub)) if sub == "created" => Some(("created", "dateTime")),
+                ("meta", Some(ref sub)) if sub == "lastmodified" => Some(("last_modified", "dateTime")),
+                ("meta", Some(ref sub)) if sub == "version" => Some(("version", "string")),
+                _ => None,
+            };
+            if let Some((column, type_)) = common {
+                // The common attributes are all case-exact, so they are compared as stored.
+                return Ok((Target::Scalar { expression: quote(column), type_, case_exact: Some(true) }, column.to_string()));
+            }
+            (&schema.attributes, None, String::new())
+        }
+    };
+
+    let attribute = find_attribute(attributes, &path.attribute).ok_or_else(unknown)?;
+    if is_write_only(attribute) {
+        return Err(SCIMError::InvalidFieldValue(format!("attribute cannot be filtered: {}", path)));
+    }
+    key.push_str(&column_name(&attribute.name));
+    let sub_attribute = match &path.sub_attribute {
+        Some(name) => {
+            let sub = find_sub_attribute(attribute, name).ok_or_else(unknown)?;
+            key.push('_');
+            key.push_str(&column_name(&sub.name));
+            Some(sub)
+        }
+        None => None,
+    };
+
+    let value = match &object {
+        Some(object) => format!("({} -> {})", object, literal(&attribute.name)),
+        None => quote(&column_name(&attribute.name)),
+    };
+    let target = if attribute.multi_valued {
+        Target::Array { expression: value, attribute, sub_attribute }
+    } else if let Some(sub) = sub_attribute {
+        Target::Scalar { expression: json_scalar(&value, &sub.name, &sub.type_), type_: &sub.type_, case_exact: sub.case_exact }
+    } else if attribute.type_ == "complex" {
+        Target::Object { expression: value, sub_attributes: attribute.sub_attributes.as_deref().unwrap_or(&[]) }
+    } else {
+        let expression = match &object {
+            Some(object) => json_scalar(object, &attribute.name, &attribute.type_),
+            None => value,
+        };
+        Target::Scalar { expression, type_: &attribute.type_, case_exact: attribute.case_exact }
+    };
+    Ok((target, key))
+}
+
+/// Returns the index key and the `USING ... (expression)` part of the lookup index for an attribute path.
+fn index_target(schema: &Schema, extensions: &[Schema], path: &str) -> Result<(String, String), SCIMError> {
+    let not_found = || SCIMError::InvalidFieldValue(format!("unknown attribute path for index: {}", path));
+    let path = match Filter::parse(&format!("{} pr", path)) {
+        Ok(Filter::Present(path)) => path,
+        _ => return Err(not_found()),
+    };
+    let (target, key) = resolve(schema, extensions, &path).map_err(|_| not_found())?;
+    match target {
+        Target::Scalar { expression, type_, case_exact } if is_string_type(type_) => Ok((key, format!("({})", case_fold(expression, case_exact)))),
+        Target::Scalar { expression, .. } => Ok((key, format!("(({}))", expression))),
+        // Multi-valued attributes are searched by containment (`@>`) in the array of their (case-folded) values.
+        Target::Array { expression, attribute, sub_attribute } => {
+            let value = element_value(attribute, sub_attribute).ok_or_else(not_found)?;
+            let key = match (sub_attribute, value.key) {
+                (None, Some(sub)) => format!("{}_{}", key, column_name(sub)),
+                _ => key,
+            };
+            Ok((key, format!("USING GIN (({}) jsonb_path_ops)", array_values(&expression, &value))))
+        }
+        Target::Object { .. } => Err(not_found()),
+    }
+}
+
+/// Returns the `WHERE` clause for an optional filter, binding its values to `params`.
+fn where_clause(schema: &Schema, extensions: &[Schema], filter: Option<&str>, params: &mut Vec<String>) -> Result<String, SCIMError> {
+    match filter {
+        Some(filter) => {
+            let filter = Filter::parse(filter)?;
+            Ok(format!(" WHERE {}", condition(schema, extensions, &filter, None, params)?))
+        }
+        None => Ok(String::new()),
+    }
+}
+
+/// The complex value that the paths inside a value filter such as `emails[type eq "work"]` are relative to.
+struct Scope<'a> {
+    expression: String,
+    sub_attributes: &'a [SubAttributes],
+}
+
+/// Translates a filter into a boolean SQL expression. Comparisons are left bare so they can use indexes, and `not`
+/// treats their `NULL` results for unassigned attributes as false.
+fn condition<'a>(schema: &'a Schema, extensions: &'a [Schema], filter: &Filter, scope: Option<&Scope<'a>>, params: &mut Vec<String>) -> Result<String, SCIMError> {
+    let target = |path: &AttributePath| -> Result<Target<'a>, SCIMError> {
+        match scope {
+            None => Ok(resolve(schema, extensions, path)?.0),
+            Some(scope) => {
+                let sub = match (&path.urn, &path.sub_attribute) {
+                    (None, None) => scope.sub_attributes.iter().find(|sub| sub.name.eq_ignore_ascii_case(&path.attribute)),
+                    _ => None,
+                };
+                let sub = sub.ok_or_else(|| SCIMError::InvalidFieldValue(format!("unknown attribute path in value filter: {}", path)))?;
+                Ok(Target::Scalar { expression: json_scalar(&scope.expression, &sub.name, &sub.type_), type_: &sub.type_, case_exact: sub.case_exact })
+            }
+        }
+    };
+    match filter {
+        Filter::And(left, right) => Ok(format!(
+            "({} AND {})",
+            condition(schema, extensions, left, scope, params)?,
+            condition(schema, extensions, right, scope, params)?
+        )),
+        Filter::Or(left, right) => Ok(format!(
+            "({} OR {})",
+            condition(schema, extensions, left, scope, params)?,
+            condition(schema, extensions, right, scope, params)?
+        )),
+        Filter::Not(filter) => Ok(format!("NOT COALESCE({}, false)", condition(schema, extensions, filter, scope, params)?)),
+        Filter::Present(path) => present(target(path)?),
+        Filter::Compare(path, operator, Value::Null) => match operator {
+            Operator::Eq => Ok(format!("NOT COALESCE({}, false)", present(target(path)?)?)),
+            Operator::Ne => present(target(path)?),
+            _ => Err(SCIMError::InvalidFieldValue(format!("cannot compare {} {} null", path, operator))),
+        },
+        Filter::Compare(path, operator, value) => match target(path)? {
+            Target::Scalar { expression, type_, case_exact } => compare(&expression, type_, case_exact, *operator, value, params),
+            Target::Array { expression, attribute, sub_attribute } => {
+                let element = element_value(attribute, sub_attribute)
+                    .ok_or_else(|| SCIMError::InvalidFieldValue(format!("cannot compare complex attribute: {}", path)))?;
+                if *operator == Operator::Eq && value.is_string() && is_string_type(element.type_) {
+                    // Matches the expression of the GIN index created by `generate_ddl`.
+                    let param = case_fold(bind(params, value), element.case_exact);
+                    return Ok(format!("{} @> jsonb_build_array({})", array_values(&expression, &element), param));
+                }
+                let comparison = compare(&element_expression("\"v\"", &element), element.type_, element.case_exact, *operator, value, params)?;
+                Ok(format!("EXISTS (SELECT 1 FROM {} AS \"v\" WHERE {})", array_elements(&expression), comparison))
+            }
+            Target::Object { .. } => Err(SCIMError::InvalidFieldValue(format!("cannot compare complex attribute: {}", path))),
+        },
+        Filter::ValuePath(path, filter) => {
+            if scope.is_some() {
+                return Err(SCIMError::InvalidFieldValue(format!("value filters cannot be nested: {}", path)));
+            }
+            match target(path)? {
+                Target::Object { expression, sub_attributes } => {
+                    let scope = Scope { expression, sub_attributes };
+                    condition(schema, extensions, filter, Some(&scope), params)
+                }
+                Target::Array { expression, attribute, sub_attribute: None } if attribute.sub_attributes.is_some() => {
+                    let scope = Scope { expression: "\"v\"".to_string(), sub_attributes: attribute.sub_attributes.as_deref().unwrap_or(&[]) };
+                    let condition = condition(schema, extensions, filter, Some(&scope), params)?;
+                    Ok(format!("EXISTS (SELECT 1 FROM {} AS \"v\" WHERE {})", array_elements(&expression), condition))
+                }
+                _ => Err(SCIMError::InvalidFieldValue(format!("value filters need a complex attribute: {}", path))),
+            }
+        }
+    }
+}
+
+/// Returns the condition for `attrPath pr`: the attribute has a non-null, non-empty value.
+fn present(target: Target) -> Result<String, SCIMError> {
+    match target {
+        Target::Scalar { expression, type_, .. } if is_string_type(type_) => Ok(format!("{} <> ''", expression)),
+        Target::Scalar { expression, .. } => Ok(format!("({} IS NOT NULL)", expression)),
+        Target::Object { expression, .. } => Ok(format!("COALESCE(jsonb_typeof({0}) = 'object' AND {0} <> '{{}}'::jsonb, false)", expression)),
+        Target::Array { expression, attribute, sub_attribute } => match sub_attribute.and_then(|sub| element_value(attribute, Some(sub))) {
+            Some(element) => {
+                let present = present(Target::Scalar { expression: element_expression("\"v\"", &element), type_: element.type_, case_exact: None })?;
+                Ok(format!("EXISTS (SELECT 1 FROM {} AS \"v\" WHERE {})", array_elements(&expression), present))
+            }
+            None => Ok(format!("COALESCE(jsonb_typeof({0}) = 'array' AND {0} <> '[]'::jsonb, false)", expression)),
+        },
+    }
+}
+
+/// Returns the condition comparing a scalar expression with a filter value of the attribute's type.
+fn compare(expression: &str, type_: &str, case_exact: Option<bool>, operator: Operator, value: &Value, params: &mut Vec<String>) -> Result<String, SCIMError> {
+    let invalid = || SCIMError::InvalidFieldValue(format!("cannot compare a {} attribute with {} {}", type_, operator, value));
+    let cast = match (type_, value) {
+        ("boolean", Value::Bool(_)) => {
+            let param = bind(params, value);
+            return match operator {
+                Operator::Eq => Ok(format!("{} = {}::boolean", expression, param)),
+                Operator::Ne => Ok(format!("({} IS DISTINCT FROM {}::boolean)", expression, param)),
+                _ => Err(invalid()),
+            };
+        }
+        ("integer", Value::Number(_)) | ("decimal", Value::Number(_)) => "numeric",
+        ("dateTime", Value::String(_)) => "timestamptz",
+        ("boolean", _) | ("integer", _) | ("decimal", _) | ("dateTime", _) | ("complex", _) => return Err(invalid()),
+        (_, Value::String(_)) => {
+            let expression = case_fold(expression.to_string(), case_exact);
+            let param = case_fold(bind(params, value), case_exact);
+            let condition = match operator {
+                Operator::Ne => return Ok(format!("({} IS DISTINCT FROM {})", expression, param)),
+                Operator::Co => format!("strpos({}, {}) > 0", expression, param),
+                Operator::Sw => format!("starts_with({}, {})", expression, param),
+                Operator::Ew => format!("right({0}, length({1})) = {1}", expression, param),
+                operator => format!("{} {} {}", expression, comparison_operator(operator), param),
+            };
+            return Ok(condition);
+        }
+        _ => return Err(invalid()),
+    };
+    let param = format!("{}::{}", bind(params, value), cast);
+    match operator {
+        Operator::Ne => Ok(format!("({} IS DISTINCT FROM {})", expression, param)),
+        Operator::Co | Operator::Sw | Operator::Ew => Err(invalid()),
+        operator => Ok(format!("{} {} {}", expression, comparison_operator(operator), param)),
+    }
+}
+
+fn comparison_operator(operator: Operator) -> &'static str {
+    match operator {
+        Operator::Gt => ">",
+        Operator::Ge => ">=",
+        Operator::Lt => "<",
+        Operator::Le => "<=",
+        _ => "=",
+    }
+}
+
+/// Returns the `ORDER BY` expression for an attribute path.
+fn sort_expression(schema: &Schema, extensions: &[Schema], path: &AttributePath) -> Result<String, SCIMError> {
+    let invalid = || SCIMError::InvalidFieldValue(format!("cannot sort by complex attribute: {}", path));
+    match resolve(schema, extensions, path)?.0 {
+        Target::Scalar { expression, type_, case_exact } if is_string_type(type_) => Ok(case_fold(expression, case_exact)),
+        Target::Scalar { expression, .. } => Ok(expression),
+        // Multi-valued attributes sort by their primary value, or by their first value if none is primary.
+        Target::Array { expression, attribute, sub_attribute } => {
+            let element = element_value(attribute, sub_attribute).ok_or_else(invalid)?;
+            let mut value = element_expression("\"v\"", &element);
+            if is_string_type(element.type_) {
+                value = case_fold(value, element.case_exact);
+            }
+            Ok(format!(
+                "(SELECT {} FROM {} WITH ORDINALITY AS \"e\"(\"v\", \"n\") ORDER BY COALESCE((\"v\" ->> 'primary') = 'true', false) DESC, \"n\" LIMIT 1)",
+                value,
+                array_elements(&expression)
+            ))
+        }
+        Target::Object { .. } => Err(invalid()),
+    }
+}
+
+/// Returns the `jsonb_strip_nulls(...)` expression that rebuilds a stored row as a SCIM resource.
+fn resource_expression(schema: &Schema, extensions: &[Schema]) -> String {
+    let mut schemas = format!("jsonb_build_array({})", literal(&schema.id));
+    for extension in extensions {
+        schemas.push_str(&format!(
+            " || CASE WHEN {} IS NULL THEN '[]'::jsonb ELSE jsonb_build_array({}) END",
+            quote(&column_name(&extension.name)),
+            literal(&extension.id)
+        ));
+    }
+    let mut keys = vec![
+        ("schemas".to_string(), schemas),
+        ("id".to_string(), quote("id")),
+        ("externalId".to_string(), quote("external_id")),
+        (
+            "meta".to_string(),
+            format!("jsonb_build_object('resourceType', {}, 'created', \"created\", 'lastModified', \"last_modified\", 'version', \"version\")", literal(&schema.name)),
+        ),
+    ];
+    for attribute in schema.attributes.iter().filter(|attribute| !is_write_only(attribute)) {
+        keys.push((attribute.name.clone(), quote(&column_name(&attribute.name))));
+    }
+    for extension in extensions {
+        keys.push((extension.id.clone(), quote(&column_name(&extension.name))));
+    }
+    let objects: Vec<String> = keys
+        .chunks(MAX_OBJECT_KEYS)
+        .map(|chunk| {
+            let arguments: Vec<String> = chunk.iter().map(|(key, value)| format!("{}, {}", literal(key), value)).collect();
+            format!("jsonb_build_object({})", arguments.join(", "))
+        })
+        .collect();
+    format!("jsonb_strip_nulls({})", objects.join(" || "))
+}
+
+/// Returns the value compared in each element of a multi-valued attribute. Complex attributes compare the named
+/// sub-attribute, or `value` if none is named.
+fn element_value<'a>(attribute: &'a Attributes, sub_attribute: Option<&'a SubAttributes>) -> Option<ElementValue<'a>> {
+    let sub = match (sub_attribute, attribute.sub_attributes.as_deref()) {
+        (Some(sub), _) => sub,
+        (None, None) => return Some(ElementValue { key: None, type_: &attribute.type_, case_exact: attribute.case_exact }),
+        (None, Some(subs)) => subs.iter().find(|sub| sub.name == "value")?,
+    };
+    Some(ElementValue { key: Some(&sub.name), type_: &sub.type_, case_exact: sub.case_exact })
+}
+
+fn element_expression(element: &str, value: &ElementValue) -> String {
+    match value.key {
+        Some(key) => json_scalar(element, key, value.type_),
+        None => json_cast(format!("({} #>> '{{}}')", element), value.type_),
+    }
+}
+
+/// Returns the `JSONB` array of the values of a multi-valued attribute, lower-cased unless they are case-exact.
+fn array_values(expression: &str, value: &ElementValue) -> String {
+    let path = match value.key {
+        Some(key) => format!("$[*].\"{}\"", key),
+        None => "$[*]".to_string(),
+    };
+    let values = format!("jsonb_path_query_array({}, {})", expression, literal(&path));
+    if is_string_type(value.type_) && value.case_exact != Some(true) {
+        format!("lower({}::text)::jsonb", values)
+    } else {
+        values
+    }
+}
+
+fn array_elements(expression: &str) -> String {
+    format!("jsonb_array_elements(CASE WHEN jsonb_typeof({0}) = 'array' THEN {0} ELSE '[]'::jsonb END)", expression)
+}
+
+/// Returns the expression that reads a key of a `JSONB` object as the attribute's SQL type.
+fn json_scalar(object: &str, key: &str, type_: &str) -> String {
+    json_cast(format!("({} ->> {})", object, literal(key)), type_)
+}
+
+fn json_cast(text: String, type_: &str) -> String {
+    match type_ {
+        "boolean" => format!("{}::boolean", text),
+        "integer" => format!("{}::bigint", text),
+        "decimal" => format!("{}::numeric", text),
+        "dateTime" => format!("{}::timestamptz", text),
+        _ => text,
+    }
+}
+
+/// Adds a filter value to the parameters and returns its placeholder.
+fn bind(params: &mut Vec<String>, value: &Value) -> String {
+    params.push(match value {
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    });
+    format!("${}::text", params.len())
+}
+
+fn is_string_type(type_: &str) -> bool {
+    matches!(type_, "string" | "reference" | "binary")
+}
+
+/// Attributes that are never returned, such as `password`, are only ever written.
+fn is_write_only(attribute: &Attributes) -> bool {
+    attribute.returned.as_deref() == Some("never")
+}
+
+/// Returns `<table>_<key>_<suffix>`, shortened with a hash suffix if it would exceed the identifier limit.
+fn index_name(table: &str, key: &str, suffix: &str) -> String {
+    let name = format!("{}_{}_{}", table, key, suffix);
+    if name.len() <= MAX_IDENTIFIER_LENGTH {
+        return name;
+    }
+    let hash: String = Sha256::digest(name.as_bytes()).iter().take(4).map(|byte| format!("{:02x}", byte)).collect();
+    let mut end = MAX_IDENTIFIER_LENGTH - hash.len() - 1;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}_{}", &name[..end], hash)
+}
+
+fn find_attribute<'a>(attributes: &'a [Attributes], name: &str) -> Option<&'a Attributes> {
+    attributes.iter().find(|attribute| attribute.name.eq_ignore_ascii_case(name))
+}
+
+fn find_sub_attribute<'a>(attribute: &'a Attributes, name: &str) -> Option<&'a SubAttributes> {
+    attribute.sub_attributes.as_ref()?.iter().find(|sub| sub.name.eq_ignore_ascii_case(name))
+}
+
+/// Wraps an expression in `lower()` unless the attribute is case-exact, so lookups are case-insensitive.
+fn case_fold(expression: String, case_exact: Option<bool>) -> String {
+    if case_exact == Some(true) {
+        expression
+    } else {
+        format!("lower({})", expression)
+    }
+}
+
+fn column_type(attribute: &Attributes) -> &'static str {
+    if attribute.multi_valued {
+        return "JSONB";
+    }
+    match attribute.type_.as_str() {
+        "boolean" => "BOOLEAN",
+        "integer" => "BIGINT",
+        "decimal" => "NUMERIC",
+        "dateTime" => "TIMESTAMPTZ",
+        "complex" => "JSONB",
+        _ => "TEXT",
+    }
+}
+
+/// Converts a SCIM attribute name such as `userName` or `x509Certificates` into a snake_case column name.
+fn column_name(name: &str) -> String {
+    let mut column = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !column.ends_with('_') {
+                column.push('_');
+            }
+            column.push(c.to_ascii_lowercase());
+        } else if c.is_ascii_alphanumeric() {
+            column.push(c);
+        } else if !column.ends_with('_') {
+            column.push('_');
+        }
+    }
+    column
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::models::scim_schema::get_schemas;
+
+    use super::*;
+
+    #[test]
+    fn generate_ddl_creates_typed_and_jsonb_columns() {
+        let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+        let statements = generate_ddl("scim_users", &schemas[0], &schemas[1..], &[]).unwrap();
+
+        let create_table = &statements[0];
+        assert!(create_table.starts_with("CREATE TABLE IF NOT EXISTS \"scim_users\""));
+        assert!(create_table.contains("\"user_name\" TEXT NOT NULL"));
+        assert!(create_table.contains("\"active\" BOOLEAN"));
+        assert!(create_table.contains("\"name\" JSONB"));
+        assert!(create_table.contains("\"emails\" JSONB"));
+        assert!(create_table.contains("\"x509_certificates\" JSONB"));
+        assert!(create_table.contains("\"enterprise_user\" JSONB"));
+        assert!(create_table.contains("\"password_hash\" TEXT"));
+        assert!(!create_table.contains("\"password\" TEXT"));
+        assert_eq!(statements[1], "CREATE UNIQUE INDEX IF NOT EXISTS \"scim_users_user_name_key\" ON \"scim_users\" (lower(\"user_name\"))");
+        assert_eq!(statements.len(), 2);
+    }
+
+    #[test]
+    fn generate_ddl_indexes_filterable_attributes() {
+        let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+        let statements = generate_ddl(
+            "scim_users",
+            &schemas[0],
+            &schemas[1..],
+            &[
+                "userName",
+                "emails.value",
+                "emails",
+                "emails.type",
+                "externalId",
+                "name.familyName",
+                "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:employeeNumber",
+            ],
+        ).unwrap();
+
+        // `userName` is already served by its unique index, and `emails` compares the same values as `emails.value`.
+        assert_eq!(&statements[2..], &[
+            "CREATE INDEX IF NOT EXISTS \"scim_users_emails_value_idx\" ON \"scim_users\" USING GIN ((lower(jsonb_path_query_array(\"emails\", '$[*].\"value\"')::text)::jsonb) jsonb_path_ops)",
+            "CREATE INDEX IF NOT EXISTS \"scim_users_emails_type_idx\" ON \"scim_users\" USING GIN ((lower(jsonb_path_query_array(\"emails\", '$[*].\"type\"')::text)::jsonb) jsonb_path_ops)",
+            "CREATE INDEX IF NOT EXISTS \"scim_users_external_id_idx\" ON \"scim_users\" (\"external_id\")",
+            "CREATE INDEX IF NOT EXISTS \"scim_users_name_family_name_idx\" ON \"scim_users\" (lower((\"name\" ->> 'familyName')))",
+            "CREATE INDEX IF NOT EXISTS \"scim_users_enterprise_user_employee_number_idx\" ON \"scim_users\" (lower((\"enterprise_user\" ->> 'employeeNumber')))",
+        ]);
+    }
+
+    #[test]
+    fn generate_ddl_shortens_long_index_names() {
+        let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+        let table = "tenant_0123456789abcdef_scim_users";
+        let paths = [
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.displayName",
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.value",
+        ];
+        let statements = generate_ddl(table, &schemas[0], &schemas[1..], &paths).unwrap();
+
+        let names: Vec<&str> = statements[2..].iter().map(|statement| statement.split('"').nth(1).unwrap()).collect();
+        assert_eq!(names.len(), 2);
+        assert_ne!(names[0], names[1]);
+        for name in names {
+            assert_eq!(name.len(), MAX_IDENTIFIER_LENGTH);
+            assert!(name.starts_with("tenant_0123456789abcdef_scim_users_enterprise_user_man_"));
+        }
+        assert!(matches!(generate_ddl(&"t".repeat(64), &schemas[0], &[], &[]), Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn generate_ddl_rejects_unknown_attribute() {
+        let schemas = get_schemas(vec!["user", "group"]).unwrap();
+        for path in ["members.nonexistent", "members[type eq \"User\"]", "meta"] {
+            let result = generate_ddl("scim_groups", &schemas[1], &[], &[path]);
+            assert!(matches!(result, Err(SCIMError::InvalidFieldValue(_))), "{}", path);
+        }
+        assert!(matches!(generate_ddl("scim_users", &schemas[0], &[], &["password"]), Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn generate_select_translates_filters_to_typed_conditions() {
+        let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+        let request = SearchRequest {
+            filter: Some(r#"(active eq true or not (title pr)) and emails[type eq "work" and value ew "@example.com"] and meta.lastModified gt "2011-05-13T04:42:34Z" and urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.value eq "26118915""#.to_string()),
+            sort_by: Some("emails".to_string()),
+            sort_order: Some("descending".to_string()),
+            start_index: Some(11),
+            count: Some(5),
+            ..Default::default()
+        };
+        let query = generate_select("scim_users", &schemas[0], &schemas[1..], &request).unwrap();
+
+        let where_clause = query.sql.split_once(" WHERE ").unwrap().1.split_once(" ORDER BY ").unwrap().0;
+        assert_eq!(where_clause, concat!(
+            "((((\"active\" = $1::text::boolean OR NOT COALESCE(\"title\" <> '', false)) AND ",
+            "EXISTS (SELECT 1 FROM jsonb_array_elements(CASE WHEN jsonb_typeof(\"emails\") = 'array' THEN \"emails\" ELSE '[]'::jsonb END) AS \"v\" ",
+            "WHERE (lower((\"v\" ->> 'type')) = lower($2::text) AND right(lower((\"v\" ->> 'value')), length(lower($3::text))) = lower($3::text)))) AND ",
+            "\"last_modified\" > $4::text::timestamptz) AND ",
+            "lower(((\"enterprise_user\" -> 'manager') ->> 'value')) = lower($5::text))",
+        ));
+        assert_eq!(query.params, vec!["true", "work", "@example.com", "2011-05-13T04:42:34Z", "26118915"]);
+        assert!(query.sql.starts_with("SELECT jsonb_strip_nulls(jsonb_build_object('schemas', jsonb_build_array('urn:ietf:params:scim:schemas:core:2.0:User') || CASE WHEN \"enterprise_user\" IS NULL"));
+        assert!(!query.sql.contains("password"));
+        assert!(query.sql.ends_with("DESC NULLS LAST, \"id\" LIMIT 5 OFFSET 10"));
+    }
+
+    #[test]
+    fn generate_select_rejects_untranslatable_requests() {
+        let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+        let filters = [
+            "nickName eq",
+            "unknown eq \"x\"",
+            "password eq \"secret\"",
+            "active co \"t\"",
+            "active eq \"true\"",
+            "name eq \"Barbara\"",
+            "meta.created eq 5",
+            "name[givenName eq \"Barbara\"] and emails[value pr and display[value pr]]",
+        ];
+        for filter in filters {
+            let request = SearchRequest { filter: Some(filter.to_string()), ..Default::default() };
+            assert!(generate_select("scim_users", &schemas[0], &schemas[1..], &request).is_err(), "{}", filter);
+        }
+        let request = SearchRequest { sort_by: Some("name".to_string()), ..Default::default() };
+        assert!(matches!(generate_select("scim_users", &schemas[0], &[], &request), Err(SCIMError::InvalidFieldValue(_))));
+        let request = SearchRequest { cursor: Some(String::new()), ..Default::default() };
+        assert!(matches!(generate_select("scim_users", &schemas[0], &[], &request), Err(SCIMError::UnsupportedCapability(_))));
+    }
+
+    /// Runs `test` against a Postgres database: the one at `SCIM_TEST_POSTGRES_URL`, or a server spawned with the
+    /// `initdb` and `pg_ctl` binaries on the `PATH`. Panics if neither is available.
+    #[cfg(scim_postgres_tests)]
+    fn with_postgres(test: impl FnOnce(&mut postgres::Client)) {
+        use std::process::{Command, Stdio};
+
+        if let Ok(url) = std::env::var("SCIM_TEST_POSTGRES_URL") {
+            let mut client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+            return test(&mut client);
+        }
+
+        let directory = std::env::temp_dir().join(format!("scim_v2_postgres_{}", std::process::id()));
+        let data = directory.join("data");
+        let initdb = Command::new("initdb").arg("-D").arg(&data).args(["-A", "trust", "-U", "postgres"]).stdout(Stdio::null()).stderr(Stdio::null()).status();
+        if !matches!(initdb, Ok(status) if status.success()) {
+            let _ = std::fs::remove_dir_all(&directory);
+            panic!("set SCIM_TEST_POSTGRES_URL or put initdb and pg_ctl on the PATH (as a non-root user)");
+        }
+
+        struct Server(std::path::PathBuf);
+        impl Drop for Server {
+            fn drop(&mut self) {
+                let _ = Command::new("pg_ctl").arg("-D").arg(self.0.join("data")).args(["-m", "immediate", "stop"]).stdout(Stdio::null()).status();
+                let _ = std::fs::remove_dir_all(&self.0);
+            }
+        }
+        let server = Server(directory.clone());
+        let options = format!("-c listen_addresses='' -k {}", directory.display());
+        let started = Command::new("pg_ctl").arg("-D").arg(&data).args(["-w", "-o", &options, "-l"]).arg(directory.join("log")).arg("start").stdout(Stdio::null()).status().unwrap();
+        assert!(started.success(), "pg_ctl failed to start the server");
+
+        let mut client = postgres::Client::connect(&format!("host={} user=postgres dbname=postgres", directory.display()), postgres::NoTls).unwrap();
+        test(&mut client);
+        drop(server);
+    }
+
+    #[test]
+    #[cfg(scim_postgres_tests)]
+    #[ignore = "needs a PostgreSQL server: run with --ignored and SCIM_TEST_POSTGRES_URL, or initdb and pg_ctl on the PATH"]
+    fn generated_sql_runs_against_postgres() {
+        with_postgres(|client| {
+            let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+            let (schema, extensions) = (&schemas[0], &schemas[1..]);
+            let table = format!("scim_users_test_{}", std::process::id());
+            let indexed = ["emails.value", "externalId", "name.familyName", "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:employeeNumber"];
+
+            client.batch_execute(&format!("DROP TABLE IF EXISTS {}", quote(&table))).unwrap();
+            for statement in generate_ddl(&table, schema, extensions, &indexed).unwrap() {
+                client.batch_execute(&statement).unwrap();
+            }
+            let users = [
+                ("1", "bjensen", true, r#"{"familyName": "Jensen", "givenName": "Barbara"}"#, r#"[{"value": "BJensen@Example.com", "type": "work", "primary": true}, {"value": "babs@jensen.org", "type": "home"}]"#, Some(r#"{"employeeNumber": "701984", "manager": {"value": "2"}}"#)),
+                ("2", "jsmith", true, r#"{"familyName": "Smith"}"#, r#"[{"value": "zed@example.com", "type": "home"}, {"value": "jsmith@example.com", "type": "work", "primary": true}]"#, None),
+                ("3", "adoe", false, r#"{"familyName": "doe"}"#, "[]", Some(r#"{"employeeNumber": "42"}"#)),
+            ];
+            for (id, user_name, active, name, emails, enterprise) in users {
+                client.execute(
+                    format!("INSERT INTO {} (\"id\", \"user_name\", \"active\", \"name\", \"emails\", \"enterprise_user\", \"password_hash\") VALUES ($1, $2, $3, $4::text::jsonb, $5::text::jsonb, $6::text::jsonb, 'hash')", quote(&table)).as_str(),
+                    &[&id, &user_name, &active, &name, &emails, &enterprise],
+                ).unwrap();
+            }
+
+            let search = |client: &mut postgres::Client, request: SearchRequest| -> (Vec<String>, i64) {
+                let query = generate_select(&table, schema, extensions, &request).unwrap();
+                let params: Vec<&(dyn postgres::types::ToSql + Sync)> = query.params.iter().map(|param| param as _).collect();
+                let ids = client.query(query.sql.as_str(), &params).unwrap().iter().map(|row| row.get::<_, Value>(0)["id"].as_str().unwrap().to_string()).collect();
+                let query = generate_count(&table, schema, extensions, &request).unwrap();
+                let params: Vec<&(dyn postgres::types::ToSql + Sync)> = query.params.iter().map(|param| param as _).collect();
+                (ids, client.query_one(query.sql.as_str(), &params).unwrap().get(0))
+            };
+            let filtered = |client: &mut postgres::Client, filter: &str| search(client, SearchRequest { filter: Some(filter.to_string()), ..Default::default() }).0;
+
+            assert_eq!(filtered(client, r#"emails.value eq "bjensen@example.com""#), vec!["1"]);
+            assert_eq!(filtered(client, r#"emails[type eq "work" and value sw "JSMITH"]"#), vec!["2"]);
+            assert_eq!(filtered(client, r#"userName co "J" and active eq true"#), vec!["1", "2"]);
+            assert_eq!(filtered(client, r#"not (active eq true) or name.familyName eq "JENSEN""#), vec!["1", "3"]);
+            assert_eq!(filtered(client, "emails pr"), vec!["1", "2"]);
+            assert_eq!(filtered(client, "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:employeeNumber eq \"42\""), vec!["3"]);
+            assert_eq!(filtered(client, "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.value pr"), vec!["1"]);
+            assert_eq!(filtered(client, "externalId eq null and meta.created le \"2999-01-01T00:00:00Z\""), vec!["1", "2", "3"]);
+
+            let page = search(client, SearchRequest { sort_by: Some("emails.value".to_string()), sort_order: Some("descending".to_string()), start_index: Some(2), count: Some(1), ..Default::default() });
+            assert_eq!(page, (vec!["1".to_string()], 3));
+            let sorted = search(client, SearchRequest { filter: Some("name pr".to_string()), sort_by: Some("name.familyName".to_string()), ..Default::default() });
+            assert_eq!(sorted, (vec!["3".to_string(), "1".to_string(), "2".to_string()], 3));
+
+            let resource: Value = client.query_one(generate_select(&table, schema, extensions, &SearchRequest { count: Some(1), ..Default::default() }).unwrap().sql.as_str(), &[]).unwrap().get(0);
+            assert_eq!(resource["schemas"], serde_json::json!(["urn:ietf:params:scim:schemas:core:2.0:User", "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User"]));
+            assert_eq!(resource["meta"]["resourceType"], "User");
+            assert_eq!(resource["name"]["givenName"], "Barbara");
+            assert!(resource.get("password").is_none() && resource.get("externalId").is_none());
+
+            // The containment lookup uses the GIN index instead of scanning the table.
+            client.batch_execute("SET enable_seqscan = off").unwrap();
+            let query = generate_select(&table, schema, extensions, &SearchRequest { filter: Some(r#"emails.value eq "x""#.to_string()), ..Default::default() }).unwrap();
+            let plan: Vec<String> = client.query(format!("EXPLAIN {}", query.sql).as_str(), &[&query.params[0]]).unwrap().iter().map(|row| row.get(0)).collect();
+            assert!(plan.iter().any(|line| line.contains(&format!("{}_emails_value_idx", table))), "{:#?}", plan);
+
+            client.batch_execute(&format!("DROP TABLE {}", quote(&table))).unwrap();
+        });
+    }
+}
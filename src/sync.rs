@@ -0,0 +1,537 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::group::{Group, GroupRequest, Member};
+use crate::models::others::{ListResponse, PatchOp, PatchOperations, SearchRequest};
+use crate::models::user::User;
+use crate::provider::resource_provider::ResourceProvider;
+use crate::utils::error::SCIMError;
+use crate::utils::patch::diff;
+
+/// How source users are matched with the users already provisioned in the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MatchBy {
+    /// Match on `externalId`, compared exactly. Groups are matched on `externalId` too.
+    ExternalId,
+    /// Match on `userName`, ignoring case. Groups are matched on `displayName`, ignoring case.
+    UserName,
+    /// Match on the primary (or else first) email address, ignoring case. Groups are matched on `displayName`,
+    /// ignoring case.
+    Email,
+}
+
+/// What to do with target resources that have no counterpart in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Unmatched {
+    /// Leave them alone.
+    Ignore,
+    /// Set `active` to false. Groups have no `active` attribute and are left alone.
+    Deactivate,
+    /// Delete them.
+    Delete,
+}
+
+/// Options for computing a `UserPlan` and a `GroupPlan`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncOptions {
+    pub match_by: MatchBy,
+    pub unmatched: Unmatched,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            match_by: MatchBy::ExternalId,
+            unmatched: Unmatched::Deactivate,
+        }
+    }
+}
+
+/// The changes that make the users of a target match a source.
+///
+/// The plan serializes to JSON, so it can be printed for a dry run before it is applied.
+#[derive(Debug, Serialize, Default)]
+pub struct UserPlan {
+    /// Users to create with `POST /Users`.
+    pub creates: Vec<User>,
+    /// Target user ids and the `PatchOp` to send to each with `PATCH /Users/{id}`.
+    pub updates: Vec<(String, PatchOp)>,
+    /// Target user ids to deactivate, by sending a `PatchOp` from `deactivation()`.
+    pub deactivations: Vec<String>,
+    /// Target user ids to delete with `DELETE /Users/{id}`.
+    pub deletes: Vec<String>,
+    /// The target id of every matched source user, keyed by its match key.
+    pub user_ids: HashMap<String, String>,
+}
+
+/// The changes that make the groups of a target match a source.
+#[derive(Debug, Serialize, Default)]
+pub struct GroupPlan {
+    /// Groups to create with `POST /Groups`, with members already resolved to target user ids.
+    pub creates: Vec<Group>,
+    /// Target group ids and the `PatchOp` to send to each with `PATCH /Groups/{id}`.
+    pub updates: Vec<(String, PatchOp)>,
+    /// Target group ids to delete with `DELETE /Groups/{id}`.
+    pub deletes: Vec<String>,
+}
+
+impl UserPlan {
+    /// Returns whether the target already matches the source.
+    pub fn is_empty(&self) -> bool {
+        self.creates.is_empty() && self.updates.is_empty() && self.deactivations.is_empty() && self.deletes.is_empty()
+    }
+}
+
+impl GroupPlan {
+    /// Returns whether the target already matches the source.
+    pub fn is_empty(&self) -> bool {
+        self.creates.is_empty() && self.updates.is_empty() && self.deletes.is_empty()
+    }
+}
+
+/// Returns the `PatchOp` that deactivates a user.
+pub fn deactivation() -> PatchOp {
+    PatchOp {
+        operations: vec![PatchOperations {
+            op: "replace".to_string(),
+            path: Some("active".to_string()),
+            value: Some(Value::Bool(false)),
+        }],
+        ..Default::default()
+    }
+}
+
+/// Computes the changes that make the users of a target match the source.
+///
+/// `target` holds the users currently provisioned, as returned by the service provider, so each must have an `id`.
+/// Source users are created when unmatched and patched when they differ from their match; read-only (`groups`) and
+/// write-only (`password`) attributes are not compared. Target users without a source counterpart are handled as
+/// `options.unmatched` says.
+///
+/// Run it first, apply the creates, and add the ids the service provider assigned to `user_ids`. Then pass
+/// `user_ids` to `plan_groups` so group members can be resolved. `sync_users` and `sync_groups` do all of this
+/// against a `ResourceProvider`.
+///
+/// # Errors
+///
+/// * `SCIMError::MissingRequiredField` - If a source user has no match key or a target user has no `id`.
+/// * `SCIMError::ConflictError` - If two source users or two target users share a match key.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::user::{User, UserRequest};
+/// use scim_v2::sync::{plan_users, MatchBy, SyncOptions, Unmatched};
+///
+/// let user = |id: Option<&str>, user_name: &str, title: &str| User {
+///     id: id.map(|id| id.to_string()),
+///     body: UserRequest {
+///         user_name: user_name.to_string(),
+///         title: Some(title.to_string()),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// let source = vec![user(None, "bjensen", "Tour Guide"), user(None, "mpepperidge", "Pilot")];
+/// let target = vec![user(Some("2819c223"), "BJensen", "Guide"), user(Some("902c246b"), "jsmith", "Driver")];
+///
+/// let options = SyncOptions { match_by: MatchBy::UserName, unmatched: Unmatched::Deactivate };
+/// let plan = plan_users(&source, &target, &options).unwrap();
+///
+/// assert_eq!(plan.creates.len(), 1);
+/// assert_eq!(plan.updates[0].0, "2819c223");
+/// assert_eq!(plan.deactivations, vec!["902c246b".to_string()]);
+/// println!("{}", serde_json::to_string_pretty(&plan).unwrap());
+/// ```
+pub fn plan_users(source: &[User], target: &[User], options: &SyncOptions) -> Result<UserPlan, SCIMError> {
+    let mut targets: HashMap<String, &User> = HashMap::new();
+    for user in target {
+        let key = user_key(user, options.match_by)?;
+        if user.id.as_deref().map_or(true, str::is_empty) {
+            return Err(SCIMError::MissingRequiredField(format!("id of target user {}", key)));
+        }
+        if targets.insert(key.clone(), user).is_some() {
+            return Err(SCIMError::ConflictError(format!("several target users match {}", key)));
+        }
+    }
+
+    let mut plan = UserPlan::default();
+    let mut seen = HashSet::new();
+    for user in source {
+        let key = user_key(user, options.match_by)?;
+        if !seen.insert(key.clone()) {
+            return Err(SCIMError::ConflictError(format!("several source users match {}", key)));
+        }
+        match targets.remove(&key) {
+            None => plan.creates.push(copy(user)?),
+            Some(existing) => {
+                let id = existing.id.clone().unwrap_or_default();
+                let patch = diff(&comparable(existing, &["groups", "password"])?, &comparable(user, &["groups", "password"])?)?;
+                if !patch.operations.is_empty() {
+                    plan.updates.push((id.clone(), patch));
+                }
+                plan.user_ids.insert(key, id);
+            }
+        }
+    }
+
+    let mut unmatched: Vec<&User> = targets.into_values().collect();
+    unmatched.sort_by(|a, b| a.id.cmp(&b.id));
+    for user in unmatched {
+        let id = user.id.clone().unwrap_or_default();
+        match options.unmatched {
+            Unmatched::Ignore => {}
+            Unmatched::Deactivate if user.body.active == Some(false) => {}
+            Unmatched::Deactivate => plan.deactivations.push(id),
+            Unmatched::Delete => plan.deletes.push(id),
+        }
+    }
+    Ok(plan)
+}
+
+/// Computes the changes that make the groups of a target match the source.
+///
+/// The `value` of each source member is the match key of a source user (its `externalId`, `userName` or email, as
+/// set by `options.match_by`), and is resolved to a target user id through `user_ids`. Members are compared by
+/// `value` only. Unmatched target groups are deleted when `options.unmatched` is `Unmatched::Delete`.
+///
+/// # Errors
+///
+/// * `SCIMError::NotFoundError` - If a source member does not resolve to a target user id.
+/// * `SCIMError::MissingRequiredField` - If a source group has no match key or a target group has no `id`.
+/// * `SCIMError::ConflictError` - If two source groups or two target groups share a match key.
+pub fn plan_groups(source: &[Group], target: &[Group], user_ids: &HashMap<String, String>, options: &SyncOptions) -> Result<GroupPlan, SCIMError> {
+    let mut targets: HashMap<String, &Group> = HashMap::new();
+    for group in target {
+        let key = group_key(group, options.match_by)?;
+        if group.id.as_deref().map_or(true, str::is_empty) {
+            return Err(SCIMError::MissingRequiredField(format!("id of target group {}", key)));
+        }
+        if targets.insert(key.clone(), group).is_some() {
+            return Err(SCIMError::ConflictError(format!("several target groups match {}", key)));
+        }
+    }
+
+    let mut plan = GroupPlan::default();
+    let mut seen = HashSet::new();
+    for group in source {
+        let key = group_key(group, options.match_by)?;
+        if !seen.insert(key.clone()) {
+            return Err(SCIMError::ConflictError(format!("several source groups match {}", key)));
+        }
+
+        let mut desired = copy(group)?;
+        desired.body.members = resolve_members(group, user_ids, options.match_by)?;
+        match targets.remove(&key) {
+            None => plan.creates.push(desired),
+            Some(existing) => {
+                let mut current = copy(existing)?;
+                current.body.members = current.body.members.map(|members| members.into_iter().map(|member| Member { value: member.value, ..Default::default() }).collect());
+                let patch = diff(&current, &desired)?;
+                if !patch.operations.is_empty() {
+                    plan.updates.push((existing.id.clone().unwrap_or_default(), patch));
+                }
+            }
+        }
+    }
+
+    if options.unmatched == Unmatched::Delete {
+        let mut unmatched: Vec<String> = targets.into_values().map(|group| group.id.clone().unwrap_or_default()).collect();
+        unmatched.sort();
+        plan.deletes = unmatched;
+    }
+    Ok(plan)
+}
+
+/// Makes the users of a provider match the source, and returns the plan it applied.
+///
+/// The target users are read from `provider` page by page, planned with `plan_users`, and the plan is applied in
+/// order: creates, updates, deactivations, then deletes. The `user_ids` of the returned plan include the users it
+/// created, so they can be passed straight to `sync_groups`.
+///
+/// # Errors
+///
+/// The errors of `plan_users`, or the first error returned by the provider. Changes applied before an error are kept.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::group::{Group, GroupRequest, Member};
+/// use scim_v2::models::user::{User, UserRequest};
+/// use scim_v2::provider::memory::MemoryProvider;
+/// use scim_v2::provider::resource_provider::ResourceProvider;
+/// use scim_v2::sync::{sync_groups, sync_users, SyncOptions};
+///
+/// let provider = MemoryProvider::new("https://example.com/v2");
+/// let source = vec![User {
+///     body: UserRequest {
+///         external_id: Some("701984".to_string()),
+///         user_name: "bjensen".to_string(),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// }];
+/// let groups = vec![Group {
+///     body: GroupRequest {
+///         external_id: Some("g1".to_string()),
+///         display_name: "Tour Guides".to_string(),
+///         members: Some(vec![Member { value: Some("701984".to_string()), ..Default::default() }]),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// }];
+///
+/// let options = SyncOptions::default();
+/// let plan = sync_users(&provider, &source, &options).unwrap();
+/// sync_groups(&provider, &groups, &plan.user_ids, &options).unwrap();
+///
+/// let user = provider.get_user(&plan.user_ids["701984"]).unwrap();
+/// assert_eq!(user.groups.unwrap()[0].display, Some("Tour Guides".to_string()));
+/// assert!(sync_users(&provider, &source, &options).unwrap().is_empty());
+/// ```
+pub fn sync_users(provider: &dyn ResourceProvider, source: &[User], options: &SyncOptions) -> Result<UserPlan, SCIMError> {
+    let target: Vec<User> = read_all(|search| provider.search_users(search))?.into_iter().map(User::from).collect();
+    let mut plan = plan_users(source, &target, options)?;
+    for user in &plan.creates {
+        let created = provider.create_user(user.body.clone())?;
+        plan.user_ids.insert(user_key(user, options.match_by)?, created.id);
+    }
+    for (id, patch) in &plan.updates {
+        provider.patch_user(id, patch)?;
+    }
+    for id in &plan.deactivations {
+        provider.patch_user(id, &deactivation())?;
+    }
+    for id in &plan.deletes {
+        provider.delete_user(id)?;
+    }
+    Ok(plan)
+}
+
+/// Makes the groups of a provider match the source, and returns the plan it applied.
+///
+/// The target groups are read from `provider`, planned with `plan_groups`, and the plan is applied in order: creates,
+/// updates, then deletes. Run it after `sync_users`, with the `user_ids` of the plan that returned.
+///
+/// # Errors
+///
+/// The errors of `plan_groups`, or the first error returned by the provider. Changes applied before an error are kept.
+pub fn sync_groups(provider: &dyn ResourceProvider, source: &[Group], user_ids: &HashMap<String, String>, options: &SyncOptions) -> Result<GroupPlan, SCIMError> {
+    let target: Vec<Group> = read_all(|search| provider.search_groups(search))?.into_iter().map(Group::from).collect();
+    let plan = plan_groups(source, &target, user_ids, options)?;
+    for group in &plan.creates {
+        provider.create_group(GroupRequest::from(group.clone()))?;
+    }
+    for (id, patch) in &plan.updates {
+        provider.patch_group(id, patch)?;
+    }
+    for id in &plan.deletes {
+        provider.delete_group(id)?;
+    }
+    Ok(plan)
+}
+
+/// Reads every resource of a search, following `startIndex` until `totalResults` have been read.
+fn read_all<T>(search: impl Fn(&SearchRequest) -> Result<ListResponse<T>, SCIMError>) -> Result<Vec<T>, SCIMError> {
+    let mut resources = Vec::new();
+    loop {
+        let request = SearchRequest {
+            start_index: Some(i64::try_from(resources.len()).unwrap_or(i64::MAX).saturating_add(1)),
+            ..Default::default()
+        };
+        let page = search(&request)?;
+        let read = page.resources.is_empty();
+        resources.extend(page.resources);
+        if read || i64::try_from(resources.len()).map_or(true, |len| len >= page.total_results) {
+            return Ok(resources);
+        }
+    }
+}
+
+fn user_key(user: &User, match_by: MatchBy) -> Result<String, SCIMError> {
+    let key = match match_by {
+        MatchBy::ExternalId => user.body.external_id.clone(),
+        MatchBy::UserName => Some(user.body.user_name.to_lowercase()),
+        MatchBy::Email => user.body.emails.as_ref().and_then(|emails| {
+            emails.iter()
+                .find(|email| email.primary == Some(true))
+                .or_else(|| emails.first())
+                .and_then(|email| email.value.as_ref())
+                .map(|value| value.to_lowercase())
+        }),
+    };
+    key.filter(|key| !key.is_empty())
+        .ok_or_else(|| SCIMError::MissingRequiredField(format!("{:?} of user {}", match_by, user.body.user_name)))
+}
+
+fn group_key(group: &Group, match_by: MatchBy) -> Result<String, SCIMError> {
+    let key = match match_by {
+        MatchBy::ExternalId => group.body.external_id.clone(),
+        MatchBy::UserName | MatchBy::Email => Some(group.body.display_name.to_lowercase()),
+    };
+    key.filter(|key| !key.is_empty())
+        .ok_or_else(|| SCIMError::MissingRequiredField(format!("match key of group {}", group.body.display_name)))
+}
+
+fn resolve_members(group: &Group, user_ids: &HashMap<String, String>, match_by: MatchBy) -> Result<Option<Vec<Member>>, SCIMError> {
+    let members = match &group.body.members {
+        Some(members) => members,
+        None => return Ok(None),
+    };
+    members.iter()
+        .map(|member| {
+            let key = member.value.clone().unwrap_or_default();
+            let key = if match_by == MatchBy::ExternalId { key } else { key.to_lowercase() };
+            user_ids.get(&key)
+                .map(|id| Member { value: Some(id.clone()), ..Default::default() })
+                .ok_or_else(|| SCIMError::NotFoundError(format!("member {} of group {}", key, group.body.display_name)))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// Serializes a resource without the given attributes, for comparison.
+fn comparable(user: &User, skipped: &[&str]) -> Result<Value, SCIMError> {
+    let mut value = serde_json::to_value(user).map_err(SCIMError::SerializationError)?;
+    if let Some(object) = value.as_object_mut() {
+        for attribute in skipped {
+            object.remove(*attribute);
+        }
+    }
+    Ok(value)
+}
+
+fn copy<T: Serialize + serde::de::DeserializeOwned>(resource: &T) -> Result<T, SCIMError> {
+    let value = serde_json::to_value(resource).map_err(SCIMError::SerializationError)?;
+    serde_json::from_value(value).map_err(SCIMError::DeserializationError)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::models::user::{Email, UserRequest};
+    use crate::provider::memory::MemoryProvider;
+
+    use super::*;
+
+    fn user(id: Option<&str>, external_id: &str, user_name: &str) -> User {
+        User {
+            id: id.map(|id| id.to_string()),
+            body: UserRequest {
+                external_id: Some(external_id.to_string()),
+                user_name: user_name.to_string(),
+                emails: Some(vec![Email { value: Some(format!("{}@example.com", user_name)), primary: Some(true), ..Default::default() }]),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn group(id: &str, external_id: &str, display_name: &str, members: &[&str]) -> Group {
+        Group {
+            id: Some(id.to_string()).filter(|id| !id.is_empty()),
+            body: GroupRequest {
+                external_id: Some(external_id.to_string()),
+                display_name: display_name.to_string(),
+                members: Some(members.iter().map(|value| Member { value: Some(value.to_string()), display: Some("ignored".to_string()), ..Default::default() }).collect()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plan_users_creates_updates_and_deactivates() {
+        let source = vec![user(None, "e1", "bjensen"), user(None, "e2", "mpepperidge"), user(None, "e3", "new")];
+        let mut renamed = user(Some("id-2"), "e2", "mandy");
+        renamed.body.password = Some("hash".to_string());
+        let mut inactive = user(Some("id-5"), "e5", "gone");
+        inactive.body.active = Some(false);
+        let target = vec![user(Some("id-1"), "e1", "bjensen"), renamed, user(Some("id-4"), "e4", "jsmith"), inactive];
+
+        let plan = plan_users(&source, &target, &SyncOptions::default()).unwrap();
+
+        assert_eq!(plan.creates.iter().map(|user| user.body.user_name.as_str()).collect::<Vec<_>>(), vec!["new"]);
+        assert_eq!(plan.updates.len(), 1);
+        assert_eq!(plan.updates[0].0, "id-2");
+        let paths: Vec<_> = plan.updates[0].1.operations.iter().map(|operation| operation.path.clone().unwrap()).collect();
+        assert_eq!(paths, vec![r#"emails[value eq "mandy@example.com"]"#, "emails", "userName"]);
+        assert_eq!(plan.deactivations, vec!["id-4".to_string()]);
+        assert!(plan.deletes.is_empty());
+        assert_eq!(plan.user_ids.get("e1"), Some(&"id-1".to_string()));
+        assert!(serde_json::to_value(&plan).is_ok());
+    }
+
+    #[test]
+    fn plan_users_matches_by_user_name_or_email_and_rejects_duplicates() {
+        let source = vec![user(None, "x", "BJensen")];
+        let target = vec![user(Some("id-1"), "x", "bjensen")];
+
+        let by_name = plan_users(&source, &target, &SyncOptions { match_by: MatchBy::UserName, unmatched: Unmatched::Delete }).unwrap();
+        assert_eq!(by_name.user_ids.get("bjensen"), Some(&"id-1".to_string()));
+        let by_email = plan_users(&source, &target, &SyncOptions { match_by: MatchBy::Email, unmatched: Unmatched::Delete }).unwrap();
+        assert_eq!(by_email.user_ids.get("bjensen@example.com"), Some(&"id-1".to_string()));
+        assert!(by_email.deletes.is_empty());
+
+        let duplicates = vec![user(None, "x", "a"), user(None, "x", "b")];
+        assert!(matches!(plan_users(&duplicates, &[], &SyncOptions::default()), Err(SCIMError::ConflictError(_))));
+        assert!(matches!(plan_users(&[], &[user(None, "x", "a")], &SyncOptions::default()), Err(SCIMError::MissingRequiredField(_))));
+    }
+
+    #[test]
+    fn plan_groups_resolves_members_after_users() {
+        let options = SyncOptions { match_by: MatchBy::ExternalId, unmatched: Unmatched::Delete };
+        let source_users = vec![user(None, "e1", "bjensen"), user(None, "e2", "new")];
+        let target_users = vec![user(Some("id-1"), "e1", "bjensen")];
+        let mut user_ids = plan_users(&source_users, &target_users, &options).unwrap().user_ids;
+        // The service provider assigned an id to the created user.
+        user_ids.insert("e2".to_string(), "id-2".to_string());
+
+        let source = vec![group("", "g1", "Tour Guides", &["e1", "e2"]), group("", "g2", "Pilots", &["e2"])];
+        let target = vec![group("gid-1", "g1", "Tour Guides", &["id-1"]), group("gid-3", "g3", "Old", &[])];
+        let plan = plan_groups(&source, &target, &user_ids, &options).unwrap();
+
+        assert_eq!(plan.creates.len(), 1);
+        assert_eq!(plan.creates[0].body.members.as_ref().unwrap()[0].value, Some("id-2".to_string()));
+        assert_eq!(plan.updates.len(), 1);
+        assert_eq!(plan.updates[0].0, "gid-1");
+        assert_eq!(plan.updates[0].1.operations, vec![PatchOperations {
+            op: "add".to_string(),
+            path: Some("members".to_string()),
+            value: Some(serde_json::json!([{"value": "id-2"}])),
+        }]);
+        assert_eq!(plan.deletes, vec!["gid-3".to_string()]);
+
+        let unknown = vec![group("", "g1", "Tour Guides", &["missing"])];
+        assert!(matches!(plan_groups(&unknown, &[], &user_ids, &options), Err(SCIMError::NotFoundError(_))));
+    }
+
+    #[test]
+    fn sync_applies_plans_to_a_provider() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let options = SyncOptions::default();
+        let source = vec![user(None, "e1", "bjensen"), user(None, "e2", "mpepperidge")];
+        let groups = vec![group("", "g1", "Tour Guides", &["e1", "e2"])];
+        let plan = sync_users(&provider, &source, &options).unwrap();
+        sync_groups(&provider, &groups, &plan.user_ids, &options).unwrap();
+
+        let source = vec![user(None, "e1", "babs")];
+        let groups = vec![group("", "g1", "Tour Guides", &["e1"])];
+        let plan = sync_users(&provider, &source, &options).unwrap();
+        assert_eq!(plan.updates.len(), 1);
+        assert_eq!(plan.deactivations.len(), 1);
+        let group_plan = sync_groups(&provider, &groups, &plan.user_ids, &options).unwrap();
+        assert_eq!(group_plan.updates.len(), 1);
+
+        let babs = provider.get_user(&plan.user_ids["e1"]).unwrap();
+        assert_eq!(babs.user.user_name, "babs");
+        assert_eq!(provider.get_user(&plan.deactivations[0]).unwrap().user.active, Some(false));
+        let guides = provider.search_groups(&SearchRequest::default()).unwrap().resources.remove(0);
+        assert_eq!(guides.group.members.unwrap().len(), 1);
+        assert!(sync_users(&provider, &source, &options).unwrap().is_empty());
+        assert!(sync_groups(&provider, &groups, &plan.user_ids, &options).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use serde_json::{Map, Value};
+
+use crate::models::scim_schema::{Attributes, Schema, SubAttributes};
+
+/// Attributes every resource may carry which are not defined by its schema (RFC 7643 §3.1).
+pub(crate) const COMMON_ATTRIBUTES: [&str; 4] = ["schemas", "id", "externalId", "meta"];
+
+/// Sub-attributes of `meta` (RFC 7643 §3.1).
+pub(crate) const META_ATTRIBUTES: [&str; 5] = ["resourceType", "created", "lastModified", "version", "location"];
+
+/// Rewrites the attribute names of a JSON representation of a resource to the case used by its schemas.
+///
+/// RFC 7643 §2.1 makes attribute names case-insensitive, while the serde renames of the models match exactly, so
+/// `"username"` would otherwise be dropped. Attributes and sub-attributes are matched case-insensitively against the
+/// schemas and the common attributes (`schemas`, `id`, `externalId` and `meta`). Extension objects are recognised by a
+/// key matching a schema URN in any case; the key is rewritten to the URN and its attributes are looked up in that
+/// schema. URNs listed in `schemas` are rewritten the same way. Unknown names are left untouched.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::scim_schema::get_schemas;
+/// use scim_v2::utils::case::canonicalize_json;
+/// use serde_json::json;
+///
+/// let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+/// let mut user = json!({
+///     "USERNAME": "bjensen",
+///     "Emails": [{"VALUE": "bjensen@example.com"}],
+///     "URN:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"employeenumber": "701984"}
+/// });
+/// canonicalize_json(&mut user, &schemas);
+/// assert_eq!(user, json!({
+///     "userName": "bjensen",
+///     "emails": [{"value": "bjensen@example.com"}],
+///     "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"employeeNumber": "701984"}
+/// }));
+/// ```
+pub fn canonicalize_json(value: &mut Value, schemas: &[Schema]) {
+    canonicalize_with(value, &schemas.iter().collect::<Vec<_>>());
+}
+
+/// Canonicalises a resource whose type is only known from the URNs in its `schemas` (or its `meta.resourceType`),
+/// such as an entry of a `ListResponse`, using the built-in schemas it declares.
+pub(crate) fn canonicalize_resource(value: &mut Value, built_in: &[Schema]) {
+    canonicalize_with(value, &[]);
+    let declared: Vec<&str> = value.get("schemas")
+        .and_then(Value::as_array)
+        .map(|schemas| schemas.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let resource_type = value.get("meta").and_then(|meta| meta.get("resourceType")).and_then(Value::as_str);
+    let schemas: Vec<&Schema> = built_in.iter()
+        .filter(|schema| {
+            declared.iter().any(|urn| urn.eq_ignore_ascii_case(&schema.id))
+                || resource_type.map_or(false, |resource_type| resource_type.eq_ignore_ascii_case(&schema.name))
+        })
+        .collect();
+    canonicalize_with(value, &schemas);
+}
+
+/// Rewrites the keys of an object, or of every object in an array, to the matching name in `names`.
+///
+/// Used for the API messages (`ListResponse`, `PatchOp`, `SearchRequest`), which have no schema definition.
+pub(crate) fn canonicalize_names(value: &mut Value, names: &[&str]) {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                canonicalize_names(value, names);
+            }
+        }
+        Value::Object(object) => {
+            let entries = std::mem::take(object);
+            for (original, value) in entries {
+                let key = names.iter()
+                    .find(|name| original.eq_ignore_ascii_case(name))
+                    .map_or_else(|| original.clone(), |name| name.to_string());
+                insert_canonical(object, &original, key, value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn canonicalize_with(value: &mut Value, schemas: &[&Schema]) {
+    let object = match value.as_object_mut() {
+        Some(object) => object,
+        None => return,
+    };
+    let is_extension = |schema: &&&Schema| object.keys().any(|key| key.eq_ignore_ascii_case(&schema.id));
+    let core_attributes: Vec<&Attributes> = schemas.iter()
+        .filter(|schema| !is_extension(schema))
+        .flat_map(|schema| schema.attributes.iter())
+        .collect();
+
+    let entries = std::mem::take(object);
+    for (original, mut value) in entries {
+        let key = if let Some(extension) = schemas.iter().find(|schema| original.eq_ignore_ascii_case(&schema.id)) {
+            if let Some(extension_object) = value.as_object_mut() {
+                let attributes: Vec<&Attributes> = extension.attributes.iter().collect();
+                canonicalize_attributes(extension_object, &attributes);
+            }
+            extension.id.clone()
+        } else if let Some(common) = COMMON_ATTRIBUTES.iter().find(|common| original.eq_ignore_ascii_case(common)) {
+            match *common {
+                "schemas" => canonicalize_urns(&mut value, schemas),
+                "meta" => canonicalize_names(&mut value, &META_ATTRIBUTES),
+                _ => {}
+            }
+            common.to_string()
+        } else if let Some(attribute) = core_attributes.iter().find(|attribute| original.eq_ignore_ascii_case(&attribute.name)) {
+            canonicalize_sub_attributes(&mut value, &attribute.sub_attributes);
+            attribute.name.clone()
+        } else {
+            original.clone()
+        };
+        insert_canonical(object, &original, key, value);
+    }
+}
+
+fn canonicalize_attributes(object: &mut Map<String, Value>, attributes: &[&Attributes]) {
+    let entries = std::mem::take(object);
+    for (original, mut value) in entries {
+        let key = match attributes.iter().find(|attribute| original.eq_ignore_ascii_case(&attribute.name)) {
+            Some(attribute) => {
+                canonicalize_sub_attributes(&mut value, &attribute.sub_attributes);
+                attribute.name.clone()
+            }
+            None => original.clone(),
+        };
+        insert_canonical(object, &original, key, value);
+    }
+}
+
+fn canonicalize_sub_attributes(value: &mut Value, sub_attributes: &Option<Vec<SubAttributes>>) {
+    if let Some(sub_attributes) = sub_attributes {
+        let names: Vec<&str> = sub_attributes.iter().map(|sub_attribute| sub_attribute.name.as_str()).collect();
+        canonicalize_names(value, &names);
+    }
+}
+
+fn canonicalize_urns(value: &mut Value, schemas: &[&Schema]) {
+    if let Value::Array(values) = value {
+        for value in values {
+            if let Some(schema) = value.as_str().and_then(|urn| schemas.iter().find(|schema| urn.eq_ignore_ascii_case(&schema.id))) {
+                *value = Value::String(schema.id.clone());
+            }
+        }
+    }
+}
+
+/// Inserts an entry under its canonical name; a key already spelled canonically wins over a differently cased duplicate.
+fn insert_canonical(object: &mut Map<String, Value>, original: &str, key: String, value: Value) {
+    if original == key || !object.contains_key(&key) {
+        object.insert(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::models::scim_schema::get_schemas;
+
+    use super::*;
+
+    #[test]
+    fn canonicalize_json_rewrites_common_and_meta_attributes() {
+        let schemas = get_schemas(vec!["group"]).unwrap();
+        let mut group = json!({
+            "Schemas": ["URN:IETF:PARAMS:SCIM:SCHEMAS:CORE:2.0:GROUP"],
+            "ID": "e9e30dba-f08f-4109-8486-d5c6a331660a",
+            "displayname": "Tour Guides",
+            "Members": [{"Value": "2819c223-7f76-453a-919d-413861904646", "$REF": "https://example.com/v2/Users/2819c223"}],
+            "META": {"ResourceType": "Group", "LASTMODIFIED": "2011-05-13T04:42:34Z"},
+            "unknownAttribute": true
+        });
+
+        canonicalize_json(&mut group, &schemas);
+
+        assert_eq!(group, json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+            "id": "e9e30dba-f08f-4109-8486-d5c6a331660a",
+            "displayName": "Tour Guides",
+            "members": [{"value": "2819c223-7f76-453a-919d-413861904646", "$ref": "https://example.com/v2/Users/2819c223"}],
+            "meta": {"resourceType": "Group", "lastModified": "2011-05-13T04:42:34Z"},
+            "unknownAttribute": true
+        }));
+    }
+
+    #[test]
+    fn canonicalize_json_keeps_the_canonical_spelling_of_duplicates() {
+        let schemas = get_schemas(vec!["user"]).unwrap();
+        let mut user = json!({"username": "sloppy", "userName": "bjensen"});
+
+        canonicalize_json(&mut user, &schemas);
+
+        assert_eq!(user, json!({"userName": "bjensen"}));
+    }
+
+    #[test]
+    fn discovery_schemas_are_available_for_canonicalisation() {
+        let schemas = get_schemas(vec!["resource_type", "service_provider_config", "schema"]).unwrap();
+        let mut config = json!({"ChangePassword": {"Supported": true}, "authenticationschemes": [{"SPECURI": "https://example.com"}]});
+
+        canonicalize_json(&mut config, &schemas[1..2]);
+
+        assert_eq!(config, json!({"changePassword": {"supported": true}, "authenticationSchemes": [{"specUri": "https://example.com"}]}));
+    }
+}
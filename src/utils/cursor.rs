@@ -0,0 +1,111 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::utils::error::SCIMError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length in bytes of the HMAC-SHA256 tag appended to every cursor.
+const TAG_LENGTH: usize = 32;
+
+/// Encodes and decodes opaque, tamper-evident pagination cursors (RFC 9865).
+///
+/// A cursor carries whatever position the service provider needs to resume a listing, such as the last returned id.
+/// The position is signed with HMAC-SHA256 and the result is base64url encoded, so clients cannot read a meaningful
+/// structure from it and any change to the cursor is detected on decode.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::utils::cursor::CursorCodec;
+///
+/// let codec = CursorCodec::new(b"server-side secret");
+/// let cursor = codec.encode("2819c223-7f76-453a-919d-413861904646");
+///
+/// match codec.decode(&cursor) {
+///     Ok(position) => println!("Resume after: {}", position),
+///     Err(e) => println!("Rejected cursor: {}", e),
+/// }
+/// ```
+#[derive(Clone)]
+pub struct CursorCodec {
+    key: Vec<u8>,
+}
+
+impl std::fmt::Debug for CursorCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorCodec").finish_non_exhaustive()
+    }
+}
+
+impl CursorCodec {
+    /// Creates a codec that signs cursors with the given secret key.
+    pub fn new(key: &[u8]) -> Self {
+        CursorCodec { key: key.to_vec() }
+    }
+
+    /// Encodes a position into an opaque cursor string that is safe to use in a URL.
+    pub fn encode(&self, position: &str) -> String {
+        let mut data = position.as_bytes().to_vec();
+        data.extend_from_slice(&self.mac(position.as_bytes()).finalize().into_bytes());
+        URL_SAFE_NO_PAD.encode(data)
+    }
+
+    /// Decodes a cursor produced by `encode` back into its position.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidCursor` if the cursor is malformed, was modified, or was signed with another key.
+    pub fn decode(&self, cursor: &str) -> Result<String, SCIMError> {
+        let data = URL_SAFE_NO_PAD.decode(cursor)
+            .map_err(|_| SCIMError::InvalidCursor("cursor is not valid base64url".to_string()))?;
+        if data.len() < TAG_LENGTH {
+            return Err(SCIMError::InvalidCursor("cursor is too short".to_string()));
+        }
+        let (position, tag) = data.split_at(data.len() - TAG_LENGTH);
+        self.mac(position).verify_slice(tag)
+            .map_err(|_| SCIMError::InvalidCursor("cursor signature does not match".to_string()))?;
+        String::from_utf8(position.to_vec())
+            .map_err(|_| SCIMError::InvalidCursor("cursor position is not valid UTF-8".to_string()))
+    }
+
+    fn mac(&self, data: &[u8]) -> HmacSha256 {
+        // HMAC accepts keys of any length, so this cannot fail.
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC can take a key of any size");
+        mac.update(data);
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn decode_returns_encoded_position() {
+        let codec = CursorCodec::new(b"secret");
+        let cursor = codec.encode("user:42");
+
+        assert!(!cursor.contains("user:42"));
+        assert_eq!(codec.decode(&cursor).unwrap(), "user:42");
+    }
+
+    #[test]
+    fn decode_rejects_tampered_or_foreign_cursor() {
+        let codec = CursorCodec::new(b"secret");
+        let cursor = codec.encode("user:42");
+
+        let mut data = URL_SAFE_NO_PAD.decode(&cursor).unwrap();
+        data[0] ^= 1;
+        let tampered = URL_SAFE_NO_PAD.encode(data);
+        assert!(matches!(codec.decode(&tampered), Err(SCIMError::InvalidCursor(_))));
+
+        let other = CursorCodec::new(b"other secret");
+        assert!(matches!(other.decode(&cursor), Err(SCIMError::InvalidCursor(_))));
+        assert!(matches!(codec.decode("not a cursor!"), Err(SCIMError::InvalidCursor(_))));
+    }
+}
@@ -6,15 +6,19 @@ pub enum SCIMError {
     // Todo: Add 400 bad request SCIM Detail Error Keyword Values mentioned here: https://datatracker.ietf.org/doc/html/rfc7644#section-3.12
     ConflictError(String),
     DeserializationError(serde_json::Error),
+    InvalidCursor(String),
     InvalidFieldValue(String),
     InvalidJsonFormat,
+    InvalidSyntax(String),
     MissingRequiredField(String),
     NotFoundError(String),
     OtherError(String),
+    PreconditionFailed(String),
     RequestError(String),
     ResourceTypeNotFound(String),
     SchemaNotFound(String),
     SerializationError(serde_json::Error),
+    UnsupportedCapability(String),
 }
 
 impl Display for SCIMError {
@@ -22,15 +26,19 @@ impl Display for SCIMError {
         match self {
             SCIMError::ConflictError(msg) => write!(f, "Conflict error: {}", msg),
             SCIMError::DeserializationError(e) => write!(f, "Deserialization error: {}", e),
+            SCIMError::InvalidCursor(msg) => write!(f, "Invalid cursor: {}", msg),
             SCIMError::InvalidFieldValue(msg) => write!(f, "Invalid field value: {}", msg),
             SCIMError::InvalidJsonFormat => write!(f, "Invalid JSON format"),
+            SCIMError::InvalidSyntax(msg) => write!(f, "Invalid syntax: {}", msg),
             SCIMError::MissingRequiredField(msg) => write!(f, "Missing required field: {}", msg),
             SCIMError::NotFoundError(msg) => write!(f, "Not found error: {}", msg),
             SCIMError::OtherError(msg) => write!(f, "Other Error: {}", msg),
+            SCIMError::PreconditionFailed(msg) => write!(f, "Precondition failed: {}", msg),
             SCIMError::RequestError(msg) => write!(f, "Request error: {}", msg),
             SCIMError::ResourceTypeNotFound(msg) => write!(f, "Resource type not found: {}", msg),
             SCIMError::SchemaNotFound(msg) => write!(f, "Schema not found: {}", msg),
             SCIMError::SerializationError(e) => write!(f, "Serialization error: {}", e),
+            SCIMError::UnsupportedCapability(msg) => write!(f, "Unsupported capability: {}", msg),
         }
     }
 }
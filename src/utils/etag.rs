@@ -0,0 +1,410 @@
+use std::fmt;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::models::errors::ScimHttpError;
+use crate::models::scim_schema::Meta;
+use crate::utils::error::SCIMError;
+
+/// An HTTP entity tag (RFC 9110, section 8.8.3), as carried in `ETag`, `If-Match` and `If-None-Match` headers.
+///
+/// SCIM service providers that advertise `etag` support in their `ServiceProviderConfig` return the version of a
+/// resource both in `meta.version` and in the `ETag` header, and clients send it back to make conditional requests.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::utils::etag::ETag;
+///
+/// let etag = ETag::parse(r#"W/"3694e05e9dff591""#).unwrap();
+/// assert!(etag.weak);
+/// assert_eq!(etag.to_string(), r#"W/"3694e05e9dff591""#);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag {
+    pub weak: bool,
+    pub tag: String,
+}
+
+impl ETag {
+    /// Creates a strong entity tag.
+    pub fn strong(tag: &str) -> Self {
+        ETag { weak: false, tag: tag.to_string() }
+    }
+
+    /// Creates a weak entity tag.
+    pub fn weak(tag: &str) -> Self {
+        ETag { weak: true, tag: tag.to_string() }
+    }
+
+    /// Parses a single entity tag such as `"abc"` or `W/"abc"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidFieldValue` if the value is not a quoted entity tag.
+    pub fn parse(value: &str) -> Result<Self, SCIMError> {
+        let value = value.trim();
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let tag = quoted.strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .filter(|tag| !tag.contains('"'))
+            .ok_or_else(|| SCIMError::InvalidFieldValue(format!("invalid entity tag: {}", value)))?;
+        Ok(ETag { weak, tag: tag.to_string() })
+    }
+
+    /// Returns the entity tag for a resource from its `meta.version`.
+    ///
+    /// `meta.version` normally holds a complete entity tag such as `W/"3694e05e9dff591"`. A bare version string is
+    /// treated as the opaque value of a weak tag.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(ETag)` - If the resource has a version.
+    /// * `None` - If `meta.version` is not set.
+    pub fn from_meta(meta: &Meta) -> Option<Self> {
+        let version = meta.version.as_deref()?;
+        Some(ETag::parse(version).unwrap_or_else(|_| ETag::weak(version)))
+    }
+
+    /// Returns a strong entity tag derived from a SHA-256 hash of the JSON representation of `resource`.
+    ///
+    /// Use this for service providers that do not track a version for their resources. Any change to the
+    /// serialized resource, including its `meta`, produces a different tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::SerializationError` if the resource cannot be serialized.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::models::group::{Group, GroupRequest};
+    /// use scim_v2::utils::etag::ETag;
+    ///
+    /// let group = Group {
+    ///     body: GroupRequest {
+    ///         display_name: "Tour Guides".to_string(),
+    ///         ..Default::default()
+    ///     },
+    ///     ..Default::default()
+    /// };
+    /// let etag = ETag::from_content(&group).unwrap();
+    /// assert!(!etag.weak);
+    /// ```
+    pub fn from_content<T: Serialize>(resource: &T) -> Result<Self, SCIMError> {
+        let json = serde_json::to_vec(resource).map_err(SCIMError::SerializationError)?;
+        Ok(ETag::strong(&URL_SAFE_NO_PAD.encode(Sha256::digest(json))))
+    }
+
+    /// Strong comparison: both tags are strong and their values are identical.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison: the values are identical, regardless of either tag being weak.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// What a service provider should do with a conditional request once its preconditions have been evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    /// The preconditions hold; process the request.
+    Proceed,
+    /// The client's copy is current; answer a GET or HEAD with `304 Not Modified` and no body.
+    NotModified,
+}
+
+/// Evaluates the `If-Match` and `If-None-Match` headers of a request against the current version of a resource.
+///
+/// Follows RFC 9110, section 13.2.2: `If-Match` uses strong comparison and `If-None-Match` uses weak comparison,
+/// and `*` matches any existing resource. When `If-Match` is present, `If-None-Match` is still evaluated afterwards.
+///
+/// # Arguments
+///
+/// * `current` - The entity tag of the resource, or `None` if the resource does not exist.
+/// * `if_match` - The raw value of the `If-Match` header, if any.
+/// * `if_none_match` - The raw value of the `If-None-Match` header, if any.
+/// * `safe_method` - Whether the request is a GET or HEAD.
+///
+/// # Returns
+///
+/// * `Ok(Precondition::Proceed)` - If the request should be processed.
+/// * `Ok(Precondition::NotModified)` - If a GET or HEAD matched `If-None-Match`.
+/// * `Err(ScimHttpError)` - A `412 Precondition Failed` error, or a `400` error if a header cannot be parsed.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::utils::etag::{evaluate_preconditions, ETag, Precondition};
+///
+/// let current = ETag::weak("3694e05e9dff591");
+///
+/// // A replace with a stale version is rejected.
+/// let result = evaluate_preconditions(Some(&current), Some(r#"W/"3694e05e9dff590""#), None, false);
+/// assert_eq!(result.unwrap_err().status, "412");
+///
+/// // A GET with the current version is answered with 304.
+/// let result = evaluate_preconditions(Some(&current), None, Some(r#"W/"3694e05e9dff591""#), true);
+/// assert_eq!(result.unwrap(), Precondition::NotModified);
+/// ```
+pub fn evaluate_preconditions(current: Option<&ETag>, if_match: Option<&str>, if_none_match: Option<&str>, safe_method: bool) -> Result<Precondition, ScimHttpError> {
+    if let Some(header) = if_match {
+        let matched = match parse_header(header)? {
+            None => current.is_some(),
+            Some(tags) => current.map_or(false, |current| tags.iter().any(|tag| tag.strong_eq(current) || weak_version_match(tag, current))),
+        };
+        if !matched {
+            return Err(precondition_failed("The resource has changed on the service provider."));
+        }
+    }
+
+    if let Some(header) = if_none_match {
+        let matched = match parse_header(header)? {
+            None => current.is_some(),
+            Some(tags) => current.map_or(false, |current| tags.iter().any(|tag| tag.weak_eq(current))),
+        };
+        if matched {
+            if safe_method {
+                return Ok(Precondition::NotModified);
+            }
+            return Err(precondition_failed("The resource already exists with a matching version."));
+        }
+    }
+
+    Ok(Precondition::Proceed)
+}
+
+/// Updates a resource with optimistic concurrency, whatever carries the requests.
+///
+/// Each attempt fetches the resource and its entity tag, merges the caller's changes into it, and writes the result
+/// conditionally on that entity tag, e.g. with an `If-Match` header or `ResourceProvider::replace_user_if_match`. When
+/// the write fails with `SCIMError::PreconditionFailed` (a `412` response), because the resource changed after it was
+/// fetched, the next attempt starts over from a fresh fetch.
+///
+/// # Arguments
+///
+/// * `attempts` - How many times to write at most; at least one write is made.
+/// * `fetch` - Reads the current resource and its entity tag.
+/// * `merge` - Applies the caller's changes to the current resource.
+/// * `write` - Writes the merged resource if the resource still has the given entity tag.
+///
+/// # Errors
+///
+/// Returns the first error of `fetch` or `merge`, the first error of `write` other than
+/// `SCIMError::PreconditionFailed`, or `SCIMError::PreconditionFailed` once every attempt has failed.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::user::UserRequest;
+/// use scim_v2::provider::memory::MemoryProvider;
+/// use scim_v2::provider::resource_provider::ResourceProvider;
+/// use scim_v2::utils::error::SCIMError;
+/// use scim_v2::utils::etag::{update_with_retry, ETag};
+///
+/// let provider = MemoryProvider::new("https://example.com/v2");
+/// let id = provider.create_user(UserRequest { user_name: "bjensen".to_string(), ..Default::default() }).unwrap().id;
+///
+/// let user = update_with_retry(
+///     3,
+///     || {
+///         let user = provider.get_user(&id)?;
+///         let etag = ETag::from_meta(&user.meta).ok_or_else(|| SCIMError::MissingRequiredField("meta.version".to_string()))?;
+///         Ok((user, etag))
+///     },
+///     |user| Ok(UserRequest { title: Some("Tour Guide".to_string()), ..user.user }),
+///     |request, etag| provider.replace_user_if_match(&id, request, &etag.to_string()),
+/// ).unwrap();
+/// assert_eq!(user.user.title, Some("Tour Guide".to_string()));
+/// ```
+pub fn update_with_retry<T, W, R>(
+    attempts: usize,
+    mut fetch: impl FnMut() -> Result<(T, ETag), SCIMError>,
+    mut merge: impl FnMut(T) -> Result<W, SCIMError>,
+    mut write: impl FnMut(W, &ETag) -> Result<R, SCIMError>,
+) -> Result<R, SCIMError> {
+    let mut attempt = 1;
+    loop {
+        let (current, etag) = fetch()?;
+        match write(merge(current)?, &etag) {
+            Err(SCIMError::PreconditionFailed(_)) if attempt < attempts => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// SCIM versions are weak tags (RFC 7644, section 3.14), so a strict strong comparison would make `If-Match` useless
+/// for them. A weak version in `If-Match` still matches the identical weak version held by the service provider.
+fn weak_version_match(tag: &ETag, current: &ETag) -> bool {
+    tag.weak && current.weak && tag.tag == current.tag
+}
+
+/// Parses an `If-Match` or `If-None-Match` header. `None` stands for `*`.
+fn parse_header(header: &str) -> Result<Option<Vec<ETag>>, ScimHttpError> {
+    if header.trim() == "*" {
+        return Ok(None);
+    }
+    split_list(header)
+        .into_iter()
+        .filter(|tag| !tag.trim().is_empty())
+        .map(ETag::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+        .map_err(|e| ScimHttpError {
+            scim_type: Some("invalidValue".to_string()),
+            detail: Some(e.to_string()),
+            status: "400".to_string(),
+            ..Default::default()
+        })
+}
+
+/// Splits a header list at the commas outside quoted strings, since entity tags may themselves contain commas.
+fn split_list(header: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in header.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(&header[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&header[start..]);
+    items
+}
+
+fn precondition_failed(detail: &str) -> ScimHttpError {
+    ScimHttpError {
+        detail: Some(detail.to_string()),
+        status: "412".to_string(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::models::user::UserRequest;
+    use crate::provider::memory::MemoryProvider;
+    use crate::provider::resource_provider::ResourceProvider;
+
+    use super::*;
+
+    #[test]
+    fn parse_and_format_entity_tags() {
+        assert_eq!(ETag::parse(r#""abc""#).unwrap(), ETag::strong("abc"));
+        assert_eq!(ETag::parse(r#" W/"abc" "#).unwrap(), ETag::weak("abc"));
+        assert_eq!(ETag::weak("abc").to_string(), r#"W/"abc""#);
+        assert!(matches!(ETag::parse("abc"), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(ETag::parse(r#""a"b""#), Err(SCIMError::InvalidFieldValue(_))));
+    }
+
+    #[test]
+    fn etag_from_meta_version_and_content() {
+        let meta = Meta {
+            version: Some(r#"W/"3694e05e9dff591""#.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ETag::from_meta(&meta), Some(ETag::weak("3694e05e9dff591")));
+        assert_eq!(ETag::from_meta(&Meta { version: Some("7".to_string()), ..Default::default() }), Some(ETag::weak("7")));
+        assert_eq!(ETag::from_meta(&Meta::default()), None);
+
+        let first = ETag::from_content(&serde_json::json!({"displayName": "Tour Guides"})).unwrap();
+        let second = ETag::from_content(&serde_json::json!({"displayName": "Guides"})).unwrap();
+        assert!(!first.strong_eq(&second));
+        assert!(first.strong_eq(&ETag::from_content(&serde_json::json!({"displayName": "Tour Guides"})).unwrap()));
+    }
+
+    #[test]
+    fn strong_and_weak_comparison() {
+        assert!(ETag::strong("1").strong_eq(&ETag::strong("1")));
+        assert!(!ETag::weak("1").strong_eq(&ETag::weak("1")));
+        assert!(ETag::weak("1").weak_eq(&ETag::strong("1")));
+        assert!(!ETag::weak("1").weak_eq(&ETag::weak("2")));
+    }
+
+    #[test]
+    fn if_match_rejects_stale_version_with_412() {
+        let current = ETag::weak("2");
+
+        assert_eq!(evaluate_preconditions(Some(&current), Some(r#"W/"1", W/"2""#), None, false).unwrap(), Precondition::Proceed);
+        assert_eq!(evaluate_preconditions(Some(&current), Some("*"), None, false).unwrap(), Precondition::Proceed);
+        let error = evaluate_preconditions(Some(&current), Some(r#"W/"1""#), None, false).unwrap_err();
+        assert_eq!(error.status, "412");
+        assert_eq!(error.scim_type, None);
+        assert_eq!(evaluate_preconditions(None, Some("*"), None, false).unwrap_err().status, "412");
+        assert_eq!(evaluate_preconditions(Some(&current), Some("2"), None, false).unwrap_err().status, "400");
+
+        let current = ETag::weak("a,b");
+        assert_eq!(evaluate_preconditions(Some(&current), Some(r#"W/"a,b""#), None, false).unwrap(), Precondition::Proceed);
+        assert_eq!(evaluate_preconditions(Some(&current), Some(r#""x", W/"a,b""#), None, false).unwrap(), Precondition::Proceed);
+        assert_eq!(evaluate_preconditions(Some(&current), Some(r#"W/"a", W/"b""#), None, false).unwrap_err().status, "412");
+        assert_eq!(evaluate_preconditions(Some(&current), Some(r#"W/"a,b"#), None, false).unwrap_err().status, "400");
+    }
+
+    #[test]
+    fn if_none_match_returns_not_modified_on_get() {
+        let current = ETag::strong("2");
+
+        assert_eq!(evaluate_preconditions(Some(&current), None, Some(r#"W/"2""#), true).unwrap(), Precondition::NotModified);
+        assert_eq!(evaluate_preconditions(Some(&current), None, Some(r#""1""#), true).unwrap(), Precondition::Proceed);
+        assert_eq!(evaluate_preconditions(Some(&current), None, Some("*"), false).unwrap_err().status, "412");
+        assert_eq!(evaluate_preconditions(None, None, Some("*"), false).unwrap(), Precondition::Proceed);
+    }
+
+    #[test]
+    fn update_with_retry_refetches_after_a_failed_precondition() {
+        let provider = MemoryProvider::new("https://example.com/v2");
+        let id = provider.create_user(UserRequest { user_name: "bjensen".to_string(), ..Default::default() }).unwrap().id;
+        let fetch = || {
+            let user = provider.get_user(&id)?;
+            Ok((user.user, ETag::from_meta(&user.meta).unwrap()))
+        };
+        let mut writes = 0;
+
+        let user = update_with_retry(3, fetch, |user| Ok(UserRequest { title: Some("Guide".to_string()), ..user }), |request, etag| {
+            writes += 1;
+            if writes == 1 {
+                // Another client renames the user between the fetch and the write.
+                provider.replace_user(&id, UserRequest { user_name: "babs".to_string(), ..Default::default() })?;
+            }
+            provider.replace_user_if_match(&id, request, &etag.to_string())
+        }).unwrap();
+        assert_eq!(writes, 2);
+        assert_eq!(user.user.user_name, "babs");
+        assert_eq!(user.user.title, Some("Guide".to_string()));
+
+        let always_stale = update_with_retry(2, fetch, Ok, |request, _| provider.replace_user_if_match(&id, request, r#"W/"0""#));
+        assert!(matches!(always_stale, Err(SCIMError::PreconditionFailed(_))));
+        let patch = serde_json::from_value(serde_json::json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [{"op": "replace", "path": "title", "value": "Pilot"}]
+        })).unwrap();
+        assert!(matches!(provider.patch_user_if_match(&id, &patch, "W/\"0\""), Err(SCIMError::PreconditionFailed(_))));
+        assert!(matches!(provider.patch_user_if_match(&id, &patch, "0"), Err(SCIMError::InvalidFieldValue(_))));
+        assert_eq!(provider.patch_user_if_match(&id, &patch, "*").unwrap().user.title, Some("Pilot".to_string()));
+    }
+}
@@ -0,0 +1,579 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+use crate::models::scim_schema::Schema;
+use crate::utils::error::SCIMError;
+use crate::utils::patch::PatchPath;
+
+/// A parsed SCIM filter (RFC 7644 section 3.4.2.2).
+///
+/// `not` binds tighter than `and`, which binds tighter than `or`. Inside a value path such as
+/// `emails[type eq "work"]`, attribute paths name sub-attributes of the enclosing attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// `attrPath op value`, such as `userName eq "bjensen"`.
+    Compare(AttributePath, Operator, Value),
+    /// `attrPath pr`.
+    Present(AttributePath),
+    /// `attrPath[valFilter]`, which matches if a value of the attribute matches the inner filter.
+    ValuePath(AttributePath, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+/// An attribute path in a filter: `[urn:]attribute[.subAttribute]`.
+///
+/// Core schema URN prefixes such as `urn:ietf:params:scim:schemas:core:2.0:User:` are dropped, so `urn` is only set for
+/// extension attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributePath {
+    pub urn: Option<String>,
+    pub attribute: String,
+    pub sub_attribute: Option<String>,
+}
+
+/// The comparison operators of a filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Operator {
+    fn parse(word: &str) -> Option<Operator> {
+        let operator = match word.to_ascii_lowercase().as_str() {
+            "eq" => Operator::Eq,
+            "ne" => Operator::Ne,
+            "co" => Operator::Co,
+            "sw" => Operator::Sw,
+            "ew" => Operator::Ew,
+            "gt" => Operator::Gt,
+            "ge" => Operator::Ge,
+            "lt" => Operator::Lt,
+            "le" => Operator::Le,
+            _ => return None,
+        };
+        Some(operator)
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            Operator::Eq => "eq",
+            Operator::Ne => "ne",
+            Operator::Co => "co",
+            Operator::Sw => "sw",
+            Operator::Ew => "ew",
+            Operator::Gt => "gt",
+            Operator::Ge => "ge",
+            Operator::Lt => "lt",
+            Operator::Le => "le",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+impl Display for AttributePath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(urn) = &self.urn {
+            write!(f, "{}:", urn)?;
+        }
+        write!(f, "{}", self.attribute)?;
+        if let Some(sub_attribute) = &self.sub_attribute {
+            write!(f, ".{}", sub_attribute)?;
+        }
+        Ok(())
+    }
+}
+
+impl Filter {
+    /// Parses a filter expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SCIMError::InvalidSyntax` if the filter does not follow the grammar of RFC 7644 section 3.4.2.2.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::utils::filter::{AttributePath, Filter, Operator};
+    /// use serde_json::json;
+    ///
+    /// let filter = Filter::parse(r#"userName eq "bjensen" and emails[type eq "work"]"#).unwrap();
+    /// let user_name = AttributePath { urn: None, attribute: "userName".to_string(), sub_attribute: None };
+    /// match filter {
+    ///     Filter::And(left, _) => assert_eq!(*left, Filter::Compare(user_name, Operator::Eq, json!("bjensen"))),
+    ///     other => panic!("unexpected filter {:?}", other),
+    /// }
+    /// ```
+    pub fn parse(filter: &str) -> Result<Filter, SCIMError> {
+        let tokens = tokenize(filter)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let parsed = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(parsed),
+            Some(token) => Err(invalid(&format!("unexpected {}", token))),
+        }
+    }
+
+    /// Returns the `(attribute, value)` of an `attr eq "value"` filter, such as the `userName eq "..."` lookups
+    /// identity providers send before creating a user.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::utils::filter::Filter;
+    ///
+    /// let filter = Filter::parse(r#"userName eq "Test_User_dfeef4c5""#).unwrap();
+    /// assert_eq!(filter.equality(), Some(("userName".to_string(), "Test_User_dfeef4c5".to_string())));
+    /// assert_eq!(Filter::parse(r#"userName sw "Test""#).unwrap().equality(), None);
+    /// ```
+    pub fn equality(&self) -> Option<(String, String)> {
+        match self {
+            Filter::Compare(path, Operator::Eq, Value::String(value)) => Some((path.to_string(), value.clone())),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the JSON representation of a resource matches the filter.
+    ///
+    /// `schemas` are the core schema of the resource followed by its extensions. String attributes they mark
+    /// `caseExact` are compared exactly and other strings case-insensitively; `id` and `externalId` are always
+    /// compared exactly. Attribute names are matched case-insensitively. A comparison with a multi-valued attribute
+    /// matches if any of its values matches, using the `value` sub-attribute of complex values. `eq null` matches an
+    /// absent attribute and `ne null` a present one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::models::scim_schema::get_schemas;
+    /// use scim_v2::utils::filter::Filter;
+    /// use serde_json::json;
+    ///
+    /// let schemas = get_schemas(vec!["user"]).unwrap();
+    /// let user = json!({"userName": "BJensen", "emails": [{"type": "work", "value": "bjensen@example.com"}]});
+    ///
+    /// assert!(Filter::parse(r#"userName eq "bjensen""#).unwrap().matches(&user, &schemas));
+    /// assert!(Filter::parse(r#"emails[type eq "work" and value ew "@example.com"]"#).unwrap().matches(&user, &schemas));
+    /// assert!(!Filter::parse("title pr").unwrap().matches(&user, &schemas));
+    /// ```
+    pub fn matches(&self, resource: &Value, schemas: &[Schema]) -> bool {
+        self.evaluate(resource, schemas, None)
+    }
+
+    /// Evaluates the filter against `resource`, or against a value of the `parent` attribute inside a value path.
+    fn evaluate(&self, resource: &Value, schemas: &[Schema], parent: Option<&AttributePath>) -> bool {
+        match self {
+            Filter::And(left, right) => left.evaluate(resource, schemas, parent) && right.evaluate(resource, schemas, parent),
+            Filter::Or(left, right) => left.evaluate(resource, schemas, parent) || right.evaluate(resource, schemas, parent),
+            Filter::Not(filter) => !filter.evaluate(resource, schemas, parent),
+            Filter::Present(path) => lookup(resource, path, parent).iter().any(|value| is_present(value)),
+            Filter::ValuePath(path, filter) => lookup_elements(resource, path)
+                .iter()
+                .any(|element| filter.evaluate(element, schemas, Some(path))),
+            Filter::Compare(path, operator, expected) => {
+                let values = lookup(resource, path, parent);
+                let case_exact = is_case_exact(schemas, path, parent);
+                let any = |operator: Operator| values.iter().any(|value| compare(value, operator, expected, case_exact));
+                match (operator, expected) {
+                    (Operator::Eq, Value::Null) => values.is_empty(),
+                    (Operator::Ne, Value::Null) => !values.is_empty(),
+                    (Operator::Ne, _) => !any(Operator::Eq),
+                    (operator, _) => any(*operator),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the object member named `key`, ignoring case.
+fn member<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    let object = value.as_object()?;
+    object.get(key).or_else(|| object.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)).map(|(_, value)| value))
+}
+
+/// Returns the attribute (or the extension object holding it) that `path` names, without descending into
+/// sub-attributes.
+fn attribute<'a>(resource: &'a Value, path: &AttributePath) -> Option<&'a Value> {
+    let container = match &path.urn {
+        Some(urn) => member(resource, urn)?,
+        None => resource,
+    };
+    member(container, &path.attribute).filter(|value| !value.is_null())
+}
+
+/// Returns the values of the attribute `path` names as a flat list: the values of a multi-valued attribute, or the
+/// `value` sub-attribute of complex multi-valued attributes, are listed individually.
+fn lookup<'a>(resource: &'a Value, path: &AttributePath, parent: Option<&AttributePath>) -> Vec<&'a Value> {
+    let value = match parent {
+        Some(_) => member(resource, &path.attribute).filter(|value| !value.is_null()),
+        None => attribute(resource, path),
+    };
+    let values: Vec<&Value> = match value {
+        Some(Value::Array(values)) => values.iter().collect(),
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    values.into_iter()
+        .filter_map(|value| match (&path.sub_attribute, value) {
+            (Some(sub_attribute), value) => member(value, sub_attribute),
+            (None, Value::Object(_)) => member(value, "value"),
+            (None, value) => Some(value),
+        })
+        .filter(|value| !value.is_null())
+        .collect()
+}
+
+/// Returns the values a value path such as `emails[type eq "work"]` filters.
+fn lookup_elements<'a>(resource: &'a Value, path: &AttributePath) -> Vec<&'a Value> {
+    match attribute(resource, path) {
+        Some(Value::Array(values)) => values.iter().collect(),
+        Some(value) => vec![value],
+        None => Vec::new(),
+    }
+}
+
+fn is_present(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::String(string) => !string.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(object) => !object.is_empty(),
+        _ => true,
+    }
+}
+
+/// Returns whether the schemas mark the attribute `path` names as `caseExact`.
+fn is_case_exact(schemas: &[Schema], path: &AttributePath, parent: Option<&AttributePath>) -> bool {
+    let (top, sub_attribute) = match parent {
+        Some(parent) => (parent, Some(&path.attribute)),
+        None => (path, path.sub_attribute.as_ref()),
+    };
+    let schema = match &top.urn {
+        Some(urn) => schemas.iter().find(|schema| schema.id.eq_ignore_ascii_case(urn)),
+        None => schemas.first(),
+    };
+    let attribute = schema.and_then(|schema| schema.attributes.iter().find(|attribute| attribute.name.eq_ignore_ascii_case(&top.attribute)));
+    let case_exact = match (attribute, sub_attribute) {
+        (Some(attribute), sub_attribute) => {
+            let sub_attribute = sub_attribute.map(String::as_str).or_else(|| attribute.sub_attributes.as_ref().map(|_| "value"));
+            match sub_attribute {
+                Some(name) => attribute.sub_attributes.iter().flatten()
+                    .find(|sub_attribute| sub_attribute.name.eq_ignore_ascii_case(name))
+                    .and_then(|sub_attribute| sub_attribute.case_exact),
+                None => attribute.case_exact,
+            }
+        }
+        (None, _) => None,
+    };
+    case_exact.unwrap_or_else(|| {
+        parent.is_none() && path.urn.is_none() && path.sub_attribute.is_none()
+            && (path.attribute.eq_ignore_ascii_case("id") || path.attribute.eq_ignore_ascii_case("externalId"))
+    })
+}
+
+fn compare(actual: &Value, operator: Operator, expected: &Value, case_exact: bool) -> bool {
+    match (actual, expected) {
+        (Value::String(actual), Value::String(expected)) => {
+            let (actual, expected) = if case_exact {
+                (actual.clone(), expected.clone())
+            } else {
+                (actual.to_lowercase(), expected.to_lowercase())
+            };
+            match operator {
+                Operator::Co => actual.contains(&expected),
+                Operator::Sw => actual.starts_with(&expected),
+                Operator::Ew => actual.ends_with(&expected),
+                operator => ordered(actual.cmp(&expected), operator),
+            }
+        }
+        (Value::Number(actual), Value::Number(expected)) => match (actual.as_f64(), expected.as_f64()) {
+            (Some(actual), Some(expected)) => actual.partial_cmp(&expected).map_or(false, |ordering| ordered(ordering, operator)),
+            _ => false,
+        },
+        (Value::Bool(actual), Value::Bool(expected)) => operator == Operator::Eq && actual == expected,
+        _ => false,
+    }
+}
+
+/// Applies an ordering operator (`eq`, `gt`, `ge`, `lt` or `le`) to the result of a comparison.
+fn ordered(ordering: Ordering, operator: Operator) -> bool {
+    match operator {
+        Operator::Eq => ordering == Ordering::Equal,
+        Operator::Gt => ordering == Ordering::Greater,
+        Operator::Ge => ordering != Ordering::Less,
+        Operator::Lt => ordering == Ordering::Less,
+        Operator::Le => ordering != Ordering::Greater,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Word(String),
+    String(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::OpenBracket => write!(f, "'['"),
+            Token::CloseBracket => write!(f, "']'"),
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::String(string) => write!(f, "{:?}", string),
+        }
+    }
+}
+
+fn invalid(message: &str) -> SCIMError {
+    SCIMError::InvalidSyntax(format!("invalid filter: {}", message))
+}
+
+fn tokenize(filter: &str) -> Result<Vec<Token>, SCIMError> {
+    let mut tokens = Vec::new();
+    let mut chars = filter.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '[' => tokens.push(Token::OpenBracket),
+            ']' => tokens.push(Token::CloseBracket),
+            '"' => {
+                let mut end = None;
+                while let Some((index, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => {
+                            end = Some(index);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let end = end.ok_or_else(|| invalid("unterminated string"))?;
+                let string = serde_json::from_str(&filter[start..=end]).map_err(|_| invalid("invalid string literal"))?;
+                tokens.push(Token::String(string));
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(index, next)) = chars.peek() {
+                    if next.is_whitespace() || "()[]\"".contains(next) {
+                        break;
+                    }
+                    end = index + next.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(filter[start..end].to_string()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), SCIMError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(invalid(&format!("expected {}, found {}", expected, token))),
+            None => Err(invalid(&format!("expected {}", expected))),
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter, SCIMError> {
+        let mut filter = self.and()?;
+        while self.peek_keyword("or") {
+            self.position += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, SCIMError> {
+        let mut filter = self.not()?;
+        while self.peek_keyword("and") {
+            self.position += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> Result<Filter, SCIMError> {
+        if self.peek_keyword("not") {
+            self.position += 1;
+            self.expect(Token::Open)?;
+            let filter = self.or()?;
+            self.expect(Token::Close)?;
+            return Ok(Filter::Not(Box::new(filter)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Filter, SCIMError> {
+        let path = match self.next() {
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                self.expect(Token::Close)?;
+                return Ok(filter);
+            }
+            Some(Token::Word(word)) => attribute_path(&word)?,
+            Some(token) => return Err(invalid(&format!("expected an attribute path, found {}", token))),
+            None => return Err(invalid("expected an attribute path")),
+        };
+        match self.next() {
+            Some(Token::OpenBracket) => {
+                let filter = self.or()?;
+                self.expect(Token::CloseBracket)?;
+                Ok(Filter::ValuePath(path, Box::new(filter)))
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("pr") => Ok(Filter::Present(path)),
+            Some(Token::Word(word)) => {
+                let operator = Operator::parse(&word).ok_or_else(|| invalid(&format!("unknown operator {}", word)))?;
+                let value = match self.next() {
+                    Some(Token::String(string)) => Value::String(string),
+                    Some(Token::Word(word)) => match serde_json::from_str::<Value>(&word) {
+                        Ok(value) if !value.is_array() && !value.is_object() => value,
+                        _ => return Err(invalid(&format!("invalid comparison value {}", word))),
+                    },
+                    _ => return Err(invalid(&format!("expected a value after {}", operator))),
+                };
+                Ok(Filter::Compare(path, operator, value))
+            }
+            _ => Err(invalid(&format!("expected an operator after {}", path))),
+        }
+    }
+}
+
+fn attribute_path(word: &str) -> Result<AttributePath, SCIMError> {
+    let path = PatchPath::parse(word).map_err(|_| invalid(&format!("invalid attribute path {}", word)))?;
+    Ok(AttributePath {
+        urn: path.urn,
+        attribute: path.attribute,
+        sub_attribute: path.sub_attribute,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    fn path(attribute: &str, sub_attribute: Option<&str>) -> AttributePath {
+        AttributePath { urn: None, attribute: attribute.to_string(), sub_attribute: sub_attribute.map(str::to_string) }
+    }
+
+    #[test]
+    fn parse_respects_precedence_and_value_paths() {
+        let filter = Filter::parse(r#"title pr or not (userType eq "Employee") and emails[type eq "work" and value co "@example.com"]"#).unwrap();
+
+        assert_eq!(filter, Filter::Or(
+            Box::new(Filter::Present(path("title", None))),
+            Box::new(Filter::And(
+                Box::new(Filter::Not(Box::new(Filter::Compare(path("userType", None), Operator::Eq, json!("Employee"))))),
+                Box::new(Filter::ValuePath(path("emails", None), Box::new(Filter::And(
+                    Box::new(Filter::Compare(path("type", None), Operator::Eq, json!("work"))),
+                    Box::new(Filter::Compare(path("value", None), Operator::Co, json!("@example.com"))),
+                )))),
+            )),
+        ));
+    }
+
+    #[test]
+    fn parse_reads_urns_literals_and_escapes() {
+        let filter = Filter::parse(r#"urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.value EQ "a \"b\"" AND meta.lastModified gt "2011-05-13T04:42:34Z" and active eq true and urn:ietf:params:scim:schemas:core:2.0:User:userName ne null"#).unwrap();
+
+        let mut comparisons = Vec::new();
+        let mut pending = vec![filter];
+        while let Some(filter) = pending.pop() {
+            match filter {
+                Filter::And(left, right) => {
+                    pending.push(*right);
+                    pending.push(*left);
+                }
+                Filter::Compare(path, operator, value) => comparisons.push((path.to_string(), operator, value)),
+                other => panic!("unexpected filter {:?}", other),
+            }
+        }
+        assert_eq!(comparisons, vec![
+            ("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.value".to_string(), Operator::Eq, json!("a \"b\"")),
+            ("meta.lastModified".to_string(), Operator::Gt, json!("2011-05-13T04:42:34Z")),
+            ("active".to_string(), Operator::Eq, json!(true)),
+            ("userName".to_string(), Operator::Ne, Value::Null),
+        ]);
+    }
+
+    #[test]
+    fn matches_evaluates_filters_against_resources() {
+        let schemas = crate::models::scim_schema::get_schemas(vec!["user", "enterprise_user"]).unwrap();
+        let user = json!({
+            "id": "2819c223",
+            "userName": "BJensen",
+            "title": "",
+            "active": true,
+            "emails": [{"type": "work", "value": "BJensen@Example.com", "primary": true}, {"type": "home", "value": "babs@jensen.org"}],
+            "meta": {"lastModified": "2011-05-13T04:42:34Z"},
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"employeeNumber": "701984", "manager": {"value": "26118915"}}
+        });
+        let matches = |filter: &str| Filter::parse(filter).unwrap().matches(&user, &schemas);
+
+        assert!(matches(r#"userName eq "bjensen""#));
+        assert!(matches(r#"USERNAME sw "bj" and not (userName ew "x")"#));
+        assert!(!matches(r#"id eq "2819C223""#));
+        assert!(matches(r#"emails co "@example.com""#));
+        assert!(matches(r#"emails.type eq "home""#));
+        assert!(matches(r#"emails[type eq "work" and primary eq true]"#));
+        assert!(!matches(r#"emails[type eq "home" and primary eq true]"#));
+        assert!(matches(r#"meta.lastModified gt "2011-01-01T00:00:00Z" and active eq true"#));
+        assert!(matches(r#"urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.value eq "26118915""#));
+        assert!(matches(r#"urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:employeeNumber ge "701984""#));
+        assert!(!matches("title pr"));
+        assert!(matches("externalId eq null and userName ne null"));
+        assert!(matches(r#"nickName ne "Babs" or userName eq "nobody""#));
+        assert!(!matches(r#"emails.value ne "babs@jensen.org""#));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_filters() {
+        for filter in ["", "userName", "userName eq", "userName like \"b\"", "(userName pr", "not userName pr", "userName eq \"b", "emails[type eq \"work\"", "userName pr pr", "userName eq [1]"] {
+            assert!(matches!(Filter::parse(filter), Err(SCIMError::InvalidSyntax(_))), "{}", filter);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+
+use percent_encoding::utf8_percent_encode;
+
+use crate::models::enterprise_user::EnterpriseUser;
+use crate::models::others::PATH_SEGMENT;
+use crate::models::user::User;
+use crate::utils::error::SCIMError;
+
+/// Looks up User resources by id, so manager references can be checked against the service provider's data.
+///
+/// Supplied by the service provider; `provider::resource_provider::ProviderLookup` implements it for a `ResourceProvider`.
+pub trait UserLookup {
+    /// Returns the User resource with the given id, or `None` if there is none.
+    fn lookup(&self, id: &str) -> Option<User>;
+}
+
+impl<F: Fn(&str) -> Option<User>> UserLookup for F {
+    fn lookup(&self, id: &str) -> Option<User> {
+        self(id)
+    }
+}
+
+/// Resolves the `manager` of an enterprise user against the service provider's users.
+///
+/// The manager's `value` must be the id of an existing User. Its `$ref` and `displayName` are readOnly (RFC 7643,
+/// section 4.3), so any values sent by the client are replaced with the manager's location under `base_url` and its
+/// `displayName`. When the user already has an id, the chain above the manager is walked to make sure it does not
+/// lead back to the user; managers missing further up the chain, and cycles that do not involve the user, are
+/// existing data and are left alone. Nothing happens when no manager `value` is set.
+///
+/// # Arguments
+///
+/// * `enterprise_user` - The enterprise extension of the user being created or updated.
+/// * `user_id` - The id of that user, or `None` if it has not been assigned yet.
+/// * `base_url` - The SCIM base URL, e.g. `https://example.com/v2`.
+/// * `lookup` - The lookup configured by the service provider.
+///
+/// # Errors
+///
+/// Returns `SCIMError::InvalidFieldValue` if the manager does not exist, or if the user would end up managing itself.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::enterprise_user::{EnterpriseUser, Manager};
+/// use scim_v2::models::user::{User, UserRequest};
+/// use scim_v2::utils::manager::resolve_manager;
+///
+/// let lookup = |id: &str| match id {
+///     "26118915-6090-4610-87e4-49d8ca9f808d" => Some(User {
+///         id: Some(id.to_string()),
+///         body: UserRequest {
+///             user_name: "jsmith".to_string(),
+///             display_name: Some("John Smith".to_string()),
+///             ..Default::default()
+///         },
+///         ..Default::default()
+///     }),
+///     _ => None,
+/// };
+/// let mut enterprise_user = EnterpriseUser {
+///     manager: Some(Manager { value: Some("26118915-6090-4610-87e4-49d8ca9f808d".to_string()), ..Default::default() }),
+///     ..Default::default()
+/// };
+///
+/// resolve_manager(&mut enterprise_user, Some("2819c223-7f76-453a-919d-413861904646"), "https://example.com/v2", &lookup).unwrap();
+/// let manager = enterprise_user.manager.unwrap();
+/// assert_eq!(manager.ref_, Some("https://example.com/v2/Users/26118915-6090-4610-87e4-49d8ca9f808d".to_string()));
+/// assert_eq!(manager.display_name, Some("John Smith".to_string()));
+/// ```
+pub fn resolve_manager(enterprise_user: &mut EnterpriseUser, user_id: Option<&str>, base_url: &str, lookup: &dyn UserLookup) -> Result<(), SCIMError> {
+    let manager = match enterprise_user.manager.as_mut() {
+        Some(manager) => manager,
+        None => return Ok(()),
+    };
+    let manager_id = match manager.value.as_deref().filter(|value| !value.is_empty()) {
+        Some(manager_id) => manager_id.to_string(),
+        None => return Ok(()),
+    };
+    let manager_user = lookup.lookup(&manager_id)
+        .ok_or_else(|| SCIMError::InvalidFieldValue(format!("manager {} does not exist", manager_id)))?;
+    if user_id.map_or(false, |user_id| chain_reaches(&manager_id, user_id, lookup)) {
+        return Err(SCIMError::InvalidFieldValue(format!("manager {} creates a management cycle", manager_id)));
+    }
+    manager.ref_ = Some(format!("{}/Users/{}", base_url.trim_end_matches('/'), utf8_percent_encode(&manager_id, PATH_SEGMENT)));
+    manager.display_name = manager_user.body.display_name;
+    Ok(())
+}
+
+/// Returns the managers above a user, from its direct manager up to the top of the hierarchy.
+///
+/// Use it to build reporting lines for org charts: the length of the chain is the user's depth in the hierarchy.
+///
+/// # Errors
+///
+/// Returns `SCIMError::InvalidFieldValue` if a manager in the chain does not exist, or if the chain contains a cycle.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::enterprise_user::{EnterpriseUser, Manager};
+/// use scim_v2::models::user::{User, UserRequest};
+/// use scim_v2::utils::manager::manager_chain;
+///
+/// let user = |id: &str, manager: Option<&str>| User {
+///     id: Some(id.to_string()),
+///     body: UserRequest {
+///         user_name: id.to_string(),
+///         enterprise_user: Some(EnterpriseUser {
+///             manager: manager.map(|manager| Manager { value: Some(manager.to_string()), ..Default::default() }),
+///             ..Default::default()
+///         }),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// let lookup = |id: &str| match id {
+///     "cto" => Some(user("cto", Some("ceo"))),
+///     "ceo" => Some(user("ceo", None)),
+///     _ => None,
+/// };
+///
+/// let chain = manager_chain(&user("engineer", Some("cto")), &lookup).unwrap();
+/// assert_eq!(chain.iter().map(|manager| manager.body.user_name.as_str()).collect::<Vec<_>>(), vec!["cto", "ceo"]);
+/// ```
+pub fn manager_chain(user: &User, lookup: &dyn UserLookup) -> Result<Vec<User>, SCIMError> {
+    match manager_of(user) {
+        Some(manager_id) => walk_chain(manager_id, user.id.as_deref(), lookup),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn manager_of(user: &User) -> Option<&str> {
+    user.body.enterprise_user.as_ref()?
+        .manager.as_ref()?
+        .value.as_deref()
+        .filter(|value| !value.is_empty())
+}
+
+/// Returns whether the chain of managers starting at `manager_id` passes through `user_id`. The walk ends at a missing
+/// manager or at a cycle that does not pass through `user_id`.
+fn chain_reaches(manager_id: &str, user_id: &str, lookup: &dyn UserLookup) -> bool {
+    let mut visited = HashSet::new();
+    let mut next = Some(manager_id.to_string());
+    while let Some(id) = next {
+        if id == user_id {
+            return true;
+        }
+        if !visited.insert(id.clone()) {
+            return false;
+        }
+        next = lookup.lookup(&id).and_then(|manager| manager_of(&manager).map(str::to_string));
+    }
+    false
+}
+
+/// Looks up the managers starting at `manager_id`, failing on a missing manager or on a repeated id (including the
+/// id of the user the chain starts from).
+fn walk_chain(manager_id: &str, user_id: Option<&str>, lookup: &dyn UserLookup) -> Result<Vec<User>, SCIMError> {
+    let mut visited: HashSet<String> = user_id.map(str::to_string).into_iter().collect();
+    let mut chain: Vec<User> = Vec::new();
+    let mut next = Some(manager_id.to_string());
+    while let Some(id) = next {
+        if visited.contains(&id) {
+            return Err(SCIMError::InvalidFieldValue(format!("manager {} creates a management cycle", id)));
+        }
+        let manager = lookup.lookup(&id)
+            .ok_or_else(|| SCIMError::InvalidFieldValue(format!("manager {} does not exist", id)))?;
+        next = manager_of(&manager).map(str::to_string);
+        visited.insert(id);
+        chain.push(manager);
+    }
+    Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::models::enterprise_user::Manager;
+    use crate::models::user::UserRequest;
+
+    use super::*;
+
+    fn user(id: &str, manager: Option<&str>) -> User {
+        User {
+            id: Some(id.to_string()),
+            body: UserRequest {
+                user_name: id.to_string(),
+                enterprise_user: Some(EnterpriseUser {
+                    manager: manager.map(|manager| Manager { value: Some(manager.to_string()), ..Default::default() }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn lookup(id: &str) -> Option<User> {
+        match id {
+            "alice" => Some(user("alice", Some("bob"))),
+            "bob" => Some(user("bob", Some("carol"))),
+            "carol" => Some(user("carol", None)),
+            "dave" => Some(user("dave", Some("erin"))),
+            "erin" => Some(user("erin", Some("dave"))),
+            "grace" => Some(user("grace", Some("heidi"))),
+            "ivan judy" => Some(user("ivan judy", None)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn resolve_manager_rejects_dangling_references_and_cycles() {
+        let lookup: &dyn UserLookup = &lookup;
+        let reports_to = |manager: &str| EnterpriseUser {
+            manager: Some(Manager { value: Some(manager.to_string()), ..Default::default() }),
+            ..Default::default()
+        };
+
+        let dangling = resolve_manager(&mut reports_to("mallory"), Some("alice"), "https://example.com/v2", lookup);
+        assert_eq!(dangling.unwrap_err().to_string(), "Invalid field value: manager mallory does not exist");
+
+        let cycle = resolve_manager(&mut reports_to("alice"), Some("carol"), "https://example.com/v2", lookup);
+        assert_eq!(cycle.unwrap_err().to_string(), "Invalid field value: manager alice creates a management cycle");
+        let cycle = resolve_manager(&mut reports_to("carol"), Some("carol"), "https://example.com/v2", lookup);
+        assert_eq!(cycle.unwrap_err().to_string(), "Invalid field value: manager carol creates a management cycle");
+
+        // Problems further up the chain that do not involve the user are not the user's to fix.
+        assert!(resolve_manager(&mut reports_to("grace"), Some("alice"), "https://example.com/v2", lookup).is_ok());
+        assert!(resolve_manager(&mut reports_to("dave"), Some("alice"), "https://example.com/v2", lookup).is_ok());
+        assert!(resolve_manager(&mut reports_to("alice"), None, "https://example.com/v2", lookup).is_ok());
+
+        let mut encoded = reports_to("ivan judy");
+        resolve_manager(&mut encoded, Some("alice"), "https://example.com/v2", lookup).unwrap();
+        assert_eq!(encoded.manager.unwrap().ref_, Some("https://example.com/v2/Users/ivan%20judy".to_string()));
+
+        let mut enterprise_user = EnterpriseUser {
+            manager: Some(Manager { value: Some("carol".to_string()), ref_: Some("https://evil.example/Users/carol".to_string()), display_name: Some("Spoofed".to_string()) }),
+            ..Default::default()
+        };
+        resolve_manager(&mut enterprise_user, None, "https://example.com/v2/", lookup).unwrap();
+        let manager = enterprise_user.manager.unwrap();
+        assert_eq!(manager.ref_, Some("https://example.com/v2/Users/carol".to_string()));
+        assert_eq!(manager.display_name, None);
+    }
+
+    #[test]
+    fn manager_chain_walks_to_the_top_and_detects_existing_cycles() {
+        let lookup: &dyn UserLookup = &lookup;
+
+        let chain = manager_chain(&user("alice", Some("bob")), lookup).unwrap();
+        assert_eq!(chain.iter().map(|manager| manager.body.user_name.as_str()).collect::<Vec<_>>(), vec!["bob", "carol"]);
+        assert!(manager_chain(&user("carol", None), lookup).unwrap().is_empty());
+        assert!(manager_chain(&user("frank", Some("dave")), lookup).is_err());
+    }
+}
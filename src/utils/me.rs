@@ -0,0 +1,127 @@
+use percent_encoding::utf8_percent_encode;
+
+use crate::models::errors::ScimHttpError;
+use crate::models::others::PATH_SEGMENT;
+
+/// Maps the authenticated subject of a request to the id of its User resource.
+///
+/// Supplied by the service provider, which knows how its authentication tokens relate to users.
+/// `provider::resource_provider::ProviderLookup` implements it for subjects that are the `userName` of their User.
+pub trait MeResolver {
+    /// Returns the id of the User resource for `principal`, or `None` if the subject has no User resource.
+    fn resolve(&self, principal: &str) -> Option<String>;
+}
+
+impl<F: Fn(&str) -> Option<String>> MeResolver for F {
+    fn resolve(&self, principal: &str) -> Option<String> {
+        self(principal)
+    }
+}
+
+/// Rewrites a request path under `/Me` (RFC 7644, section 3.11) to the canonical path of the authenticated subject.
+///
+/// A `POST` to `/Me` creates a User for the subject, so it is rewritten to the `/Users` collection and does not need
+/// an authenticated subject. Every other method is rewritten to `/Users/{id}`, with the id percent-encoded as a path
+/// segment. Requests to the rewritten path have the same semantics as requests to that path, and the `Location`
+/// header of the response should point to the rewritten path rather than to `/Me`. `/Me` has no sub-resources, so
+/// paths such as `/Me/.search` are rejected; searches go to `/Users/.search`.
+///
+/// # Arguments
+///
+/// * `method` - The HTTP method of the request, e.g. `GET` or `POST`.
+/// * `path` - The request path relative to the SCIM base URL, e.g. `/Me` or `/Me?attributes=userName`.
+/// * `principal` - The authenticated subject, if the request was authenticated.
+/// * `resolver` - The resolver configured by the service provider, if any.
+///
+/// # Returns
+///
+/// * `Ok(None)` - If `path` is not under `/Me`.
+/// * `Ok(Some(path))` - The canonical path to send the request to.
+/// * `Err(ScimHttpError)` - `501` if no resolver is configured, `401` if the request is not authenticated, or `404`
+///   if the subject has no User resource or the path is below `/Me`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::utils::me::{resolve_me_path, MeResolver};
+///
+/// let resolver = |principal: &str| Some(format!("id of {}", principal));
+///
+/// let path = resolve_me_path("GET", "/Me", Some("bjensen"), Some(&resolver as &dyn MeResolver));
+/// assert_eq!(path.unwrap(), Some("/Users/id%20of%20bjensen".to_string()));
+///
+/// let path = resolve_me_path("POST", "/Me", None, Some(&resolver as &dyn MeResolver));
+/// assert_eq!(path.unwrap(), Some("/Users".to_string()));
+///
+/// let path = resolve_me_path("GET", "/Me", Some("bjensen"), None);
+/// assert_eq!(path.unwrap_err().status, "501");
+/// ```
+pub fn resolve_me_path(method: &str, path: &str, principal: Option<&str>, resolver: Option<&dyn MeResolver>) -> Result<Option<String>, ScimHttpError> {
+    let query = match path.strip_prefix("/Me") {
+        Some(rest) if rest.is_empty() || rest.starts_with('?') => rest,
+        Some(rest) if rest.starts_with('/') => return Err(error("404", "The /Me endpoint has no sub-resources; search /Users/.search instead.")),
+        _ => return Ok(None),
+    };
+    let resolver = resolver.ok_or_else(|| error("501", "The /Me endpoint is not supported."))?;
+    if method.eq_ignore_ascii_case("POST") {
+        return Ok(Some(format!("/Users{}", query)));
+    }
+    let principal = principal.ok_or_else(|| error("401", "The /Me endpoint requires an authenticated subject."))?;
+    let id = resolver.resolve(principal)
+        .ok_or_else(|| error("404", "The authenticated subject has no User resource."))?;
+    Ok(Some(format!("/Users/{}{}", utf8_percent_encode(&id, PATH_SEGMENT), query)))
+}
+
+fn error(status: &str, detail: &str) -> ScimHttpError {
+    ScimHttpError {
+        detail: Some(detail.to_string()),
+        status: status.to_string(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn resolver(principal: &str) -> Option<String> {
+        match principal {
+            "bjensen" => Some("2819c223-7f76-453a-919d-413861904646".to_string()),
+            "jsmith" => Some("j/smith?".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn me_paths_resolve_to_canonical_user_paths() {
+        let resolver: &dyn MeResolver = &resolver;
+
+        assert_eq!(resolve_me_path("GET", "/Me", Some("bjensen"), Some(resolver)).unwrap(), Some("/Users/2819c223-7f76-453a-919d-413861904646".to_string()));
+        assert_eq!(resolve_me_path("GET", "/Me?attributes=userName", Some("bjensen"), Some(resolver)).unwrap(), Some("/Users/2819c223-7f76-453a-919d-413861904646?attributes=userName".to_string()));
+        assert_eq!(resolve_me_path("PATCH", "/Me", Some("jsmith"), Some(resolver)).unwrap(), Some("/Users/j%2Fsmith%3F".to_string()));
+        assert_eq!(resolve_me_path("GET", "/Users/42", Some("bjensen"), Some(resolver)).unwrap(), None);
+        assert_eq!(resolve_me_path("GET", "/Members", Some("bjensen"), None).unwrap(), None);
+    }
+
+    #[test]
+    fn post_to_me_creates_in_the_users_collection() {
+        let resolver: &dyn MeResolver = &resolver;
+
+        assert_eq!(resolve_me_path("POST", "/Me", None, Some(resolver)).unwrap(), Some("/Users".to_string()));
+        assert_eq!(resolve_me_path("post", "/Me?attributes=id", Some("unknown"), Some(resolver)).unwrap(), Some("/Users?attributes=id".to_string()));
+        assert_eq!(resolve_me_path("POST", "/Me", None, None).unwrap_err().status, "501");
+    }
+
+    #[test]
+    fn me_paths_fail_without_resolver_principal_or_user() {
+        let resolver: &dyn MeResolver = &resolver;
+
+        assert_eq!(resolve_me_path("GET", "/Me", Some("bjensen"), None).unwrap_err().status, "501");
+        assert_eq!(resolve_me_path("GET", "/Me", None, Some(resolver)).unwrap_err().status, "401");
+        assert_eq!(resolve_me_path("GET", "/Me", Some("unknown"), Some(resolver)).unwrap_err().status, "404");
+        assert_eq!(resolve_me_path("POST", "/Me/.search", Some("bjensen"), Some(resolver)).unwrap_err().status, "404");
+        assert_eq!(resolve_me_path("GET", "/Me/groups", Some("bjensen"), Some(resolver)).unwrap_err().status, "404");
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::models::scim_schema::{get_schemas, Schema, SubAttributes};
+use crate::utils::case::{canonicalize_json, canonicalize_resource, COMMON_ATTRIBUTES, META_ATTRIBUTES};
+use crate::utils::error::SCIMError;
+
+/// The names of all built-in schemas, in the order `get_schemas` accepts them.
+pub(crate) const BUILT_IN_SCHEMAS: [&str; 6] = ["user", "enterprise_user", "group", "resource_type", "service_provider_config", "schema"];
+
+/// The built-in schemas parsed so far, keyed by the names they were requested with.
+static PARSED_SCHEMAS: Mutex<Vec<(String, Arc<Vec<Schema>>)>> = Mutex::new(Vec::new());
+
+/// How attributes that a model does not define are handled when it is deserialized.
+///
+/// Service providers can pick a mode per endpoint, for example `Strict` for `POST /Users` and `Lenient` for
+/// payloads relayed from other systems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Keep unknown attributes in the model's `additional_attributes`, so they survive a round-trip.
+    #[default]
+    Lenient,
+    /// Reject attributes (and extension schemas) that are not defined by the model's schemas with
+    /// `SCIMError::InvalidSyntax`, naming the offending attribute path.
+    Strict,
+}
+
+/// Returns the path of the first attribute in a JSON representation of a resource that its schemas do not define.
+///
+/// Names are compared exactly, since only the canonical names bind to the models; enable the `case-insensitive`
+/// feature to canonicalise them first. Extension objects are recognised by a key matching a schema URN, and their
+/// attributes are reported as `urn:...:attribute`. Sub-attributes are reported as `attribute.subAttribute`. The common
+/// attributes `schemas`, `id`, `externalId` and `meta` are always allowed.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::scim_schema::get_schemas;
+/// use scim_v2::utils::parse::unknown_attribute;
+/// use serde_json::json;
+///
+/// let schemas = get_schemas(vec!["user"]).unwrap();
+/// assert_eq!(unknown_attribute(&json!({"userName": "bjensen", "emials": []}), &schemas), Some("emials".to_string()));
+/// assert_eq!(unknown_attribute(&json!({"userName": "bjensen", "name": {"givenNmae": "Barbara"}}), &schemas), Some("name.givenNmae".to_string()));
+/// assert_eq!(unknown_attribute(&json!({"userName": "bjensen", "emails": [{"value": "bjensen@example.com"}]}), &schemas), None);
+/// ```
+pub fn unknown_attribute(value: &Value, schemas: &[Schema]) -> Option<String> {
+    let object = value.as_object()?;
+    let is_extension = |schema: &&Schema| object.contains_key(&schema.id);
+    let core_schemas: Vec<&Schema> = schemas.iter().filter(|schema| !is_extension(schema)).collect();
+
+    for (key, value) in object {
+        if let Some(extension) = schemas.iter().find(|schema| key == &schema.id) {
+            if let Some(path) = value.as_object().and_then(|object| {
+                object.iter().find_map(|(key, value)| unknown_in_schema(key, value, &[extension]))
+            }) {
+                return Some(format!("{}:{}", extension.id, path));
+            }
+        } else if COMMON_ATTRIBUTES.contains(&key.as_str()) {
+            if key == "meta" {
+                if let Some(sub_attribute) = unknown_name(value, &META_ATTRIBUTES) {
+                    return Some(format!("meta.{}", sub_attribute));
+                }
+            }
+        } else if let Some(path) = unknown_in_schema(key, value, &core_schemas) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Deserializes a model from JSON in the given mode, canonicalising attribute names with the named built-in schemas
+/// first when the `case-insensitive` feature is enabled.
+pub(crate) fn from_json<T: DeserializeOwned>(json: &str, schema_names: &[&str], mode: ParseMode) -> Result<T, SCIMError> {
+    if mode == ParseMode::Lenient && !cfg!(feature = "case-insensitive") {
+        return serde_json::from_str(json).map_err(SCIMError::DeserializationError);
+    }
+    let mut value: Value = serde_json::from_str(json).map_err(SCIMError::DeserializationError)?;
+    let schemas = built_in_schemas(schema_names)?;
+    if cfg!(feature = "case-insensitive") {
+        canonicalize_json(&mut value, &schemas);
+    }
+    if mode == ParseMode::Strict {
+        if let Some(path) = unknown_attribute(&value, &schemas) {
+            return Err(SCIMError::InvalidSyntax(format!("unknown attribute: {}", path)));
+        }
+    }
+    serde_json::from_value(value).map_err(SCIMError::DeserializationError)
+}
+
+/// Deserializes an API message such as a `PatchOp`, letting `canonicalize` rewrite its attribute names first when the
+/// `case-insensitive` feature is enabled.
+pub(crate) fn message_from_json<T: DeserializeOwned>(json: &str, canonicalize: impl FnOnce(&mut Value) -> Result<(), SCIMError>) -> Result<T, SCIMError> {
+    if !cfg!(feature = "case-insensitive") {
+        return serde_json::from_str(json).map_err(SCIMError::DeserializationError);
+    }
+    let mut value: Value = serde_json::from_str(json).map_err(SCIMError::DeserializationError)?;
+    canonicalize(&mut value)?;
+    serde_json::from_value(value).map_err(SCIMError::DeserializationError)
+}
+
+/// Canonicalises the attribute names of a resource with the built-in schemas it declares when the `case-insensitive`
+/// feature is enabled, for resources whose type is only known at runtime.
+pub(crate) fn canonicalize_declared(value: &mut Value) -> Result<(), SCIMError> {
+    if cfg!(feature = "case-insensitive") {
+        canonicalize_resource(value, &built_in_schemas(&BUILT_IN_SCHEMAS)?);
+    }
+    Ok(())
+}
+
+/// Returns the named built-in schemas, parsing the embedded JSON only the first time a list of names is requested.
+pub(crate) fn built_in_schemas(schema_names: &[&str]) -> Result<Arc<Vec<Schema>>, SCIMError> {
+    let key = schema_names.join(",");
+    let mut parsed = PARSED_SCHEMAS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, schemas)) = parsed.iter().find(|(names, _)| *names == key) {
+        return Ok(Arc::clone(schemas));
+    }
+    let schemas = Arc::new(get_schemas(schema_names.to_vec())?);
+    parsed.push((key, Arc::clone(&schemas)));
+    Ok(schemas)
+}
+
+/// Deserializes the additional attributes of a request model, dropping the server-assigned `id` and `meta`.
+pub(crate) fn without_server_attributes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Map<String, Value>, D::Error> {
+    let mut attributes = Map::deserialize(deserializer)?;
+    attributes.remove("id");
+    attributes.remove("meta");
+    Ok(attributes)
+}
+
+fn unknown_in_schema(key: &str, value: &Value, schemas: &[&Schema]) -> Option<String> {
+    let attribute = schemas.iter().flat_map(|schema| schema.attributes.iter()).find(|attribute| attribute.name == key);
+    match attribute {
+        Some(attribute) => unknown_sub_attribute(value, &attribute.sub_attributes)
+            .map(|sub_attribute| format!("{}.{}", key, sub_attribute)),
+        None => Some(key.to_string()),
+    }
+}
+
+fn unknown_sub_attribute(value: &Value, sub_attributes: &Option<Vec<SubAttributes>>) -> Option<String> {
+    let names: Vec<&str> = sub_attributes.as_ref()?.iter().map(|sub_attribute| sub_attribute.name.as_str()).collect();
+    unknown_name(value, &names)
+}
+
+/// Returns the first key of an object, or of any object in an array, that is not in `names`.
+fn unknown_name(value: &Value, names: &[&str]) -> Option<String> {
+    match value {
+        Value::Array(values) => values.iter().find_map(|value| unknown_name(value, names)),
+        Value::Object(object) => object.keys().find(|key| !names.contains(&key.as_str())).cloned(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::models::resource_types::{get_resource_types, ResourceType};
+    use crate::models::service_provider_config::{AuthenticationScheme, Pagination, ServiceProviderConfig};
+
+    use super::*;
+
+    #[test]
+    fn unknown_attribute_reports_extension_and_meta_paths() {
+        let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+
+        let manager = json!({
+            "userName": "bjensen",
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"manager": {"value": "26118915", "name": "Boss"}}
+        });
+        let meta = json!({"userName": "bjensen", "meta": {"resourceType": "User", "etag": "W/\"1\""}});
+        let unregistered = json!({"userName": "bjensen", "urn:example:params:scim:schemas:extension:custom:2.0:User": {}});
+
+        assert_eq!(unknown_attribute(&manager, &schemas), Some("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager.name".to_string()));
+        assert_eq!(unknown_attribute(&meta, &schemas), Some("meta.etag".to_string()));
+        assert_eq!(unknown_attribute(&unregistered, &schemas), Some("urn:example:params:scim:schemas:extension:custom:2.0:User".to_string()));
+    }
+
+    #[test]
+    fn discovery_models_round_trip_in_strict_mode() {
+        let config = ServiceProviderConfig::builder()
+            .patch(true)
+            .etag(true)
+            .authentication_scheme(AuthenticationScheme { primary: Some(true), ..AuthenticationScheme::oauth_bearer_token() })
+            .pagination(Pagination { cursor: true, ..Default::default() })
+            .build()
+            .unwrap();
+        ServiceProviderConfig::deserialize_with_mode(&config.serialize().unwrap(), ParseMode::Strict).unwrap();
+
+        for resource_type in get_resource_types(vec!["user", "group", "enterprise_user"], "https://example.com/v2").unwrap() {
+            ResourceType::deserialize_with_mode(&resource_type.serialize().unwrap(), ParseMode::Strict).unwrap();
+        }
+        for schema in get_schemas(vec!["user", "group", "enterprise_user"]).unwrap() {
+            Schema::deserialize_with_mode(&schema.serialize().unwrap(), ParseMode::Strict).unwrap();
+        }
+    }
+}
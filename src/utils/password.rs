@@ -0,0 +1,439 @@
+use serde_json::Value;
+
+use crate::models::errors::ScimHttpError;
+use crate::models::others::{PatchOp, PatchOperations};
+use crate::models::service_provider_config::{Capability, ServiceProviderConfig};
+use crate::models::user::{User, UserRequest};
+use crate::utils::error::SCIMError;
+use crate::utils::patch::PatchPath;
+
+/// Hashes passwords before a service provider stores them.
+///
+/// Implement this with the password hashing function of your choice (e.g. Argon2 or bcrypt); the crate does not
+/// ship one.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::utils::error::SCIMError;
+/// use scim_v2::utils::password::PasswordHasher;
+///
+/// struct Argon2Hasher;
+///
+/// impl PasswordHasher for Argon2Hasher {
+///     fn hash(&self, password: &str) -> Result<String, SCIMError> {
+///         // Call into your hashing library here.
+///         Ok(format!("$argon2id${}", password.len()))
+///     }
+/// }
+/// ```
+pub trait PasswordHasher {
+    /// Returns the value to store in place of the cleartext `password`.
+    fn hash(&self, password: &str) -> Result<String, SCIMError>;
+}
+
+/// Rules that incoming passwords must satisfy.
+///
+/// The default policy requires at least 8 characters and no character classes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: Option<usize>,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Rejects passwords that contain the user's `userName`, ignoring case.
+    pub reject_user_name: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: None,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: false,
+            require_symbol: false,
+            reject_user_name: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Checks a password against the policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `400` `ScimHttpError` with `scimType` `invalidValue` describing the first rule that is not met.
+    /// The password itself is never included in the error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::utils::password::PasswordPolicy;
+    ///
+    /// let policy = PasswordPolicy {
+    ///     require_digit: true,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert!(policy.check("t1meMa$heen", "bjensen").is_ok());
+    /// assert_eq!(policy.check("timeMachine", "bjensen").unwrap_err().scim_type, Some("invalidValue".to_string()));
+    /// ```
+    pub fn check(&self, password: &str, user_name: &str) -> Result<(), ScimHttpError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(invalid_password(&format!("must be at least {} characters long", self.min_length)));
+        }
+        if let Some(max_length) = self.max_length {
+            if length > max_length {
+                return Err(invalid_password(&format!("must be at most {} characters long", max_length)));
+            }
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            return Err(invalid_password("must contain an uppercase letter"));
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            return Err(invalid_password("must contain a lowercase letter"));
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            return Err(invalid_password("must contain a digit"));
+        }
+        if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+            return Err(invalid_password("must contain a symbol"));
+        }
+        if self.reject_user_name && !user_name.is_empty() && password.to_lowercase().contains(&user_name.to_lowercase()) {
+            return Err(invalid_password("must not contain the userName"));
+        }
+        Ok(())
+    }
+}
+
+/// Applies a `PasswordPolicy` and a `PasswordHasher` to the `password` of incoming users.
+///
+/// Call `on_create` for `POST` requests, `on_replace` for `PUT` requests and `on_patch` for `PATCH` requests before
+/// they are applied to the stored user, then store the result. Pass every user returned to a client through
+/// `strip_password`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::service_provider_config::{ServiceProviderConfig, Supported};
+/// use scim_v2::models::user::CreateUserRequest;
+/// use scim_v2::utils::error::SCIMError;
+/// use scim_v2::utils::password::{PasswordHandler, PasswordHasher, PasswordPolicy};
+///
+/// struct ReversingHasher;
+///
+/// impl PasswordHasher for ReversingHasher {
+///     fn hash(&self, password: &str) -> Result<String, SCIMError> {
+///         Ok(password.chars().rev().collect())
+///     }
+/// }
+///
+/// let handler = PasswordHandler::new(PasswordPolicy::default(), ReversingHasher);
+/// let mut request = CreateUserRequest {
+///     user_name: "bjensen".to_string(),
+///     password: Some("t1meMa$heen".to_string()),
+///     ..Default::default()
+/// };
+///
+/// handler.on_create(&mut request).unwrap();
+/// assert_eq!(request.password, Some("neeh$aMem1t".to_string()));
+/// ```
+#[derive(Debug)]
+pub struct PasswordHandler<H: PasswordHasher> {
+    policy: PasswordPolicy,
+    hasher: H,
+}
+
+impl<H: PasswordHasher> PasswordHandler<H> {
+    /// Creates a handler with the given policy and hasher.
+    pub fn new(policy: PasswordPolicy, hasher: H) -> Self {
+        PasswordHandler { policy, hasher }
+    }
+
+    /// Checks and hashes the initial password of a user being created, if one was sent.
+    ///
+    /// Setting the initial password is part of creating the user, so it is allowed even when the service provider
+    /// does not advertise `changePassword`.
+    ///
+    /// # Errors
+    ///
+    /// * A `400` `ScimHttpError` with `scimType` `invalidValue` if the password breaks the policy.
+    /// * A `500` `ScimHttpError` if the hasher fails.
+    pub fn on_create(&self, request: &mut UserRequest) -> Result<(), ScimHttpError> {
+        self.hash_password(request)
+    }
+
+    /// Checks and hashes a new password sent in a replace request, if one was sent.
+    ///
+    /// The request must not carry the stored hash: it would be hashed again. Do not use this for `PATCH` requests
+    /// applied to a stored user; use `on_patch` instead.
+    ///
+    /// # Errors
+    ///
+    /// * A `501` `ScimHttpError` if a password is sent and `config` does not advertise `changePassword`.
+    /// * A `400` `ScimHttpError` with `scimType` `invalidValue` if the password breaks the policy.
+    /// * A `500` `ScimHttpError` if the hasher fails.
+    pub fn on_replace(&self, config: &ServiceProviderConfig, request: &mut UserRequest) -> Result<(), ScimHttpError> {
+        if request.password.is_none() {
+            return Ok(());
+        }
+        require_change_password(config)?;
+        self.hash_password(request)
+    }
+
+    /// Checks and hashes the new passwords set by the operations of a modify request, before it is applied.
+    ///
+    /// Only `add` and `replace` operations whose `path` is `password`, or whose path-less `value` contains
+    /// `password`, are touched, so a patch of other attributes is passed through unchanged. `user_name` is the
+    /// `userName` of the user being modified, for `PasswordPolicy::reject_user_name`.
+    ///
+    /// # Errors
+    ///
+    /// * A `501` `ScimHttpError` if an operation targets the password and `config` does not advertise
+    ///   `changePassword`.
+    /// * A `400` `ScimHttpError` with `scimType` `invalidValue` if a password is not a string or breaks the policy.
+    /// * A `500` `ScimHttpError` if the hasher fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scim_v2::models::others::{PatchOp, PatchOperations};
+    /// use scim_v2::models::service_provider_config::{ServiceProviderConfig, Supported};
+    /// use scim_v2::utils::error::SCIMError;
+    /// use scim_v2::utils::password::{PasswordHandler, PasswordHasher, PasswordPolicy};
+    /// use serde_json::json;
+    ///
+    /// struct ReversingHasher;
+    ///
+    /// impl PasswordHasher for ReversingHasher {
+    ///     fn hash(&self, password: &str) -> Result<String, SCIMError> {
+    ///         Ok(password.chars().rev().collect())
+    ///     }
+    /// }
+    ///
+    /// let handler = PasswordHandler::new(PasswordPolicy::default(), ReversingHasher);
+    /// let config = ServiceProviderConfig {
+    ///     change_password: Supported { supported: true },
+    ///     ..Default::default()
+    /// };
+    /// let mut patch = PatchOp {
+    ///     operations: vec![PatchOperations {
+    ///         op: "replace".to_string(),
+    ///         path: Some("password".to_string()),
+    ///         value: Some(json!("t1meMa$heen")),
+    ///     }],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// handler.on_patch(&config, "bjensen", &mut patch).unwrap();
+    /// assert_eq!(patch.operations[0].value, Some(json!("neeh$aMem1t")));
+    /// ```
+    pub fn on_patch(&self, config: &ServiceProviderConfig, user_name: &str, patch: &mut PatchOp) -> Result<(), ScimHttpError> {
+        for operation in &mut patch.operations {
+            let password = match password_of(operation) {
+                Some(password) => password,
+                None => continue,
+            };
+            require_change_password(config)?;
+            if let Some(password) = password {
+                let hash = match password {
+                    Value::String(cleartext) => self.hash(cleartext, user_name)?,
+                    _ => return Err(invalid_password("must be a string")),
+                };
+                *password = Value::String(hash);
+            }
+        }
+        Ok(())
+    }
+
+    fn hash_password(&self, request: &mut UserRequest) -> Result<(), ScimHttpError> {
+        let password = match request.password.as_deref() {
+            Some(password) => password,
+            None => return Ok(()),
+        };
+        request.password = Some(self.hash(password, &request.user_name)?);
+        Ok(())
+    }
+
+    fn hash(&self, password: &str, user_name: &str) -> Result<String, ScimHttpError> {
+        self.policy.check(password, user_name)?;
+        self.hasher.hash(password).map_err(|e| ScimHttpError {
+            detail: Some(e.to_string()),
+            status: "500".to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Returns `Some` if a patch operation targets the password, with the new password if the operation sets one.
+fn password_of(operation: &mut PatchOperations) -> Option<Option<&mut Value>> {
+    match operation.path.as_deref().map(PatchPath::parse) {
+        Some(Ok(path)) if path.urn.is_none() && path.filter.is_none() && path.sub_attribute.is_none()
+            && path.attribute.eq_ignore_ascii_case("password") => {
+            Some(operation.value.as_mut().filter(|_| !operation.op.eq_ignore_ascii_case("remove")))
+        }
+        Some(_) => None,
+        None => {
+            let value = operation.value.as_mut()?.as_object_mut()?;
+            let key = value.keys().find(|key| key.eq_ignore_ascii_case("password"))?.clone();
+            Some(value.get_mut(&key))
+        }
+    }
+}
+
+fn require_change_password(config: &ServiceProviderConfig) -> Result<(), ScimHttpError> {
+    config.require(Capability::ChangePassword).map_err(|e| ScimHttpError {
+        detail: Some(e.to_string()),
+        status: "501".to_string(),
+        ..Default::default()
+    })
+}
+
+/// Removes the password from a user before it is returned to a client.
+///
+/// `password` is `returned: never` in the User schema (RFC 7643, section 4.1.1), so neither the cleartext nor the
+/// stored hash may appear in a response.
+pub fn strip_password(mut user: User) -> User {
+    user.body.password = None;
+    user
+}
+
+fn invalid_password(rule: &str) -> ScimHttpError {
+    ScimHttpError {
+        scim_type: Some("invalidValue".to_string()),
+        detail: Some(format!("The password {}.", rule)),
+        status: "400".to_string(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::models::service_provider_config::Supported;
+
+    use super::*;
+
+    struct PrefixHasher;
+
+    impl PasswordHasher for PrefixHasher {
+        fn hash(&self, password: &str) -> Result<String, SCIMError> {
+            Ok(format!("hashed:{}", password.len()))
+        }
+    }
+
+    fn request(password: &str) -> UserRequest {
+        UserRequest {
+            user_name: "bjensen".to_string(),
+            password: Some(password.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn policy_rejects_weak_passwords_with_invalid_value() {
+        let policy = PasswordPolicy {
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            reject_user_name: true,
+            ..Default::default()
+        };
+
+        assert!(policy.check("t1meMa$heen", "bjensen").is_ok());
+        for password in ["t1M$", "t1memachine$", "timeMachine$", "t1meMachine", "Bjensen$1234"] {
+            let error = policy.check(password, "bjensen").unwrap_err();
+            assert_eq!(error.status, "400");
+            assert_eq!(error.scim_type, Some("invalidValue".to_string()));
+            assert!(!error.detail.unwrap().contains(password));
+        }
+    }
+
+    #[test]
+    fn handler_hashes_password_on_create() {
+        let handler = PasswordHandler::new(PasswordPolicy::default(), PrefixHasher);
+
+        let mut created = request("t1meMa$heen");
+        handler.on_create(&mut created).unwrap();
+        assert_eq!(created.password, Some("hashed:11".to_string()));
+
+        let mut too_short = request("short");
+        assert_eq!(handler.on_create(&mut too_short).unwrap_err().status, "400");
+        assert_eq!(too_short.password, Some("short".to_string()));
+    }
+
+    #[test]
+    fn handler_rejects_password_change_without_capability() {
+        let handler = PasswordHandler::new(PasswordPolicy::default(), PrefixHasher);
+        let disabled = ServiceProviderConfig::default();
+        let enabled = ServiceProviderConfig {
+            change_password: Supported { supported: true },
+            ..Default::default()
+        };
+
+        assert_eq!(handler.on_replace(&disabled, &mut request("t1meMa$heen")).unwrap_err().status, "501");
+        assert!(handler.on_replace(&disabled, &mut UserRequest::default()).is_ok());
+        let mut replaced = request("t1meMa$heen");
+        handler.on_replace(&enabled, &mut replaced).unwrap();
+        assert_eq!(replaced.password, Some("hashed:11".to_string()));
+    }
+
+    #[test]
+    fn handler_hashes_only_password_operations_on_patch() {
+        let handler = PasswordHandler::new(PasswordPolicy::default(), PrefixHasher);
+        let disabled = ServiceProviderConfig::default();
+        let enabled = ServiceProviderConfig {
+            change_password: Supported { supported: true },
+            ..Default::default()
+        };
+        let operation = |op: &str, path: Option<&str>, value: Option<Value>| PatchOperations {
+            op: op.to_string(),
+            path: path.map(str::to_string),
+            value,
+        };
+        let patch = |operations: Vec<PatchOperations>| PatchOp { operations, ..Default::default() };
+
+        let mut unrelated = patch(vec![
+            operation("replace", Some("displayName"), Some(json!("Babs Jensen"))),
+            operation("replace", None, Some(json!({"active": false}))),
+        ]);
+        let expected = unrelated.clone();
+        handler.on_patch(&disabled, "bjensen", &mut unrelated).unwrap();
+        assert_eq!(unrelated, expected);
+
+        let mut by_path = patch(vec![operation("replace", Some("Password"), Some(json!("t1meMa$heen")))]);
+        assert_eq!(handler.on_patch(&disabled, "bjensen", &mut by_path).unwrap_err().status, "501");
+        handler.on_patch(&enabled, "bjensen", &mut by_path).unwrap();
+        assert_eq!(by_path.operations[0].value, Some(json!("hashed:11")));
+
+        let mut by_value = patch(vec![operation("replace", None, Some(json!({"displayName": "Babs", "password": "t1meMa$heen"})))]);
+        handler.on_patch(&enabled, "bjensen", &mut by_value).unwrap();
+        assert_eq!(by_value.operations[0].value, Some(json!({"displayName": "Babs", "password": "hashed:11"})));
+
+        let mut removed = patch(vec![operation("remove", Some("password"), None)]);
+        assert_eq!(handler.on_patch(&disabled, "bjensen", &mut removed).unwrap_err().status, "501");
+        assert!(handler.on_patch(&enabled, "bjensen", &mut removed).is_ok());
+
+        let mut not_a_string = patch(vec![operation("add", Some("password"), Some(json!(12345678)))]);
+        assert_eq!(handler.on_patch(&enabled, "bjensen", &mut not_a_string).unwrap_err().scim_type, Some("invalidValue".to_string()));
+    }
+
+    #[test]
+    fn strip_password_removes_stored_hash() {
+        let user = User {
+            body: UserRequest {
+                password: Some("hashed:11".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(strip_password(user).body.password, None);
+    }
+}
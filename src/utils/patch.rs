@@ -0,0 +1,691 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::models::enterprise_user::ENTERPRISE_USER_URN;
+use crate::models::others::{PatchOp, PatchOperations};
+use crate::utils::error::SCIMError;
+
+/// Attributes assigned by the service provider, which a patch never modifies.
+const SERVER_ATTRIBUTES: [&str; 3] = ["id", "meta", "schemas"];
+
+/// Core schema URNs, whose attributes are top-level attributes of a resource.
+const CORE_URNS: [&str; 2] = ["urn:ietf:params:scim:schemas:core:2.0:User", "urn:ietf:params:scim:schemas:core:2.0:Group"];
+
+/// Extension schema URNs, whose attributes are held in an object keyed by the URN.
+const EXTENSION_URNS: [&str; 1] = [ENTERPRISE_USER_URN];
+
+/// Computes the `PatchOp` that turns `current` into `desired`.
+///
+/// Works on any serializable resource, such as `User`, `Group` or a `serde_json::Value`. Only changed attributes
+/// are included:
+///
+/// * Complex attributes such as `name` are patched per sub-attribute (`name.givenName`).
+/// * Multi-valued attributes whose values can be told apart by `type` (or else by `value`) are patched per value
+///   with value-filter paths such as `emails[type eq "work"].value`. Other multi-valued attributes are replaced.
+/// * Extension attributes use URN-qualified paths such as
+///   `urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department`.
+///
+/// `id`, `meta` and `schemas` are ignored. Clear other read-only attributes (such as `User.groups`) before diffing if
+/// they are not meant to be compared.
+///
+/// # Errors
+///
+/// Returns `SCIMError::SerializationError` if a resource cannot be serialized, or `SCIMError::InvalidFieldValue`
+/// if a resource does not serialize to a JSON object.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::user::{Email, User, UserRequest};
+/// use scim_v2::utils::patch::diff;
+///
+/// let email = |address: &str| Email { value: Some(address.to_string()), type_: Some("work".to_string()), ..Default::default() };
+/// let current = User { body: UserRequest { user_name: "bjensen".to_string(), emails: Some(vec![email("bjensen@example.com")]), ..Default::default() }, ..Default::default() };
+/// let desired = User { body: UserRequest { user_name: "bjensen".to_string(), emails: Some(vec![email("babs@example.com")]), ..Default::default() }, ..Default::default() };
+///
+/// let patch = diff(&current, &desired).unwrap();
+/// assert_eq!(patch.operations.len(), 1);
+/// assert_eq!(patch.operations[0].path, Some(r#"emails[type eq "work"].value"#.to_string()));
+/// ```
+pub fn diff<T: Serialize>(current: &T, desired: &T) -> Result<PatchOp, SCIMError> {
+    let current = to_object(current)?;
+    let desired = to_object(desired)?;
+    let mut operations = Vec::new();
+
+    let mut core_current = Map::new();
+    let mut core_desired = Map::new();
+    let mut extensions: Vec<&String> = Vec::new();
+    for (resource, core) in [(&current, &mut core_current), (&desired, &mut core_desired)] {
+        for (key, value) in resource {
+            if SERVER_ATTRIBUTES.contains(&key.as_str()) {
+                continue;
+            }
+            if is_extension(key, value) {
+                if !extensions.contains(&key) {
+                    extensions.push(key);
+                }
+            } else {
+                core.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    diff_attributes(&core_current, &core_desired, "", &mut operations);
+    let empty = Map::new();
+    for urn in extensions {
+        let current_extension = current.get(urn).and_then(Value::as_object).unwrap_or(&empty);
+        let desired_extension = desired.get(urn).and_then(Value::as_object).unwrap_or(&empty);
+        diff_attributes(current_extension, desired_extension, &format!("{}:", urn), &mut operations);
+    }
+
+    Ok(PatchOp {
+        operations,
+        ..Default::default()
+    })
+}
+
+/// Applies a `PatchOp` to a resource and returns the patched resource.
+///
+/// See `apply` for the supported operations.
+///
+/// # Errors
+///
+/// Returns the errors of `apply`, or `SCIMError::DeserializationError` if the patched resource is no longer a valid `T`.
+pub fn apply_to<T: Serialize + DeserializeOwned>(resource: &T, patch: &PatchOp) -> Result<T, SCIMError> {
+    let mut value = serde_json::to_value(resource).map_err(SCIMError::SerializationError)?;
+    apply(&mut value, patch)?;
+    serde_json::from_value(value).map_err(SCIMError::DeserializationError)
+}
+
+/// Applies a `PatchOp` to the JSON representation of a resource.
+///
+/// Supports `add`, `replace` and `remove` (case-insensitively) with the following kinds of path:
+/// * no path
+/// * attribute paths such as `name.givenName`
+/// * URN-qualified paths; a path that is just an extension URN targets the whole extension object, and core URN
+///   prefixes such as `urn:ietf:params:scim:schemas:core:2.0:User:` are dropped
+/// * value-filter paths of the form `attr[subAttr eq value]` or `attr[subAttr eq value].subAttr`
+///
+/// Other filter expressions are rejected.
+///
+/// # Errors
+///
+/// * `SCIMError::InvalidFieldValue` - If an operation, path or filter is not supported, or a filter matches no value.
+/// * `SCIMError::MissingRequiredField` - If `add` or `replace` has no `value`, or `remove` has no `path`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::others::{PatchOp, PatchOperations};
+/// use scim_v2::utils::patch::apply;
+/// use serde_json::json;
+///
+/// let mut user = json!({"userName": "bjensen", "emails": [{"type": "work", "value": "bjensen@example.com"}]});
+/// let patch = PatchOp {
+///     operations: vec![PatchOperations {
+///         op: "replace".to_string(),
+///         path: Some(r#"emails[type eq "work"].value"#.to_string()),
+///         value: Some(json!("babs@example.com")),
+///     }],
+///     ..Default::default()
+/// };
+///
+/// apply(&mut user, &patch).unwrap();
+/// assert_eq!(user["emails"][0]["value"], "babs@example.com");
+/// ```
+pub fn apply(resource: &mut Value, patch: &PatchOp) -> Result<(), SCIMError> {
+    let root = resource.as_object_mut()
+        .ok_or_else(|| SCIMError::InvalidFieldValue("resource is not a JSON object".to_string()))?;
+    for operation in &patch.operations {
+        apply_operation(root, operation)?;
+    }
+    Ok(())
+}
+
+fn to_object<T: Serialize>(resource: &T) -> Result<Map<String, Value>, SCIMError> {
+    match serde_json::to_value(resource).map_err(SCIMError::SerializationError)? {
+        Value::Object(object) => Ok(object),
+        _ => Err(SCIMError::InvalidFieldValue("resource is not a JSON object".to_string())),
+    }
+}
+
+fn is_extension(key: &str, value: &Value) -> bool {
+    key.len() > 4 && key[..4].eq_ignore_ascii_case("urn:") && value.is_object()
+}
+
+fn diff_attributes(current: &Map<String, Value>, desired: &Map<String, Value>, prefix: &str, operations: &mut Vec<PatchOperations>) {
+    let keys = current.keys().chain(desired.keys().filter(|key| !current.contains_key(*key)));
+    for key in keys {
+        let path = format!("{}{}", prefix, key);
+        match (present(current.get(key)), present(desired.get(key))) {
+            (Some(_), None) => operations.push(operation("remove", path, None)),
+            (None, Some(desired)) => operations.push(operation("add", path, Some(desired.clone()))),
+            (Some(current), Some(desired)) if current != desired => match (current, desired) {
+                (Value::Array(current), Value::Array(desired)) => diff_multi_valued(&path, current, desired, operations),
+                (Value::Object(current), Value::Object(desired)) => diff_sub_attributes(&path, current, desired, operations),
+                _ => operations.push(operation("replace", path, Some(desired.clone()))),
+            },
+            _ => {}
+        }
+    }
+}
+
+fn diff_sub_attributes(path: &str, current: &Map<String, Value>, desired: &Map<String, Value>, operations: &mut Vec<PatchOperations>) {
+    let keys = current.keys().chain(desired.keys().filter(|key| !current.contains_key(*key)));
+    for key in keys {
+        let sub_path = format!("{}.{}", path, key);
+        match (present(current.get(key)), present(desired.get(key))) {
+            (Some(_), None) => operations.push(operation("remove", sub_path, None)),
+            (None, Some(desired)) => operations.push(operation("add", sub_path, Some(desired.clone()))),
+            (Some(current), Some(desired)) if current != desired => operations.push(operation("replace", sub_path, Some(desired.clone()))),
+            _ => {}
+        }
+    }
+}
+
+fn diff_multi_valued(path: &str, current: &[Value], desired: &[Value], operations: &mut Vec<PatchOperations>) {
+    let key = match ["type", "value"].iter().find(|key| identifies_values(key, current) && identifies_values(key, desired)) {
+        Some(key) => *key,
+        None => {
+            operations.push(operation("replace", path.to_string(), Some(Value::Array(desired.to_vec()))));
+            return;
+        }
+    };
+    let find = |values: &[Value], identity: &Value| values.iter().find(|value| value.get(key) == Some(identity)).cloned();
+
+    for current_value in current {
+        let identity = &current_value[key];
+        if find(desired, identity).is_none() {
+            operations.push(operation("remove", value_path(path, key, identity), None));
+        }
+    }
+    let mut added = Vec::new();
+    for desired_value in desired {
+        let identity = &desired_value[key];
+        match find(current, identity) {
+            None => added.push(desired_value.clone()),
+            Some(current_value) if &current_value != desired_value => {
+                if let (Value::Object(current_value), Value::Object(desired_value)) = (current_value, desired_value) {
+                    diff_sub_attributes(&value_path(path, key, identity), &current_value, desired_value, operations);
+                }
+            }
+            _ => {}
+        }
+    }
+    if !added.is_empty() {
+        operations.push(operation("add", path.to_string(), Some(Value::Array(added))));
+    }
+}
+
+/// Checks that every value is an object with a distinct scalar `key`, so values can be addressed with a filter.
+///
+/// Filters compare strings case-insensitively, so identities that differ only by case are not distinct.
+fn identifies_values(key: &str, values: &[Value]) -> bool {
+    let identities: Vec<&Value> = values.iter().filter_map(|value| value.get(key)).collect();
+    identities.len() == values.len()
+        && identities.iter().all(|identity| identity.is_string() || identity.is_number() || identity.is_boolean())
+        && identities.iter().enumerate().all(|(index, identity)| !identities[..index].iter().any(|other| same_identity(other, identity)))
+}
+
+fn same_identity(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.eq_ignore_ascii_case(b),
+        (a, b) => a == b,
+    }
+}
+
+fn value_path(path: &str, key: &str, identity: &Value) -> String {
+    format!("{}[{} eq {}]", path, key, identity)
+}
+
+fn present(value: Option<&Value>) -> Option<&Value> {
+    value.filter(|value| !value.is_null())
+}
+
+fn operation(op: &str, path: String, value: Option<Value>) -> PatchOperations {
+    PatchOperations {
+        op: op.to_string(),
+        path: Some(path),
+        value,
+    }
+}
+
+/// A parsed patch path: `[urn:]attribute[[key eq value]][.subAttribute]`.
+#[derive(Debug)]
+pub(crate) struct PatchPath {
+    pub(crate) urn: Option<String>,
+    pub(crate) attribute: String,
+    pub(crate) filter: Option<(String, Value)>,
+    pub(crate) sub_attribute: Option<String>,
+}
+
+impl PatchPath {
+    pub(crate) fn parse(path: &str) -> Result<PatchPath, SCIMError> {
+        let invalid = || SCIMError::InvalidFieldValue(format!("unsupported patch path: {}", path));
+        let head_end = path.find('[').unwrap_or(path.len());
+        let head = &path[..head_end];
+
+        // A path naming a known extension is the extension object itself, a top-level attribute keyed by its URN.
+        if let Some(extension) = EXTENSION_URNS.iter().find(|urn| head.eq_ignore_ascii_case(urn)) {
+            if head_end < path.len() {
+                return Err(invalid());
+            }
+            return Ok(PatchPath { urn: None, attribute: extension.to_string(), filter: None, sub_attribute: None });
+        }
+        let known = |urn: &&&str| {
+            head.get(..urn.len()).map_or(false, |prefix| prefix.eq_ignore_ascii_case(urn))
+                && head[urn.len()..].starts_with(':')
+        };
+        let (urn, rest) = if let Some(core) = CORE_URNS.iter().find(known) {
+            (None, &path[core.len() + 1..])
+        } else if let Some(extension) = EXTENSION_URNS.iter().find(known) {
+            (Some(extension.to_string()), &path[extension.len() + 1..])
+        } else {
+            match head.rfind(':') {
+                Some(index) => (Some(path[..index].to_string()), &path[index + 1..]),
+                None => (None, path),
+            }
+        };
+
+        let (attribute, filter, sub_attribute) = match rest.find('[') {
+            Some(open) => {
+                let close = rest.rfind(']').filter(|close| *close > open).ok_or_else(invalid)?;
+                let mut parts = rest[open + 1..close].trim().splitn(3, char::is_whitespace);
+                let key = parts.next().ok_or_else(invalid)?;
+                if !parts.next().map_or(false, |operator| operator.eq_ignore_ascii_case("eq")) {
+                    return Err(invalid());
+                }
+                let value: Value = parts.next().and_then(|value| serde_json::from_str(value.trim()).ok()).ok_or_else(invalid)?;
+                let sub_attribute = match &rest[close + 1..] {
+                    "" => None,
+                    after => Some(after.strip_prefix('.').ok_or_else(invalid)?.to_string()),
+                };
+                (rest[..open].to_string(), Some((key.to_string(), value)), sub_attribute)
+            }
+            None => match rest.split_once('.') {
+                Some((attribute, sub_attribute)) => (attribute.to_string(), None, Some(sub_attribute.to_string())),
+                None => (rest.to_string(), None, None),
+            },
+        };
+        if attribute.is_empty() || sub_attribute.as_deref() == Some("") {
+            return Err(invalid());
+        }
+        Ok(PatchPath { urn, attribute, filter, sub_attribute })
+    }
+}
+
+/// Finds the actual key of an attribute, ignoring case as attribute names are case-insensitive.
+pub(crate) fn key_of(object: &Map<String, Value>, name: &str) -> String {
+    object.keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| name.to_string())
+}
+
+fn apply_operation(root: &mut Map<String, Value>, operation: &PatchOperations) -> Result<(), SCIMError> {
+    let op = operation.op.to_lowercase();
+    if op != "add" && op != "replace" && op != "remove" {
+        return Err(SCIMError::InvalidFieldValue(format!("unsupported patch operation: {}", operation.op)));
+    }
+    let replace = op == "replace";
+
+    let path = match &operation.path {
+        Some(path) => PatchPath::parse(path)?,
+        None if op == "remove" => return Err(SCIMError::MissingRequiredField("path".to_string())),
+        None => {
+            let value = operation.value.as_ref()
+                .and_then(Value::as_object)
+                .ok_or_else(|| SCIMError::MissingRequiredField("value".to_string()))?;
+            for (key, value) in value {
+                if is_extension(key, value) {
+                    let extension_key = key_of(root, key);
+                    let extension = root.entry(extension_key).or_insert_with(|| Value::Object(Map::new()));
+                    let extension = extension.as_object_mut()
+                        .ok_or_else(|| SCIMError::InvalidFieldValue(format!("{} is not an object", key)))?;
+                    for (attribute, value) in value.as_object().into_iter().flatten() {
+                        set_attribute(extension, &PatchPath::parse(attribute)?, value.clone(), replace)?;
+                    }
+                } else {
+                    // Keys may be attribute paths themselves, including URN-qualified extension attributes.
+                    apply_operation(root, &PatchOperations {
+                        op: op.clone(),
+                        path: Some(key.clone()),
+                        value: Some(value.clone()),
+                    })?;
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    let container = match &path.urn {
+        Some(urn) => {
+            let key = key_of(root, urn);
+            if op == "remove" && !root.contains_key(&key) {
+                return Ok(());
+            }
+            root.entry(key)
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .ok_or_else(|| SCIMError::InvalidFieldValue(format!("{} is not an object", urn)))?
+        }
+        None => root,
+    };
+
+    if op == "remove" {
+        return remove_attribute(container, &path);
+    }
+    let value = operation.value.clone().ok_or_else(|| SCIMError::MissingRequiredField("value".to_string()))?;
+    set_attribute(container, &path, value, replace)
+}
+
+fn set_attribute(container: &mut Map<String, Value>, path: &PatchPath, value: Value, replace: bool) -> Result<(), SCIMError> {
+    let key = key_of(container, &path.attribute);
+    if let Some((filter_key, filter_value)) = &path.filter {
+        let values = matching_values(container, &key, filter_key, filter_value)?;
+        for matched in values {
+            match &path.sub_attribute {
+                Some(sub_attribute) => {
+                    let object = matched.as_object_mut()
+                        .ok_or_else(|| SCIMError::InvalidFieldValue(format!("{} values are not objects", key)))?;
+                    let sub_key = key_of(object, sub_attribute);
+                    object.insert(sub_key, value.clone());
+                }
+                None if replace => *matched = value.clone(),
+                None => merge(matched, value.clone()),
+            }
+        }
+        return Ok(());
+    }
+
+    match &path.sub_attribute {
+        Some(sub_attribute) => {
+            let target = container.entry(key).or_insert_with(|| Value::Object(Map::new()));
+            match target {
+                Value::Object(object) => {
+                    let sub_key = key_of(object, sub_attribute);
+                    object.insert(sub_key, value);
+                }
+                // A sub-attribute of a multi-valued attribute without a filter targets every value.
+                Value::Array(values) => {
+                    for item in values.iter_mut().filter_map(Value::as_object_mut) {
+                        let sub_key = key_of(item, sub_attribute);
+                        item.insert(sub_key, value.clone());
+                    }
+                }
+                _ => return Err(SCIMError::InvalidFieldValue(format!("{} has no sub-attributes", path.attribute))),
+            }
+        }
+        None => match container.get_mut(&key) {
+            Some(Value::Array(values)) if !replace => match value {
+                Value::Array(added) => values.extend(added),
+                value => values.push(value),
+            },
+            Some(existing) if !replace => merge(existing, value),
+            _ => {
+                container.insert(key, value);
+            }
+        },
+    }
+    Ok(())
+}
+
+fn remove_attribute(container: &mut Map<String, Value>, path: &PatchPath) -> Result<(), SCIMError> {
+    let key = key_of(container, &path.attribute);
+    match (&path.filter, &path.sub_attribute) {
+        (None, None) => {
+            container.remove(&key);
+        }
+        (None, Some(sub_attribute)) => match container.get_mut(&key) {
+            Some(Value::Object(object)) => {
+                let sub_key = key_of(object, sub_attribute);
+                object.remove(&sub_key);
+            }
+            Some(Value::Array(values)) => {
+                for item in values.iter_mut().filter_map(Value::as_object_mut) {
+                    let sub_key = key_of(item, sub_attribute);
+                    item.remove(&sub_key);
+                }
+            }
+            _ => {}
+        },
+        (Some((filter_key, filter_value)), Some(sub_attribute)) => {
+            for matched in matching_values(container, &key, filter_key, filter_value)? {
+                if let Some(object) = matched.as_object_mut() {
+                    let sub_key = key_of(object, sub_attribute);
+                    object.remove(&sub_key);
+                }
+            }
+        }
+        (Some((filter_key, filter_value)), None) => {
+            matching_values(container, &key, filter_key, filter_value)?;
+            if let Some(Value::Array(values)) = container.get_mut(&key) {
+                values.retain(|value| !filter_matches(value, filter_key, filter_value));
+                if values.is_empty() {
+                    container.remove(&key);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the values of a multi-valued attribute that match a filter, failing if none do (`noTarget`).
+fn matching_values<'a>(container: &'a mut Map<String, Value>, key: &str, filter_key: &str, filter_value: &Value) -> Result<Vec<&'a mut Value>, SCIMError> {
+    let no_target = || SCIMError::InvalidFieldValue(format!("no values of {} match {} eq {}", key, filter_key, filter_value));
+    let values = match container.get_mut(key) {
+        Some(Value::Array(values)) => values,
+        _ => return Err(no_target()),
+    };
+    let matched: Vec<&mut Value> = values.iter_mut().filter(|value| filter_matches(value, filter_key, filter_value)).collect();
+    if matched.is_empty() {
+        return Err(no_target());
+    }
+    Ok(matched)
+}
+
+fn filter_matches(value: &Value, filter_key: &str, filter_value: &Value) -> bool {
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => return false,
+    };
+    match (object.get(&key_of(object, filter_key)), filter_value) {
+        (Some(Value::String(actual)), Value::String(expected)) => actual.eq_ignore_ascii_case(expected),
+        (Some(actual), expected) => actual == expected,
+        (None, _) => false,
+    }
+}
+
+fn merge(target: &mut Value, value: Value) {
+    match (target, value) {
+        (Value::Object(target), Value::Object(value)) => {
+            for (key, value) in value {
+                let key = key_of(target, &key);
+                target.insert(key, value);
+            }
+        }
+        (target, value) => *target = value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::models::enterprise_user::EnterpriseUser;
+    use crate::models::group::{Group, GroupRequest, Member};
+    use crate::models::user::{Email, Name, User, UserRequest};
+
+    use super::*;
+
+    fn email(type_: &str, value: &str) -> Email {
+        Email {
+            value: Some(value.to_string()),
+            type_: Some(type_.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn paths(patch: &PatchOp) -> Vec<(String, String)> {
+        patch.operations.iter().map(|operation| (operation.op.clone(), operation.path.clone().unwrap_or_default())).collect()
+    }
+
+    #[test]
+    fn diff_of_users_uses_value_filters_and_urn_paths() {
+        let current = User {
+            id: Some("2819c223-7f76-453a-919d-413861904646".to_string()),
+            body: UserRequest {
+                user_name: "bjensen".to_string(),
+                name: Some(Name { given_name: Some("Barbara".to_string()), family_name: Some("Jensen".to_string()), ..Default::default() }),
+                title: Some("Tour Guide".to_string()),
+                emails: Some(vec![email("work", "bjensen@example.com"), email("home", "babs@jensen.org")]),
+                enterprise_user: Some(EnterpriseUser { department: Some("Tour Operations".to_string()), ..Default::default() }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let desired = User {
+            body: UserRequest {
+                user_name: "bjensen".to_string(),
+                name: Some(Name { given_name: Some("Babs".to_string()), family_name: Some("Jensen".to_string()), ..Default::default() }),
+                nick_name: Some("Babs".to_string()),
+                emails: Some(vec![email("work", "babs@example.com"), email("other", "babs@example.org")]),
+                enterprise_user: Some(EnterpriseUser { department: Some("Marketing".to_string()), ..Default::default() }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let patch = diff(&current, &desired).unwrap();
+
+        assert_eq!(paths(&patch), vec![
+            ("remove".to_string(), r#"emails[type eq "home"]"#.to_string()),
+            ("replace".to_string(), r#"emails[type eq "work"].value"#.to_string()),
+            ("add".to_string(), "emails".to_string()),
+            ("replace".to_string(), "name.givenName".to_string()),
+            ("remove".to_string(), "title".to_string()),
+            ("add".to_string(), "nickName".to_string()),
+            ("replace".to_string(), "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department".to_string()),
+        ]);
+
+        let patched = apply_to(&current, &patch).unwrap();
+        assert_eq!(patched.id, current.id);
+        assert!(diff(&patched, &desired).unwrap().operations.is_empty());
+    }
+
+    #[test]
+    fn diff_of_groups_patches_members_by_value() {
+        let member = |value: &str| Member { value: Some(value.to_string()), ..Default::default() };
+        let group = |members| Group { body: GroupRequest { display_name: "Tour Guides".to_string(), members: Some(members), ..Default::default() }, ..Default::default() };
+        let current = group(vec![member("a"), member("b")]);
+        let desired = group(vec![member("b"), member("c")]);
+
+        let patch = diff(&current, &desired).unwrap();
+
+        assert_eq!(paths(&patch), vec![
+            ("remove".to_string(), r#"members[value eq "a"]"#.to_string()),
+            ("add".to_string(), "members".to_string()),
+        ]);
+        assert!(diff(&apply_to(&current, &patch).unwrap(), &desired).unwrap().operations.is_empty());
+        assert!(diff(&current, &current).unwrap().operations.is_empty());
+    }
+
+    #[test]
+    fn diff_replaces_multi_valued_attributes_without_identity() {
+        let current = json!({"userName": "bjensen", "emails": [{"value": "a@example.com"}, {"value": "a@example.com"}]});
+        let desired = json!({"userName": "bjensen", "emails": [{"value": "b@example.com"}]});
+
+        let patch = diff(&current, &desired).unwrap();
+
+        assert_eq!(paths(&patch), vec![("replace".to_string(), "emails".to_string())]);
+        let mut patched = current.clone();
+        apply(&mut patched, &patch).unwrap();
+        assert_eq!(patched, desired);
+    }
+
+    #[test]
+    fn diff_treats_identities_differing_only_by_case_as_colliding() {
+        let user = |emails: Vec<Email>| User {
+            body: UserRequest {
+                user_name: "bjensen".to_string(),
+                emails: Some(emails),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let current = user(vec![email("work", "bjensen@example.com"), email("Work", "babs@example.com")]);
+        let desired = user(vec![email("work", "bjensen@example.com"), email("Work", "barbara@example.com")]);
+
+        let patch = diff(&current, &desired).unwrap();
+        assert_eq!(paths(&patch), vec![
+            ("remove".to_string(), r#"emails[value eq "babs@example.com"]"#.to_string()),
+            ("add".to_string(), "emails".to_string()),
+        ]);
+        assert_eq!(apply_to(&current, &patch).unwrap().body.emails, desired.body.emails);
+
+        let current = user(vec![email("work", "bjensen@example.com"), email("Work", "BJensen@example.com")]);
+        let patch = diff(&current, &desired).unwrap();
+        assert_eq!(paths(&patch), vec![("replace".to_string(), "emails".to_string())]);
+        assert_eq!(apply_to(&current, &patch).unwrap().body.emails, desired.body.emails);
+    }
+
+    #[test]
+    fn apply_supports_pathless_and_case_insensitive_operations() {
+        let mut user = json!({"userName": "bjensen", "emails": [{"type": "work", "value": "bjensen@example.com"}]});
+        let patch: PatchOp = serde_json::from_value(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [
+                {"op": "Add", "value": {"nickName": "Babs", "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"department": "Marketing"}}},
+                {"op": "Add", "value": {"urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:costCenter": "4130"}},
+                {"op": "Add", "path": "emails", "value": [{"type": "home", "value": "babs@jensen.org"}]},
+                {"op": "Remove", "path": "EMAILS[TYPE eq \"Work\"]"},
+                {"op": "replace", "path": "name.givenName", "value": "Barbara"}
+            ]
+        })).unwrap();
+
+        apply(&mut user, &patch).unwrap();
+
+        assert_eq!(user, json!({
+            "userName": "bjensen",
+            "nickName": "Babs",
+            "name": {"givenName": "Barbara"},
+            "emails": [{"type": "home", "value": "babs@jensen.org"}],
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"department": "Marketing", "costCenter": "4130"}
+        }));
+    }
+
+    #[test]
+    fn apply_resolves_schema_urn_paths() {
+        let enterprise = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User";
+        let patch = |op: &str, path: &str, value: Option<Value>| PatchOp {
+            operations: vec![PatchOperations { op: op.to_string(), path: Some(path.to_string()), value }],
+            ..Default::default()
+        };
+        let mut user = json!({"userName": "bjensen", enterprise: {"department": "Ops", "costCenter": "4130"}});
+
+        apply(&mut user, &patch("add", enterprise, Some(json!({"department": "Sales"})))).unwrap();
+        assert_eq!(user, json!({"userName": "bjensen", enterprise: {"department": "Sales", "costCenter": "4130"}}));
+
+        apply(&mut user, &patch("replace", "urn:ietf:params:scim:schemas:core:2.0:User:userName", Some(json!("babs")))).unwrap();
+        assert_eq!(user, json!({"userName": "babs", enterprise: {"department": "Sales", "costCenter": "4130"}}));
+
+        apply(&mut user, &patch("remove", &enterprise.to_uppercase(), None)).unwrap();
+        assert_eq!(user, json!({"userName": "babs"}));
+    }
+
+    #[test]
+    fn apply_rejects_unsupported_paths_and_missing_targets() {
+        let mut user = json!({"userName": "bjensen", "emails": [{"type": "work", "value": "bjensen@example.com"}]});
+        let patch = |op: &str, path: &str| PatchOp {
+            operations: vec![PatchOperations { op: op.to_string(), path: Some(path.to_string()), value: Some(json!("x")) }],
+            ..Default::default()
+        };
+
+        assert!(matches!(apply(&mut user, &patch("replace", r#"emails[type co "w"].value"#)), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(apply(&mut user, &patch("replace", r#"emails[type eq "home"].value"#)), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(apply(&mut user, &patch("move", "userName")), Err(SCIMError::InvalidFieldValue(_))));
+        assert!(matches!(apply(&mut user, &PatchOp { operations: vec![PatchOperations { op: "remove".to_string(), ..Default::default() }], ..Default::default() }), Err(SCIMError::MissingRequiredField(_))));
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use crate::models::scim_schema::{Attributes, Schema, SubAttributes};
+
+/// The placeholder written in place of a redacted value.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Number of characters of a binary value kept when it is truncated for logging.
+const BINARY_PREFIX_LENGTH: usize = 16;
+
+/// Wraps a value that must not appear in logs.
+///
+/// `Debug` and `Display` print `[REDACTED]` instead of the wrapped value, which is still available as `.0`.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::utils::redact::Sensitive;
+///
+/// let password = Sensitive("t1meMa$heen".to_string());
+/// assert_eq!(format!("{:?}", password), "[REDACTED]");
+/// assert_eq!(password.0, "t1meMa$heen");
+/// ```
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct Sensitive<T>(pub T);
+
+impl<T> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Shortens a binary (base64) value to a short prefix followed by its original length.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::utils::redact::truncate_binary;
+///
+/// assert_eq!(truncate_binary("MIIDQzCCAqygAwIBAgICEAAwDQYJKoZIhvcNAQEFBQAw"), "MIIDQzCCAqygAwIB... (44 chars)");
+/// assert_eq!(truncate_binary("MIIDQzCC"), "MIIDQzCC");
+/// ```
+pub fn truncate_binary(value: &str) -> String {
+    match value.char_indices().nth(BINARY_PREFIX_LENGTH) {
+        Some((end, _)) => format!("{}... ({} chars)", &value[..end], value.chars().count()),
+        None => value.to_string(),
+    }
+}
+
+/// Redacts a JSON representation of a resource in place, using the schemas that describe it.
+///
+/// Attributes marked `returned: never` (such as `password`) are replaced with `[REDACTED]`, and values of
+/// `binary` attributes (such as `x509Certificates.value`) are truncated with `truncate_binary`. Attribute names are
+/// matched case-insensitively. Extension attributes are looked up in the schema whose URN is the key of the
+/// extension object; all other top-level attributes are looked up in the remaining schemas.
+///
+/// # Examples
+///
+/// ```rust
+/// use scim_v2::models::scim_schema::get_schemas;
+/// use scim_v2::utils::redact::redact_json;
+/// use serde_json::json;
+///
+/// let schemas = get_schemas(vec!["user"]).unwrap();
+/// let mut user = json!({"userName": "bjensen", "password": "t1meMa$heen"});
+/// redact_json(&mut user, &schemas);
+/// assert_eq!(user, json!({"userName": "bjensen", "password": "[REDACTED]"}));
+/// ```
+pub fn redact_json(value: &mut Value, schemas: &[Schema]) {
+    let object = match value.as_object_mut() {
+        Some(object) => object,
+        None => return,
+    };
+    let is_extension = |schema: &&Schema| object.keys().any(|key| key.eq_ignore_ascii_case(&schema.id));
+    let core_attributes: Vec<&Attributes> = schemas.iter()
+        .filter(|schema| !is_extension(schema))
+        .flat_map(|schema| schema.attributes.iter())
+        .collect();
+
+    for (key, value) in object.iter_mut() {
+        if let Some(extension) = schemas.iter().find(|schema| key.eq_ignore_ascii_case(&schema.id)) {
+            if let Some(extension_object) = value.as_object_mut() {
+                let attributes: Vec<&Attributes> = extension.attributes.iter().collect();
+                redact_attributes(extension_object, &attributes);
+            }
+        } else if let Some(attribute) = core_attributes.iter().find(|attribute| key.eq_ignore_ascii_case(&attribute.name)) {
+            redact_attribute(value, &attribute.returned, &attribute.type_, &attribute.sub_attributes);
+        }
+    }
+}
+
+fn redact_attributes(object: &mut Map<String, Value>, attributes: &[&Attributes]) {
+    for (key, value) in object.iter_mut() {
+        if let Some(attribute) = attributes.iter().find(|attribute| key.eq_ignore_ascii_case(&attribute.name)) {
+            redact_attribute(value, &attribute.returned, &attribute.type_, &attribute.sub_attributes);
+        }
+    }
+}
+
+fn redact_attribute(value: &mut Value, returned: &Option<String>, type_: &str, sub_attributes: &Option<Vec<SubAttributes>>) {
+    if returned.as_deref() == Some("never") {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    }
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                redact_attribute(value, returned, type_, sub_attributes);
+            }
+        }
+        Value::Object(object) => {
+            if let Some(sub_attributes) = sub_attributes {
+                for (key, value) in object.iter_mut() {
+                    if let Some(sub_attribute) = sub_attributes.iter().find(|sub_attribute| key.eq_ignore_ascii_case(&sub_attribute.name)) {
+                        redact_attribute(value, &sub_attribute.returned, &sub_attribute.type_, &None);
+                    }
+                }
+            }
+        }
+        Value::String(string) if type_ == "binary" => *string = truncate_binary(string),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::models::scim_schema::get_schemas;
+
+    use super::*;
+
+    #[test]
+    fn redact_json_hides_never_returned_and_truncates_binary_attributes() {
+        let schemas = get_schemas(vec!["user", "enterprise_user"]).unwrap();
+        let mut user = json!({
+            "userName": "bjensen",
+            "PASSWORD": "t1meMa$heen",
+            "x509Certificates": [{"value": "MIIDQzCCAqygAwIBAgICEAAwDQYJKoZIhvcNAQEFBQAw", "primary": true}],
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"employeeNumber": "701984"}
+        });
+
+        redact_json(&mut user, &schemas);
+
+        assert_eq!(user, json!({
+            "userName": "bjensen",
+            "PASSWORD": "[REDACTED]",
+            "x509Certificates": [{"value": "MIIDQzCCAqygAwIB... (44 chars)", "primary": true}],
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": {"employeeNumber": "701984"}
+        }));
+    }
+
+    #[test]
+    fn sensitive_hides_value_in_debug_and_display() {
+        let secret = Sensitive(42);
+        assert_eq!(format!("{:?} {}", secret, secret), "[REDACTED] [REDACTED]");
+    }
+}